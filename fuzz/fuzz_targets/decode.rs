@@ -0,0 +1,17 @@
+//! Checks that parsing and decoding arbitrary input never panics and that
+//! everything which is accepted can be encoded again.
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use minsky_automaton::Program;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(program) = Program::from_bytes(data) {
+        assert_eq!(Program::from_bytes(&program.to_bytes()), Ok(program));
+    }
+    if let Ok(src) = std::str::from_utf8(data) {
+        if let Ok(program) = Program::parse_asm(src) {
+            let asm = Program::parse_asm(&program.disassemble()).unwrap();
+            assert_eq!(asm, program);
+        }
+    }
+});
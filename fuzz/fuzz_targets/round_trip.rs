@@ -0,0 +1,14 @@
+//! Checks that programs survive a round trip through all supported formats.
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use minsky_automaton::Program;
+
+fuzz_target!(|program: Program| {
+    let asm = Program::parse_asm(&program.disassemble()).unwrap();
+    assert_eq!(asm, program);
+    let binary = Program::from_bytes(&program.to_bytes()).unwrap();
+    assert_eq!(binary, program);
+    for &instruction in program.instructions() {
+        assert_eq!(instruction.to_string().parse(), Ok(instruction));
+    }
+});
@@ -0,0 +1,239 @@
+use crate::{Instruction, Program};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A loop which decrements `counter` until it is zero while
+/// adding constants to other registers, see [`Program::loop_summary`].
+///
+/// Starting at `header` with `counter` holding `c`, the loop runs for
+/// `c * iteration_steps` steps, adding `c * n` to each register in `increments`,
+/// until it is back at `header` with `counter` being zero.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoopSummary {
+    /// The `Decrement` instruction which starts each iteration.
+    pub header: u16,
+    /// The register decremented by the header.
+    pub counter: u8,
+    /// The amount added to each register in a single iteration.
+    pub increments: BTreeMap<u8, u64>,
+    /// The target of the header once `counter` is zero.
+    pub exit: u16,
+    /// The number of steps of a single iteration, including the header.
+    pub iteration_steps: u64,
+}
+
+impl LoopSummary {
+    /// The number of steps until leaving the loop if `counter` holds `value`
+    /// at the header, or `None` if this does not fit into a `u64`.
+    pub fn steps(&self, value: u64) -> Option<u64> {
+        value.checked_mul(self.iteration_steps)?.checked_add(1)
+    }
+}
+
+/// The effect of a [`BlockSummary`] on a single register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RegisterEffect {
+    /// Adds a constant to the register.
+    Add(u64),
+    /// Sets the register to a constant.
+    Set(u64),
+}
+
+/// A sequence of instructions without branches, see [`Program::block_summary`].
+///
+/// Starting at `start`, the block runs for `steps` steps, applying `effects`,
+/// and continues at `next`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockSummary {
+    pub start: u16,
+    pub next: u16,
+    pub steps: u64,
+    pub effects: BTreeMap<u8, RegisterEffect>,
+}
+
+impl Program {
+    /// Summarizes the straight-line code starting at `at`.
+    ///
+    /// This follows the chain of `Increment`, `AddConst`, `Clear`, `Jump` and `Nop`
+    /// instructions starting at `at` until reaching any other instruction or an
+    /// instruction which is already part of the chain. Returns `None` if the
+    /// instruction at `at` is not part of such a chain.
+    pub fn block_summary(&self, at: u16) -> Option<BlockSummary> {
+        let mut effects = BTreeMap::new();
+        let mut visited = BTreeSet::new();
+        let mut next = at;
+        while !visited.contains(&next) {
+            let (reg, effect) = match self.instruction(next) {
+                Instruction::Increment(reg, _) => (reg, RegisterEffect::Add(1)),
+                Instruction::AddConst(reg, n, _) => (reg, RegisterEffect::Add(n)),
+                Instruction::Clear(reg, _) => (reg, RegisterEffect::Set(0)),
+                Instruction::Jump(target) | Instruction::Nop(target) => {
+                    visited.insert(next);
+                    next = target;
+                    continue;
+                }
+                _ => break,
+            };
+            let combined = match (effects.get(&reg), effect) {
+                (None, effect) | (Some(_), effect @ RegisterEffect::Set(_)) => Some(effect),
+                (Some(&RegisterEffect::Add(old)), RegisterEffect::Add(n)) => {
+                    old.checked_add(n).map(RegisterEffect::Add)
+                }
+                (Some(&RegisterEffect::Set(old)), RegisterEffect::Add(n)) => {
+                    old.checked_add(n).map(RegisterEffect::Set)
+                }
+            };
+            match combined {
+                Some(effect) => effects.insert(reg, effect),
+                None => break,
+            };
+            visited.insert(next);
+            next = self.instruction(next).targets().next().unwrap();
+        }
+        if visited.is_empty() {
+            return None;
+        }
+        Some(BlockSummary {
+            start: at,
+            next,
+            steps: visited.len() as u64,
+            effects,
+        })
+    }
+
+    /// Summarizes the loop starting at `at`, if it is a simple loop.
+    ///
+    /// A simple loop starts with `Decrement(counter, body, exit)`, followed by a chain of
+    /// `Increment`, `AddConst`, `Jump` and `Nop` instructions which leads back to the
+    /// header and never modifies `counter`. Transferring a register to others, e.g. the
+    /// code generated for `$dst += $src`, is the most common example of such a loop.
+    pub fn loop_summary(&self, at: u16) -> Option<LoopSummary> {
+        let (counter, mut next, exit) = match self.instruction(at) {
+            Instruction::Decrement(counter, then, els) => (counter, then, els),
+            _ => return None,
+        };
+        let mut increments = BTreeMap::new();
+        let mut iteration_steps = 1;
+        while next != at {
+            if iteration_steps > self.len() as u64 {
+                return None;
+            }
+            iteration_steps += 1;
+            let (reg, n) = match self.instruction(next) {
+                Instruction::Increment(reg, target) => {
+                    next = target;
+                    (reg, 1)
+                }
+                Instruction::AddConst(reg, n, target) => {
+                    next = target;
+                    (reg, n)
+                }
+                Instruction::Jump(target) | Instruction::Nop(target) => {
+                    next = target;
+                    continue;
+                }
+                _ => return None,
+            };
+            if reg == counter {
+                return None;
+            }
+            let amount = increments.entry(reg).or_insert(0u64);
+            *amount = amount.checked_add(n)?;
+        }
+        Some(LoopSummary {
+            header: at,
+            counter,
+            increments,
+            exit,
+            iteration_steps,
+        })
+    }
+
+    /// The summaries of all simple loops of this program, see [`Program::loop_summary`].
+    pub fn loop_summaries(&self) -> Vec<LoopSummary> {
+        self.iter()
+            .filter_map(|(at, _)| self.loop_summary(at))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Machine, RunOutcome};
+
+    #[test]
+    fn loop_summary() {
+        // $1 += 2 * $0, $2 += $0, then count $3 down to zero one step at a time.
+        let program = Program::new(
+            [
+                Instruction::Decrement(0, 1, 4),
+                Instruction::Increment(1, 2),
+                Instruction::AddConst(2, 1, 3),
+                Instruction::Increment(1, 0),
+                Instruction::Decrement(3, 5, 6),
+                Instruction::Jump(4),
+                Instruction::Decrement(4, 7, 8),
+                Instruction::Increment(4, 6),
+            ]
+            .iter()
+            .copied(),
+        );
+        let mut increments = BTreeMap::new();
+        increments.insert(1, 2);
+        increments.insert(2, 1);
+        let summary = LoopSummary {
+            header: 0,
+            counter: 0,
+            increments,
+            exit: 4,
+            iteration_steps: 4,
+        };
+        assert_eq!(program.loop_summary(0), Some(summary.clone()));
+        assert_eq!(summary.steps(3), Some(13));
+        assert_eq!(summary.steps(u64::MAX), None);
+        assert_eq!(program.loop_summary(1), None);
+        // The loop at 6 modifies its own counter.
+        assert_eq!(
+            program
+                .loop_summaries()
+                .iter()
+                .map(|s| s.header)
+                .collect::<Vec<_>>(),
+            [0, 4]
+        );
+
+        let mut prog: Machine = Machine::new(&program);
+        prog.set_register(0, 1 << 40);
+        prog.set_register(3, 1 << 50);
+        assert_eq!(
+            prog.run_accelerated(u64::MAX),
+            RunOutcome::Halted {
+                steps: (1 << 42) + (1 << 51) + 3,
+                code: 0
+            }
+        );
+        assert_eq!(prog.registers()[..5], [0, 1 << 41, 1 << 40, 0, 0]);
+
+        // Running out of steps in the middle of an accelerated loop.
+        for max_steps in 0..20 {
+            let mut slow: Machine = Machine::new(&program);
+            let mut fast = slow.clone();
+            for prog in [&mut slow, &mut fast].iter_mut() {
+                prog.set_register(0, 3);
+                prog.set_register(3, 2);
+            }
+            assert_eq!(slow.run(max_steps), fast.run_accelerated(max_steps));
+            assert_eq!(slow.registers(), fast.registers());
+            assert_eq!(slow.ptr(), fast.ptr());
+        }
+
+        // Loops which would overflow are executed one step at a time.
+        let mut prog: Machine<u8> = Machine::new(&program);
+        prog.set_register(0, 200);
+        assert_eq!(
+            prog.run_accelerated(u64::MAX),
+            RunOutcome::Overflow { at: 3, reg: 1 }
+        );
+        assert_eq!(prog.registers()[..3], [72, 255, 128]);
+    }
+}
@@ -0,0 +1,457 @@
+use crate::{Instruction, Program};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The register overwritten by `instruction` without using its old value.
+fn overwritten(instruction: Instruction) -> Option<u8> {
+    match instruction {
+        Instruction::Clear(reg, _) | Instruction::Read(reg, _) => Some(reg),
+        _ => None,
+    }
+}
+
+/// The registers whose values are used by `instruction`.
+fn reads(instruction: Instruction) -> impl Iterator<Item = u8> {
+    let overwritten = overwritten(instruction);
+    instruction
+        .registers()
+        .filter(move |&reg| Some(reg) != overwritten)
+}
+
+/// The registers which may be changed by `instruction`.
+fn writes(instruction: Instruction) -> impl Iterator<Item = u8> {
+    let written = !matches!(
+        instruction,
+        Instruction::BranchZero(..) | Instruction::Compare(..) | Instruction::Write(..)
+    );
+    instruction.registers().filter(move |_| written)
+}
+
+/// The live registers of a program, see [`Program::liveness`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Liveness {
+    live_in: Vec<BTreeSet<u8>>,
+    live_out: Vec<BTreeSet<u8>>,
+    outputs: BTreeSet<u8>,
+}
+
+impl Liveness {
+    /// The registers whose values before executing the instruction at `at`
+    /// may affect the result of the program.
+    ///
+    /// For positions past the end of the program these are the outputs.
+    pub fn live_in(&self, at: u16) -> &BTreeSet<u8> {
+        self.live_in.get(usize::from(at)).unwrap_or(&self.outputs)
+    }
+
+    /// The registers whose values after executing the instruction at `at`
+    /// may affect the result of the program.
+    pub fn live_out(&self, at: u16) -> &BTreeSet<u8> {
+        self.live_out.get(usize::from(at)).unwrap_or(&self.outputs)
+    }
+}
+
+impl Program {
+    /// All registers whose values are used by an instruction of this program.
+    ///
+    /// This excludes registers which are only overwritten by `Clear` or `Read`.
+    pub fn registers_read(&self) -> BTreeSet<u8> {
+        self.iter()
+            .flat_map(|(_, instruction)| reads(instruction))
+            .collect()
+    }
+
+    /// All registers which may be changed by an instruction of this program.
+    pub fn registers_written(&self) -> BTreeSet<u8> {
+        self.iter()
+            .flat_map(|(_, instruction)| writes(instruction))
+            .collect()
+    }
+
+    /// Computes which registers are live before and after each instruction,
+    /// given the registers whose values are the result of the program.
+    ///
+    /// A register is live if its current value may affect the result, i.e. if it may
+    /// be used before being overwritten by `Clear` or `Read`. This means that only the
+    /// registers in `live_in(0)` have to be initialized before running the program.
+    /// As `Return` may continue after any `Call`, every register used by the program
+    /// is considered to be live after a `Return`.
+    pub fn liveness(&self, outputs: impl IntoIterator<Item = u8>) -> Liveness {
+        let outputs: BTreeSet<u8> = outputs.into_iter().collect();
+        let cfg = self.control_flow_graph();
+        let mut after_return = self.registers_read();
+        after_return.extend(&outputs);
+
+        let mut live_in = vec![BTreeSet::new(); cfg.len()];
+        live_in[usize::from(cfg.exit())] = outputs.clone();
+        let mut live_out = vec![BTreeSet::new(); self.len()];
+        let mut worklist: Vec<u16> = (0..cfg.exit()).collect();
+        while let Some(at) = worklist.pop() {
+            let instruction = self.instruction(at);
+            let mut out = match instruction {
+                Instruction::Return => after_return.clone(),
+                _ => BTreeSet::new(),
+            };
+            for &succ in cfg.successors(at) {
+                out.extend(&live_in[usize::from(succ)]);
+            }
+            let mut live = out.clone();
+            if let Some(reg) = overwritten(instruction) {
+                live.remove(&reg);
+            }
+            live.extend(reads(instruction));
+            live_out[usize::from(at)] = out;
+            if live != live_in[usize::from(at)] {
+                live_in[usize::from(at)] = live;
+                worklist.extend(cfg.predecessors(at));
+            }
+        }
+        live_in.truncate(self.len());
+        Liveness {
+            live_in,
+            live_out,
+            outputs,
+        }
+    }
+
+    /// The registers which are zero whenever the instruction at each position is
+    /// executed, assuming that all registers except for `inputs` start at zero.
+    ///
+    /// This is `None` for unreachable instructions. Only registers which are used by
+    /// the program are included, all other registers are trivially zero unless they
+    /// are inputs. As `Return` may continue after any `Call`, nothing is known about
+    /// the registers at the instruction after a `Call`.
+    pub fn zero_registers(
+        &self,
+        inputs: impl IntoIterator<Item = u8>,
+    ) -> Vec<Option<BTreeSet<u8>>> {
+        let inputs: BTreeSet<u8> = inputs.into_iter().collect();
+        let used: BTreeSet<u8> = self
+            .iter()
+            .flat_map(|(_, instruction)| instruction.registers())
+            .collect();
+        let mut zero: Vec<Option<BTreeSet<u8>>> = vec![None; self.len()];
+        if self.is_empty() {
+            return zero;
+        }
+        zero[0] = Some(used.difference(&inputs).copied().collect());
+        let mut worklist = vec![0];
+        while let Some(at) = worklist.pop() {
+            let instruction = self.instruction(at);
+            let before = zero[usize::from(at)].clone().unwrap();
+            let is_zero = |reg| before.contains(&reg);
+            let mut after = before.clone();
+            let mut set = |reg, value: bool| {
+                if value {
+                    after.insert(reg);
+                } else {
+                    after.remove(&reg);
+                }
+            };
+            match instruction {
+                Instruction::Increment(reg, _) | Instruction::Read(reg, _) => set(reg, false),
+                Instruction::AddConst(reg, n, _) => set(reg, is_zero(reg) && n == 0),
+                Instruction::Clear(reg, _) => set(reg, true),
+                Instruction::Transfer { src, dst, .. } if src != dst => {
+                    set(dst, is_zero(src) && is_zero(dst));
+                    set(src, true);
+                }
+                Instruction::Copy {
+                    src, dst, scratch, ..
+                } if src != dst && src != scratch && dst != scratch => {
+                    set(dst, is_zero(src) && is_zero(dst) && is_zero(scratch));
+                    set(src, is_zero(src) && is_zero(scratch));
+                    set(scratch, true);
+                }
+                Instruction::Swap(a, b, _) => {
+                    set(a, is_zero(b));
+                    set(b, is_zero(a));
+                }
+                _ => {}
+            }
+
+            let successors: Vec<(u16, Option<BTreeSet<u8>>)> = match instruction {
+                // The register is zero when taking the second branch.
+                Instruction::Decrement(reg, then, els)
+                | Instruction::BranchZero(reg, els, then) => {
+                    let mut zero_branch = after.clone();
+                    zero_branch.insert(reg);
+                    vec![(then, Some(after)), (els, Some(zero_branch))]
+                }
+                Instruction::Call(target) => {
+                    vec![(target, Some(after)), (at.wrapping_add(1), None)]
+                }
+                _ => instruction
+                    .targets()
+                    .map(|target| (target, Some(after.clone())))
+                    .collect(),
+            };
+            for (succ, state) in successors {
+                let current = match zero.get_mut(usize::from(succ)) {
+                    Some(current) => current,
+                    None => continue,
+                };
+                let state = state.unwrap_or_default();
+                let new = match current {
+                    Some(current) => current.intersection(&state).copied().collect(),
+                    None => state,
+                };
+                if current.as_ref() != Some(&new) {
+                    *current = Some(new);
+                    worklist.push(succ);
+                }
+            }
+        }
+        zero
+    }
+
+    /// The registers whose value is known whenever the instruction at each position is
+    /// executed, given the initial value of the `inputs` and assuming that all other
+    /// registers start at zero. Inputs whose initial value is `None` are unknown.
+    ///
+    /// This is `None` for instructions which are unreachable for these inputs, e.g.
+    /// because they are only reached by branching on a register which is known to be
+    /// zero. Like [`Program::zero_registers`], this only includes registers which are
+    /// used by the program and nothing is known at the instruction after a `Call`.
+    /// Known values never overflow, the result of an operation which could overflow
+    /// is unknown.
+    pub fn constant_registers(
+        &self,
+        inputs: impl IntoIterator<Item = (u8, Option<u64>)>,
+    ) -> Vec<Option<BTreeMap<u8, u64>>> {
+        let inputs: BTreeMap<u8, Option<u64>> = inputs.into_iter().collect();
+        let mut known: Vec<Option<BTreeMap<u8, u64>>> = vec![None; self.len()];
+        if self.is_empty() {
+            return known;
+        }
+        known[0] = Some(
+            self.iter()
+                .flat_map(|(_, instruction)| instruction.registers())
+                .filter_map(|reg| match inputs.get(&reg) {
+                    Some(&value) => value.map(|value| (reg, value)),
+                    None => Some((reg, 0)),
+                })
+                .collect(),
+        );
+        let mut worklist = vec![0];
+        while let Some(at) = worklist.pop() {
+            let instruction = self.instruction(at);
+            let before = known[usize::from(at)].clone().unwrap();
+            let value = |reg| before.get(&reg).copied();
+            let mut after = before.clone();
+            let mut set = |reg, value: Option<u64>| {
+                match value {
+                    Some(value) => after.insert(reg, value),
+                    None => after.remove(&reg),
+                };
+            };
+            match instruction {
+                Instruction::Increment(reg, _) => {
+                    set(reg, value(reg).and_then(|v| v.checked_add(1)));
+                }
+                Instruction::Decrement(reg, ..) => {
+                    set(reg, value(reg).map(|v| v.saturating_sub(1)));
+                }
+                Instruction::AddConst(reg, n, _) => {
+                    set(reg, value(reg).and_then(|v| v.checked_add(n)));
+                }
+                Instruction::SubConst(reg, n, ..) => {
+                    set(reg, value(reg).map(|v| v.checked_sub(n).unwrap_or(v)));
+                }
+                Instruction::Clear(reg, _) => {
+                    set(reg, Some(0));
+                }
+                Instruction::Read(reg, _) => {
+                    set(reg, None);
+                }
+                Instruction::Transfer { src, dst, .. } if src != dst => {
+                    set(dst, value(src).and_then(|v| v.checked_add(value(dst)?)));
+                    set(src, Some(0));
+                }
+                Instruction::Copy {
+                    src, dst, scratch, ..
+                } if src != dst && src != scratch && dst != scratch => {
+                    set(dst, value(src).and_then(|v| v.checked_add(value(dst)?)));
+                    set(src, value(src).and_then(|v| v.checked_add(value(scratch)?)));
+                    set(scratch, Some(0));
+                }
+                Instruction::Swap(a, b, _) => {
+                    set(a, value(b));
+                    set(b, value(a));
+                }
+                _ => {}
+            }
+
+            // Only takes the branch which is known to be taken. If the condition is
+            // unknown and `zero` is set, this register is zero when taking `els`.
+            let branch = |condition: Option<bool>, then, els, zero: Option<u8>| match condition {
+                Some(true) => vec![(then, Some(after.clone()))],
+                Some(false) => vec![(els, Some(after.clone()))],
+                None => {
+                    let mut zero_branch = after.clone();
+                    zero_branch.extend(zero.map(|reg| (reg, 0)));
+                    vec![(then, Some(after.clone())), (els, Some(zero_branch))]
+                }
+            };
+            let successors: Vec<(u16, Option<BTreeMap<u8, u64>>)> = match instruction {
+                Instruction::Decrement(reg, then, els) => {
+                    branch(value(reg).map(|v| v > 0), then, els, Some(reg))
+                }
+                Instruction::SubConst(reg, n, then, els) => {
+                    let zero = if n == 1 { Some(reg) } else { None };
+                    branch(value(reg).map(|v| v >= n), then, els, zero)
+                }
+                Instruction::BranchZero(reg, zero, nonzero) => {
+                    branch(value(reg).map(|v| v != 0), nonzero, zero, Some(reg))
+                }
+                Instruction::Compare(a, b, ge, lt) => {
+                    let condition = value(a).and_then(|a| Some(a >= value(b)?));
+                    branch(condition, ge, lt, None)
+                }
+                Instruction::Call(target) => {
+                    vec![(target, Some(after.clone())), (at.wrapping_add(1), None)]
+                }
+                _ => instruction
+                    .targets()
+                    .map(|target| (target, Some(after.clone())))
+                    .collect(),
+            };
+            for (succ, state) in successors {
+                let current = match known.get_mut(usize::from(succ)) {
+                    Some(current) => current,
+                    None => continue,
+                };
+                let state = state.unwrap_or_default();
+                let new = match current {
+                    Some(current) => current
+                        .iter()
+                        .filter(|&(reg, value)| state.get(reg) == Some(value))
+                        .map(|(&reg, &value)| (reg, value))
+                        .collect(),
+                    None => state,
+                };
+                if current.as_ref() != Some(&new) {
+                    *current = Some(new);
+                    worklist.push(succ);
+                }
+            }
+        }
+        known
+    }
+
+    /// The positions of all instructions of this program which can be reached
+    /// from `entry`, including `entry` itself.
+    ///
+    /// Returning from a `Call` continues with the instruction after it, so this
+    /// instruction is considered reachable from the `Call`. Jumps past the end of
+    /// the program reach the implicit `Halt`, which is not included.
+    pub fn reachable_from(&self, entry: u16) -> BTreeSet<u16> {
+        let mut reachable = BTreeSet::new();
+        let mut worklist = vec![entry];
+        while let Some(at) = worklist.pop() {
+            if at as usize >= self.len() || !reachable.insert(at) {
+                continue;
+            }
+            let instruction = self.instruction(at);
+            worklist.extend(instruction.targets());
+            if let Instruction::Call(_) = instruction {
+                worklist.push(at.wrapping_add(1));
+            }
+        }
+        reachable
+    }
+
+    /// Replaces all instructions which are not reachable from the
+    /// first instruction with `Purged`, see [`Program::reachable_from`].
+    pub fn purge_unreachable(&self) -> Program {
+        let reachable = self.reachable_from(0);
+        Program::new(self.iter().map(|(at, instruction)| {
+            if reachable.contains(&at) {
+                instruction
+            } else {
+                Instruction::Purged
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IsaLevel;
+
+    #[test]
+    fn registers() {
+        // $0 = $1 * $2, clearing $3 first and using it as temporary storage.
+        let program = Program::new(
+            [
+                Instruction::Clear(3, 1),
+                Instruction::Decrement(1, 2, 7),
+                Instruction::Decrement(2, 3, 5),
+                Instruction::Increment(0, 4),
+                Instruction::Increment(3, 2),
+                Instruction::Decrement(3, 6, 1),
+                Instruction::Increment(2, 5),
+                Instruction::Read(4, 8),
+            ]
+            .iter()
+            .copied(),
+        );
+        let collect = |set: &BTreeSet<u8>| set.iter().copied().collect::<Vec<_>>();
+        assert_eq!(collect(&program.registers_read()), [0, 1, 2, 3]);
+        assert_eq!(collect(&program.registers_written()), [0, 1, 2, 3, 4]);
+
+        let liveness = program.liveness(Some(0));
+        assert_eq!(collect(liveness.live_in(0)), [0, 1, 2]);
+        assert_eq!(collect(liveness.live_out(0)), [0, 1, 2, 3]);
+        assert_eq!(collect(liveness.live_in(7)), [0]);
+        assert_eq!(collect(liveness.live_in(8)), [0]);
+        let liveness = program.liveness(vec![4]);
+        assert_eq!(collect(liveness.live_in(0)), [0, 1, 2]);
+        assert_eq!(collect(liveness.live_out(7)), [4]);
+
+        let zero = program.zero_registers(vec![1, 2]);
+        assert_eq!(collect(zero[0].as_ref().unwrap()), [0, 3, 4]);
+        assert_eq!(collect(zero[1].as_ref().unwrap()), [3, 4]);
+        assert_eq!(collect(zero[5].as_ref().unwrap()), [4]);
+        assert_eq!(collect(zero[7].as_ref().unwrap()), [1, 3, 4]);
+
+        let unreachable = Program::new(
+            [Instruction::Halt, Instruction::Increment(0, 0)]
+                .iter()
+                .copied(),
+        );
+        assert_eq!(unreachable.zero_registers(None)[1], None);
+    }
+
+    #[test]
+    fn reachability() {
+        let program = Program::new(
+            [
+                Instruction::Decrement(0, 1, 3),
+                Instruction::Increment(1, 0),
+                Instruction::Increment(2, 1),
+                Instruction::Call(5),
+                Instruction::Halt,
+                Instruction::Return,
+                Instruction::Jump(2),
+            ]
+            .iter()
+            .copied(),
+        );
+        let reachable: Vec<_> = program.reachable_from(0).into_iter().collect();
+        assert_eq!(reachable, [0, 1, 3, 4, 5]);
+        let reachable: Vec<_> = program.reachable_from(6).into_iter().collect();
+        assert_eq!(reachable, [0, 1, 2, 3, 4, 5, 6]);
+        assert!(program.reachable_from(7).is_empty());
+
+        let purged = program.purge_unreachable();
+        assert_eq!(
+            purged.diff(&program),
+            [
+                (2, Instruction::Purged, Instruction::Increment(2, 1)),
+                (6, Instruction::Purged, Instruction::Jump(2)),
+            ]
+        );
+        assert_eq!(purged.validate(IsaLevel::Extended), Ok(()));
+    }
+}
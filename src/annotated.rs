@@ -0,0 +1,210 @@
+//! A JSON format for programs together with their metadata, useful to
+//! curate collections of programs as data files.
+//!
+//! A collection is an object with the format `version`, currently `1`, and a list
+//! of `programs`. Every program is stored as an object with the following fields,
+//! all of which except for `program` are optional:
+//!
+//! - `name`, `author` and `description`: strings.
+//! - `registers`: an object mapping register names to register numbers.
+//! - `program`: the instructions, see the `serde` support of [`Program`].
+//! - `examples`: a list of objects with the register values `inputs` and the
+//!   expected register values `outputs` after halting, both mapping register
+//!   numbers to values, and the expected number of `steps`.
+//!
+//! ```text
+//! {
+//!   "version": 1,
+//!   "programs": [
+//!     {
+//!       "name": "addition",
+//!       "registers": { "acc": 0, "x": 1 },
+//!       "program": ["DEC r1 -> 1, 2", "INC r0 -> 0"],
+//!       "examples": [{ "inputs": { "0": 2, "1": 3 }, "outputs": { "0": 5, "1": 0 }, "steps": 7 }]
+//!     }
+//!   ]
+//! }
+//! ```
+
+use crate::{Machine, Program, RegisterFile, RunOutcome};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+
+/// The version of the collection format written by [`save_collection`].
+pub const COLLECTION_VERSION: u32 = 1;
+
+/// A program together with its metadata, see the [module level documentation](self).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AnnotatedProgram {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The names of the registers used by the program.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub registers: BTreeMap<String, u8>,
+    pub program: Program,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub examples: Vec<Example>,
+}
+
+/// An expected run of an [`AnnotatedProgram`].
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Example {
+    /// The initial values of the registers, all other registers start at zero.
+    #[serde(default)]
+    pub inputs: BTreeMap<u8, u64>,
+    /// The expected values of some registers after the program halted.
+    #[serde(default)]
+    pub outputs: BTreeMap<u8, u64>,
+    /// The expected number of steps until the program halts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub steps: Option<u64>,
+}
+
+impl AnnotatedProgram {
+    pub fn new(program: Program) -> AnnotatedProgram {
+        AnnotatedProgram {
+            name: None,
+            author: None,
+            description: None,
+            registers: BTreeMap::new(),
+            program,
+            examples: Vec::new(),
+        }
+    }
+
+    /// The register names as a [`RegisterFile`], e.g. for [`Program::disassemble_with`].
+    ///
+    /// Names which map to an already named register are ignored.
+    pub fn register_file(&self) -> RegisterFile {
+        let mut registers = RegisterFile::new();
+        let mut by_reg: Vec<_> = self.registers.iter().collect();
+        by_reg.sort_by_key(|&(_, &reg)| reg);
+        for (name, &reg) in by_reg {
+            registers.define(name, reg);
+        }
+        registers
+    }
+
+    /// Runs all examples for at most `max_steps` steps each, returning
+    /// the index of the first example which does not behave as expected.
+    pub fn check(&self, max_steps: u64) -> Result<(), usize> {
+        for (index, example) in self.examples.iter().enumerate() {
+            let mut prog: Machine = Machine::new(&self.program);
+            for (&reg, &value) in &example.inputs {
+                prog.set_register(reg, value);
+            }
+            let steps = match prog.run(max_steps) {
+                RunOutcome::Halted { steps, .. } => steps,
+                _ => return Err(index),
+            };
+            if example.steps.is_some_and(|expected| expected != steps)
+                || example
+                    .outputs
+                    .iter()
+                    .any(|(&reg, value)| prog.get_register(reg) != value)
+            {
+                return Err(index);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn from_json(json: &str) -> Result<AnnotatedProgram, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Collection<P> {
+    version: u32,
+    programs: P,
+}
+
+/// Reads a collection of programs, see the [module level documentation](self).
+pub fn load_collection(reader: impl Read) -> Result<Vec<AnnotatedProgram>, serde_json::Error> {
+    let collection: Collection<Vec<AnnotatedProgram>> = serde_json::from_reader(reader)?;
+    if collection.version != COLLECTION_VERSION {
+        return Err(serde::de::Error::custom(format_args!(
+            "unsupported collection version {}",
+            collection.version
+        )));
+    }
+    Ok(collection.programs)
+}
+
+/// Writes a collection of programs which can be read using [`load_collection`].
+pub fn save_collection(
+    writer: impl Write,
+    programs: &[AnnotatedProgram],
+) -> Result<(), serde_json::Error> {
+    let collection = Collection {
+        version: COLLECTION_VERSION,
+        programs,
+    };
+    serde_json::to_writer_pretty(writer, &collection)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Instruction;
+
+    #[test]
+    fn collection() {
+        let json = r#"{
+            "version": 1,
+            "programs": [
+                {
+                    "name": "addition",
+                    "registers": { "acc": 0, "x": 1 },
+                    "program": ["DEC r1 -> 1, 2", "INC r0 -> 0"],
+                    "examples": [{ "inputs": { "0": 2, "1": 3 }, "outputs": { "0": 5, "1": 0 }, "steps": 7 }]
+                },
+                { "program": [] }
+            ]
+        }"#;
+        let programs = load_collection(json.as_bytes()).unwrap();
+        assert_eq!(programs.len(), 2);
+        let addition = &programs[0];
+        assert_eq!(addition.name.as_deref(), Some("addition"));
+        assert_eq!(
+            addition.program.instruction(1),
+            Instruction::Increment(0, 0)
+        );
+        assert_eq!(addition.register_file().name(1), Some("x"));
+        assert_eq!(addition.check(100), Ok(()));
+        assert_eq!(programs[1], AnnotatedProgram::new(Program::empty()));
+
+        let mut out = Vec::new();
+        save_collection(&mut out, &programs).unwrap();
+        assert_eq!(load_collection(&out[..]).unwrap(), programs);
+        let single = AnnotatedProgram::from_json(&addition.to_json()).unwrap();
+        assert_eq!(&single, addition);
+
+        let mut wrong = addition.clone();
+        wrong.examples.push(Example {
+            steps: Some(2),
+            ..Example::default()
+        });
+        assert_eq!(wrong.check(100), Err(1));
+        wrong.examples[1].steps = None;
+        assert_eq!(wrong.check(100), Ok(()));
+        wrong.examples[1].outputs.insert(0, 1);
+        assert_eq!(wrong.check(100), Err(1));
+
+        assert!(load_collection(&br#"{"version":2,"programs":[]}"#[..]).is_err());
+        assert!(AnnotatedProgram::from_json(r#"{"program":[],"nme":"typo"}"#).is_err());
+    }
+}
@@ -0,0 +1,67 @@
+//! Generating random instructions and programs with `arbitrary`, e.g. for fuzzing.
+
+use crate::binary::{constant, set_constant, template};
+use crate::{Instruction, Program};
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+impl<'a> Arbitrary<'a> for Instruction {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Instruction> {
+        let mut instruction = template(u.int_in_range(0..=20)?).unwrap();
+        for reg in instruction.registers_mut() {
+            *reg = u.arbitrary()?;
+        }
+        if constant(instruction).is_some() {
+            let value: u64 = u.arbitrary()?;
+            if !set_constant(&mut instruction, value) {
+                set_constant(&mut instruction, value & 0xffff);
+            }
+        }
+        for target in instruction.targets_mut() {
+            *target = u.arbitrary()?;
+        }
+        Ok(instruction)
+    }
+}
+
+impl<'a> Arbitrary<'a> for Program {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Program> {
+        let instructions = u.arbitrary_iter()?.collect::<Result<Vec<Instruction>>>()?;
+        Ok(Program::new(instructions))
+    }
+
+    fn arbitrary_take_rest(u: Unstructured<'a>) -> Result<Program> {
+        let instructions = u
+            .arbitrary_take_rest_iter()?
+            .collect::<Result<Vec<Instruction>>>()?;
+        Ok(Program::new(instructions))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::SplitMix64;
+
+    #[test]
+    fn round_trip() {
+        let mut rng = SplitMix64::new(0);
+        for _ in 0..200 {
+            let bytes: Vec<u8> = (0..256).map(|_| rng.next_u64() as u8).collect();
+            let program = Program::arbitrary_take_rest(Unstructured::new(&bytes)).unwrap();
+
+            let asm = Program::parse_asm(&program.disassemble()).unwrap();
+            assert_eq!(asm, program);
+            let binary = Program::from_bytes(&program.to_bytes()).unwrap();
+            assert_eq!(binary, program);
+            for &instruction in program.instructions() {
+                assert_eq!(instruction.to_string().parse(), Ok(instruction));
+            }
+            #[cfg(feature = "serde")]
+            {
+                let json = serde_json::to_string(&program).unwrap();
+                let parsed: Program = serde_json::from_str(&json).unwrap();
+                assert_eq!(parsed, program);
+            }
+        }
+    }
+}
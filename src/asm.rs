@@ -0,0 +1,617 @@
+use crate::{Instruction, Program, RegisterFile, MAX_INSTRUCTIONS};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt::{self, Write};
+use std::str::FromStr;
+
+/// The reason why [`Program::parse_asm`] failed, see [`AsmError`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsmErrorKind {
+    /// The instruction name is not known.
+    UnknownMnemonic(String),
+    /// Found `found` where `expected` was expected, `found` is empty at the end of the line.
+    Unexpected {
+        expected: &'static str,
+        found: String,
+    },
+    /// The token is not a register, registers are written as `r3` or `$3`.
+    InvalidRegister(String),
+    /// The token is not a number in the range of the operand.
+    InvalidNumber(String),
+    /// A jump target refers to a label which was never defined.
+    UndefinedLabel(String),
+    /// The label was defined more than once.
+    DuplicateLabel(String),
+    /// The line is prefixed with the index `found`, but the instruction is at `expected`.
+    WrongIndex { expected: u16, found: u16 },
+    /// The program has more than [`MAX_INSTRUCTIONS`] instructions.
+    TooLong,
+}
+
+/// An error returned by [`Program::parse_asm`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    /// The line causing the error, starting at `1`.
+    pub line: usize,
+    pub kind: AsmErrorKind,
+}
+
+impl fmt::Display for AsmErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AsmErrorKind::UnknownMnemonic(name) => write!(f, "unknown instruction `{}`", name),
+            AsmErrorKind::Unexpected { expected, found } if found.is_empty() => {
+                write!(f, "expected {}, found end of line", expected)
+            }
+            AsmErrorKind::Unexpected { expected, found } => {
+                write!(f, "expected {}, found `{}`", expected, found)
+            }
+            AsmErrorKind::InvalidRegister(token) => write!(f, "invalid register `{}`", token),
+            AsmErrorKind::InvalidNumber(token) => write!(f, "invalid number `{}`", token),
+            AsmErrorKind::UndefinedLabel(label) => write!(f, "undefined label `{}`", label),
+            AsmErrorKind::DuplicateLabel(label) => {
+                write!(f, "label `{}` is defined twice", label)
+            }
+            AsmErrorKind::WrongIndex { expected, found } => write!(
+                f,
+                "instruction is prefixed with {}, but it is at {}",
+                found, expected
+            ),
+            AsmErrorKind::TooLong => {
+                write!(f, "program has more than {} instructions", MAX_INSTRUCTIONS)
+            }
+        }
+    }
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+impl Error for AsmError {}
+
+/// Splits a line into words, `,`, `:` and `->`.
+fn tokenize(line: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut rest = line.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let len = if rest.starts_with("->") {
+            2
+        } else if c == ',' || c == ':' {
+            1
+        } else {
+            match rest.find(|c: char| c.is_whitespace() || c == ',' || c == ':' || c == '-') {
+                Some(0) => c.len_utf8(),
+                Some(len) => len,
+                None => rest.len(),
+            }
+        };
+        tokens.push(&rest[..len]);
+        rest = rest[len..].trim_start();
+    }
+    tokens
+}
+
+/// The operands of each instruction: `r` is a register, `n` a `u64`, `c` a `u16`
+/// and `t` a jump target, `>` and `,` stand for `->` and `,`.
+fn operands(mnemonic: &str) -> Option<&'static str> {
+    Some(match mnemonic {
+        "HALT" | "PURGED" | "RET" => "",
+        "INC" | "CLR" | "READ" | "WRITE" => "r>t",
+        "DEC" | "JZ" => "r>t,t",
+        "JMP" | "NOP" | "CALL" => "t",
+        "ADD" => "r,n>t",
+        "SUB" => "r,n>t,t",
+        "MOV" | "SWAP" => "r,r>t",
+        "COPY" => "r,r,r>t",
+        "CMP" => "r,r>t,t",
+        "CHOOSE" => "t,t",
+        "RAND" => "c>t,t",
+        _ => return None,
+    })
+}
+
+fn parse_register(token: &str) -> Result<u8, AsmErrorKind> {
+    token
+        .strip_prefix('r')
+        .or_else(|| token.strip_prefix('$'))
+        .and_then(|digits| digits.parse().ok())
+        .ok_or_else(|| AsmErrorKind::InvalidRegister(token.to_owned()))
+}
+
+fn parse_number<T: std::str::FromStr>(token: &str) -> Result<T, AsmErrorKind> {
+    token
+        .parse()
+        .map_err(|_| AsmErrorKind::InvalidNumber(token.to_owned()))
+}
+
+fn is_label(token: &str) -> bool {
+    let mut chars = token.chars();
+    matches!(chars.next(), Some(c) if c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// A parsed instruction whose targets may still refer to labels.
+struct Parsed<'a> {
+    instruction: Instruction,
+    labels: [Option<&'a str>; 2],
+}
+
+/// Parses the instruction in `tokens`, which are not empty.
+fn parse_instruction<'a>(tokens: &[&'a str]) -> Result<Parsed<'a>, AsmErrorKind> {
+    let mnemonic = tokens[0].to_ascii_uppercase();
+    let mut pattern =
+        operands(&mnemonic).ok_or_else(|| AsmErrorKind::UnknownMnemonic(tokens[0].to_owned()))?;
+    if mnemonic == "HALT" && tokens.len() > 1 {
+        pattern = "c";
+    }
+
+    let (mut regs, mut numbers, mut targets) = (Vec::new(), Vec::new(), Vec::new());
+    let mut tokens = tokens[1..].iter().copied();
+    for kind in pattern.chars() {
+        let token = tokens.next().unwrap_or("");
+        let expected = match kind {
+            'r' => "a register",
+            'n' | 'c' => "a number",
+            't' => "a jump target",
+            ',' => "`,`",
+            _ => "`->`",
+        };
+        let unexpected = || AsmErrorKind::Unexpected {
+            expected,
+            found: token.to_owned(),
+        };
+        match kind {
+            _ if token.is_empty() => return Err(unexpected()),
+            'r' => regs.push(parse_register(token)?),
+            'n' => numbers.push(parse_number::<u64>(token)?),
+            'c' => numbers.push(u64::from(parse_number::<u16>(token)?)),
+            't' if is_label(token) => targets.push((0, Some(token))),
+            't' => targets.push((parse_number::<u16>(token)?, None)),
+            ',' if token == "," => {}
+            '>' if token == "->" => {}
+            _ => return Err(unexpected()),
+        }
+    }
+    if let Some(token) = tokens.next() {
+        return Err(AsmErrorKind::Unexpected {
+            expected: "end of line",
+            found: token.to_owned(),
+        });
+    }
+
+    let mut instruction = match mnemonic.as_str() {
+        "HALT" if numbers.is_empty() => Instruction::Halt,
+        "HALT" => Instruction::HaltWith(numbers[0] as u16),
+        "PURGED" => Instruction::Purged,
+        "INC" => Instruction::Increment(regs[0], 0),
+        "DEC" => Instruction::Decrement(regs[0], 0, 0),
+        "JMP" => Instruction::Jump(0),
+        "NOP" => Instruction::Nop(0),
+        "CLR" => Instruction::Clear(regs[0], 0),
+        "ADD" => Instruction::AddConst(regs[0], numbers[0], 0),
+        "SUB" => Instruction::SubConst(regs[0], numbers[0], 0, 0),
+        "MOV" => Instruction::Transfer {
+            src: regs[0],
+            dst: regs[1],
+            then: 0,
+        },
+        "COPY" => Instruction::Copy {
+            src: regs[0],
+            dst: regs[1],
+            scratch: regs[2],
+            then: 0,
+        },
+        "JZ" => Instruction::BranchZero(regs[0], 0, 0),
+        "CMP" => Instruction::Compare(regs[0], regs[1], 0, 0),
+        "SWAP" => Instruction::Swap(regs[0], regs[1], 0),
+        "READ" => Instruction::Read(regs[0], 0),
+        "WRITE" => Instruction::Write(regs[0], 0),
+        "CHOOSE" => Instruction::Choose(0, 0),
+        "RAND" => Instruction::Random(numbers[0] as u16, 0, 0),
+        "CALL" => Instruction::Call(0),
+        _ => Instruction::Return,
+    };
+    let mut labels = [None, None];
+    for ((target, label), (addr, name)) in instruction
+        .targets_mut()
+        .zip(labels.iter_mut())
+        .zip(targets)
+    {
+        *target = addr;
+        *label = name;
+    }
+    Ok(Parsed {
+        instruction,
+        labels,
+    })
+}
+
+/// Parses a single instruction in the assembly language of [`Program::parse_asm`].
+///
+/// Jump targets have to be instruction indices, labels are not supported.
+impl FromStr for Instruction {
+    type Err = AsmError;
+
+    fn from_str(s: &str) -> Result<Instruction, AsmError> {
+        let error = |kind| AsmError { line: 1, kind };
+        let tokens = tokenize(s);
+        if tokens.is_empty() {
+            return Err(error(AsmErrorKind::Unexpected {
+                expected: "an instruction",
+                found: String::new(),
+            }));
+        }
+        let parsed = parse_instruction(&tokens).map_err(error)?;
+        match parsed.labels.iter().flatten().next() {
+            Some(label) => Err(error(AsmErrorKind::UndefinedLabel((*label).to_owned()))),
+            None => Ok(parsed.instruction),
+        }
+    }
+}
+
+impl Program {
+    /// Parses a program written in a simple assembly language.
+    ///
+    /// Each non-empty line contains a single instruction, optionally preceded by
+    /// any number of `label:` and the index of the instruction, e.g. `3: loop: INC r1 -> loop`.
+    /// Jump targets are either labels or instruction indices, registers are written
+    /// as `r1` or `$1`. Instruction names are case-insensitive and everything after
+    /// a `#` is a comment. A label which is not followed by an instruction refers to
+    /// the implicit `Halt` at the end of the program.
+    ///
+    /// | syntax                   | instruction                              |
+    /// |--------------------------|------------------------------------------|
+    /// | `HALT`, `HALT code`      | `Halt`, `HaltWith(code)`                 |
+    /// | `PURGED`                 | `Purged`                                 |
+    /// | `INC r -> t`             | `Increment(r, t)`                        |
+    /// | `DEC r -> t, e`          | `Decrement(r, t, e)`                     |
+    /// | `JMP t`, `NOP t`         | `Jump(t)`, `Nop(t)`                      |
+    /// | `CLR r -> t`             | `Clear(r, t)`                            |
+    /// | `ADD r, n -> t`          | `AddConst(r, n, t)`                      |
+    /// | `SUB r, n -> t, e`       | `SubConst(r, n, t, e)`                   |
+    /// | `MOV src, dst -> t`      | `Transfer { src, dst, then: t }`         |
+    /// | `COPY src, dst, s -> t`  | `Copy { src, dst, scratch: s, then: t }` |
+    /// | `JZ r -> z, nz`          | `BranchZero(r, z, nz)`                   |
+    /// | `CMP a, b -> ge, lt`     | `Compare(a, b, ge, lt)`                  |
+    /// | `SWAP a, b -> t`         | `Swap(a, b, t)`                          |
+    /// | `READ r -> t`            | `Read(r, t)`                             |
+    /// | `WRITE r -> t`           | `Write(r, t)`                            |
+    /// | `CHOOSE a, b`            | `Choose(a, b)`                           |
+    /// | `RAND p -> t, e`         | `Random(p, t, e)`                        |
+    /// | `CALL t`, `RET`          | `Call(t)`, `Return`                      |
+    pub fn parse_asm(src: &str) -> Result<Program, AsmError> {
+        let mut parsed = Vec::new();
+        let mut labels = HashMap::new();
+        for (line, text) in src.lines().enumerate() {
+            let line = line + 1;
+            let error = |kind| AsmError { line, kind };
+            let text = text.split('#').next().unwrap_or("");
+            let mut tokens = &tokenize(text)[..];
+            let position = parsed.len();
+            while tokens.len() >= 2 && tokens[1] == ":" {
+                if is_label(tokens[0]) {
+                    if labels.insert(tokens[0], position).is_some() {
+                        return Err(error(AsmErrorKind::DuplicateLabel(tokens[0].to_owned())));
+                    }
+                } else {
+                    let found = parse_number::<u16>(tokens[0]).map_err(error)?;
+                    match u16::try_from(position) {
+                        Ok(expected) if expected == found => {}
+                        Ok(expected) => {
+                            return Err(error(AsmErrorKind::WrongIndex { expected, found }))
+                        }
+                        Err(_) => return Err(error(AsmErrorKind::TooLong)),
+                    }
+                }
+                tokens = &tokens[2..];
+            }
+            if tokens.is_empty() {
+                continue;
+            } else if position >= MAX_INSTRUCTIONS {
+                return Err(error(AsmErrorKind::TooLong));
+            }
+            parsed.push((line, parse_instruction(tokens).map_err(error)?));
+        }
+
+        let mut instructions = Vec::with_capacity(parsed.len());
+        for (
+            line,
+            Parsed {
+                mut instruction,
+                labels: names,
+            },
+        ) in parsed
+        {
+            for (target, name) in instruction.targets_mut().zip(&names) {
+                if let Some(name) = name {
+                    // A label after the last possible instruction refers to
+                    // an implicit `Halt` which does not fit into a `u16`.
+                    *target = match labels.get(name).map(|&position| u16::try_from(position)) {
+                        Some(Ok(position)) => position,
+                        Some(Err(_)) => {
+                            return Err(AsmError {
+                                line,
+                                kind: AsmErrorKind::TooLong,
+                            })
+                        }
+                        None => {
+                            return Err(AsmError {
+                                line,
+                                kind: AsmErrorKind::UndefinedLabel((*name).to_owned()),
+                            })
+                        }
+                    };
+                }
+            }
+            instructions.push(instruction);
+        }
+        Ok(Program::new(instructions))
+    }
+
+    /// Writes this program in the assembly language of [`Program::parse_asm`],
+    /// one instruction per line, each prefixed with its index.
+    ///
+    /// Trailing `Halt`s are omitted as they are equivalent to the implicit `Halt`
+    /// at the end of the program.
+    pub fn disassemble(&self) -> String {
+        self.disassemble_with(&RegisterFile::new())
+    }
+
+    /// Like [`Program::disassemble`], but adds a comment with the names
+    /// of the named registers used by each instruction.
+    pub fn disassemble_with(&self, registers: &RegisterFile) -> String {
+        let mut out = String::new();
+        for (at, instruction) in self.iter() {
+            write!(out, "{}: {}", at, instruction).unwrap();
+            let mut names = instruction
+                .registers()
+                .filter_map(|reg| registers.name(reg).map(|name| (reg, name)))
+                .peekable();
+            if names.peek().is_some() {
+                out.push_str(" #");
+                for (i, (reg, name)) in names.enumerate() {
+                    let sep = if i == 0 { " " } else { ", " };
+                    write!(out, "{}r{} = {}", sep, reg, name).unwrap();
+                }
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Machine, RunOutcome};
+
+    #[test]
+    fn parse() {
+        let program = Program::parse_asm(
+            "
+            # $0 = $1 * $2
+            0: outer: DEC r1 -> inner, done
+            inner: dec $2 -> 2, restore
+            INC r0 -> 3
+            3: INC r3 -> inner
+            restore: DEC r3 -> 5, outer # move $3 back to $2
+            INC r2 -> restore
+            done:
+            ",
+        )
+        .unwrap();
+        assert_eq!(program.len(), 6);
+        assert_eq!(program.instruction(0), Instruction::Decrement(1, 1, 6));
+        assert_eq!(program.instruction(4), Instruction::Decrement(3, 5, 0));
+        let mut prog: Machine = Machine::new(&program);
+        prog.reset_with(vec![0, 3, 4]);
+        assert!(matches!(prog.run(u64::MAX), RunOutcome::Halted { .. }));
+        assert_eq!(&prog.registers()[..4], &[12, 0, 4, 0]);
+
+        let all = Program::parse_asm(
+            "HALT\nHALT 3\nPURGED\nJMP 0\nNOP 1\nCLR r1 -> 2\nADD r1, 1000 -> 3\n\
+             SUB r1, 7 -> 4, 5\nMOV r1, r2 -> 6\nCOPY r1, r2, r3 -> 7\nJZ r4 -> 8, 9\n\
+             CMP r5, r6 -> 10, 11\nSWAP r7, r8 -> 12\nREAD r9 -> 13\nWRITE r10 -> 14\n\
+             CHOOSE 15, 16\nRAND 32768 -> 17, 18\nCALL 19\nRET",
+        )
+        .unwrap();
+        assert_eq!(
+            all.instructions(),
+            &[
+                Instruction::Halt,
+                Instruction::HaltWith(3),
+                Instruction::Purged,
+                Instruction::Jump(0),
+                Instruction::Nop(1),
+                Instruction::Clear(1, 2),
+                Instruction::AddConst(1, 1000, 3),
+                Instruction::SubConst(1, 7, 4, 5),
+                Instruction::Transfer {
+                    src: 1,
+                    dst: 2,
+                    then: 6
+                },
+                Instruction::Copy {
+                    src: 1,
+                    dst: 2,
+                    scratch: 3,
+                    then: 7
+                },
+                Instruction::BranchZero(4, 8, 9),
+                Instruction::Compare(5, 6, 10, 11),
+                Instruction::Swap(7, 8, 12),
+                Instruction::Read(9, 13),
+                Instruction::Write(10, 14),
+                Instruction::Choose(15, 16),
+                Instruction::Random(32768, 17, 18),
+                Instruction::Call(19),
+                Instruction::Return,
+            ]
+        );
+    }
+
+    #[test]
+    fn disassemble() {
+        let program = Program::new(
+            [
+                Instruction::Decrement(1, 1, 4),
+                Instruction::AddConst(0, 5, 2),
+                Instruction::Copy {
+                    src: 0,
+                    dst: 2,
+                    scratch: 3,
+                    then: 0,
+                },
+                Instruction::Halt,
+                Instruction::Halt,
+            ]
+            .iter()
+            .copied(),
+        );
+        let text = program.disassemble();
+        assert_eq!(
+            text,
+            "0: DEC r1 -> 1, 4\n1: ADD r0, 5 -> 2\n2: COPY r0, r2, r3 -> 0\n"
+        );
+        let parsed = Program::parse_asm(&text).unwrap();
+        assert_eq!(parsed.len(), 3);
+        assert_eq!(parsed.diff(&program), []);
+        assert_eq!(Program::empty().disassemble(), "");
+
+        let mut registers = RegisterFile::new();
+        registers.define("acc", 0);
+        registers.define("tmp", 3);
+        let text = program.disassemble_with(&registers);
+        assert_eq!(
+            text.lines().collect::<Vec<_>>(),
+            [
+                "0: DEC r1 -> 1, 4",
+                "1: ADD r0, 5 -> 2 # r0 = acc",
+                "2: COPY r0, r2, r3 -> 0 # r0 = acc, r3 = tmp",
+            ]
+        );
+        assert_eq!(Program::parse_asm(&text).unwrap(), parsed);
+    }
+
+    #[test]
+    fn round_trip() {
+        let all = [
+            Instruction::Halt,
+            Instruction::HaltWith(3),
+            Instruction::Purged,
+            Instruction::Increment(255, 0),
+            Instruction::Decrement(1, 2, 3),
+            Instruction::Jump(4),
+            Instruction::Nop(5),
+            Instruction::Clear(6, 7),
+            Instruction::AddConst(8, u64::MAX, 9),
+            Instruction::SubConst(10, 11, 12, 13),
+            Instruction::Transfer {
+                src: 14,
+                dst: 15,
+                then: 16,
+            },
+            Instruction::Copy {
+                src: 17,
+                dst: 18,
+                scratch: 19,
+                then: 20,
+            },
+            Instruction::BranchZero(21, 22, 23),
+            Instruction::Compare(24, 25, 26, 27),
+            Instruction::Swap(28, 29, 30),
+            Instruction::Read(31, 32),
+            Instruction::Write(33, 34),
+            Instruction::Choose(35, 36),
+            Instruction::Random(u16::MAX, 37, 38),
+            Instruction::Call(39),
+            Instruction::Return,
+        ];
+        let program = Program::new(all.iter().copied());
+        assert_eq!(Program::parse_asm(&program.disassemble()), Ok(program));
+        for instruction in &all {
+            assert_eq!(instruction.to_string().parse(), Ok(*instruction));
+        }
+        assert_eq!(
+            "JMP a".parse::<Instruction>().unwrap_err().kind,
+            AsmErrorKind::UndefinedLabel("a".to_owned())
+        );
+        assert!(" ".parse::<Instruction>().is_err());
+    }
+
+    #[test]
+    fn errors() {
+        let error = |src: &str| Program::parse_asm(src).unwrap_err();
+        assert_eq!(
+            error("INC r0 -> 1\nFOO r1"),
+            AsmError {
+                line: 2,
+                kind: AsmErrorKind::UnknownMnemonic("FOO".to_owned())
+            }
+        );
+        assert_eq!(
+            error("DEC r0 -> 1").to_string(),
+            "line 1: expected `,`, found end of line"
+        );
+        assert_eq!(
+            error("INC r0, 1").to_string(),
+            "line 1: expected `->`, found `,`"
+        );
+        assert_eq!(
+            error("INC x -> 1").kind,
+            AsmErrorKind::InvalidRegister("x".to_owned())
+        );
+        assert_eq!(
+            error("INC r256 -> 1").kind,
+            AsmErrorKind::InvalidRegister("r256".to_owned())
+        );
+        assert_eq!(
+            error("JMP 70000").kind,
+            AsmErrorKind::InvalidNumber("70000".to_owned())
+        );
+        assert_eq!(
+            error("JMP 1 2").to_string(),
+            "line 1: expected end of line, found `2`"
+        );
+        assert_eq!(
+            error("a: HALT\n\na: HALT"),
+            AsmError {
+                line: 3,
+                kind: AsmErrorKind::DuplicateLabel("a".to_owned())
+            }
+        );
+        assert_eq!(
+            error("JMP a\nJMP b\nb:"),
+            AsmError {
+                line: 1,
+                kind: AsmErrorKind::UndefinedLabel("a".to_owned())
+            }
+        );
+        assert_eq!(
+            error("HALT\n0: HALT").to_string(),
+            "line 2: instruction is prefixed with 0, but it is at 1"
+        );
+
+        // Labels after the last possible instruction don't fit into a `u16`.
+        let full = "HALT\n".repeat(1 << 16);
+        assert!(Program::parse_asm(&format!("JMP end\n{}end:", &full[10..])).is_ok());
+        assert_eq!(
+            error(&format!("JMP end\n{}end:", &full[5..])),
+            AsmError {
+                line: 1,
+                kind: AsmErrorKind::TooLong
+            }
+        );
+        assert_eq!(
+            error(&format!("{}0:", full)),
+            AsmError {
+                line: (1 << 16) + 1,
+                kind: AsmErrorKind::TooLong
+            }
+        );
+    }
+}
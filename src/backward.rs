@@ -0,0 +1,291 @@
+use crate::{Instruction, Program};
+use std::collections::{BTreeMap, HashMap};
+
+/// A constraint on the value of a register, see [`BackwardCertificate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Sign {
+    Zero,
+    Positive,
+}
+
+/// A set of configurations: the instruction pointer and constraints on some registers.
+/// Registers without a constraint may have any value.
+pub type AbstractState = (u16, BTreeMap<u8, Sign>);
+
+/// A proof that a program never halts when starting with all registers at zero,
+/// see [`Program::find_backward_proof`].
+///
+/// The `states` include all halting configurations, and every configuration which
+/// leads to one of the `states` in a single step is part of the `states` as well.
+/// As the start configuration is not part of the `states`, it can never halt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackwardCertificate {
+    pub states: Vec<AbstractState>,
+}
+
+impl BackwardCertificate {
+    /// Checks that the `states` of this certificate include all halting configurations,
+    /// are closed under predecessors, and don't include the start configuration.
+    pub fn verify(&self, program: &Program) -> bool {
+        let edges = match reverse_edges(program) {
+            Some(edges) => edges,
+            None => return false,
+        };
+        let mut states: HashMap<u16, Vec<&BTreeMap<u8, Sign>>> = HashMap::new();
+        for (at, constraints) in &self.states {
+            states.entry(*at).or_default().push(constraints);
+        }
+        let covered = |at: u16, constraints: &BTreeMap<u8, Sign>| {
+            states
+                .get(&at)
+                .is_some_and(|states| states.iter().any(|s| covers(s, constraints)))
+        };
+
+        halting_positions(program).all(|at| covered(at, &BTreeMap::new()))
+            && !self
+                .states
+                .iter()
+                .any(|(at, constraints)| *at == 0 && includes_start(constraints))
+            && self.states.iter().all(|(target, constraints)| {
+                edges.get(target).is_none_or(|edges| {
+                    edges.iter().all(|&(at, branch)| {
+                        pre_image(program.instruction(at), branch, constraints)
+                            .is_none_or(|pre| covered(at, &pre))
+                    })
+                })
+            })
+    }
+}
+
+/// Whether every configuration satisfying `constraints` also satisfies `general`.
+fn covers(general: &BTreeMap<u8, Sign>, constraints: &BTreeMap<u8, Sign>) -> bool {
+    general
+        .iter()
+        .all(|(reg, sign)| constraints.get(reg) == Some(sign))
+}
+
+/// Whether `constraints` are satisfied if all registers are zero.
+fn includes_start(constraints: &BTreeMap<u8, Sign>) -> bool {
+    constraints.values().all(|&sign| sign == Sign::Zero)
+}
+
+/// The position of the first implicit `Halt` after the end of `program`
+/// which is used for all jumps past its end.
+fn canonical(program: &Program, at: u16) -> u16 {
+    at.min(program.len() as u16)
+}
+
+/// The positions of all instructions of `program` which halt.
+fn halting_positions(program: &Program) -> impl Iterator<Item = u16> + '_ {
+    program
+        .iter()
+        .filter(|(_, instruction)| {
+            matches!(instruction, Instruction::Halt | Instruction::HaltWith(_))
+        })
+        .map(|(at, _)| at)
+        .chain(std::iter::once(program.len() as u16))
+}
+
+/// The position and branch of all instructions which jump to each position,
+/// or `None` if `program` uses `Call` or `Return`.
+fn reverse_edges(program: &Program) -> Option<HashMap<u16, Vec<(u16, usize)>>> {
+    let mut edges: HashMap<u16, Vec<(u16, usize)>> = HashMap::new();
+    for (at, instruction) in program.iter() {
+        if let Instruction::Call(_) | Instruction::Return = instruction {
+            return None;
+        }
+        for (branch, target) in instruction.targets().enumerate() {
+            edges
+                .entry(canonical(program, target))
+                .or_default()
+                .push((at, branch));
+        }
+    }
+    Some(edges)
+}
+
+/// The configurations which reach a configuration satisfying `post` by
+/// taking the `branch`th target of `instruction`, or `None` if there are none.
+///
+/// This may include more configurations than necessary.
+fn pre_image(
+    instruction: Instruction,
+    branch: usize,
+    post: &BTreeMap<u8, Sign>,
+) -> Option<BTreeMap<u8, Sign>> {
+    let mut pre = post.clone();
+    let get = |reg| post.get(&reg).copied();
+    let refine = |pre: &mut BTreeMap<u8, Sign>, reg, sign| match pre.insert(reg, sign) {
+        Some(old) if old != sign => None,
+        _ => Some(()),
+    };
+    match instruction {
+        Instruction::Increment(reg, _) | Instruction::AddConst(reg, 1..=u64::MAX, _) => {
+            if get(reg) == Some(Sign::Zero) {
+                return None;
+            }
+            pre.remove(&reg);
+        }
+        Instruction::Clear(reg, _) => {
+            if get(reg) == Some(Sign::Positive) {
+                return None;
+            }
+            pre.remove(&reg);
+        }
+        Instruction::Read(reg, _) => {
+            pre.remove(&reg);
+        }
+        Instruction::Decrement(reg, ..) | Instruction::SubConst(reg, 1, ..) => {
+            if branch == 0 {
+                pre.insert(reg, Sign::Positive);
+            } else {
+                refine(&mut pre, reg, Sign::Zero)?;
+            }
+        }
+        Instruction::SubConst(reg, n, ..) if n != 0 && branch == 0 => {
+            pre.insert(reg, Sign::Positive);
+        }
+        Instruction::SubConst(_, 0, ..) if branch == 1 => return None,
+        Instruction::BranchZero(reg, ..) => {
+            let sign = if branch == 0 {
+                Sign::Zero
+            } else {
+                Sign::Positive
+            };
+            refine(&mut pre, reg, sign)?;
+        }
+        Instruction::Compare(a, b, ..) => {
+            if branch == 1 {
+                if a == b {
+                    return None;
+                }
+                refine(&mut pre, b, Sign::Positive)?;
+            } else if get(a) == Some(Sign::Zero) {
+                refine(&mut pre, b, Sign::Zero)?;
+            } else if get(b) == Some(Sign::Positive) {
+                refine(&mut pre, a, Sign::Positive)?;
+            }
+        }
+        Instruction::Swap(a, b, _) => {
+            pre.remove(&a);
+            pre.remove(&b);
+            pre.extend(get(b).map(|sign| (a, sign)));
+            pre.extend(get(a).map(|sign| (b, sign)));
+        }
+        Instruction::Transfer { src, dst, .. } if src != dst => {
+            if get(src) == Some(Sign::Positive) {
+                return None;
+            }
+            pre.remove(&src);
+            pre.remove(&dst);
+            if get(dst) == Some(Sign::Zero) {
+                pre.insert(src, Sign::Zero);
+                pre.insert(dst, Sign::Zero);
+            }
+        }
+        Instruction::Copy {
+            src, dst, scratch, ..
+        } if src != dst && src != scratch && dst != scratch => {
+            if get(scratch) == Some(Sign::Positive) {
+                return None;
+            }
+            pre.remove(&src);
+            pre.remove(&dst);
+            pre.remove(&scratch);
+            if get(dst) == Some(Sign::Zero) {
+                pre.insert(src, Sign::Zero);
+                pre.insert(dst, Sign::Zero);
+            }
+            if get(src) == Some(Sign::Zero) {
+                pre.insert(src, Sign::Zero);
+                pre.insert(scratch, Sign::Zero);
+            }
+        }
+        _ => {}
+    }
+    Some(pre)
+}
+
+impl Program {
+    /// Tries to prove that this program never halts when starting with all registers
+    /// at zero by searching backwards from all halting instructions.
+    ///
+    /// The search tracks which registers are known to be zero or positive and stops
+    /// once no new sets of configurations which lead to a `Halt` are found. If the
+    /// start configuration is not part of any of them, the result is a certificate
+    /// that it never halts. Returns `None` if the start configuration may halt, if
+    /// more than `max_states` sets of configurations are needed, or if the program
+    /// uses `Call` or `Return`.
+    pub fn find_backward_proof(&self, max_states: usize) -> Option<BackwardCertificate> {
+        let edges = reverse_edges(self)?;
+        let mut found: HashMap<u16, Vec<BTreeMap<u8, Sign>>> = HashMap::new();
+        let mut states = Vec::new();
+        let mut worklist: Vec<AbstractState> = halting_positions(self)
+            .map(|at| (at, BTreeMap::new()))
+            .collect();
+        while let Some((at, constraints)) = worklist.pop() {
+            let known = found.entry(at).or_default();
+            if known.iter().any(|known| covers(known, &constraints)) {
+                continue;
+            }
+            if at == 0 && includes_start(&constraints) || states.len() == max_states {
+                return None;
+            }
+            known.push(constraints.clone());
+            states.push((at, constraints.clone()));
+            for &(pred, branch) in edges.get(&at).into_iter().flatten() {
+                if let Some(pre) = pre_image(self.instruction(pred), branch, &constraints) {
+                    worklist.push((pred, pre));
+                }
+            }
+        }
+        Some(BackwardCertificate { states })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::examples;
+
+    #[test]
+    fn backward_proof() {
+        // $0 is never zero when testing it.
+        let program = Program::new(
+            [
+                Instruction::Increment(0, 1),
+                Instruction::Increment(1, 2),
+                Instruction::BranchZero(0, 3, 0),
+                Instruction::HaltWith(1),
+            ]
+            .iter()
+            .copied(),
+        );
+        let certificate = program.find_backward_proof(100).unwrap();
+        assert!(certificate.verify(&program));
+        let mut states = certificate.states.clone();
+        states.sort();
+        let zero: BTreeMap<u8, Sign> = [(0, Sign::Zero)].iter().copied().collect();
+        assert_eq!(
+            states,
+            [
+                (1, zero.clone()),
+                (2, zero),
+                (3, BTreeMap::new()),
+                (4, BTreeMap::new()),
+            ]
+        );
+
+        let mut incomplete = certificate.clone();
+        incomplete.states.retain(|&(at, _)| at != 1);
+        assert!(!incomplete.verify(&program));
+        let mut incomplete = certificate;
+        incomplete.states.retain(|&(at, _)| at != 4);
+        assert!(!incomplete.verify(&program));
+
+        assert_eq!(program.find_backward_proof(2), None);
+        assert_eq!(examples::multiplication().find_backward_proof(1000), None);
+        let call = Program::new([Instruction::Call(0)].iter().copied());
+        assert_eq!(call.find_backward_proof(100), None);
+    }
+}
@@ -0,0 +1,332 @@
+use crate::{Instruction, Program, MAX_INSTRUCTIONS};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+/// The first bytes of every program encoded with [`Program::to_bytes`].
+pub const MAGIC: [u8; 4] = *b"MNSK";
+
+/// The version of the binary format written by [`Program::to_bytes`].
+pub const FORMAT_VERSION: u8 = 1;
+
+/// An error returned by [`Program::from_bytes`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The data does not start with [`MAGIC`].
+    BadMagic,
+    /// The data uses a different version of the format.
+    UnsupportedVersion(u8),
+    /// The data ends in the middle of the program.
+    UnexpectedEnd,
+    /// The instruction at `at` has an unknown opcode.
+    InvalidOpcode { at: u16, opcode: u8 },
+    /// A number in the instruction at `at` does not fit its operand.
+    InvalidNumber { at: u16 },
+    /// The program has more than [`MAX_INSTRUCTIONS`] instructions.
+    TooLong,
+    /// There are additional bytes after the end of the program.
+    TrailingBytes,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            DecodeError::BadMagic => f.write_str("data is not an encoded program"),
+            DecodeError::UnsupportedVersion(version) => {
+                write!(f, "unsupported format version {}", version)
+            }
+            DecodeError::UnexpectedEnd => f.write_str("unexpected end of data"),
+            DecodeError::InvalidOpcode { at, opcode } => {
+                write!(f, "instruction {} has the invalid opcode {}", at, opcode)
+            }
+            DecodeError::InvalidNumber { at } => {
+                write!(f, "instruction {} contains an out of range number", at)
+            }
+            DecodeError::TooLong => {
+                write!(f, "program has more than {} instructions", MAX_INSTRUCTIONS)
+            }
+            DecodeError::TrailingBytes => f.write_str("unexpected data after the program"),
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        let (&first, rest) = self.bytes.split_first().ok_or(DecodeError::UnexpectedEnd)?;
+        self.bytes = rest;
+        Ok(first)
+    }
+
+    /// Reads a varint, returning `None` if it does not fit into a `u64`.
+    fn varint(&mut self) -> Result<Option<u64>, DecodeError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            let bits = u64::from(byte & 0x7f);
+            if bits << shift >> shift != bits {
+                return Ok(None);
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(Some(value));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// The opcode of `instruction` in the binary format.
+pub(crate) fn opcode(instruction: Instruction) -> u8 {
+    match instruction {
+        Instruction::Halt => 0,
+        Instruction::HaltWith(_) => 1,
+        Instruction::Purged => 2,
+        Instruction::Increment(..) => 3,
+        Instruction::Decrement(..) => 4,
+        Instruction::Jump(_) => 5,
+        Instruction::Nop(_) => 6,
+        Instruction::Clear(..) => 7,
+        Instruction::AddConst(..) => 8,
+        Instruction::SubConst(..) => 9,
+        Instruction::Transfer { .. } => 10,
+        Instruction::Copy { .. } => 11,
+        Instruction::BranchZero(..) => 12,
+        Instruction::Compare(..) => 13,
+        Instruction::Swap(..) => 14,
+        Instruction::Read(..) => 15,
+        Instruction::Write(..) => 16,
+        Instruction::Choose(..) => 17,
+        Instruction::Random(..) => 18,
+        Instruction::Call(_) => 19,
+        Instruction::Return => 20,
+    }
+}
+
+/// The instruction with the opcode `opcode` whose operands are all zero.
+pub(crate) fn template(opcode: u8) -> Option<Instruction> {
+    Some(match opcode {
+        0 => Instruction::Halt,
+        1 => Instruction::HaltWith(0),
+        2 => Instruction::Purged,
+        3 => Instruction::Increment(0, 0),
+        4 => Instruction::Decrement(0, 0, 0),
+        5 => Instruction::Jump(0),
+        6 => Instruction::Nop(0),
+        7 => Instruction::Clear(0, 0),
+        8 => Instruction::AddConst(0, 0, 0),
+        9 => Instruction::SubConst(0, 0, 0, 0),
+        10 => Instruction::Transfer {
+            src: 0,
+            dst: 0,
+            then: 0,
+        },
+        11 => Instruction::Copy {
+            src: 0,
+            dst: 0,
+            scratch: 0,
+            then: 0,
+        },
+        12 => Instruction::BranchZero(0, 0, 0),
+        13 => Instruction::Compare(0, 0, 0, 0),
+        14 => Instruction::Swap(0, 0, 0),
+        15 => Instruction::Read(0, 0),
+        16 => Instruction::Write(0, 0),
+        17 => Instruction::Choose(0, 0),
+        18 => Instruction::Random(0, 0, 0),
+        19 => Instruction::Call(0),
+        20 => Instruction::Return,
+        _ => return None,
+    })
+}
+
+/// The constant operand of `instruction`, e.g. the exit code of `HaltWith`.
+pub(crate) fn constant(instruction: Instruction) -> Option<u64> {
+    match instruction {
+        Instruction::HaltWith(n) | Instruction::Random(n, ..) => Some(u64::from(n)),
+        Instruction::AddConst(_, n, _) | Instruction::SubConst(_, n, ..) => Some(n),
+        _ => None,
+    }
+}
+
+/// Sets the constant operand of `instruction`, returning `false`
+/// if it does not have one or `value` is out of range.
+pub(crate) fn set_constant(instruction: &mut Instruction, value: u64) -> bool {
+    match instruction {
+        Instruction::HaltWith(n) | Instruction::Random(n, ..) => match u16::try_from(value) {
+            Ok(value) => *n = value,
+            Err(_) => return false,
+        },
+        Instruction::AddConst(_, n, _) | Instruction::SubConst(_, n, ..) => *n = value,
+        _ => return false,
+    }
+    true
+}
+
+/// Appends the encoding of `instruction` to `out`, see [`Program::to_bytes`].
+pub(crate) fn write_instruction(out: &mut Vec<u8>, instruction: Instruction) {
+    out.push(opcode(instruction));
+    out.extend(instruction.registers());
+    for value in constant(instruction)
+        .into_iter()
+        .chain(instruction.targets().map(u64::from))
+    {
+        write_varint(out, value);
+    }
+}
+
+impl Program {
+    /// Encodes this program in a compact binary format.
+    ///
+    /// The data starts with [`MAGIC`] and [`FORMAT_VERSION`], followed by the number
+    /// of instructions. Each instruction is stored as its opcode, followed by its registers
+    /// as single bytes and then its constants and jump targets as LEB128 varints, in the
+    /// order of the fields of [`Instruction`]. Trailing `Halt`s are omitted.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.push(FORMAT_VERSION);
+        write_varint(&mut out, self.len() as u64);
+        for &instruction in self.instructions() {
+            write_instruction(&mut out, instruction);
+        }
+        out
+    }
+
+    /// Decodes a program encoded with [`Program::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Program, DecodeError> {
+        let mut reader = Reader { bytes };
+        for &magic in &MAGIC {
+            if reader.byte().map_err(|_| DecodeError::BadMagic)? != magic {
+                return Err(DecodeError::BadMagic);
+            }
+        }
+        let version = reader.byte()?;
+        if version != FORMAT_VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let len = match reader.varint()? {
+            Some(len) if len <= MAX_INSTRUCTIONS as u64 => len as usize,
+            _ => return Err(DecodeError::TooLong),
+        };
+
+        let mut instructions = Vec::with_capacity(len.min(bytes.len()));
+        for at in 0..len {
+            let at = at as u16;
+            let op = reader.byte()?;
+            let mut instruction =
+                template(op).ok_or(DecodeError::InvalidOpcode { at, opcode: op })?;
+            for reg in instruction.registers_mut() {
+                *reg = reader.byte()?;
+            }
+            let invalid = DecodeError::InvalidNumber { at };
+            let small = |value: Option<u64>| value.and_then(|v| u16::try_from(v).ok());
+            if constant(instruction).is_some() {
+                let value = reader.varint()?.ok_or(invalid)?;
+                if !set_constant(&mut instruction, value) {
+                    return Err(invalid);
+                }
+            }
+            for target in instruction.targets_mut() {
+                *target = small(reader.varint()?).ok_or(invalid)?;
+            }
+            instructions.push(instruction);
+        }
+        if !reader.bytes.is_empty() {
+            return Err(DecodeError::TrailingBytes);
+        }
+        Ok(Program::new(instructions))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let program = Program::new(
+            [
+                Instruction::Decrement(1, 1, 300),
+                Instruction::AddConst(0, u64::MAX, 2),
+                Instruction::HaltWith(7),
+                Instruction::Copy {
+                    src: 1,
+                    dst: 2,
+                    scratch: 3,
+                    then: u16::MAX,
+                },
+                Instruction::Return,
+                Instruction::Halt,
+            ]
+            .iter()
+            .copied(),
+        );
+        let bytes = program.to_bytes();
+        assert_eq!(&bytes[..6], b"MNSK\x01\x05");
+        assert_eq!(&bytes[6..11], &[4, 1, 1, 0xac, 0x02]);
+        assert_eq!(bytes.len(), 6 + 5 + 13 + 2 + 7 + 1);
+        let decoded = Program::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.len(), 5);
+        assert_eq!(decoded.diff(&program), []);
+        assert_eq!(
+            Program::from_bytes(&Program::empty().to_bytes()),
+            Ok(Program::empty())
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let bytes = Program::new([Instruction::Jump(1000)].iter().copied()).to_bytes();
+        assert_eq!(Program::from_bytes(b"MNS"), Err(DecodeError::BadMagic));
+        assert_eq!(
+            Program::from_bytes(b"ABCD\x01\x00"),
+            Err(DecodeError::BadMagic)
+        );
+        assert_eq!(
+            Program::from_bytes(b"MNSK\x02\x00"),
+            Err(DecodeError::UnsupportedVersion(2))
+        );
+        assert_eq!(
+            Program::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(DecodeError::UnexpectedEnd)
+        );
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(
+            Program::from_bytes(&trailing),
+            Err(DecodeError::TrailingBytes)
+        );
+        assert_eq!(
+            Program::from_bytes(b"MNSK\x01\x01\x15"),
+            Err(DecodeError::InvalidOpcode { at: 0, opcode: 21 })
+        );
+        assert_eq!(
+            Program::from_bytes(b"MNSK\x01\x01\x05\x80\x80\x04"),
+            Err(DecodeError::InvalidNumber { at: 0 })
+        );
+        assert_eq!(
+            Program::from_bytes(b"MNSK\x01\x81\x80\x04"),
+            Err(DecodeError::TooLong)
+        );
+        let mut overflow = b"MNSK\x01\x01\x08\x00".to_vec();
+        overflow.extend(&[0xff; 9]);
+        overflow.push(0x02);
+        assert_eq!(
+            Program::from_bytes(&overflow),
+            Err(DecodeError::InvalidNumber { at: 0 })
+        );
+    }
+}
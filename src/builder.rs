@@ -0,0 +1,704 @@
+use crate::{Instruction, Program, RegisterFile, MAX_INSTRUCTIONS};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt::{self, Debug};
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// A jump target of a [`ProgramBuilder`], either a label or an absolute instruction index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Target<L> {
+    Label(L),
+    Addr(u16),
+}
+
+impl<L> From<L> for Target<L> {
+    fn from(label: L) -> Target<L> {
+        Target::Label(label)
+    }
+}
+
+/// An error returned by [`ProgramBuilder::build`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildError<L> {
+    /// A jump target refers to a label which was never defined.
+    UndefinedLabel(L),
+    /// The label was defined more than once.
+    DuplicateLabel(L),
+    /// The program has more than [`MAX_INSTRUCTIONS`] instructions.
+    TooLong,
+}
+
+impl<L: Debug> fmt::Display for BuildError<L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::UndefinedLabel(label) => write!(f, "undefined label {:?}", label),
+            BuildError::DuplicateLabel(label) => write!(f, "label {:?} is defined twice", label),
+            BuildError::TooLong => {
+                write!(f, "program has more than {} instructions", MAX_INSTRUCTIONS)
+            }
+        }
+    }
+}
+
+impl<L: Debug> Error for BuildError<L> {}
+
+/// Builds a [`Program`] using symbolic labels instead of absolute jump targets.
+///
+/// Labels can be of any type, e.g. strings or a user-defined enum, and are
+/// resolved when calling [`ProgramBuilder::build`]. A label defined after the
+/// last instruction refers to the implicit `Halt` at the end of the program.
+///
+/// The builder also allocates registers using a [`RegisterFile`], mapping symbolic
+/// register names to physical indices and handing out fresh scratch registers, which
+/// makes it easy to combine blocks from [`routines`](crate::routines) without collisions.
+/// Allocated registers start at `$0`, so registers allocated this way should
+/// not be mixed with hardcoded register indices.
+#[derive(Debug, Clone)]
+pub struct ProgramBuilder<L = &'static str> {
+    instructions: Vec<(Instruction, [Option<L>; 2])>,
+    labels: HashMap<L, u16>,
+    duplicate: Option<L>,
+    too_long: bool,
+    registers: RegisterFile,
+}
+
+impl<L: Clone + Eq + Hash> Default for ProgramBuilder<L> {
+    fn default() -> ProgramBuilder<L> {
+        ProgramBuilder::new()
+    }
+}
+
+impl<L: Clone + Eq + Hash> ProgramBuilder<L> {
+    pub fn new() -> ProgramBuilder<L> {
+        ProgramBuilder {
+            instructions: Vec::new(),
+            labels: HashMap::new(),
+            duplicate: None,
+            too_long: false,
+            registers: RegisterFile::new(),
+        }
+    }
+
+    /// The index of the next instruction.
+    pub fn position(&self) -> u16 {
+        self.instructions.len() as u16
+    }
+
+    /// Uses `registers` for all further register allocations,
+    /// e.g. to follow a convention for the inputs of the program.
+    pub fn with_registers(mut self, registers: RegisterFile) -> ProgramBuilder<L> {
+        self.registers = registers;
+        self
+    }
+
+    /// The names and allocations of all registers used by the builder.
+    pub fn registers(&self) -> &RegisterFile {
+        &self.registers
+    }
+
+    /// The physical register for the symbolic register `name`, allocating it on first use.
+    ///
+    /// # Panics
+    ///
+    /// Panics if all 256 registers are already in use.
+    pub fn register(&mut self, name: &str) -> u8 {
+        self.registers
+            .resolve(name)
+            .expect("all registers are in use")
+    }
+
+    /// Allocates `N` fresh scratch registers, e.g. for a single routine.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are not enough unused registers left.
+    pub fn scratch<const N: usize>(&mut self) -> [u8; N] {
+        let mut regs = [0; N];
+        for reg in regs.iter_mut() {
+            *reg = self.registers.allocate().expect("all registers are in use");
+        }
+        regs
+    }
+
+    /// Appends a relocatable block, e.g. one of [`routines`](crate::routines).
+    ///
+    /// Halting the block, explicitly or by jumping past its end, instead continues
+    /// with the instruction following the block.
+    pub fn block(&mut self, block: &Program) -> &mut ProgramBuilder<L> {
+        let start = self.position();
+        let relocated = u16::try_from(block.len())
+            .ok()
+            .and_then(|len| start.checked_add(len))
+            .and_then(|end| block.relocate(start, Some(end)));
+        match relocated {
+            Some(instructions) => self
+                .instructions
+                .extend(instructions.into_iter().map(|i| (i, [None, None]))),
+            None => self.too_long = true,
+        }
+        self
+    }
+
+    /// Defines `label` to refer to the next instruction.
+    pub fn label(&mut self, label: L) -> &mut ProgramBuilder<L> {
+        let position = self.position();
+        if self.labels.insert(label.clone(), position).is_some() && self.duplicate.is_none() {
+            self.duplicate = Some(label);
+        }
+        self
+    }
+
+    /// Adds `instruction` with its targets as absolute instruction indices.
+    pub fn instruction(&mut self, instruction: Instruction) -> &mut ProgramBuilder<L> {
+        self.instructions.push((instruction, [None, None]));
+        self
+    }
+
+    /// Adds `instruction`, replacing its targets, in order, with `targets`.
+    fn push(
+        &mut self,
+        mut instruction: Instruction,
+        targets: &[Target<L>],
+    ) -> &mut ProgramBuilder<L> {
+        let mut labels = [None, None];
+        for ((target, label), new) in instruction
+            .targets_mut()
+            .zip(labels.iter_mut())
+            .zip(targets)
+        {
+            match new {
+                Target::Label(l) => *label = Some(l.clone()),
+                Target::Addr(addr) => *target = *addr,
+            }
+        }
+        self.instructions.push((instruction, labels));
+        self
+    }
+
+    pub fn halt(&mut self) -> &mut ProgramBuilder<L> {
+        self.instruction(Instruction::Halt)
+    }
+
+    pub fn halt_with(&mut self, code: u16) -> &mut ProgramBuilder<L> {
+        self.instruction(Instruction::HaltWith(code))
+    }
+
+    pub fn purged(&mut self) -> &mut ProgramBuilder<L> {
+        self.instruction(Instruction::Purged)
+    }
+
+    pub fn increment(&mut self, reg: u8, target: impl Into<Target<L>>) -> &mut ProgramBuilder<L> {
+        self.push(Instruction::Increment(reg, 0), &[target.into()])
+    }
+
+    pub fn decrement(
+        &mut self,
+        reg: u8,
+        then: impl Into<Target<L>>,
+        els: impl Into<Target<L>>,
+    ) -> &mut ProgramBuilder<L> {
+        self.push(
+            Instruction::Decrement(reg, 0, 0),
+            &[then.into(), els.into()],
+        )
+    }
+
+    pub fn jump(&mut self, target: impl Into<Target<L>>) -> &mut ProgramBuilder<L> {
+        self.push(Instruction::Jump(0), &[target.into()])
+    }
+
+    pub fn nop(&mut self, target: impl Into<Target<L>>) -> &mut ProgramBuilder<L> {
+        self.push(Instruction::Nop(0), &[target.into()])
+    }
+
+    pub fn clear(&mut self, reg: u8, target: impl Into<Target<L>>) -> &mut ProgramBuilder<L> {
+        self.push(Instruction::Clear(reg, 0), &[target.into()])
+    }
+
+    pub fn add_const(
+        &mut self,
+        reg: u8,
+        n: u64,
+        target: impl Into<Target<L>>,
+    ) -> &mut ProgramBuilder<L> {
+        self.push(Instruction::AddConst(reg, n, 0), &[target.into()])
+    }
+
+    pub fn sub_const(
+        &mut self,
+        reg: u8,
+        n: u64,
+        then: impl Into<Target<L>>,
+        els: impl Into<Target<L>>,
+    ) -> &mut ProgramBuilder<L> {
+        self.push(
+            Instruction::SubConst(reg, n, 0, 0),
+            &[then.into(), els.into()],
+        )
+    }
+
+    pub fn transfer(
+        &mut self,
+        src: u8,
+        dst: u8,
+        then: impl Into<Target<L>>,
+    ) -> &mut ProgramBuilder<L> {
+        self.push(Instruction::Transfer { src, dst, then: 0 }, &[then.into()])
+    }
+
+    pub fn copy(
+        &mut self,
+        src: u8,
+        dst: u8,
+        scratch: u8,
+        then: impl Into<Target<L>>,
+    ) -> &mut ProgramBuilder<L> {
+        self.push(
+            Instruction::Copy {
+                src,
+                dst,
+                scratch,
+                then: 0,
+            },
+            &[then.into()],
+        )
+    }
+
+    pub fn branch_zero(
+        &mut self,
+        reg: u8,
+        zero: impl Into<Target<L>>,
+        nonzero: impl Into<Target<L>>,
+    ) -> &mut ProgramBuilder<L> {
+        self.push(
+            Instruction::BranchZero(reg, 0, 0),
+            &[zero.into(), nonzero.into()],
+        )
+    }
+
+    pub fn compare(
+        &mut self,
+        a: u8,
+        b: u8,
+        ge: impl Into<Target<L>>,
+        lt: impl Into<Target<L>>,
+    ) -> &mut ProgramBuilder<L> {
+        self.push(Instruction::Compare(a, b, 0, 0), &[ge.into(), lt.into()])
+    }
+
+    pub fn swap(&mut self, a: u8, b: u8, target: impl Into<Target<L>>) -> &mut ProgramBuilder<L> {
+        self.push(Instruction::Swap(a, b, 0), &[target.into()])
+    }
+
+    pub fn read(&mut self, reg: u8, target: impl Into<Target<L>>) -> &mut ProgramBuilder<L> {
+        self.push(Instruction::Read(reg, 0), &[target.into()])
+    }
+
+    pub fn write(&mut self, reg: u8, target: impl Into<Target<L>>) -> &mut ProgramBuilder<L> {
+        self.push(Instruction::Write(reg, 0), &[target.into()])
+    }
+
+    pub fn choose(
+        &mut self,
+        first: impl Into<Target<L>>,
+        second: impl Into<Target<L>>,
+    ) -> &mut ProgramBuilder<L> {
+        self.push(Instruction::Choose(0, 0), &[first.into(), second.into()])
+    }
+
+    pub fn random(
+        &mut self,
+        probability: u16,
+        then: impl Into<Target<L>>,
+        els: impl Into<Target<L>>,
+    ) -> &mut ProgramBuilder<L> {
+        self.push(
+            Instruction::Random(probability, 0, 0),
+            &[then.into(), els.into()],
+        )
+    }
+
+    pub fn call(&mut self, target: impl Into<Target<L>>) -> &mut ProgramBuilder<L> {
+        self.push(Instruction::Call(0), &[target.into()])
+    }
+
+    pub fn ret(&mut self) -> &mut ProgramBuilder<L> {
+        self.instruction(Instruction::Return)
+    }
+
+    /// Resolves all labels and builds the program.
+    pub fn build(&self) -> Result<Program, BuildError<L>> {
+        if let Some(label) = &self.duplicate {
+            return Err(BuildError::DuplicateLabel(label.clone()));
+        }
+        if self.too_long || self.instructions.len() > MAX_INSTRUCTIONS {
+            return Err(BuildError::TooLong);
+        }
+
+        let mut instructions = Vec::with_capacity(self.instructions.len());
+        for (instruction, labels) in &self.instructions {
+            let mut instruction = *instruction;
+            for (target, label) in instruction.targets_mut().zip(labels) {
+                if let Some(label) = label {
+                    *target = match self.labels.get(label) {
+                        Some(&addr) => addr,
+                        None => return Err(BuildError::UndefinedLabel(label.clone())),
+                    };
+                }
+            }
+            instructions.push(instruction);
+        }
+        Ok(Program::new(instructions))
+    }
+}
+
+static NEXT_STATE_BUILDER: AtomicU32 = AtomicU32::new(0);
+
+/// A state of a [`StateBuilder`] which can be used as a jump target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct State {
+    builder: u32,
+    index: u16,
+}
+
+impl State {
+    /// The instruction index of this state in the built program.
+    pub fn addr(self) -> u16 {
+        self.index
+    }
+}
+
+/// The unique permission to define the instruction of a [`State`].
+///
+/// Defining a state consumes its slot, so states can't be defined twice.
+#[must_use = "states have to be defined using their slot"]
+#[derive(Debug)]
+pub struct Slot {
+    state: State,
+}
+
+impl Slot {
+    pub fn state(&self) -> State {
+        self.state
+    }
+}
+
+/// Builds a [`Program`] from states which are created before they are defined.
+///
+/// Unlike labels of a [`ProgramBuilder`], jump targets are [`State`]s created by
+/// the builder itself, so they always refer to an existing state. Each state
+/// is defined using its [`Slot`], which can only be used once. States are placed
+/// in the order they are created, so the first created state is the entry point
+/// and `Call` returns to the state created right after the calling one.
+///
+/// Using a state or slot of another builder panics.
+#[derive(Debug)]
+pub struct StateBuilder {
+    id: u32,
+    instructions: Vec<Option<Instruction>>,
+}
+
+impl Default for StateBuilder {
+    fn default() -> StateBuilder {
+        StateBuilder::new()
+    }
+}
+
+impl StateBuilder {
+    pub fn new() -> StateBuilder {
+        StateBuilder {
+            id: NEXT_STATE_BUILDER.fetch_add(1, Ordering::Relaxed),
+            instructions: Vec::new(),
+        }
+    }
+
+    /// Creates a new undefined state.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the builder already has [`MAX_INSTRUCTIONS`] states.
+    pub fn state(&mut self) -> (State, Slot) {
+        assert!(
+            self.instructions.len() < MAX_INSTRUCTIONS,
+            "too many states"
+        );
+        let state = State {
+            builder: self.id,
+            index: self.instructions.len() as u16,
+        };
+        self.instructions.push(None);
+        (state, Slot { state })
+    }
+
+    fn addr(&self, state: State) -> u16 {
+        assert_eq!(state.builder, self.id, "state of a different builder");
+        state.index
+    }
+
+    /// Defines the state of `slot` as `instruction`, whose jump targets
+    /// are used as is, see [`State::addr`].
+    pub fn set(&mut self, slot: Slot, instruction: Instruction) {
+        let at = self.addr(slot.state);
+        self.instructions[at as usize] = Some(instruction);
+    }
+
+    pub fn halt(&mut self, slot: Slot) {
+        self.set(slot, Instruction::Halt)
+    }
+
+    pub fn halt_with(&mut self, slot: Slot, code: u16) {
+        self.set(slot, Instruction::HaltWith(code))
+    }
+
+    pub fn purged(&mut self, slot: Slot) {
+        self.set(slot, Instruction::Purged)
+    }
+
+    pub fn increment(&mut self, slot: Slot, reg: u8, target: State) {
+        let target = self.addr(target);
+        self.set(slot, Instruction::Increment(reg, target))
+    }
+
+    pub fn decrement(&mut self, slot: Slot, reg: u8, then: State, els: State) {
+        let (then, els) = (self.addr(then), self.addr(els));
+        self.set(slot, Instruction::Decrement(reg, then, els))
+    }
+
+    pub fn jump(&mut self, slot: Slot, target: State) {
+        let target = self.addr(target);
+        self.set(slot, Instruction::Jump(target))
+    }
+
+    pub fn nop(&mut self, slot: Slot, target: State) {
+        let target = self.addr(target);
+        self.set(slot, Instruction::Nop(target))
+    }
+
+    pub fn clear(&mut self, slot: Slot, reg: u8, target: State) {
+        let target = self.addr(target);
+        self.set(slot, Instruction::Clear(reg, target))
+    }
+
+    pub fn add_const(&mut self, slot: Slot, reg: u8, n: u64, target: State) {
+        let target = self.addr(target);
+        self.set(slot, Instruction::AddConst(reg, n, target))
+    }
+
+    pub fn sub_const(&mut self, slot: Slot, reg: u8, n: u64, then: State, els: State) {
+        let (then, els) = (self.addr(then), self.addr(els));
+        self.set(slot, Instruction::SubConst(reg, n, then, els))
+    }
+
+    pub fn transfer(&mut self, slot: Slot, src: u8, dst: u8, then: State) {
+        let then = self.addr(then);
+        self.set(slot, Instruction::Transfer { src, dst, then })
+    }
+
+    pub fn copy(&mut self, slot: Slot, src: u8, dst: u8, scratch: u8, then: State) {
+        let then = self.addr(then);
+        self.set(
+            slot,
+            Instruction::Copy {
+                src,
+                dst,
+                scratch,
+                then,
+            },
+        )
+    }
+
+    pub fn branch_zero(&mut self, slot: Slot, reg: u8, zero: State, nonzero: State) {
+        let (zero, nonzero) = (self.addr(zero), self.addr(nonzero));
+        self.set(slot, Instruction::BranchZero(reg, zero, nonzero))
+    }
+
+    pub fn compare(&mut self, slot: Slot, a: u8, b: u8, ge: State, lt: State) {
+        let (ge, lt) = (self.addr(ge), self.addr(lt));
+        self.set(slot, Instruction::Compare(a, b, ge, lt))
+    }
+
+    pub fn swap(&mut self, slot: Slot, a: u8, b: u8, target: State) {
+        let target = self.addr(target);
+        self.set(slot, Instruction::Swap(a, b, target))
+    }
+
+    pub fn read(&mut self, slot: Slot, reg: u8, target: State) {
+        let target = self.addr(target);
+        self.set(slot, Instruction::Read(reg, target))
+    }
+
+    pub fn write(&mut self, slot: Slot, reg: u8, target: State) {
+        let target = self.addr(target);
+        self.set(slot, Instruction::Write(reg, target))
+    }
+
+    pub fn choose(&mut self, slot: Slot, first: State, second: State) {
+        let (first, second) = (self.addr(first), self.addr(second));
+        self.set(slot, Instruction::Choose(first, second))
+    }
+
+    pub fn random(&mut self, slot: Slot, probability: u16, then: State, els: State) {
+        let (then, els) = (self.addr(then), self.addr(els));
+        self.set(slot, Instruction::Random(probability, then, els))
+    }
+
+    pub fn call(&mut self, slot: Slot, target: State) {
+        let target = self.addr(target);
+        self.set(slot, Instruction::Call(target))
+    }
+
+    pub fn ret(&mut self, slot: Slot) {
+        self.set(slot, Instruction::Return)
+    }
+
+    /// Builds the program, failing if any state has not been defined.
+    pub fn build(&self) -> Result<Program, BuildError<State>> {
+        let mut instructions = Vec::with_capacity(self.instructions.len());
+        for (index, instruction) in self.instructions.iter().enumerate() {
+            match instruction {
+                Some(instruction) => instructions.push(*instruction),
+                None => {
+                    return Err(BuildError::UndefinedLabel(State {
+                        builder: self.id,
+                        index: index as u16,
+                    }))
+                }
+            }
+        }
+        Ok(Program::new(instructions))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{routines, Machine, RunOutcome};
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    enum L {
+        Outer,
+        Inner,
+        Restore,
+        Next,
+        Done,
+    }
+
+    #[test]
+    fn multiply() {
+        // $0 = $1 * $2, see `multiply` in the machine tests.
+        let mut b = ProgramBuilder::new();
+        b.label(L::Outer).decrement(1, L::Inner, L::Done);
+        b.label(L::Inner).decrement(2, Target::Addr(2), L::Restore);
+        b.increment(0, Target::Addr(3)).increment(3, L::Inner);
+        b.label(L::Restore).decrement(3, Target::Addr(5), L::Next);
+        b.increment(2, L::Restore);
+        b.label(L::Next).jump(L::Outer);
+        b.label(L::Done);
+        let program = b.build().unwrap();
+        assert_eq!(program.len(), 7);
+        assert_eq!(program.instruction(1), Instruction::Decrement(2, 2, 4));
+
+        let mut prog: Machine = Machine::new(&program);
+        prog.reset_with(vec![0, 3, 4]);
+        assert!(matches!(prog.run(u64::MAX), RunOutcome::Halted { .. }));
+        assert_eq!(&prog.registers()[..4], &[12, 0, 4, 0]);
+    }
+
+    #[test]
+    fn registers() {
+        // $result = ($x + $y) * $x
+        let mut b = ProgramBuilder::<&str>::new();
+        let (x, y) = (b.register("x"), b.register("y"));
+        let (sum, result) = (b.register("sum"), b.register("result"));
+        assert_eq!(b.register("x"), x);
+        let [s] = b.scratch();
+        b.block(&routines::add(x, y, sum, s));
+        let [s1, s2] = b.scratch();
+        b.block(&routines::multiply(sum, x, result, [s1, s2]));
+        assert_eq!(b.registers().len(), 7);
+        assert_eq!(b.registers().get("result"), Some(result));
+        assert_eq!(b.registers().get("z"), None);
+        let program = b.build().unwrap();
+
+        let mut prog: Machine = Machine::new(&program);
+        prog.set_register(x, 3);
+        prog.set_register(y, 4);
+        assert!(matches!(prog.run(u64::MAX), RunOutcome::Halted { .. }));
+        assert_eq!(prog.get_register(result), &21);
+        assert_eq!(prog.ptr() as usize, program.len());
+
+        let mut convention = RegisterFile::new();
+        convention.define("input", 0);
+        convention.define("output", 1);
+        let mut b = ProgramBuilder::<&str>::new().with_registers(convention);
+        assert_eq!(b.register("output"), 1);
+        assert_eq!(b.scratch(), [2, 3]);
+        assert_eq!(b.registers().display(0).to_string(), "input");
+    }
+
+    #[test]
+    fn states() {
+        // $0 = $1 * $2, see `multiply` above.
+        let mut b = StateBuilder::new();
+        let (outer, outer_slot) = b.state();
+        let (inner, inner_slot) = b.state();
+        let (inc, inc_slot) = b.state();
+        let (save, save_slot) = b.state();
+        let (restore, restore_slot) = b.state();
+        let (restore_inc, restore_inc_slot) = b.state();
+        let (done, done_slot) = b.state();
+        b.halt(done_slot);
+        b.decrement(restore_slot, 3, restore_inc, outer);
+        b.increment(restore_inc_slot, 2, restore);
+        b.decrement(outer_slot, 1, inner, done);
+        b.decrement(inner_slot, 2, inc, restore);
+        b.increment(inc_slot, 0, save);
+        b.increment(save_slot, 3, inner);
+        let program = b.build().unwrap();
+        assert_eq!(program.len(), 6);
+        assert_eq!(program.instruction(4), Instruction::Decrement(3, 5, 0));
+
+        let mut prog: Machine = Machine::new(&program);
+        prog.reset_with(vec![0, 3, 4]);
+        assert!(matches!(prog.run(u64::MAX), RunOutcome::Halted { .. }));
+        assert_eq!(&prog.registers()[..4], &[12, 0, 4, 0]);
+
+        let mut b = StateBuilder::new();
+        let (start, start_slot) = b.state();
+        let (missing, _missing_slot) = b.state();
+        b.jump(start_slot, missing);
+        assert_eq!(start.addr(), 0);
+        assert_eq!(b.build(), Err(BuildError::UndefinedLabel(missing)));
+    }
+
+    #[test]
+    #[should_panic(expected = "state of a different builder")]
+    fn foreign_state() {
+        let mut a = StateBuilder::new();
+        let mut b = StateBuilder::new();
+        let (state, _) = a.state();
+        let (_, slot) = b.state();
+        b.jump(slot, state);
+    }
+
+    #[test]
+    fn errors() {
+        let mut b = ProgramBuilder::new();
+        b.label("a").jump("b");
+        assert_eq!(b.build(), Err(BuildError::UndefinedLabel("b")));
+        b.label("b").label("a");
+        assert_eq!(b.build(), Err(BuildError::DuplicateLabel("a")));
+        assert_eq!(
+            BuildError::UndefinedLabel("b").to_string(),
+            "undefined label \"b\""
+        );
+
+        let mut b = ProgramBuilder::<&str>::new();
+        for _ in 0..=MAX_INSTRUCTIONS {
+            b.halt();
+        }
+        assert_eq!(b.build(), Err(BuildError::TooLong));
+    }
+}
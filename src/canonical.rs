@@ -0,0 +1,401 @@
+use crate::binary::{constant, opcode};
+use crate::{Instruction, Program};
+use std::collections::{HashMap, VecDeque};
+
+/// Whether jumping to `at` halts immediately with exit code zero.
+fn halts(program: &Program, at: u16) -> bool {
+    program.instruction(at) == Instruction::Halt
+}
+
+/// Renumbers the registers of `instructions` in order of their first use.
+fn renumber_registers(instructions: &mut [Instruction]) {
+    let mut registers: HashMap<u8, u8> = HashMap::new();
+    for instruction in instructions {
+        for reg in instruction.registers_mut() {
+            let next = registers.len() as u8;
+            *reg = *registers.entry(*reg).or_insert(next);
+        }
+        if let Instruction::Swap(a, b, _) = instruction {
+            if a > b {
+                std::mem::swap(a, b);
+            }
+        }
+    }
+}
+
+/// The instructions reachable from the start in breadth-first order, with
+/// their original positions and their targets renumbered.
+fn renumber_states(program: &Program) -> (Vec<u16>, Vec<Instruction>) {
+    if halts(program, 0) {
+        return (Vec::new(), Vec::new());
+    }
+    let mut states: HashMap<u16, u16> = HashMap::new();
+    let mut positions = Vec::new();
+    let mut order = Vec::new();
+    let mut queue = VecDeque::new();
+    states.insert(0, 0);
+    queue.push_back(0);
+    while let Some(at) = queue.pop_front() {
+        let instruction = program.instruction(at);
+        for target in instruction.targets() {
+            if !halts(program, target) && !states.contains_key(&target) {
+                states.insert(target, states.len() as u16);
+                queue.push_back(target);
+            }
+        }
+        positions.push(at);
+        order.push(instruction);
+    }
+
+    let end = order.len() as u16;
+    for instruction in &mut order {
+        for target in instruction.targets_mut() {
+            *target = states.get(target).copied().unwrap_or(end);
+        }
+    }
+    (positions, order)
+}
+
+/// Exchanges the symmetric operands of `instruction`, returning
+/// whether this changed anything.
+fn flip(instruction: &mut Instruction) -> bool {
+    match instruction {
+        Instruction::Choose(first, second) if first != second => {
+            std::mem::swap(first, second);
+            true
+        }
+        Instruction::Swap(a, b, _) if a != b => {
+            std::mem::swap(a, b);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Canonicalizes `instructions` for every way of flipping its `symmetric` instructions,
+/// see [`Program::canonicalize`], keeping the result with the smallest encoding in `best`.
+fn search(
+    instructions: &mut Vec<Instruction>,
+    symmetric: &[u16],
+    uses_calls: bool,
+    best: &mut Option<(Vec<u8>, Program)>,
+) {
+    if let Some((&at, rest)) = symmetric.split_first() {
+        search(instructions, rest, uses_calls, best);
+        flip(&mut instructions[at as usize]);
+        search(instructions, rest, uses_calls, best);
+        flip(&mut instructions[at as usize]);
+        return;
+    }
+
+    let program = Program::new(instructions.iter().copied());
+    let mut instructions = if uses_calls {
+        program.instructions().to_vec()
+    } else {
+        renumber_states(&program).1
+    };
+    renumber_registers(&mut instructions);
+    let program = Program::new(instructions);
+    let bytes = program.to_bytes();
+    if best.as_ref().is_none_or(|(smallest, _)| bytes < *smallest) {
+        *best = Some((bytes, program));
+    }
+}
+
+/// A partial isomorphism between two programs, see [`Program::is_isomorphic`].
+#[derive(Clone, Default)]
+struct Matching {
+    states: HashMap<u16, u16>,
+    reverse_states: HashMap<u16, u16>,
+    registers: HashMap<u8, u8>,
+    reverse_registers: HashMap<u8, u8>,
+}
+
+impl Matching {
+    fn map_register(&mut self, a: u8, b: u8) -> bool {
+        *self.registers.entry(a).or_insert(b) == b
+            && *self.reverse_registers.entry(b).or_insert(a) == a
+    }
+
+    /// Matches the states of all `pending` pairs of positions and everything reachable
+    /// from them, trying both orders of symmetric operands.
+    fn solve(&mut self, left: &Program, right: &Program, mut pending: Vec<(u16, u16)>) -> bool {
+        while let Some((a, b)) = pending.pop() {
+            match (halts(left, a), halts(right, b)) {
+                (true, true) => continue,
+                (false, false) => {}
+                _ => return false,
+            }
+            match (self.states.get(&a), self.reverse_states.get(&b)) {
+                (Some(&mapped), _) if mapped == b => continue,
+                (None, None) => {}
+                _ => return false,
+            }
+            self.states.insert(a, b);
+            self.reverse_states.insert(b, a);
+
+            let (x, y) = (left.instruction(a), right.instruction(b));
+            if opcode(x) != opcode(y) || constant(x) != constant(y) {
+                return false;
+            }
+            let mut variants = vec![y];
+            match y {
+                Instruction::Choose(first, second) if first != second => {
+                    variants.push(Instruction::Choose(second, first))
+                }
+                Instruction::Swap(c, d, then) if c != d => {
+                    variants.push(Instruction::Swap(d, c, then))
+                }
+                _ => {}
+            }
+            let mut successors: Vec<(u16, u16)> = x.targets().zip(y.targets()).collect();
+            if let Instruction::Call(_) = x {
+                successors.push((a.wrapping_add(1), b.wrapping_add(1)));
+            }
+            if variants.len() == 1 {
+                if !x
+                    .registers()
+                    .zip(y.registers())
+                    .all(|(c, d)| self.map_register(c, d))
+                {
+                    return false;
+                }
+                pending.extend(successors);
+                continue;
+            }
+            return variants.into_iter().any(|y| {
+                let mut matching = self.clone();
+                let mut pending = pending.clone();
+                pending.extend(x.targets().zip(y.targets()));
+                x.registers()
+                    .zip(y.registers())
+                    .all(|(c, d)| matching.map_register(c, d))
+                    && matching.solve(left, right, pending)
+            });
+        }
+        true
+    }
+}
+
+impl Program {
+    /// Returns a canonical form of this program, so that programs which only differ in the
+    /// order of their instructions or the numbering of their registers compare equal.
+    ///
+    /// The instructions reachable from the start are renumbered in breadth-first order,
+    /// dropping all unreachable instructions. Jumps to a `Halt` are replaced by jumps to
+    /// the end of the program. Registers are renumbered in order of their first use in the
+    /// result, and the registers of `Swap` are sorted. Of all ways to order the arms of
+    /// `Choose` and the registers of `Swap`, the one with the smallest
+    /// [binary encoding](Program::to_bytes) is used, so isomorphic programs, see
+    /// [`Program::is_isomorphic`], have the same canonical form. This takes exponential
+    /// time in the number of such instructions.
+    ///
+    /// The result behaves like this program up to the renumbering of its registers.
+    /// Programs using `Call` or `Return` depend on the position of their instructions,
+    /// so only their registers are renumbered.
+    pub fn canonicalize(&self) -> Program {
+        let uses_calls = self.iter().any(|(_, instruction)| {
+            matches!(instruction, Instruction::Call(_) | Instruction::Return)
+        });
+        let positions = if uses_calls {
+            self.iter().map(|(at, _)| at).collect()
+        } else {
+            renumber_states(self).0
+        };
+        let symmetric: Vec<u16> = positions
+            .into_iter()
+            .filter(|&at| flip(&mut self.instruction(at)))
+            .collect();
+
+        let mut best = None;
+        search(
+            &mut self.instructions().to_vec(),
+            &symmetric,
+            uses_calls,
+            &mut best,
+        );
+        best.unwrap().1
+    }
+
+    /// Whether this program and `other` are the same up to the order of their instructions
+    /// and a permutation of their registers.
+    ///
+    /// Only the instructions reachable from the start are compared, and jumps to a `Halt`
+    /// are the same as jumps past the end of the program. Both arms of `Choose` and
+    /// both registers of `Swap` may be exchanged. Unlike comparing the results of
+    /// [`Program::canonicalize`], this is exact, but it may take exponential time
+    /// for programs with many `Choose` instructions.
+    pub fn is_isomorphic(&self, other: &Program) -> bool {
+        Matching::default().solve(self, other, vec![(0, 0)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Machine;
+
+    #[test]
+    fn canonicalize() {
+        // Moves `$3` into `$5` using a loop at the end of the program.
+        let program = Program::new(
+            [
+                Instruction::AddConst(3, 4, 2),
+                Instruction::Halt,
+                Instruction::Jump(4),
+                Instruction::Purged,
+                Instruction::Decrement(3, 5, 1),
+                Instruction::Increment(5, 4),
+            ]
+            .iter()
+            .copied(),
+        );
+        let canonical = program.canonicalize();
+        assert_eq!(
+            canonical.instructions(),
+            [
+                Instruction::AddConst(0, 4, 1),
+                Instruction::Jump(2),
+                Instruction::Decrement(0, 3, 4),
+                Instruction::Increment(1, 2),
+            ]
+        );
+        assert_eq!(canonical.canonicalize(), canonical);
+        let mut machine: Machine = Machine::new(&program);
+        let mut canonical_machine: Machine = Machine::new(&canonical);
+        assert_eq!(machine.run(100), canonical_machine.run(100));
+        assert_eq!(machine.registers()[5], canonical_machine.registers()[1]);
+
+        let choose = |first, second| {
+            Program::new(
+                [
+                    Instruction::Choose(first, second),
+                    Instruction::Increment(7, 3),
+                    Instruction::Swap(2, 7, 3),
+                    Instruction::Halt,
+                ]
+                .iter()
+                .copied(),
+            )
+        };
+        assert_eq!(choose(1, 2).canonicalize(), choose(2, 1).canonicalize());
+        assert_eq!(
+            choose(1, 2).canonicalize().instructions(),
+            [
+                Instruction::Choose(1, 2),
+                Instruction::Increment(0, 3),
+                Instruction::Swap(0, 1, 3),
+            ]
+        );
+
+        // The order of the arms of each `Choose` changes the order of all later instructions.
+        let program = Program::new(vec![
+            Instruction::Choose(5, 2),
+            Instruction::Choose(2, 5),
+            Instruction::SubConst(2, 2, 4, 0),
+            Instruction::Jump(5),
+            Instruction::Choose(1, 0),
+            Instruction::Swap(0, 1, 1),
+        ]);
+        let other = Program::new(vec![
+            Instruction::Choose(1, 4),
+            Instruction::SubConst(7, 2, 2, 0),
+            Instruction::Choose(0, 3),
+            Instruction::Choose(1, 4),
+            Instruction::Swap(1, 0, 3),
+            Instruction::Jump(4),
+        ]);
+        assert!(program.is_isomorphic(&other));
+        let canonical = program.canonicalize();
+        assert_eq!(canonical.canonicalize(), canonical);
+        assert_eq!(other.canonicalize(), canonical);
+        assert!(canonical.is_isomorphic(&program));
+
+        let halt = Program::new([Instruction::Halt, Instruction::Jump(0)].iter().copied());
+        assert_eq!(halt.canonicalize(), Program::empty());
+
+        // Calls return to the next instruction, so the order is kept.
+        let calls = Program::new(
+            [
+                Instruction::Call(2),
+                Instruction::Halt,
+                Instruction::Clear(9, 3),
+                Instruction::Return,
+            ]
+            .iter()
+            .copied(),
+        );
+        assert_eq!(
+            calls.canonicalize().instructions(),
+            [
+                Instruction::Call(2),
+                Instruction::Halt,
+                Instruction::Clear(0, 3),
+                Instruction::Return
+            ]
+        );
+    }
+
+    #[test]
+    fn is_isomorphic() {
+        let program = Program::new(
+            [
+                Instruction::AddConst(3, 4, 2),
+                Instruction::Halt,
+                Instruction::Choose(4, 1),
+                Instruction::Purged,
+                Instruction::Decrement(3, 5, 1),
+                Instruction::Swap(5, 3, 4),
+            ]
+            .iter()
+            .copied(),
+        );
+        let other = Program::new(
+            [
+                Instruction::AddConst(0, 4, 1),
+                Instruction::Choose(9, 2),
+                Instruction::Decrement(0, 3, 7),
+                Instruction::Swap(0, 1, 2),
+            ]
+            .iter()
+            .copied(),
+        );
+        assert!(program.is_isomorphic(&other));
+        assert!(other.is_isomorphic(&program));
+        assert!(program.canonicalize().is_isomorphic(&program));
+
+        let mut changed = other.clone();
+        changed
+            .set_instruction(0, Instruction::AddConst(0, 5, 1))
+            .unwrap();
+        assert!(!program.is_isomorphic(&changed));
+        // `$0` can't be used for both registers.
+        changed
+            .set_instruction(0, Instruction::AddConst(0, 4, 1))
+            .unwrap();
+        changed
+            .set_instruction(3, Instruction::Swap(0, 0, 2))
+            .unwrap();
+        assert!(!program.is_isomorphic(&changed));
+        // The loop no longer returns to the `Decrement`.
+        changed
+            .set_instruction(3, Instruction::Swap(0, 1, 1))
+            .unwrap();
+        assert!(!program.is_isomorphic(&changed));
+
+        let calls = |first| {
+            Program::new(
+                [
+                    Instruction::Call(first),
+                    Instruction::Halt,
+                    Instruction::Return,
+                    Instruction::Clear(0, 2),
+                ]
+                .iter()
+                .copied(),
+            )
+        };
+        assert!(calls(2).is_isomorphic(&calls(2)));
+        assert!(!calls(2).is_isomorphic(&calls(3)));
+    }
+}
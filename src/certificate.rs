@@ -0,0 +1,426 @@
+use crate::{
+    verifier, BackwardCertificate, Congruence, CyclerCertificate, Domain, Interval, Invariants,
+    Program, Sign, TranslatedCyclerCertificate,
+};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// The possible register values at each reachable position of a program,
+/// such that all halting instructions are unreachable.
+///
+/// Positions without a state are unreachable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvariantCertificate<D> {
+    pub states: BTreeMap<u16, BTreeMap<u8, D>>,
+}
+
+impl<D: Domain> From<&Invariants<D>> for InvariantCertificate<D> {
+    fn from(invariants: &Invariants<D>) -> InvariantCertificate<D> {
+        InvariantCertificate {
+            states: invariants
+                .reachable()
+                .map(|(at, state)| (at, state.clone()))
+                .collect(),
+        }
+    }
+}
+
+/// A proof that a program never halts when starting with all registers at zero.
+///
+/// Certificates are stored as text, see the [`fmt::Display`] implementation. The first
+/// line names the kind of the certificate, followed by its parameters:
+///
+/// - `cycler <start> <period>`, see [`CyclerCertificate`].
+/// - `translated-cycler <start> <period> $<register> <shift>`,
+///   see [`TranslatedCyclerCertificate`].
+/// - `backward`, see [`BackwardCertificate`], followed by one line for each set of
+///   configurations with its position and the signs of some registers, e.g. `3 $0=0 $2>0`.
+/// - `intervals` and `congruences`, followed by one line for each reachable position with
+///   the possible values of all registers, e.g. `3 $0=0..=5 $1=2..` or `3 $0=1%2 $1=4`.
+///   The residue comes first for congruences, and constants are written as a single number.
+///
+/// Empty lines and everything after a `#` are ignored. Certificates are checked
+/// by the [`verifier`], which does not depend on the deciders creating them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Certificate {
+    Cycler(CyclerCertificate),
+    TranslatedCycler(TranslatedCyclerCertificate),
+    Backward(BackwardCertificate),
+    Intervals(InvariantCertificate<Interval>),
+    Congruences(InvariantCertificate<Congruence>),
+}
+
+impl Certificate {
+    /// Checks that this certificate proves that `program` never halts, see [`verifier::verify`].
+    pub fn verify(&self, program: &Program) -> bool {
+        verifier::verify(program, self)
+    }
+}
+
+/// The reason why parsing a [`Certificate`] failed, see [`CertificateError`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CertificateErrorKind {
+    /// The text does not contain a certificate.
+    Empty,
+    /// The first line does not start with a known kind of certificate.
+    UnknownKind(String),
+    /// The token is not valid at this point.
+    InvalidToken(String),
+    /// The line ends before all parameters were given.
+    MissingToken,
+    /// The kind of certificate does not have any additional lines.
+    UnexpectedLine,
+    /// An invariant contains the same position more than once.
+    DuplicatePosition(u16),
+}
+
+/// An error returned when parsing a [`Certificate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertificateError {
+    /// The line causing the error, starting at `1`.
+    pub line: usize,
+    pub kind: CertificateErrorKind,
+}
+
+impl fmt::Display for CertificateErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CertificateErrorKind::Empty => f.write_str("missing certificate"),
+            CertificateErrorKind::UnknownKind(kind) => {
+                write!(f, "unknown kind of certificate `{}`", kind)
+            }
+            CertificateErrorKind::InvalidToken(token) => write!(f, "invalid token `{}`", token),
+            CertificateErrorKind::MissingToken => f.write_str("unexpected end of line"),
+            CertificateErrorKind::UnexpectedLine => f.write_str("unexpected line"),
+            CertificateErrorKind::DuplicatePosition(at) => {
+                write!(f, "position {} is listed more than once", at)
+            }
+        }
+    }
+}
+
+impl fmt::Display for CertificateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+impl Error for CertificateError {}
+
+fn write_states<D>(
+    f: &mut fmt::Formatter<'_>,
+    certificate: &InvariantCertificate<D>,
+    value: impl Fn(&D) -> String,
+) -> fmt::Result {
+    for (at, state) in &certificate.states {
+        write!(f, "\n{}", at)?;
+        for (reg, d) in state {
+            write!(f, " ${}={}", reg, value(d))?;
+        }
+    }
+    Ok(())
+}
+
+impl fmt::Display for Certificate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Certificate::Cycler(c) => write!(f, "cycler {} {}", c.start, c.period),
+            Certificate::TranslatedCycler(c) => write!(
+                f,
+                "translated-cycler {} {} ${} {}",
+                c.start, c.period, c.register, c.shift
+            ),
+            Certificate::Backward(c) => {
+                f.write_str("backward")?;
+                for (at, constraints) in &c.states {
+                    write!(f, "\n{}", at)?;
+                    for (reg, sign) in constraints {
+                        match sign {
+                            Sign::Zero => write!(f, " ${}=0", reg)?,
+                            Sign::Positive => write!(f, " ${}>0", reg)?,
+                        }
+                    }
+                }
+                Ok(())
+            }
+            Certificate::Intervals(c) => {
+                f.write_str("intervals")?;
+                write_states(f, c, |interval| match interval.hi {
+                    Some(hi) => format!("{}..={}", interval.lo, hi),
+                    None => format!("{}..", interval.lo),
+                })
+            }
+            Certificate::Congruences(c) => {
+                f.write_str("congruences")?;
+                write_states(f, c, |congruence| match congruence.modulus() {
+                    0 => congruence.residue().to_string(),
+                    modulus => format!("{}%{}", congruence.residue(), modulus),
+                })
+            }
+        }
+    }
+}
+
+fn parse_number<T: FromStr>(token: &str) -> Result<T, CertificateErrorKind> {
+    token
+        .parse()
+        .map_err(|_| CertificateErrorKind::InvalidToken(token.to_owned()))
+}
+
+/// Splits a token like `$3=0` into the register and the rest after the separator.
+fn parse_assignment<'a>(token: &'a str, separators: &[char]) -> Option<(u8, char, &'a str)> {
+    let rest = token.strip_prefix('$')?;
+    let split = rest.find(separators)?;
+    let reg = rest[..split].parse().ok()?;
+    let separator = rest[split..].chars().next()?;
+    Some((reg, separator, &rest[split + 1..]))
+}
+
+fn parse_interval(value: &str) -> Option<Interval> {
+    match value.split_once("..=") {
+        Some((lo, hi)) => {
+            let interval = Interval {
+                lo: lo.parse().ok()?,
+                hi: Some(hi.parse().ok()?),
+            };
+            Some(interval).filter(|interval| interval.hi >= Some(interval.lo))
+        }
+        None => Some(Interval {
+            lo: value.strip_suffix("..")?.parse().ok()?,
+            hi: None,
+        }),
+    }
+}
+
+fn parse_congruence(value: &str) -> Option<Congruence> {
+    match value.split_once('%') {
+        Some((residue, modulus)) => {
+            let modulus = modulus.parse().ok().filter(|&modulus| modulus != 0)?;
+            Congruence::new(modulus, residue.parse().ok()?)
+        }
+        None => Congruence::new(0, value.parse().ok()?),
+    }
+}
+
+/// Parses the lines of an invariant, pairs of a position and the values of all registers.
+fn parse_states<'a, D>(
+    lines: impl Iterator<Item = (usize, Vec<&'a str>)>,
+    value: impl Fn(&str) -> Option<D>,
+) -> Result<InvariantCertificate<D>, CertificateError> {
+    let mut states = BTreeMap::new();
+    for (line, tokens) in lines {
+        let error = |kind| CertificateError { line, kind };
+        let at = parse_number(tokens[0]).map_err(error)?;
+        let mut state = BTreeMap::new();
+        for &token in &tokens[1..] {
+            let invalid = || error(CertificateErrorKind::InvalidToken(token.to_owned()));
+            let (reg, _, rest) = parse_assignment(token, &['=']).ok_or_else(invalid)?;
+            state.insert(reg, value(rest).ok_or_else(invalid)?);
+        }
+        if states.insert(at, state).is_some() {
+            return Err(error(CertificateErrorKind::DuplicatePosition(at)));
+        }
+    }
+    Ok(InvariantCertificate { states })
+}
+
+impl FromStr for Certificate {
+    type Err = CertificateError;
+
+    fn from_str(s: &str) -> Result<Certificate, CertificateError> {
+        let mut lines = s.lines().enumerate().filter_map(|(index, line)| {
+            let line = line.split('#').next().unwrap();
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            Some((index + 1, tokens)).filter(|(_, tokens)| !tokens.is_empty())
+        });
+        let (line, header) = lines.next().ok_or(CertificateError {
+            line: 1,
+            kind: CertificateErrorKind::Empty,
+        })?;
+        let error = |kind| CertificateError { line, kind };
+        let param = |index: usize| -> Result<&str, CertificateError> {
+            header
+                .get(index)
+                .copied()
+                .ok_or_else(|| error(CertificateErrorKind::MissingToken))
+        };
+        let number = |index| parse_number(param(index)?).map_err(error);
+        let params = match header[0] {
+            "cycler" => 3,
+            "translated-cycler" => 5,
+            "backward" | "intervals" | "congruences" => 1,
+            kind => return Err(error(CertificateErrorKind::UnknownKind(kind.to_owned()))),
+        };
+        if let Some(&token) = header.get(params) {
+            return Err(error(CertificateErrorKind::InvalidToken(token.to_owned())));
+        }
+
+        let certificate = match header[0] {
+            "cycler" => Certificate::Cycler(CyclerCertificate {
+                start: number(1)?,
+                period: number(2)?,
+            }),
+            "translated-cycler" => {
+                let register = param(3)?;
+                Certificate::TranslatedCycler(TranslatedCyclerCertificate {
+                    start: number(1)?,
+                    period: number(2)?,
+                    register: register
+                        .strip_prefix('$')
+                        .and_then(|reg| reg.parse().ok())
+                        .ok_or_else(|| {
+                            error(CertificateErrorKind::InvalidToken(register.to_owned()))
+                        })?,
+                    shift: number(4)?,
+                })
+            }
+            "backward" => {
+                let mut states = Vec::new();
+                for (line, tokens) in lines {
+                    let error = |kind| CertificateError { line, kind };
+                    let at = parse_number(tokens[0]).map_err(error)?;
+                    let mut constraints = BTreeMap::new();
+                    for &token in &tokens[1..] {
+                        let sign = match parse_assignment(token, &['=', '>']) {
+                            Some((reg, '=', "0")) => (reg, Sign::Zero),
+                            Some((reg, '>', "0")) => (reg, Sign::Positive),
+                            _ => {
+                                return Err(error(CertificateErrorKind::InvalidToken(
+                                    token.to_owned(),
+                                )))
+                            }
+                        };
+                        constraints.insert(sign.0, sign.1);
+                    }
+                    states.push((at, constraints));
+                }
+                return Ok(Certificate::Backward(BackwardCertificate { states }));
+            }
+            "intervals" => return parse_states(lines, parse_interval).map(Certificate::Intervals),
+            "congruences" => {
+                return parse_states(lines, parse_congruence).map(Certificate::Congruences)
+            }
+            _ => unreachable!(),
+        };
+        match lines.next() {
+            Some((line, _)) => Err(CertificateError {
+                line,
+                kind: CertificateErrorKind::UnexpectedLine,
+            }),
+            None => Ok(certificate),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Instruction;
+
+    #[test]
+    fn text_format() {
+        let unreachable = Program::new(
+            [
+                Instruction::Increment(0, 1),
+                Instruction::BranchZero(1, 0, 2),
+                Instruction::Halt,
+            ]
+            .iter()
+            .copied(),
+        );
+        let certificates = [
+            Certificate::Cycler(CyclerCertificate {
+                start: 3,
+                period: 4,
+            }),
+            Certificate::TranslatedCycler(TranslatedCyclerCertificate {
+                start: 0,
+                period: 1,
+                register: 7,
+                shift: 2,
+            }),
+            Certificate::Backward(unreachable.find_backward_proof(100).unwrap()),
+            Certificate::Intervals((&unreachable.intervals(None)).into()),
+            Certificate::Congruences((&unreachable.congruences(None)).into()),
+        ];
+        for certificate in &certificates {
+            let text = certificate.to_string();
+            assert_eq!(
+                text.parse::<Certificate>().as_ref(),
+                Ok(certificate),
+                "{}",
+                text
+            );
+        }
+        assert_eq!(certificates[1].to_string(), "translated-cycler 0 1 $7 2");
+        assert_eq!(
+            certificates[3].to_string(),
+            "intervals\n0 $0=0.. $1=0..=0\n1 $0=1.. $1=0..=0"
+        );
+        let text = "# A comment\nbackward\n\n3\n2 # Halt\n1 $1>0 $0=0\n";
+        match text.parse() {
+            Ok(Certificate::Backward(backward)) => {
+                assert_eq!(backward.states.len(), 3);
+                assert_eq!(
+                    backward.states[2].1.iter().collect::<Vec<_>>(),
+                    [(&0, &Sign::Zero), (&1, &Sign::Positive)]
+                );
+            }
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(
+            "congruences\n0 $0=1%3 $1=5".parse::<Certificate>(),
+            Ok(Certificate::Congruences(InvariantCertificate {
+                states: std::iter::once((
+                    0,
+                    [
+                        (0, Congruence::new(3, 1).unwrap()),
+                        (1, Congruence::new(0, 5).unwrap())
+                    ]
+                    .iter()
+                    .copied()
+                    .collect()
+                ))
+                .collect()
+            }))
+        );
+
+        let error = |text: &str| text.parse::<Certificate>().unwrap_err();
+        let kind = |line, kind| CertificateError { line, kind };
+        assert_eq!(error("\n# nothing"), kind(1, CertificateErrorKind::Empty));
+        assert_eq!(
+            error("\nloop 1 2"),
+            kind(2, CertificateErrorKind::UnknownKind("loop".to_owned()))
+        );
+        assert_eq!(
+            error("cycler 1"),
+            kind(1, CertificateErrorKind::MissingToken)
+        );
+        assert_eq!(
+            error("cycler 1 2 3"),
+            kind(1, CertificateErrorKind::InvalidToken("3".to_owned()))
+        );
+        assert_eq!(
+            error("cycler 1 2\n0"),
+            kind(2, CertificateErrorKind::UnexpectedLine)
+        );
+        assert_eq!(
+            error("translated-cycler 1 2 r0 1"),
+            kind(1, CertificateErrorKind::InvalidToken("r0".to_owned()))
+        );
+        assert_eq!(
+            error("backward\n0 $0>1"),
+            kind(2, CertificateErrorKind::InvalidToken("$0>1".to_owned()))
+        );
+        assert_eq!(
+            error("intervals\n0 $0=3..=2"),
+            kind(2, CertificateErrorKind::InvalidToken("$0=3..=2".to_owned()))
+        );
+        assert_eq!(
+            error("congruences\n0 $0=0\n0 $0=1%2"),
+            kind(3, CertificateErrorKind::DuplicatePosition(0))
+        );
+    }
+}
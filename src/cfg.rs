@@ -0,0 +1,470 @@
+//! The control-flow graph of a program, see [`ControlFlowGraph`].
+
+use crate::{Instruction, Program};
+use std::collections::BTreeSet;
+use std::ops::Range;
+
+/// A maximal sequence of instructions which are always executed one after another.
+///
+/// Only the first instruction of a block can be jumped to and only its last
+/// instruction can jump to anything other than the next instruction.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BasicBlock {
+    /// The position of the first instruction of the block.
+    pub start: u16,
+    /// The position after the last instruction of the block.
+    pub end: u16,
+}
+
+impl BasicBlock {
+    pub fn range(&self) -> Range<u16> {
+        self.start..self.end
+    }
+
+    /// The position of the last instruction of the block.
+    pub fn last(&self) -> u16 {
+        self.end - 1
+    }
+
+    pub fn len(&self) -> usize {
+        usize::from(self.end - self.start)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+/// A natural loop of a [`ControlFlowGraph`], see [`ControlFlowGraph::natural_loops`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loop {
+    /// The only entry of the loop, which dominates all nodes of the loop.
+    pub header: u16,
+    /// All nodes of the loop, including the `header`.
+    pub body: BTreeSet<u16>,
+    /// The registers which may be increased by an instruction of the loop.
+    pub increments: BTreeSet<u8>,
+    /// The registers which may be decreased by an instruction of the loop.
+    pub decrements: BTreeSet<u8>,
+}
+
+/// The registers which may be increased and decreased by `instruction`.
+fn effects(instruction: Instruction) -> (Vec<u8>, Vec<u8>) {
+    match instruction {
+        Instruction::Increment(reg, _) | Instruction::AddConst(reg, ..) => (vec![reg], vec![]),
+        Instruction::Decrement(reg, ..)
+        | Instruction::SubConst(reg, ..)
+        | Instruction::Clear(reg, _) => (vec![], vec![reg]),
+        Instruction::Transfer { src, dst, .. } if src != dst => (vec![dst], vec![src]),
+        Instruction::Copy {
+            src, dst, scratch, ..
+        } if src != dst && src != scratch && dst != scratch => (vec![src, dst], vec![scratch]),
+        Instruction::Swap(a, b, _) if a != b => (vec![a, b], vec![a, b]),
+        Instruction::Read(reg, _) => (vec![reg], vec![reg]),
+        _ => (vec![], vec![]),
+    }
+}
+
+/// The control-flow graph of a [`Program`].
+///
+/// Each stored instruction is a node, all jumps past the end of the program go to
+/// a single additional exit node at [`ControlFlowGraph::exit`], which stands for the
+/// implicit `Halt`s. A `Call` has an edge to its target and to the instruction after
+/// it, which is executed after returning, while `Return` has no successors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControlFlowGraph {
+    instructions: Vec<Instruction>,
+    successors: Vec<Vec<u16>>,
+    predecessors: Vec<Vec<u16>>,
+    blocks: Vec<BasicBlock>,
+    block_of: Vec<usize>,
+}
+
+impl ControlFlowGraph {
+    pub fn new(program: &Program) -> ControlFlowGraph {
+        let exit = program.len() as u16;
+        let node = |target: u16| target.min(exit);
+        let mut successors = Vec::with_capacity(program.len() + 1);
+        for (at, instruction) in program.iter() {
+            let mut succ: Vec<u16> = instruction.targets().map(node).collect();
+            if let Instruction::Call(_) = instruction {
+                succ.push(node(at.saturating_add(1)));
+            }
+            succ.dedup();
+            successors.push(succ);
+        }
+        successors.push(Vec::new());
+
+        let mut predecessors = vec![Vec::new(); successors.len()];
+        for (at, succ) in successors.iter().enumerate() {
+            for &target in succ {
+                predecessors[usize::from(target)].push(at as u16);
+            }
+        }
+
+        // A block starts at the entry, at every jump target which is not just the
+        // continuation of its only predecessor, and after every instruction which
+        // does not simply continue with the next one.
+        let continues = |at: usize| successors[at] == [at as u16 + 1];
+        let starts_block =
+            |at: usize| at == 0 || !continues(at - 1) || predecessors[at] != [at as u16 - 1];
+        let mut blocks = Vec::new();
+        let mut block_of = Vec::with_capacity(program.len());
+        for at in 0..program.len() {
+            if starts_block(at) {
+                blocks.push(BasicBlock {
+                    start: at as u16,
+                    end: at as u16,
+                });
+            }
+            blocks.last_mut().unwrap().end += 1;
+            block_of.push(blocks.len() - 1);
+        }
+
+        ControlFlowGraph {
+            instructions: program.instructions().to_vec(),
+            successors,
+            predecessors,
+            blocks,
+            block_of,
+        }
+    }
+
+    /// The number of nodes, including the exit node.
+    pub fn len(&self) -> usize {
+        self.successors.len()
+    }
+
+    /// Always `false`, as there is at least the exit node.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// The exit node, which stands for all jumps past the end of the program.
+    pub fn exit(&self) -> u16 {
+        (self.successors.len() - 1) as u16
+    }
+
+    /// The successors of the node `at`, in the order of the targets of its instruction.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `at` is not a node of this graph.
+    pub fn successors(&self, at: u16) -> &[u16] {
+        &self.successors[usize::from(at)]
+    }
+
+    /// The predecessors of the node `at` in increasing order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `at` is not a node of this graph.
+    pub fn predecessors(&self, at: u16) -> &[u16] {
+        &self.predecessors[usize::from(at)]
+    }
+
+    /// The basic blocks of the program, in order. The exit node is not part of any block.
+    pub fn blocks(&self) -> &[BasicBlock] {
+        &self.blocks
+    }
+
+    /// The index of the basic block containing the instruction at `at`.
+    pub fn block_of(&self, at: u16) -> Option<usize> {
+        self.block_of.get(usize::from(at)).copied()
+    }
+
+    /// The strongly connected components of this graph, in reverse topological
+    /// order: no component has an edge to a component which precedes it.
+    ///
+    /// The nodes of each component are sorted. A component can only be part
+    /// of a cycle if it has more than one node or its node jumps to itself.
+    pub fn strongly_connected_components(&self) -> Vec<Vec<u16>> {
+        // An iterative version of Tarjan's algorithm.
+        const UNVISITED: usize = usize::MAX;
+        let len = self.len();
+        let mut index = vec![UNVISITED; len];
+        let mut lowlink = vec![0; len];
+        let mut on_stack = vec![false; len];
+        let mut stack = Vec::new();
+        let mut components = Vec::new();
+        let mut next_index = 0;
+
+        for root in 0..len {
+            if index[root] != UNVISITED {
+                continue;
+            }
+            // The nodes currently being visited and the index of their next successor.
+            let mut visiting = vec![(root, 0)];
+            index[root] = next_index;
+            lowlink[root] = next_index;
+            next_index += 1;
+            stack.push(root);
+            on_stack[root] = true;
+            while let Some(&mut (node, ref mut next)) = visiting.last_mut() {
+                if let Some(&succ) = self.successors[node].get(*next) {
+                    *next += 1;
+                    let succ = usize::from(succ);
+                    if index[succ] == UNVISITED {
+                        index[succ] = next_index;
+                        lowlink[succ] = next_index;
+                        next_index += 1;
+                        stack.push(succ);
+                        on_stack[succ] = true;
+                        visiting.push((succ, 0));
+                    } else if on_stack[succ] {
+                        lowlink[node] = lowlink[node].min(index[succ]);
+                    }
+                    continue;
+                }
+
+                visiting.pop();
+                if let Some(&(parent, _)) = visiting.last() {
+                    lowlink[parent] = lowlink[parent].min(lowlink[node]);
+                }
+                if lowlink[node] == index[node] {
+                    let mut component = Vec::new();
+                    loop {
+                        let member = stack.pop().unwrap();
+                        on_stack[member] = false;
+                        component.push(member as u16);
+                        if member == node {
+                            break;
+                        }
+                    }
+                    component.sort_unstable();
+                    components.push(component);
+                }
+            }
+        }
+        components
+    }
+
+    /// The nodes reachable from the first instruction in reverse postorder.
+    fn reverse_postorder(&self) -> Vec<u16> {
+        let mut visited = vec![false; self.len()];
+        let mut postorder = Vec::with_capacity(self.len());
+        let mut visiting = vec![(0, 0)];
+        visited[0] = true;
+        while let Some(&mut (node, ref mut next)) = visiting.last_mut() {
+            match self.successors[usize::from(node)].get(*next) {
+                Some(&succ) => {
+                    *next += 1;
+                    if !visited[usize::from(succ)] {
+                        visited[usize::from(succ)] = true;
+                        visiting.push((succ, 0));
+                    }
+                }
+                None => {
+                    postorder.push(node);
+                    visiting.pop();
+                }
+            }
+        }
+        postorder.reverse();
+        postorder
+    }
+
+    /// The immediate dominator of each node, i.e. the closest node other than itself
+    /// which is part of every path from the first instruction to the node.
+    ///
+    /// This is `None` for the first instruction and all unreachable nodes.
+    pub fn immediate_dominators(&self) -> Vec<Option<u16>> {
+        // The algorithm of Cooper, Harvey and Kennedy, using the position in the
+        // reverse postorder to compare nodes.
+        let order = self.reverse_postorder();
+        let mut position = vec![usize::MAX; self.len()];
+        for (i, &node) in order.iter().enumerate() {
+            position[usize::from(node)] = i;
+        }
+        let mut idom: Vec<Option<u16>> = vec![None; self.len()];
+        idom[0] = Some(0);
+        let intersect = |idom: &[Option<u16>], mut a: u16, mut b: u16| {
+            while a != b {
+                while position[usize::from(a)] > position[usize::from(b)] {
+                    a = idom[usize::from(a)].unwrap();
+                }
+                while position[usize::from(b)] > position[usize::from(a)] {
+                    b = idom[usize::from(b)].unwrap();
+                }
+            }
+            a
+        };
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &node in &order[1..] {
+                let mut new = None;
+                for &pred in &self.predecessors[usize::from(node)] {
+                    if idom[usize::from(pred)].is_some() {
+                        new = Some(match new {
+                            None => pred,
+                            Some(new) => intersect(&idom, pred, new),
+                        });
+                    }
+                }
+                if new != idom[usize::from(node)] {
+                    idom[usize::from(node)] = new;
+                    changed = true;
+                }
+            }
+        }
+        idom[0] = None;
+        idom
+    }
+
+    /// The natural loops of this graph, sorted by their header.
+    ///
+    /// A natural loop consists of a header and all nodes which can reach a back
+    /// edge to the header without passing through the header, where a back edge
+    /// is an edge to a node which dominates its source. Loops with the same header
+    /// are merged, nested loops are returned separately.
+    pub fn natural_loops(&self) -> Vec<Loop> {
+        let idom = self.immediate_dominators();
+        let dominates = |header: u16, mut node: u16| loop {
+            if node == header {
+                return true;
+            }
+            match idom[usize::from(node)] {
+                Some(parent) => node = parent,
+                None => return false,
+            }
+        };
+
+        let mut loops: Vec<Loop> = Vec::new();
+        for (source, succ) in self.successors.iter().enumerate() {
+            let source = source as u16;
+            let reachable = source == 0 || idom[usize::from(source)].is_some();
+            for &header in succ {
+                if !reachable || !dominates(header, source) {
+                    continue;
+                }
+                let index = match loops.iter().position(|l| l.header == header) {
+                    Some(index) => index,
+                    None => {
+                        loops.push(Loop {
+                            header,
+                            body: Some(header).into_iter().collect(),
+                            increments: BTreeSet::new(),
+                            decrements: BTreeSet::new(),
+                        });
+                        loops.len() - 1
+                    }
+                };
+                let body = &mut loops[index].body;
+                let mut worklist = vec![source];
+                while let Some(node) = worklist.pop() {
+                    if body.insert(node) {
+                        worklist.extend(&self.predecessors[usize::from(node)]);
+                    }
+                }
+            }
+        }
+
+        for l in &mut loops {
+            for &node in &l.body {
+                if let Some(&instruction) = self.instructions.get(usize::from(node)) {
+                    let (increments, decrements) = effects(instruction);
+                    l.increments.extend(increments);
+                    l.decrements.extend(decrements);
+                }
+            }
+        }
+        loops.sort_by_key(|l| l.header);
+        loops
+    }
+}
+
+impl Program {
+    /// The control-flow graph of this program, see [`ControlFlowGraph`].
+    pub fn control_flow_graph(&self) -> ControlFlowGraph {
+        ControlFlowGraph::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn control_flow_graph() {
+        let program = Program::new(
+            [
+                Instruction::Increment(0, 1),
+                Instruction::Increment(0, 2),
+                Instruction::Decrement(1, 3, 7),
+                Instruction::Increment(2, 4),
+                Instruction::Jump(2),
+                Instruction::Call(2),
+                Instruction::Decrement(0, 6, 6),
+            ]
+            .iter()
+            .copied(),
+        );
+        let cfg = program.control_flow_graph();
+        assert_eq!(cfg.len(), 8);
+        assert_eq!(cfg.exit(), 7);
+        assert_eq!(cfg.successors(2), [3, 7]);
+        assert_eq!(cfg.successors(5), [2, 6]);
+        assert_eq!(cfg.successors(6), [6]);
+        assert!(cfg.successors(7).is_empty());
+        assert_eq!(cfg.predecessors(2), [1, 4, 5]);
+        assert_eq!(cfg.predecessors(6), [5, 6]);
+        assert_eq!(cfg.predecessors(7), [2]);
+
+        let blocks: Vec<_> = cfg.blocks().iter().map(BasicBlock::range).collect();
+        assert_eq!(blocks, [0..2, 2..3, 3..5, 5..6, 6..7]);
+        assert_eq!(cfg.block_of(4), Some(2));
+        assert_eq!(cfg.block_of(7), None);
+
+        let sccs = cfg.strongly_connected_components();
+        assert_eq!(
+            sccs,
+            [vec![7], vec![2, 3, 4], vec![1], vec![0], vec![6], vec![5]]
+        );
+
+        let empty = Program::empty().control_flow_graph();
+        assert_eq!(empty.exit(), 0);
+        assert!(empty.blocks().is_empty());
+    }
+
+    #[test]
+    fn loops() {
+        // $0 = $1 * $2, with the inner loop at 1..=3 and the restore loop at 4..=5.
+        let program = Program::new(
+            [
+                Instruction::Decrement(1, 1, 6),
+                Instruction::Decrement(2, 2, 4),
+                Instruction::Increment(0, 3),
+                Instruction::Increment(3, 1),
+                Instruction::Decrement(3, 5, 0),
+                Instruction::Increment(2, 4),
+            ]
+            .iter()
+            .copied(),
+        );
+        let cfg = program.control_flow_graph();
+        let idom = cfg.immediate_dominators();
+        assert_eq!(
+            idom,
+            [None, Some(0), Some(1), Some(2), Some(1), Some(4), Some(0)]
+        );
+
+        let loops = cfg.natural_loops();
+        let bodies: Vec<Vec<u16>> = loops
+            .iter()
+            .map(|l| l.body.iter().copied().collect())
+            .collect();
+        assert_eq!(bodies, [vec![0, 1, 2, 3, 4, 5], vec![1, 2, 3], vec![4, 5]]);
+        let inner = &loops[1];
+        assert_eq!(inner.header, 1);
+        assert_eq!(inner.increments.iter().copied().collect::<Vec<_>>(), [0, 3]);
+        assert_eq!(inner.decrements.iter().copied().collect::<Vec<_>>(), [2]);
+        let restore = &loops[2];
+        assert_eq!(restore.increments.iter().copied().collect::<Vec<_>>(), [2]);
+        assert_eq!(restore.decrements.iter().copied().collect::<Vec<_>>(), [3]);
+
+        let sccs = cfg.strongly_connected_components();
+        assert_eq!(sccs, [vec![6], vec![0, 1, 2, 3, 4, 5]]);
+    }
+}
@@ -0,0 +1,248 @@
+use crate::io::NoIo;
+use crate::{Machine, Program, StepResult};
+use std::convert::TryFrom;
+use std::fmt;
+
+/// The number of values of each residue class used to find a [`CollatzMap`].
+const SAMPLES: u64 = 4;
+
+fn gcd(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        let rest = a % b;
+        a = b;
+        b = rest;
+    }
+    a
+}
+
+/// The behavior of a [`CollatzMap`] for a single residue class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Piece {
+    /// Maps `x` to `(multiplier * x + offset) / divisor`, which is a whole number
+    /// for all values of the residue class. The fraction is fully reduced.
+    Affine {
+        multiplier: i128,
+        offset: i128,
+        divisor: u64,
+    },
+    /// The program halts.
+    Halts,
+}
+
+impl Piece {
+    /// The piece mapping `residue + modulus * k` to `slope * k + value`.
+    fn new(modulus: u64, residue: u64, slope: i128, value: i128) -> Piece {
+        // slope * (x - residue) / modulus + value
+        let multiplier = slope;
+        let offset = value * i128::from(modulus) - slope * i128::from(residue);
+        let common = gcd(
+            gcd(multiplier.unsigned_abs(), offset.unsigned_abs()),
+            u128::from(modulus),
+        );
+        let common = common as i128;
+        Piece::Affine {
+            multiplier: multiplier / common,
+            offset: offset / common,
+            divisor: (i128::from(modulus) / common) as u64,
+        }
+    }
+}
+
+impl fmt::Display for Piece {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (multiplier, offset, divisor) = match *self {
+            Piece::Affine {
+                multiplier,
+                offset,
+                divisor,
+            } => (multiplier, offset, divisor),
+            Piece::Halts => return f.write_str("halts"),
+        };
+        let mut numerator = match multiplier {
+            0 => String::new(),
+            1 => "x".to_owned(),
+            -1 => "-x".to_owned(),
+            multiplier => format!("{}x", multiplier),
+        };
+        match (numerator.is_empty(), offset) {
+            (true, offset) => numerator = offset.to_string(),
+            (false, 0) => {}
+            (false, offset) if offset < 0 => numerator += &format!(" - {}", -offset),
+            (false, offset) => numerator += &format!(" + {}", offset),
+        }
+        match divisor {
+            1 => f.write_str(&numerator),
+            divisor if numerator.contains(' ') => write!(f, "({})/{}", numerator, divisor),
+            divisor => write!(f, "{}/{}", numerator, divisor),
+        }
+    }
+}
+
+/// A piecewise affine map on the values of a register, see [`Program::collatz_map`].
+///
+/// The value `x` is mapped using the piece at index `x % modulus`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CollatzMap {
+    pub modulus: u64,
+    pub pieces: Vec<Piece>,
+}
+
+impl CollatzMap {
+    /// The image of `x`, or `None` if the program halts or the result is out of range.
+    pub fn apply(&self, x: u64) -> Option<u64> {
+        match self.pieces[(x % self.modulus) as usize] {
+            Piece::Affine {
+                multiplier,
+                offset,
+                divisor,
+            } => {
+                let numerator = multiplier.checked_mul(i128::from(x))?.checked_add(offset)?;
+                u64::try_from(numerator / i128::from(divisor)).ok()
+            }
+            Piece::Halts => None,
+        }
+    }
+}
+
+impl fmt::Display for CollatzMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (residue, piece) in self.pieces.iter().enumerate() {
+            if residue > 0 {
+                f.write_str("\n")?;
+            }
+            write!(f, "x = {} mod {}: {}", residue, self.modulus, piece)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs `program` from `at` with only `reg` set to `x` until it returns to `at` with all
+/// other registers at zero, returning the new value of `reg`, or `Some(None)` if it halts.
+fn iterate(program: &Program, at: u16, reg: u8, x: u64, max_steps: u64) -> Option<Option<u64>> {
+    let mut machine: Machine = Machine::new(program);
+    machine.set_ptr(at);
+    machine.set_register(reg, x);
+    for _ in 0..max_steps {
+        match machine.step_with_io(&mut NoIo) {
+            StepResult::Continued => {}
+            StepResult::Halted => return Some(None),
+            _ => return None,
+        }
+        let returned = machine.ptr() == at
+            && machine.call_stack().is_empty()
+            && machine
+                .registers()
+                .iter()
+                .enumerate()
+                .all(|(r, &value)| r == usize::from(reg) || value == 0);
+        if returned {
+            return Some(Some(*machine.get_register(reg)));
+        }
+    }
+    None
+}
+
+impl Program {
+    /// Tries to describe the effect of a loop at `at` on the register `reg` as a
+    /// generalized Collatz function: a map on the values of `reg` which is affine
+    /// on each residue class modulo some `modulus`.
+    ///
+    /// An iteration starts at `at` with all registers other than `reg` at zero and ends
+    /// once the program returns to `at` in such a configuration, or halts. For each
+    /// `modulus` up to `max_modulus`, each residue class is sampled using a few small
+    /// values, each running for at most `max_steps` steps, and the smallest modulus for
+    /// which all samples of each class either halt or fit an affine map is returned.
+    ///
+    /// The map is inferred from the samples and not proven to hold for all values.
+    /// `Read` instructions always read zero and the output of `Write` is ignored.
+    pub fn collatz_map(
+        &self,
+        at: u16,
+        reg: u8,
+        max_modulus: u64,
+        max_steps: u64,
+    ) -> Option<CollatzMap> {
+        'moduli: for modulus in 1..=max_modulus {
+            let mut pieces = Vec::new();
+            for residue in 0..modulus {
+                let mut images = Vec::new();
+                for k in 1..=SAMPLES {
+                    let x = k.checked_mul(modulus)?.checked_add(residue)?;
+                    images.push(iterate(self, at, reg, x, max_steps)?);
+                }
+                let piece = if images.iter().all(Option::is_none) {
+                    Piece::Halts
+                } else if let Some(images) = images.into_iter().collect::<Option<Vec<u64>>>() {
+                    let values: Vec<i128> = images.into_iter().map(i128::from).collect();
+                    let slope = values[1] - values[0];
+                    let value = values[0] - slope;
+                    let affine = (1..=SAMPLES)
+                        .zip(&values)
+                        .all(|(k, &y)| slope * i128::from(k) + value == y);
+                    if !affine {
+                        continue 'moduli;
+                    }
+                    Piece::new(modulus, residue, slope, value)
+                } else {
+                    continue 'moduli;
+                };
+                pieces.push(piece);
+            }
+            return Some(CollatzMap { modulus, pieces });
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Instruction;
+
+    #[test]
+    fn collatz_map() {
+        // Maps `2k` to `3k` and halts for odd values.
+        let program = Program::new(
+            [
+                Instruction::Decrement(0, 1, 3),
+                Instruction::Decrement(0, 2, 4),
+                Instruction::AddConst(1, 3, 0),
+                Instruction::Transfer {
+                    src: 1,
+                    dst: 0,
+                    then: 0,
+                },
+            ]
+            .iter()
+            .copied(),
+        );
+        let map = program.collatz_map(0, 0, 4, 1000).unwrap();
+        assert_eq!(map.modulus, 2);
+        assert_eq!(
+            map.pieces,
+            [
+                Piece::Affine {
+                    multiplier: 3,
+                    offset: 0,
+                    divisor: 2
+                },
+                Piece::Halts
+            ]
+        );
+        assert_eq!(map.to_string(), "x = 0 mod 2: 3x/2\nx = 1 mod 2: halts");
+        assert_eq!((map.apply(10), map.apply(11)), (Some(15), None));
+        assert_eq!(program.collatz_map(0, 0, 1, 1000), None);
+
+        let increment = Program::new([Instruction::Increment(0, 0)].iter().copied());
+        assert_eq!(
+            increment.collatz_map(0, 0, 3, 10).unwrap().to_string(),
+            "x = 0 mod 1: x + 1"
+        );
+        let loops = Program::new([Instruction::Increment(1, 0)].iter().copied());
+        assert_eq!(loops.collatz_map(0, 0, 3, 10), None);
+
+        assert_eq!(Piece::new(2, 1, 6, 4).to_string(), "3x + 1");
+        assert_eq!(Piece::new(3, 2, 2, -1).to_string(), "(2x - 7)/3");
+        assert_eq!(Piece::new(5, 0, 0, 7).to_string(), "7");
+    }
+}
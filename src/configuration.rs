@@ -0,0 +1,71 @@
+use crate::Counter;
+
+/// A canonical description of the state of a [`Machine`](crate::Machine).
+///
+/// Only the instruction pointer, the non-zero registers and the call stack are stored,
+/// so two machines with the same state always have equal configurations.
+/// This makes configurations suitable for hash sets, e.g. to detect cycles.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Configuration<C: Counter = u64> {
+    ptr: u16,
+    registers: Vec<(u8, C)>,
+    stack: Vec<u16>,
+}
+
+impl<C: Counter> Configuration<C> {
+    /// Creates a configuration from its sorted non-zero registers,
+    /// returning `None` if they are not sorted or contain zeros.
+    #[cfg(feature = "serde")]
+    pub(crate) fn from_parts(
+        ptr: u16,
+        registers: Vec<(u8, C)>,
+        stack: Vec<u16>,
+    ) -> Option<Configuration<C>> {
+        let sorted = registers.windows(2).all(|w| w[0].0 < w[1].0);
+        if sorted && registers.iter().all(|(_, value)| !value.is_zero()) {
+            Some(Configuration {
+                ptr,
+                registers,
+                stack,
+            })
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn new(ptr: u16, registers: &[C], stack: &[u16]) -> Configuration<C> {
+        let registers = registers
+            .iter()
+            .enumerate()
+            .filter(|(_, value)| !value.is_zero())
+            .map(|(reg, value)| (reg as u8, value.clone()))
+            .collect();
+        Configuration {
+            ptr,
+            registers,
+            stack: stack.to_vec(),
+        }
+    }
+
+    pub fn ptr(&self) -> u16 {
+        self.ptr
+    }
+
+    /// The non-zero registers, sorted by their register number.
+    pub fn registers(&self) -> &[(u8, C)] {
+        &self.registers
+    }
+
+    /// The value of `reg`, or `None` if it is zero.
+    pub fn get_register(&self, reg: u8) -> Option<&C> {
+        self.registers
+            .binary_search_by_key(&reg, |&(r, _)| r)
+            .ok()
+            .map(|i| &self.registers[i].1)
+    }
+
+    /// The return addresses of the call stack, starting with the outermost call.
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+}
@@ -0,0 +1,233 @@
+use crate::{Domain, Invariants, Program};
+use std::fmt;
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        let rest = a % b;
+        a = b;
+        b = rest;
+    }
+    a
+}
+
+/// The register values `v` with `v % modulus == residue`, where `residue < modulus`.
+///
+/// A `modulus` of zero is used for the single value `residue`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Congruence {
+    modulus: u64,
+    residue: u64,
+}
+
+impl Congruence {
+    /// Returns `None` if `residue` is not less than `modulus` and `modulus` is not zero.
+    pub fn new(modulus: u64, residue: u64) -> Option<Congruence> {
+        if modulus == 0 || residue < modulus {
+            Some(Congruence { modulus, residue })
+        } else {
+            None
+        }
+    }
+
+    pub fn modulus(&self) -> u64 {
+        self.modulus
+    }
+
+    pub fn residue(&self) -> u64 {
+        self.residue
+    }
+
+    pub fn contains(&self, value: u64) -> bool {
+        match self.modulus {
+            0 => value == self.residue,
+            modulus => value % modulus == self.residue,
+        }
+    }
+
+    /// The congruence `residue mod modulus`, reducing `residue` first.
+    fn reduced(modulus: u64, residue: u64) -> Congruence {
+        match modulus {
+            0 => Congruence::constant(residue),
+            modulus => Congruence {
+                modulus,
+                residue: residue % modulus,
+            },
+        }
+    }
+
+    fn value(&self) -> Option<u64> {
+        match self.modulus {
+            0 => Some(self.residue),
+            _ => None,
+        }
+    }
+}
+
+impl Domain for Congruence {
+    fn top() -> Congruence {
+        Congruence {
+            modulus: 1,
+            residue: 0,
+        }
+    }
+
+    fn constant(value: u64) -> Congruence {
+        Congruence {
+            modulus: 0,
+            residue: value,
+        }
+    }
+
+    fn join(self, other: Congruence) -> Congruence {
+        let difference = self.residue.max(other.residue) - self.residue.min(other.residue);
+        let modulus = gcd(gcd(self.modulus, other.modulus), difference);
+        Congruence::reduced(modulus, self.residue)
+    }
+
+    /// Only returns the exact intersection if one of the sets is a single value,
+    /// otherwise this is the set with the larger modulus.
+    fn meet(self, other: Congruence) -> Option<Congruence> {
+        if let Some(value) = self.value() {
+            return Some(self).filter(|_| other.contains(value));
+        } else if let Some(value) = other.value() {
+            return Some(other).filter(|_| self.contains(value));
+        }
+        let common = gcd(self.modulus, other.modulus);
+        if self.residue % common != other.residue % common {
+            None
+        } else if self.modulus >= other.modulus {
+            Some(self)
+        } else {
+            Some(other)
+        }
+    }
+
+    /// Sums of single values which overflow have any value.
+    fn add(self, other: Congruence) -> Congruence {
+        match gcd(self.modulus, other.modulus) {
+            0 => self
+                .residue
+                .checked_add(other.residue)
+                .map_or(Congruence::top(), Congruence::constant),
+            modulus => {
+                let sum =
+                    (u128::from(self.residue) + u128::from(other.residue)) % u128::from(modulus);
+                Congruence::reduced(modulus, sum as u64)
+            }
+        }
+    }
+
+    fn sub_const(self, n: u64) -> Option<Congruence> {
+        match self.modulus {
+            0 => self.residue.checked_sub(n).map(Congruence::constant),
+            modulus => {
+                let difference = u128::from(self.residue) + u128::from(modulus - n % modulus);
+                Some(Congruence::reduced(
+                    modulus,
+                    (difference % u128::from(modulus)) as u64,
+                ))
+            }
+        }
+    }
+
+    fn below(self, n: u64) -> Option<Congruence> {
+        match (self.value(), n) {
+            (_, 0) => None,
+            (Some(value), _) => Some(self).filter(|_| value < n),
+            (None, 1) => self.meet(Congruence::constant(0)),
+            (None, _) => Some(self),
+        }
+    }
+
+    fn refine_ge(a: Congruence, b: Congruence) -> Option<(Congruence, Congruence)> {
+        match (a.value(), b.value()) {
+            (Some(x), Some(y)) if x < y => None,
+            _ => Some((a, b)),
+        }
+    }
+
+    fn refine_lt(a: Congruence, b: Congruence) -> Option<(Congruence, Congruence)> {
+        match (a.value(), b.value()) {
+            (Some(x), Some(y)) if x >= y => None,
+            (_, Some(0)) => None,
+            _ => Some((a, b)),
+        }
+    }
+}
+
+impl fmt::Display for Congruence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.modulus {
+            0 => write!(f, "{}", self.residue),
+            modulus => write!(f, "{} mod {}", self.residue, modulus),
+        }
+    }
+}
+
+impl Program {
+    /// Computes the residues of each register at each position of this program, given the
+    /// possible initial values of `inputs` and assuming that all other registers start at zero.
+    ///
+    /// This uses [`Program::invariants`] with the [`Congruence`] domain, which finds
+    /// e.g. registers which are always even. Use the domain `(Interval, Congruence)`
+    /// to track both the range and the residue of registers.
+    pub fn congruences(
+        &self,
+        inputs: impl IntoIterator<Item = (u8, Congruence)>,
+    ) -> Invariants<Congruence> {
+        self.invariants(inputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Instruction, Interval};
+
+    #[test]
+    fn congruences() {
+        let even = Congruence::new(2, 0).unwrap();
+        let odd = Congruence::new(2, 1).unwrap();
+        assert_eq!(Congruence::new(2, 2), None);
+        assert_eq!(
+            Congruence::constant(4).join(Congruence::constant(10)),
+            Congruence::new(6, 4).unwrap()
+        );
+        assert_eq!(even.join(Congruence::constant(3)), Congruence::top());
+        assert_eq!(even.meet(odd), None);
+        assert_eq!(odd.add(odd), even);
+        assert_eq!(odd.sub_const(3), Some(even));
+        assert_eq!(Congruence::constant(2).sub_const(3), None);
+        assert_eq!(odd.below(1), None);
+        assert_eq!(Congruence::new(6, 4).unwrap().to_string(), "4 mod 6");
+
+        // Halts if $0 is odd, but $0 is always even.
+        let program = Program::new(
+            [
+                Instruction::AddConst(0, 2, 1),
+                Instruction::Copy {
+                    src: 0,
+                    dst: 1,
+                    scratch: 2,
+                    then: 2,
+                },
+                Instruction::Decrement(1, 3, 0),
+                Instruction::Decrement(1, 2, 4),
+                Instruction::HaltWith(1),
+            ]
+            .iter()
+            .copied(),
+        );
+        let congruences = program.congruences(None);
+        assert!(congruences.never_halts());
+        assert_eq!(congruences.bound(0), Some(even));
+        assert_eq!(congruences.register(3, 1), Some(odd));
+        assert!(!program.intervals(None).never_halts());
+        let both = program.invariants::<(Interval, Congruence)>(None);
+        assert!(both.never_halts());
+        assert_eq!(both.register(3, 1), Some((Interval::ALL, odd)));
+
+        // With odd inputs it may halt.
+        assert!(!program.congruences(Some((0, odd))).never_halts());
+    }
+}
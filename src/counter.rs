@@ -0,0 +1,317 @@
+use crate::OverflowPolicy;
+use std::convert::TryFrom;
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+use std::str::FromStr;
+
+/// The value stored in a single register of a [`Machine`](crate::Machine).
+///
+/// This is implemented for all unsigned integer types and, with the `bigint`
+/// feature, for `num_bigint::BigUint`, which never overflows. Other register
+/// types can be used by implementing this trait for them.
+///
+/// `Display` and `FromStr` are used for the `Read` and `Write` instructions of [`StdIo`](crate::StdIo).
+pub trait Counter: Clone + Debug + Display + FromStr + Eq + Ord + Hash {
+    fn zero() -> Self;
+
+    fn is_zero(&self) -> bool;
+
+    /// Adds one to `self`, returning `false` and leaving `self` unchanged
+    /// if this would overflow with [`OverflowPolicy::Checked`].
+    fn increment(&mut self, policy: OverflowPolicy) -> bool;
+
+    /// Subtracts one from `self`, returning `false` if `self` is zero.
+    fn decrement(&mut self) -> bool;
+
+    /// Adds `n` to `self`, returning `false` and leaving `self` unchanged
+    /// if this would overflow with [`OverflowPolicy::Checked`].
+    ///
+    /// The default implementation calls [`Counter::increment`] `n` times.
+    fn add_const(&mut self, n: u64, policy: OverflowPolicy) -> bool {
+        let mut value = self.clone();
+        for _ in 0..n {
+            if !value.increment(policy) {
+                return false;
+            }
+        }
+        *self = value;
+        true
+    }
+
+    /// Adds `other` to `self`, returning `false` and leaving `self` unchanged
+    /// if this would overflow with [`OverflowPolicy::Checked`].
+    ///
+    /// The default implementation calls [`Counter::increment`] once for each unit of `other`.
+    fn add_counter(&mut self, other: &Self, policy: OverflowPolicy) -> bool {
+        let mut value = self.clone();
+        let mut remaining = other.clone();
+        while remaining.decrement() {
+            if !value.increment(policy) {
+                return false;
+            }
+        }
+        *self = value;
+        true
+    }
+
+    /// Subtracts `n` from `self`, returning `false` and leaving `self`
+    /// unchanged if `self` is less than `n`.
+    ///
+    /// The default implementation calls [`Counter::decrement`] `n` times.
+    fn sub_const(&mut self, n: u64) -> bool {
+        let mut value = self.clone();
+        for _ in 0..n {
+            if !value.decrement() {
+                return false;
+            }
+        }
+        *self = value;
+        true
+    }
+
+    /// The value of `self` if it fits into a `u64`.
+    ///
+    /// This is used to skip loops in [`Machine::run_accelerated`](crate::Machine::run_accelerated).
+    /// The default implementation always returns `None`, which disables this.
+    fn to_u64(&self) -> Option<u64> {
+        None
+    }
+}
+
+macro_rules! impl_counter {
+    ($($t:ty),*) => {$(
+        impl Counter for $t {
+            fn zero() -> $t {
+                0
+            }
+
+            fn is_zero(&self) -> bool {
+                *self == 0
+            }
+
+            fn increment(&mut self, policy: OverflowPolicy) -> bool {
+                *self = match policy {
+                    OverflowPolicy::Checked => match self.checked_add(1) {
+                        Some(v) => v,
+                        None => return false,
+                    },
+                    OverflowPolicy::Saturating => self.saturating_add(1),
+                    OverflowPolicy::Wrapping => self.wrapping_add(1),
+                };
+                true
+            }
+
+            fn decrement(&mut self) -> bool {
+                match self.checked_sub(1) {
+                    Some(v) => {
+                        *self = v;
+                        true
+                    }
+                    None => false,
+                }
+            }
+
+            fn add_const(&mut self, n: u64, policy: OverflowPolicy) -> bool {
+                let converted = <$t>::try_from(n).ok();
+                *self = match policy {
+                    OverflowPolicy::Checked => {
+                        match converted.and_then(|n| self.checked_add(n)) {
+                            Some(v) => v,
+                            None => return false,
+                        }
+                    }
+                    OverflowPolicy::Saturating => {
+                        converted.map_or(<$t>::MAX, |n| self.saturating_add(n))
+                    }
+                    OverflowPolicy::Wrapping => self.wrapping_add(n as $t),
+                };
+                true
+            }
+
+            fn add_counter(&mut self, other: &$t, policy: OverflowPolicy) -> bool {
+                *self = match policy {
+                    OverflowPolicy::Checked => match self.checked_add(*other) {
+                        Some(v) => v,
+                        None => return false,
+                    },
+                    OverflowPolicy::Saturating => self.saturating_add(*other),
+                    OverflowPolicy::Wrapping => self.wrapping_add(*other),
+                };
+                true
+            }
+
+            fn sub_const(&mut self, n: u64) -> bool {
+                match <$t>::try_from(n).ok().and_then(|n| self.checked_sub(n)) {
+                    Some(v) => {
+                        *self = v;
+                        true
+                    }
+                    None => false,
+                }
+            }
+
+            fn to_u64(&self) -> Option<u64> {
+                u64::try_from(*self).ok()
+            }
+        }
+    )*};
+}
+
+impl_counter!(u8, u16, u32, u64, u128, usize);
+
+#[cfg(feature = "bigint")]
+impl Counter for num_bigint::BigUint {
+    fn zero() -> num_bigint::BigUint {
+        num_bigint::BigUint::default()
+    }
+
+    fn is_zero(&self) -> bool {
+        self.bits() == 0
+    }
+
+    fn increment(&mut self, _: OverflowPolicy) -> bool {
+        *self += 1u32;
+        true
+    }
+
+    fn decrement(&mut self) -> bool {
+        if self.is_zero() {
+            false
+        } else {
+            *self -= 1u32;
+            true
+        }
+    }
+
+    fn add_const(&mut self, n: u64, _: OverflowPolicy) -> bool {
+        *self += n;
+        true
+    }
+
+    fn add_counter(&mut self, other: &num_bigint::BigUint, _: OverflowPolicy) -> bool {
+        *self += other;
+        true
+    }
+
+    fn sub_const(&mut self, n: u64) -> bool {
+        if *self < num_bigint::BigUint::from(n) {
+            false
+        } else {
+            *self -= n;
+            true
+        }
+    }
+
+    fn to_u64(&self) -> Option<u64> {
+        u64::try_from(self).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Instruction, Machine, Program, RunOutcome};
+
+    /// A counter which only tracks whether it is zero or not.
+    #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    struct Flag(bool);
+
+    impl std::fmt::Display for Flag {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{}", self.0 as u8)
+        }
+    }
+
+    impl FromStr for Flag {
+        type Err = std::num::ParseIntError;
+
+        fn from_str(s: &str) -> Result<Flag, Self::Err> {
+            s.parse::<u64>().map(|v| Flag(v != 0))
+        }
+    }
+
+    impl Counter for Flag {
+        fn zero() -> Flag {
+            Flag(false)
+        }
+
+        fn is_zero(&self) -> bool {
+            !self.0
+        }
+
+        fn increment(&mut self, _: OverflowPolicy) -> bool {
+            self.0 = true;
+            true
+        }
+
+        fn decrement(&mut self) -> bool {
+            std::mem::replace(&mut self.0, false)
+        }
+    }
+
+    #[test]
+    fn small_counters() {
+        let program = Program::new([Instruction::Increment(0, 0)].iter().copied());
+        let mut prog: Machine<u8> = Machine::new(&program);
+        assert_eq!(prog.run(1000), RunOutcome::Overflow { at: 0, reg: 0 });
+        assert_eq!(*prog.get_register(0), u8::MAX);
+
+        let mut prog: Machine<u8> =
+            Machine::new(&program).with_overflow_policy(OverflowPolicy::Wrapping);
+        assert_eq!(prog.run(1000), RunOutcome::OutOfFuel);
+        assert_eq!(*prog.get_register(0), (1000 % 256) as u8);
+    }
+
+    #[test]
+    fn user_counter() {
+        let program = Program::new(
+            [
+                Instruction::Increment(0, 1),
+                Instruction::Increment(0, 2),
+                Instruction::Decrement(0, 3, 4),
+                Instruction::Decrement(0, 3, 4),
+                Instruction::Halt,
+            ]
+            .iter()
+            .copied(),
+        );
+        let mut prog: Machine<Flag> = Machine::new(&program);
+        assert_eq!(prog.run(u64::MAX), RunOutcome::Halted { steps: 4, code: 0 });
+        assert_eq!(*prog.get_register(0), Flag(false));
+    }
+
+    #[test]
+    fn add_sub_const() {
+        let mut value = 250u8;
+        assert!(!value.add_const(6, OverflowPolicy::Checked));
+        assert_eq!(value, 250);
+        assert!(value.add_const(5, OverflowPolicy::Checked));
+        assert_eq!(value, 255);
+        assert!(!value.add_const(1 << 40, OverflowPolicy::Checked));
+        assert!(value.add_const(1 << 40, OverflowPolicy::Saturating));
+        assert_eq!(value, 255);
+        assert!(value.add_const(257, OverflowPolicy::Wrapping));
+        assert_eq!(value, 0);
+
+        assert!(!value.sub_const(1));
+        value = 7;
+        assert!(!value.sub_const(1 << 40));
+        assert!(value.sub_const(7));
+        assert_eq!(value, 0);
+
+        let mut value = 200u8;
+        assert!(!value.add_counter(&56, OverflowPolicy::Checked));
+        assert!(value.add_counter(&55, OverflowPolicy::Checked));
+        assert_eq!(value, 255);
+
+        let mut flag = Flag(false);
+        assert!(flag.add_counter(&Flag(true), OverflowPolicy::Checked));
+        assert_eq!(flag, Flag(true));
+        flag = Flag(false);
+        assert!(flag.add_const(3, OverflowPolicy::Checked));
+        assert!(!flag.sub_const(2));
+        assert_eq!(flag, Flag(true));
+        assert!(flag.sub_const(1));
+        assert_eq!(flag, Flag(false));
+    }
+}
@@ -0,0 +1,366 @@
+use crate::{Configuration, Instruction, Machine, Program, RunOutcome, StepResult};
+use std::collections::{HashMap, VecDeque};
+
+/// A proof that a program never halts when starting with all registers at zero,
+/// see [`Program::find_cycle`].
+///
+/// The configuration after `start` steps is the same as after `start + period` steps,
+/// so the machine repeats the same `period` steps forever.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CyclerCertificate {
+    pub start: u64,
+    pub period: u64,
+}
+
+impl CyclerCertificate {
+    /// Checks this certificate by running `program` for `start + period` steps.
+    pub fn verify(&self, program: &Program) -> bool {
+        if self.period == 0 || !is_deterministic(program) {
+            return false;
+        }
+
+        let mut machine: Machine = Machine::new(program);
+        if machine.run(self.start) != RunOutcome::OutOfFuel {
+            return false;
+        }
+        let configuration = machine.configuration();
+        machine.run(self.period) == RunOutcome::OutOfFuel
+            && machine.configuration() == configuration
+    }
+}
+
+/// A proof that a program never halts when starting with all registers at zero and
+/// using unbounded registers, see [`Program::find_translated_cycle`].
+///
+/// The configuration after `start + period` steps is the same as after `start` steps,
+/// except that `register` is larger by `shift`. The steps in between only increment,
+/// decrement and test `register` while it is nonzero, so they repeat forever while
+/// `register` keeps growing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TranslatedCyclerCertificate {
+    pub start: u64,
+    pub period: u64,
+    pub register: u8,
+    pub shift: u64,
+}
+
+impl TranslatedCyclerCertificate {
+    /// Checks this certificate by running `program` for `start + period` steps.
+    pub fn verify(&self, program: &Program) -> bool {
+        if self.period == 0 || self.shift == 0 || !is_deterministic(program) {
+            return false;
+        }
+
+        let mut machine: Machine = Machine::new(program);
+        if machine.run(self.start) != RunOutcome::OutOfFuel {
+            return false;
+        }
+        let configuration = machine.configuration();
+        replay(&mut machine, self.period, self.register)
+            && shifted_register(&configuration, &machine.configuration())
+                == Some((self.register, self.shift))
+    }
+}
+
+/// Executes `steps` steps of `machine`, returning `false` if the branches taken
+/// by these steps, or the values written to registers other than `reg`, could
+/// depend on the value of `reg`.
+fn replay(machine: &mut Machine, steps: u64, reg: u8) -> bool {
+    for _ in 0..steps {
+        let value = *machine.get_register(reg);
+        let independent = match machine.program().instruction(machine.ptr()) {
+            Instruction::Decrement(r, ..) | Instruction::BranchZero(r, ..) if r == reg => value > 0,
+            Instruction::SubConst(r, n, ..) if r == reg => value >= n,
+            Instruction::Clear(r, _) => r != reg,
+            Instruction::Transfer { src, .. } => src != reg,
+            Instruction::Copy { src, scratch, .. } => src != reg && scratch != reg,
+            Instruction::Swap(a, b, _) | Instruction::Compare(a, b, ..) => a != reg && b != reg,
+            _ => true,
+        };
+        if !independent || machine.step() != StepResult::Continued {
+            return false;
+        }
+    }
+    true
+}
+
+/// The only register which is larger in `new` than in `old`,
+/// if all other parts of the configurations are equal.
+fn shifted_register(old: &Configuration, new: &Configuration) -> Option<(u8, u64)> {
+    if old.ptr() != new.ptr() || old.stack() != new.stack() {
+        return None;
+    }
+    let mut shifted = None;
+    for reg in new.registers().iter().map(|&(reg, _)| reg) {
+        let before = old.get_register(reg).copied().unwrap_or(0);
+        let after = new.get_register(reg).copied().unwrap_or(0);
+        if after != before {
+            if after < before || shifted.is_some() {
+                return None;
+            }
+            shifted = Some((reg, after - before));
+        }
+    }
+    let removed = old
+        .registers()
+        .iter()
+        .any(|&(reg, _)| new.get_register(reg).is_none());
+    if removed {
+        None
+    } else {
+        shifted
+    }
+}
+
+/// Whether the configuration of a machine running `program` determines its future,
+/// which is not the case for `Choose`, `Random` and input or output.
+pub(crate) fn is_deterministic(program: &Program) -> bool {
+    program.iter().all(|(_, instruction)| {
+        !matches!(
+            instruction,
+            Instruction::Read(..)
+                | Instruction::Write(..)
+                | Instruction::Choose(..)
+                | Instruction::Random(..)
+        )
+    })
+}
+
+impl Program {
+    /// Tries to prove that this program never halts when starting with all registers
+    /// at zero by finding a configuration which repeats.
+    ///
+    /// Runs the program for at most `max_steps` steps while remembering the configurations
+    /// of the last `history` steps, so this finds all cycles whose period is at most
+    /// `history` and which start within `max_steps - period` steps. The certificate
+    /// always uses the first step of the cycle as its `start`.
+    ///
+    /// Returns `None` if the program stops or no cycle is found. Programs which use
+    /// `Choose`, `Random`, `Read` or `Write` are never considered to be cycling.
+    pub fn find_cycle(&self, max_steps: u64, history: usize) -> Option<CyclerCertificate> {
+        if history == 0 || !is_deterministic(self) {
+            return None;
+        }
+
+        let mut machine: Machine = Machine::new(self);
+        let mut seen: HashMap<Configuration, u64> = HashMap::new();
+        let mut order = VecDeque::with_capacity(history.min(1 << 16));
+        for step in 0..=max_steps {
+            let configuration = machine.configuration();
+            if let Some(&start) = seen.get(&configuration) {
+                return Some(CyclerCertificate {
+                    start,
+                    period: step - start,
+                });
+            }
+            if order.len() == history {
+                seen.remove(&order.pop_front().unwrap());
+            }
+            order.push_back(configuration.clone());
+            seen.insert(configuration, step);
+
+            if step == max_steps || machine.step() != StepResult::Continued {
+                break;
+            }
+        }
+        None
+    }
+
+    /// Tries to prove that this program never halts when starting with all registers
+    /// at zero by finding a configuration which repeats up to a larger value of a
+    /// single register. This assumes unbounded registers, with fixed size registers
+    /// the program eventually stops with [`RunOutcome::Overflow`].
+    ///
+    /// Like [`Program::find_cycle`], this runs the program for at most `max_steps`
+    /// steps while remembering the configurations of the last `history` steps.
+    /// Whenever the current configuration only differs from a remembered one by a
+    /// larger value of some register, the steps in between are checked to not depend
+    /// on the value of this register, see [`TranslatedCyclerCertificate`].
+    ///
+    /// Returns `None` if the program stops or no such cycle is found, and for
+    /// programs which use `Choose`, `Random`, `Read` or `Write`.
+    pub fn find_translated_cycle(
+        &self,
+        max_steps: u64,
+        history: usize,
+    ) -> Option<TranslatedCyclerCertificate> {
+        if history == 0 || !is_deterministic(self) {
+            return None;
+        }
+
+        let mut machine: Machine = Machine::new(self);
+        let mut by_ptr: HashMap<u16, VecDeque<(u64, Configuration)>> = HashMap::new();
+        let mut order = VecDeque::with_capacity(history.min(1 << 16));
+        for step in 0..=max_steps {
+            let configuration = machine.configuration();
+            let candidates = by_ptr.entry(configuration.ptr()).or_default();
+            for (start, old) in candidates.iter() {
+                let (register, shift) = match shifted_register(old, &configuration) {
+                    Some(shifted) => shifted,
+                    None => continue,
+                };
+                let period = step - start;
+                let mut replayed: Machine = Machine::new(self);
+                replayed.restore_configuration(old);
+                if replay(&mut replayed, period, register) {
+                    return Some(TranslatedCyclerCertificate {
+                        start: *start,
+                        period,
+                        register,
+                        shift,
+                    });
+                }
+            }
+            candidates.push_back((step, configuration.clone()));
+            if order.len() == history {
+                let oldest = order.pop_front().unwrap();
+                by_ptr.get_mut(&oldest).unwrap().pop_front();
+            }
+            order.push_back(configuration.ptr());
+
+            if step == max_steps || machine.step() != StepResult::Continued {
+                break;
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cycler() {
+        let program = Program::new(
+            [
+                Instruction::AddConst(1, 3, 1),
+                Instruction::Decrement(1, 1, 2),
+                Instruction::Increment(0, 3),
+                Instruction::Decrement(0, 2, 2),
+            ]
+            .iter()
+            .copied(),
+        );
+        let certificate = program.find_cycle(100, 8).unwrap();
+        assert_eq!(
+            certificate,
+            CyclerCertificate {
+                start: 5,
+                period: 2
+            }
+        );
+        assert!(certificate.verify(&program));
+        assert!(!CyclerCertificate {
+            start: 4,
+            ..certificate
+        }
+        .verify(&program));
+        assert!(!CyclerCertificate {
+            period: 3,
+            ..certificate
+        }
+        .verify(&program));
+        assert!(!CyclerCertificate {
+            period: 0,
+            ..certificate
+        }
+        .verify(&program));
+
+        // The cycle is longer than the history or starts too late.
+        assert_eq!(program.find_cycle(100, 1), None);
+        assert_eq!(program.find_cycle(6, 8), None);
+        assert_eq!(program.find_cycle(7, 8), Some(certificate));
+
+        // Halting and growing programs never cycle.
+        let halting = Program::new([Instruction::Increment(0, 1)].iter().copied());
+        assert_eq!(halting.find_cycle(100, 100), None);
+        let growing = Program::new([Instruction::Increment(0, 0)].iter().copied());
+        assert_eq!(growing.find_cycle(1000, 1000), None);
+        let random = Program::new([Instruction::Random(1, 0, 0)].iter().copied());
+        assert_eq!(random.find_cycle(100, 100), None);
+    }
+
+    #[test]
+    fn translated_cycler() {
+        // Moves $0 to $1 and back, adding one in each round.
+        let program = Program::new(
+            [
+                Instruction::Increment(0, 1),
+                Instruction::Decrement(0, 2, 3),
+                Instruction::Increment(1, 1),
+                Instruction::Decrement(1, 4, 0),
+                Instruction::Increment(0, 3),
+            ]
+            .iter()
+            .copied(),
+        );
+        assert_eq!(program.find_cycle(1000, 1000), None);
+        // This program repeatedly tests both registers for zero,
+        // so its configurations are never just shifted.
+        assert_eq!(program.find_translated_cycle(1000, 1000), None);
+
+        // Counts $0 up while decrementing $1 in two steps, $1 is never tested for zero.
+        let program = Program::new(
+            [
+                Instruction::Increment(1, 1),
+                Instruction::Increment(0, 2),
+                Instruction::Decrement(1, 0, 0),
+            ]
+            .iter()
+            .copied(),
+        );
+        let certificate = program.find_translated_cycle(100, 10).unwrap();
+        assert_eq!(
+            certificate,
+            TranslatedCyclerCertificate {
+                start: 0,
+                period: 3,
+                register: 0,
+                shift: 1,
+            }
+        );
+        assert!(certificate.verify(&program));
+        assert!(!TranslatedCyclerCertificate {
+            shift: 2,
+            ..certificate
+        }
+        .verify(&program));
+        assert!(!TranslatedCyclerCertificate {
+            register: 1,
+            ..certificate
+        }
+        .verify(&program));
+        assert_eq!(program.find_translated_cycle(100, 2), None);
+
+        // Decrementing $0 is fine, as it is never zero when doing so.
+        let program = Program::new(
+            [
+                Instruction::AddConst(0, 2, 1),
+                Instruction::Decrement(0, 2, 4),
+                Instruction::AddConst(0, 2, 3),
+                Instruction::Jump(1),
+            ]
+            .iter()
+            .copied(),
+        );
+        let certificate = program.find_translated_cycle(100, 10).unwrap();
+        assert_eq!(certificate.start, 1);
+        assert_eq!((certificate.register, certificate.shift), (0, 1));
+        assert!(certificate.verify(&program));
+
+        // Other registers may be cleared in each round.
+        let program = Program::new(
+            [
+                Instruction::Increment(0, 1),
+                Instruction::Clear(1, 2),
+                Instruction::Jump(0),
+            ]
+            .iter()
+            .copied(),
+        );
+        let certificate = program.find_translated_cycle(100, 10).unwrap();
+        assert_eq!(certificate.register, 0);
+        assert!(certificate.verify(&program));
+    }
+}
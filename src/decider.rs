@@ -0,0 +1,375 @@
+use crate::{Certificate, Machine, Program, RunOutcome};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// The result of a [`Decider`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    /// The program halts after the given number of steps.
+    Halts(u64),
+    /// The program never halts.
+    NeverHalts(Certificate),
+    /// The decider could not decide whether the program halts.
+    Unknown,
+}
+
+/// Decides whether programs halt when starting with all registers at zero.
+///
+/// Deciders have to be sound but may return [`Decision::Unknown`] for any program,
+/// e.g. once they exceed their budget.
+pub trait Decider {
+    /// A short name of this decider, used for its [`DeciderStats`].
+    fn name(&self) -> &str;
+
+    fn decide(&self, program: &Program) -> Decision;
+}
+
+impl<D: Decider + ?Sized> Decider for Box<D> {
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    fn decide(&self, program: &Program) -> Decision {
+        (**self).decide(program)
+    }
+}
+
+/// Runs the program for at most `max_steps` steps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Simulation {
+    pub max_steps: u64,
+}
+
+impl Decider for Simulation {
+    fn name(&self) -> &str {
+        "simulation"
+    }
+
+    fn decide(&self, program: &Program) -> Decision {
+        let mut machine: Machine = Machine::new(program);
+        match machine.run(self.max_steps) {
+            RunOutcome::Halted { steps, .. } => Decision::Halts(steps),
+            _ => Decision::Unknown,
+        }
+    }
+}
+
+/// Searches for a repeating configuration, see [`Program::find_cycle`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cycler {
+    pub max_steps: u64,
+    pub history: usize,
+}
+
+impl Decider for Cycler {
+    fn name(&self) -> &str {
+        "cycler"
+    }
+
+    fn decide(&self, program: &Program) -> Decision {
+        match program.find_cycle(self.max_steps, self.history) {
+            Some(certificate) => Decision::NeverHalts(Certificate::Cycler(certificate)),
+            None => Decision::Unknown,
+        }
+    }
+}
+
+/// Searches for a configuration which repeats with a larger register,
+/// see [`Program::find_translated_cycle`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TranslatedCycler {
+    pub max_steps: u64,
+    pub history: usize,
+}
+
+impl Decider for TranslatedCycler {
+    fn name(&self) -> &str {
+        "translated cycler"
+    }
+
+    fn decide(&self, program: &Program) -> Decision {
+        match program.find_translated_cycle(self.max_steps, self.history) {
+            Some(certificate) => Decision::NeverHalts(Certificate::TranslatedCycler(certificate)),
+            None => Decision::Unknown,
+        }
+    }
+}
+
+/// Searches backwards from all halting instructions, see [`Program::find_backward_proof`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backward {
+    pub max_states: usize,
+}
+
+impl Decider for Backward {
+    fn name(&self) -> &str {
+        "backward"
+    }
+
+    fn decide(&self, program: &Program) -> Decision {
+        match program.find_backward_proof(self.max_states) {
+            Some(certificate) => Decision::NeverHalts(Certificate::Backward(certificate)),
+            None => Decision::Unknown,
+        }
+    }
+}
+
+/// Checks whether any halting instruction is reachable using [`Program::intervals`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct IntervalAnalysis;
+
+impl Decider for IntervalAnalysis {
+    fn name(&self) -> &str {
+        "intervals"
+    }
+
+    fn decide(&self, program: &Program) -> Decision {
+        let invariants = program.intervals(None);
+        if invariants.never_halts() {
+            Decision::NeverHalts(Certificate::Intervals((&invariants).into()))
+        } else {
+            Decision::Unknown
+        }
+    }
+}
+
+/// Checks whether any halting instruction is reachable using [`Program::congruences`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CongruenceAnalysis;
+
+impl Decider for CongruenceAnalysis {
+    fn name(&self) -> &str {
+        "congruences"
+    }
+
+    fn decide(&self, program: &Program) -> Decision {
+        let invariants = program.congruences(None);
+        if invariants.never_halts() {
+            Decision::NeverHalts(Certificate::Congruences((&invariants).into()))
+        } else {
+            Decision::Unknown
+        }
+    }
+}
+
+/// Statistics about a single decider of a [`Pipeline`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeciderStats {
+    pub name: String,
+    /// The number of programs this decider was run on.
+    pub runs: u64,
+    /// The number of programs this decider has shown to halt.
+    pub halting: u64,
+    /// The number of programs this decider has shown to never halt.
+    pub never_halting: u64,
+    /// The total time spent in this decider.
+    pub time: Duration,
+}
+
+impl fmt::Display for DeciderStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} runs, {} halting, {} never halting, {:?}",
+            self.name, self.runs, self.halting, self.never_halting, self.time
+        )
+    }
+}
+
+/// Runs multiple deciders in order until one of them decides the program.
+///
+/// The budget of each decider is part of its configuration, e.g. [`Simulation::max_steps`],
+/// so cheap deciders with small budgets should come first. As a pipeline is a decider
+/// itself, pipelines can be nested. Only [`Pipeline::run`] updates the statistics.
+#[derive(Default)]
+pub struct Pipeline {
+    deciders: Vec<Box<dyn Decider>>,
+    stats: Vec<DeciderStats>,
+    programs: u64,
+    unknown: u64,
+}
+
+impl fmt::Debug for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pipeline")
+            .field("stats", &self.stats)
+            .field("programs", &self.programs)
+            .field("unknown", &self.unknown)
+            .finish()
+    }
+}
+
+impl Pipeline {
+    pub fn new() -> Pipeline {
+        Pipeline::default()
+    }
+
+    /// Adds `decider` at the end of this pipeline.
+    pub fn push(&mut self, decider: impl Decider + 'static) {
+        self.stats.push(DeciderStats {
+            name: decider.name().to_owned(),
+            runs: 0,
+            halting: 0,
+            never_halting: 0,
+            time: Duration::default(),
+        });
+        self.deciders.push(Box::new(decider));
+    }
+
+    /// Adds `decider` at the end of this pipeline.
+    pub fn with(mut self, decider: impl Decider + 'static) -> Pipeline {
+        self.push(decider);
+        self
+    }
+
+    /// Runs the deciders on `program` until one of them decides it, updating the statistics.
+    pub fn run(&mut self, program: &Program) -> Decision {
+        self.programs += 1;
+        for (decider, stats) in self.deciders.iter().zip(&mut self.stats) {
+            let start = Instant::now();
+            let decision = decider.decide(program);
+            stats.time += start.elapsed();
+            stats.runs += 1;
+            match decision {
+                Decision::Halts(_) => stats.halting += 1,
+                Decision::NeverHalts(_) => stats.never_halting += 1,
+                Decision::Unknown => continue,
+            }
+            return decision;
+        }
+        self.unknown += 1;
+        Decision::Unknown
+    }
+
+    /// The statistics of each decider, in order.
+    pub fn stats(&self) -> &[DeciderStats] {
+        &self.stats
+    }
+
+    /// The number of programs passed to [`Pipeline::run`].
+    pub fn programs(&self) -> u64 {
+        self.programs
+    }
+
+    /// The number of programs which no decider could decide.
+    pub fn unknown(&self) -> u64 {
+        self.unknown
+    }
+
+    /// Resets all statistics to zero.
+    pub fn reset_stats(&mut self) {
+        for stats in &mut self.stats {
+            stats.runs = 0;
+            stats.halting = 0;
+            stats.never_halting = 0;
+            stats.time = Duration::default();
+        }
+        self.programs = 0;
+        self.unknown = 0;
+    }
+}
+
+impl Decider for Pipeline {
+    fn name(&self) -> &str {
+        "pipeline"
+    }
+
+    fn decide(&self, program: &Program) -> Decision {
+        self.deciders
+            .iter()
+            .map(|decider| decider.decide(program))
+            .find(|decision| *decision != Decision::Unknown)
+            .unwrap_or(Decision::Unknown)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Instruction;
+
+    fn decision_verifies(decision: &Decision, program: &Program) -> bool {
+        match decision {
+            Decision::NeverHalts(certificate) => certificate.verify(program),
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn pipeline() {
+        let halts = Program::new([Instruction::AddConst(0, 3, 1)].iter().copied());
+        let cycles = Program::new([Instruction::Jump(0)].iter().copied());
+        let grows = Program::new([Instruction::Increment(0, 0)].iter().copied());
+        // Only halts once `$1` becomes nonzero, which never happens.
+        let unreachable = Program::new(
+            [
+                Instruction::Increment(0, 1),
+                Instruction::BranchZero(1, 0, 2),
+                Instruction::Halt,
+            ]
+            .iter()
+            .copied(),
+        );
+        let slow = Program::new(
+            [
+                Instruction::AddConst(0, 200, 1),
+                Instruction::Decrement(0, 1, 2),
+            ]
+            .iter()
+            .copied(),
+        );
+        let programs = [&halts, &cycles, &grows, &slow];
+
+        let mut pipeline = Pipeline::new()
+            .with(Simulation { max_steps: 100 })
+            .with(Cycler {
+                max_steps: 100,
+                history: 10,
+            })
+            .with(TranslatedCycler {
+                max_steps: 100,
+                history: 10,
+            })
+            .with(IntervalAnalysis);
+        let decisions: Vec<_> = programs.iter().map(|p| pipeline.run(p)).collect();
+        assert_eq!(decisions[0], Decision::Halts(1));
+        assert!(matches!(
+            decisions[1],
+            Decision::NeverHalts(Certificate::Cycler(_))
+        ));
+        assert!(matches!(
+            decisions[2],
+            Decision::NeverHalts(Certificate::TranslatedCycler(_))
+        ));
+        assert_eq!(decisions[3], Decision::Unknown);
+        for (program, decision) in programs.iter().zip(&decisions) {
+            assert_eq!(pipeline.decide(program), *decision);
+            if let Decision::NeverHalts(_) = decision {
+                assert!(decision_verifies(decision, program));
+                assert!(!decision_verifies(decision, &halts));
+            }
+        }
+
+        let runs: Vec<_> = pipeline.stats().iter().map(|s| s.runs).collect();
+        assert_eq!(runs, [4, 3, 2, 1]);
+        assert_eq!(pipeline.stats()[2].never_halting, 1);
+        assert_eq!((pipeline.programs(), pipeline.unknown()), (4, 1));
+
+        let mut pipeline = Pipeline::new()
+            .with(Simulation { max_steps: 100 })
+            .with(Backward { max_states: 100 })
+            .with(CongruenceAnalysis);
+        let decision = pipeline.run(&unreachable);
+        assert!(matches!(
+            decision,
+            Decision::NeverHalts(Certificate::Backward(_))
+        ));
+        assert!(decision_verifies(&decision, &unreachable));
+        assert_eq!(pipeline.stats()[1].never_halting, 1);
+        assert_eq!(pipeline.stats()[2].runs, 0);
+        assert!(IntervalAnalysis.decide(&unreachable) != Decision::Unknown);
+        pipeline.reset_stats();
+        assert_eq!(pipeline.run(&slow), Decision::Unknown);
+        assert_eq!((pipeline.programs(), pipeline.unknown()), (1, 1));
+    }
+}
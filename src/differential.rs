@@ -0,0 +1,271 @@
+use crate::binary::{set_constant, template};
+use crate::rng::SplitMix64;
+use crate::{Configuration, Machine, MacroSimulator, Program, StepResult};
+use std::fmt;
+
+/// A way of running a [`Machine`] which is compared against executing single steps,
+/// see [`Program::compare_engines`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Engine {
+    /// [`Machine::step_n`].
+    Batched,
+    /// [`Machine::run_accelerated`].
+    Accelerated,
+    /// [`MacroSimulator::run`].
+    MacroStep,
+}
+
+impl Engine {
+    pub const ALL: [Engine; 3] = [Engine::Batched, Engine::Accelerated, Engine::MacroStep];
+}
+
+impl fmt::Display for Engine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Engine::Batched => "batched",
+            Engine::Accelerated => "accelerated",
+            Engine::MacroStep => "macro-step",
+        })
+    }
+}
+
+/// A checkpoint at which an engine disagrees with executing single steps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub program: Program,
+    /// The initial value of each register which does not start at zero.
+    pub inputs: Vec<(u8, u64)>,
+    pub engine: Engine,
+    /// The number of steps before the checkpoint.
+    pub steps: u64,
+    /// The configuration after single steps and after running the engine.
+    pub expected: Configuration,
+    pub found: Configuration,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "the {} engine differs from single steps after {} steps",
+            self.engine, self.steps
+        )?;
+        writeln!(f, "inputs: {:?}", self.inputs)?;
+        writeln!(f, "expected: {:?}", self.expected)?;
+        writeln!(f, "found: {:?}", self.found)?;
+        f.write_str(&self.program.disassemble())
+    }
+}
+
+/// A machine which is advanced by `engine`.
+enum Runner<'p> {
+    Machine(Machine<'p>, Engine),
+    Macro(MacroSimulator<'p>),
+}
+
+impl<'p> Runner<'p> {
+    fn new(machine: Machine<'p>, engine: Engine) -> Runner<'p> {
+        match engine {
+            Engine::Batched | Engine::Accelerated => Runner::Machine(machine, engine),
+            Engine::MacroStep => Runner::Macro(MacroSimulator::new(machine)),
+        }
+    }
+
+    /// Runs for at most `max_steps` steps.
+    fn advance(&mut self, max_steps: u64) {
+        match self {
+            Runner::Machine(machine, Engine::Accelerated) => {
+                machine.run_accelerated(max_steps);
+            }
+            Runner::Machine(machine, _) => {
+                machine.step_n(max_steps);
+            }
+            Runner::Macro(simulator) => {
+                simulator.run(max_steps);
+            }
+        }
+    }
+
+    fn configuration(&self) -> Configuration {
+        match self {
+            Runner::Machine(machine, _) => machine.configuration(),
+            Runner::Macro(simulator) => simulator.machine().configuration(),
+        }
+    }
+}
+
+/// Executes at most `max_steps` single steps, returning the number of executed steps,
+/// which is less than `max_steps` if the machine stopped.
+fn step_by_step(machine: &mut Machine<'_>, max_steps: u64) -> u64 {
+    let mut steps = 0;
+    while steps < max_steps {
+        let result = machine.step();
+        if result.executed() {
+            steps += 1;
+        }
+        if result != StepResult::Continued {
+            break;
+        }
+    }
+    steps
+}
+
+/// A random program using the registers `$0` to `$3`, without `Read` and `Write`.
+fn random_program(rng: &mut SplitMix64, len: u16) -> Program {
+    let mut next = |n: u64| rng.next_u64() % n;
+    let instructions: Vec<_> = (0..len)
+        .map(|_| {
+            let opcode = match next(19) as u8 {
+                op @ 0..=14 => op,
+                op => op + 2,
+            };
+            let mut instruction = template(opcode).unwrap();
+            for reg in instruction.registers_mut() {
+                *reg = next(4) as u8;
+            }
+            set_constant(&mut instruction, next(4));
+            for target in instruction.targets_mut() {
+                *target = next(u64::from(len) + 1) as u16;
+            }
+            instruction
+        })
+        .collect();
+    Program::new(instructions)
+}
+
+impl Program {
+    /// Runs this program by executing single steps with [`Machine::step`] and using
+    /// `engine` side by side, comparing the configurations every `interval` steps.
+    ///
+    /// Both machines start with the registers set to `inputs` and run until executing
+    /// single steps stops or `max_steps` steps were executed. Returns the first checkpoint
+    /// at which the configurations differ. An `interval` of zero is treated as one.
+    ///
+    /// Only the configurations are compared, not how the engines report stopping, as
+    /// e.g. [`Machine::run_accelerated`] already reports reaching a `Purged` instruction
+    /// when running out of fuel right in front of it.
+    pub fn compare_engines(
+        &self,
+        inputs: &[(u8, u64)],
+        engine: Engine,
+        interval: u64,
+        max_steps: u64,
+    ) -> Result<(), Box<Mismatch>> {
+        let interval = interval.max(1);
+        let start = || {
+            let mut machine: Machine = Machine::new(self);
+            for &(reg, value) in inputs {
+                machine.set_register(reg, value);
+            }
+            machine
+        };
+        let mut reference = start();
+        let mut runner = Runner::new(start(), engine);
+
+        let mut steps = 0;
+        while steps < max_steps {
+            let chunk = interval.min(max_steps - steps);
+            let executed = step_by_step(&mut reference, chunk);
+            runner.advance(chunk);
+            steps += executed;
+            let (expected, found) = (reference.configuration(), runner.configuration());
+            if expected != found {
+                return Err(Box::new(Mismatch {
+                    program: self.clone(),
+                    inputs: inputs.to_vec(),
+                    engine,
+                    steps,
+                    expected,
+                    found,
+                }));
+            }
+            if executed < chunk {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Compares `engine` against single steps on `programs` random programs with `len`
+/// instructions, see [`Program::compare_engines`].
+///
+/// The programs and their inputs are generated from `seed`, so a failure can be
+/// reproduced by running the harness again with the same arguments.
+pub fn differential_test(
+    seed: u64,
+    programs: usize,
+    len: u16,
+    engine: Engine,
+    interval: u64,
+    max_steps: u64,
+) -> Result<(), Box<Mismatch>> {
+    let mut rng = SplitMix64::new(seed);
+    for _ in 0..programs {
+        let program = random_program(&mut rng, len);
+        let inputs: Vec<_> = (0..4)
+            .map(|reg| (reg, rng.next_u64() % 16))
+            .filter(|&(_, value)| value != 0)
+            .collect();
+        program.compare_engines(&inputs, engine, interval, max_steps)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Instruction;
+
+    #[test]
+    fn engines_agree() {
+        for &engine in &Engine::ALL {
+            for &interval in &[1, 7, 64] {
+                if let Err(mismatch) = differential_test(12345, 300, 6, engine, interval, 500) {
+                    panic!("{}", mismatch);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn compare_engines() {
+        // The loop is accelerated in steps of 2, so the checkpoints
+        // after an odd number of steps are in the middle of it.
+        let program = Program::new(
+            [
+                Instruction::Decrement(0, 1, 2),
+                Instruction::Increment(1, 0),
+                Instruction::Choose(3, 0),
+                Instruction::Halt,
+            ]
+            .iter()
+            .copied(),
+        );
+        for &engine in &Engine::ALL {
+            for &interval in &[0, 1, 3, 1000] {
+                assert_eq!(
+                    program.compare_engines(&[(0, 100)], engine, interval, 500),
+                    Ok(())
+                );
+            }
+        }
+
+        let mut machine: Machine = Machine::new(&program);
+        machine.set_register(0, 3);
+        assert_eq!(step_by_step(&mut machine, 5), 5);
+        assert_eq!(step_by_step(&mut machine, 1000), 2);
+
+        let mismatch = Mismatch {
+            program: program.clone(),
+            inputs: vec![(0, 100)],
+            engine: Engine::Accelerated,
+            steps: 3,
+            expected: Machine::new(&program).configuration(),
+            found: Machine::new(&program).configuration(),
+        };
+        assert!(mismatch
+            .to_string()
+            .starts_with("the accelerated engine differs from single steps after 3 steps\n"));
+    }
+}
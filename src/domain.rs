@@ -0,0 +1,308 @@
+use crate::{Instruction, Program};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Debug;
+
+/// The number of times the state at a position may grow before it is widened,
+/// see [`Program::invariants`].
+const WIDENING_DELAY: usize = 3;
+
+/// An abstract domain describing sets of possible values of a single register,
+/// see [`Program::invariants`].
+///
+/// All operations may return larger sets than necessary, but never smaller ones.
+/// Methods returning an `Option` return `None` for the empty set.
+pub trait Domain: Copy + Eq + Debug {
+    /// All possible register values.
+    fn top() -> Self;
+
+    fn constant(value: u64) -> Self;
+
+    /// A set which contains all values of both sets.
+    fn join(self, other: Self) -> Self;
+
+    /// A set which contains all values contained in both sets.
+    fn meet(self, other: Self) -> Option<Self>;
+
+    /// Joins `self` with `new` in a way which ensures that repeatedly widening a
+    /// set eventually stops changing it.
+    ///
+    /// The default implementation uses [`Domain::join`], which is only correct
+    /// for domains without infinite ascending chains.
+    fn widen(self, new: Self) -> Self {
+        self.join(new)
+    }
+
+    /// The sums of all values of both sets.
+    fn add(self, other: Self) -> Self;
+
+    /// The values of `self` which are at least `n`, with `n` subtracted from them.
+    fn sub_const(self, n: u64) -> Option<Self>;
+
+    /// The values of `self` which are less than `n`.
+    fn below(self, n: u64) -> Option<Self>;
+
+    /// The values `a` and `b` of both sets for which `a >= b`.
+    ///
+    /// The default implementation returns both sets unchanged.
+    fn refine_ge(a: Self, b: Self) -> Option<(Self, Self)> {
+        Some((a, b))
+    }
+
+    /// The values `a` and `b` of both sets for which `a < b`.
+    ///
+    /// The default implementation returns both sets unchanged.
+    fn refine_lt(a: Self, b: Self) -> Option<(Self, Self)> {
+        Some((a, b))
+    }
+}
+
+/// Combines two domains, e.g. to track both the range and the parity of registers.
+impl<A: Domain, B: Domain> Domain for (A, B) {
+    fn top() -> Self {
+        (A::top(), B::top())
+    }
+
+    fn constant(value: u64) -> Self {
+        (A::constant(value), B::constant(value))
+    }
+
+    fn join(self, other: Self) -> Self {
+        (self.0.join(other.0), self.1.join(other.1))
+    }
+
+    fn meet(self, other: Self) -> Option<Self> {
+        Some((self.0.meet(other.0)?, self.1.meet(other.1)?))
+    }
+
+    fn widen(self, new: Self) -> Self {
+        (self.0.widen(new.0), self.1.widen(new.1))
+    }
+
+    fn add(self, other: Self) -> Self {
+        (self.0.add(other.0), self.1.add(other.1))
+    }
+
+    fn sub_const(self, n: u64) -> Option<Self> {
+        Some((self.0.sub_const(n)?, self.1.sub_const(n)?))
+    }
+
+    fn below(self, n: u64) -> Option<Self> {
+        Some((self.0.below(n)?, self.1.below(n)?))
+    }
+
+    fn refine_ge(a: Self, b: Self) -> Option<(Self, Self)> {
+        let (a0, b0) = A::refine_ge(a.0, b.0)?;
+        let (a1, b1) = B::refine_ge(a.1, b.1)?;
+        Some(((a0, a1), (b0, b1)))
+    }
+
+    fn refine_lt(a: Self, b: Self) -> Option<(Self, Self)> {
+        let (a0, b0) = A::refine_lt(a.0, b.0)?;
+        let (a1, b1) = B::refine_lt(a.1, b.1)?;
+        Some(((a0, a1), (b0, b1)))
+    }
+}
+
+/// The possible register values at each position of a program, see [`Program::invariants`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invariants<D> {
+    states: Vec<Option<BTreeMap<u8, D>>>,
+    halting: BTreeSet<u16>,
+}
+
+impl<D: Domain> Invariants<D> {
+    /// The possible values of all registers used by the program whenever the instruction
+    /// at `at` is executed, or `None` if it is unreachable. Positions past the end of the
+    /// program all share the state of the implicit `Halt` at its end.
+    pub fn at(&self, at: u16) -> Option<&BTreeMap<u8, D>> {
+        let index = usize::from(at).min(self.states.len() - 1);
+        self.states[index].as_ref()
+    }
+
+    /// The possible values of `reg` when executing the instruction at `at`.
+    pub fn register(&self, at: u16, reg: u8) -> Option<D> {
+        self.at(at)?.get(&reg).copied()
+    }
+
+    /// The possible values of `reg` during the whole execution,
+    /// or `None` if it is not used by the program.
+    pub fn bound(&self, reg: u8) -> Option<D> {
+        self.states
+            .iter()
+            .flatten()
+            .filter_map(|state| state.get(&reg).copied())
+            .reduce(D::join)
+    }
+
+    /// The state of each reachable position, including the end of the program.
+    pub fn reachable(&self) -> impl Iterator<Item = (u16, &BTreeMap<u8, D>)> {
+        self.states
+            .iter()
+            .enumerate()
+            .filter_map(|(at, state)| Some((at as u16, state.as_ref()?)))
+    }
+
+    /// Whether all halting instructions of the program are unreachable.
+    pub fn never_halts(&self) -> bool {
+        self.halting.iter().all(|&at| self.at(at).is_none())
+    }
+}
+
+/// The state after `instruction` for each of its targets, see [`Program::invariants`].
+fn transfer<D: Domain>(
+    instruction: Instruction,
+    before: &BTreeMap<u8, D>,
+) -> Vec<Option<BTreeMap<u8, D>>> {
+    let value = |reg| before[&reg];
+    let with = |changes: &[(u8, Option<D>)]| {
+        let mut after = before.clone();
+        for &(reg, value) in changes {
+            after.insert(reg, value?);
+        }
+        Some(after)
+    };
+    let zero = D::constant(0);
+    match instruction {
+        Instruction::Increment(reg, _) => {
+            vec![with(&[(reg, Some(value(reg).add(D::constant(1))))])]
+        }
+        Instruction::AddConst(reg, n, _) => {
+            vec![with(&[(reg, Some(value(reg).add(D::constant(n))))])]
+        }
+        Instruction::Clear(reg, _) => vec![with(&[(reg, Some(zero))])],
+        Instruction::Read(reg, _) => vec![with(&[(reg, Some(D::top()))])],
+        Instruction::Decrement(reg, ..) => vec![
+            with(&[(reg, value(reg).sub_const(1))]),
+            with(&[(reg, value(reg).meet(zero))]),
+        ],
+        Instruction::SubConst(reg, n, ..) => vec![
+            with(&[(reg, value(reg).sub_const(n))]),
+            with(&[(reg, value(reg).below(n))]),
+        ],
+        Instruction::BranchZero(reg, ..) => {
+            let positive = value(reg).sub_const(1).map(|v| v.add(D::constant(1)));
+            vec![
+                with(&[(reg, value(reg).meet(zero))]),
+                with(&[(reg, positive)]),
+            ]
+        }
+        Instruction::Compare(a, b, ..) if a == b => vec![Some(before.clone()), None],
+        Instruction::Compare(a, b, ..) => {
+            let refined = |refine: fn(D, D) -> Option<(D, D)>| {
+                let (x, y) = refine(value(a), value(b))?;
+                with(&[(a, Some(x)), (b, Some(y))])
+            };
+            vec![refined(D::refine_ge), refined(D::refine_lt)]
+        }
+        Instruction::Transfer { src, dst, .. } if src != dst => {
+            vec![with(&[
+                (dst, Some(value(dst).add(value(src)))),
+                (src, Some(zero)),
+            ])]
+        }
+        Instruction::Copy {
+            src, dst, scratch, ..
+        } if src != dst && src != scratch && dst != scratch => vec![with(&[
+            (dst, Some(value(dst).add(value(src)))),
+            (src, Some(value(src).add(value(scratch)))),
+            (scratch, Some(zero)),
+        ])],
+        Instruction::Swap(a, b, _) => vec![with(&[(a, Some(value(b))), (b, Some(value(a)))])],
+        _ => instruction
+            .targets()
+            .map(|_| Some(before.clone()))
+            .collect(),
+    }
+}
+
+impl Program {
+    /// Computes the possible values of each register at each position of this program
+    /// using the abstract domain `D`, given the possible initial values of `inputs` and
+    /// assuming that all other registers start at zero.
+    ///
+    /// Once the state at a position has grown a few times, it is widened using
+    /// [`Domain::widen`], so this always terminates but may include values which are
+    /// never reached. Registers are assumed to be unbounded. As `Return` may continue
+    /// after any `Call`, all registers may have any value at the instruction after a `Call`.
+    pub fn invariants<D: Domain>(
+        &self,
+        inputs: impl IntoIterator<Item = (u8, D)>,
+    ) -> Invariants<D> {
+        let inputs: BTreeMap<u8, D> = inputs.into_iter().collect();
+        let used: BTreeSet<u8> = self
+            .iter()
+            .flat_map(|(_, instruction)| instruction.registers())
+            .collect();
+        let end = self.len() as u16;
+        let halting = self
+            .iter()
+            .filter(|(_, instruction)| {
+                matches!(instruction, Instruction::Halt | Instruction::HaltWith(_))
+            })
+            .map(|(at, _)| at)
+            .chain(std::iter::once(end))
+            .collect();
+
+        let mut states: Vec<Option<BTreeMap<u8, D>>> = vec![None; self.len() + 1];
+        let mut updates = vec![0; self.len() + 1];
+        let top: BTreeMap<u8, D> = used.iter().map(|&reg| (reg, D::top())).collect();
+        states[0] = Some(
+            used.iter()
+                .map(|&reg| {
+                    let initial = inputs.get(&reg).copied();
+                    (reg, initial.unwrap_or_else(|| D::constant(0)))
+                })
+                .collect(),
+        );
+        let mut worklist = vec![0];
+        while let Some(at) = worklist.pop() {
+            let instruction = self.instruction(at);
+            let before = match &states[usize::from(at)] {
+                Some(before) => before.clone(),
+                None => continue,
+            };
+            let mut successors: Vec<(u16, Option<BTreeMap<u8, D>>)> = instruction
+                .targets()
+                .zip(transfer(instruction, &before))
+                .collect();
+            if let Instruction::Call(_) = instruction {
+                successors.push((at.wrapping_add(1), Some(top.clone())));
+            }
+
+            for (succ, state) in successors {
+                let state = match state {
+                    Some(state) => state,
+                    None => continue,
+                };
+                let succ = succ.min(end);
+                let index = usize::from(succ);
+                let new = match &states[index] {
+                    Some(current) => {
+                        let joined: BTreeMap<u8, D> = current
+                            .iter()
+                            .map(|(&reg, &value)| (reg, value.join(state[&reg])))
+                            .collect();
+                        if &joined == current {
+                            continue;
+                        }
+                        updates[index] += 1;
+                        if updates[index] > WIDENING_DELAY {
+                            current
+                                .iter()
+                                .map(|(&reg, &value)| (reg, value.widen(joined[&reg])))
+                                .collect()
+                        } else {
+                            joined
+                        }
+                    }
+                    None => state,
+                };
+                states[index] = Some(new);
+                if succ != end {
+                    worklist.push(succ);
+                }
+            }
+        }
+        Invariants { states, halting }
+    }
+}
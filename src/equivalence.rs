@@ -0,0 +1,246 @@
+use crate::binary::{constant, opcode};
+use crate::io::NoIo;
+use crate::{Instruction, Machine, Program, RunOutcome};
+use std::collections::HashSet;
+use std::mem;
+use std::ops::RangeInclusive;
+
+/// An input on which two programs behave differently, see [`Program::equivalent_on`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Counterexample {
+    /// The initial value of each input register.
+    pub inputs: Vec<(u8, u64)>,
+    pub left: RunOutcome,
+    pub right: RunOutcome,
+    /// The final values of the output registers of both programs.
+    pub left_outputs: Vec<u64>,
+    pub right_outputs: Vec<u64>,
+}
+
+/// Where execution continues after jumping to a position, skipping `Jump` and `Nop`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Node {
+    At(u16),
+    Halt(u16),
+    /// A loop of `Jump` and `Nop` instructions.
+    Diverges,
+}
+
+fn resolve(program: &Program, mut at: u16) -> Node {
+    for _ in 0..=program.len() {
+        match program.instruction(at) {
+            Instruction::Halt => return Node::Halt(0),
+            Instruction::HaltWith(code) => return Node::Halt(code),
+            Instruction::Jump(target) | Instruction::Nop(target) => at = target,
+            _ => return Node::At(at),
+        }
+    }
+    Node::Diverges
+}
+
+impl Program {
+    /// Runs this program and `other` on every combination of the initial values of the
+    /// `inputs` and compares how they stop and the final values of the `outputs`.
+    ///
+    /// Both programs have to halt with the same exit code or stop for the same reason, e.g.
+    /// by reaching a `Purged` instruction, but may take a different number of steps. Inputs
+    /// for which either program does not stop within `fuel` steps are skipped. Returns the
+    /// number of compared inputs, or the first input on which the programs differ.
+    ///
+    /// `Read` instructions always read zero and the output of `Write` is ignored.
+    pub fn equivalent_on(
+        &self,
+        other: &Program,
+        inputs: &[(u8, RangeInclusive<u64>)],
+        outputs: &[u8],
+        fuel: u64,
+    ) -> Result<u64, Counterexample> {
+        if inputs.iter().any(|(_, values)| values.is_empty()) {
+            return Ok(0);
+        }
+        let mut values: Vec<u64> = inputs.iter().map(|(_, values)| *values.start()).collect();
+        let mut compared = 0;
+        loop {
+            let run = |program| {
+                let mut machine: Machine = Machine::new(program);
+                for (&(reg, _), &value) in inputs.iter().zip(&values) {
+                    machine.set_register(reg, value);
+                }
+                let outcome = machine.run_with_io(fuel, NoIo);
+                let outputs: Vec<u64> = outputs
+                    .iter()
+                    .map(|&reg| machine.registers()[usize::from(reg)])
+                    .collect();
+                (outcome, outputs)
+            };
+            let (left, left_outputs) = run(self);
+            let (right, right_outputs) = run(other);
+            let same = match (left, right) {
+                (RunOutcome::OutOfFuel, _) | (_, RunOutcome::OutOfFuel) => None,
+                (RunOutcome::Halted { code: a, .. }, RunOutcome::Halted { code: b, .. }) => {
+                    Some(a == b && left_outputs == right_outputs)
+                }
+                (left, right) => Some(mem::discriminant(&left) == mem::discriminant(&right)),
+            };
+            match same {
+                Some(true) => compared += 1,
+                Some(false) => {
+                    return Err(Counterexample {
+                        inputs: inputs.iter().map(|(reg, _)| *reg).zip(values).collect(),
+                        left,
+                        right,
+                        left_outputs,
+                        right_outputs,
+                    })
+                }
+                None => {}
+            }
+
+            // Advances to the next combination of inputs.
+            let mut index = 0;
+            loop {
+                match inputs.get(index) {
+                    None => return Ok(compared),
+                    Some((_, range)) if values[index] < *range.end() => {
+                        values[index] += 1;
+                        break;
+                    }
+                    Some((_, range)) => {
+                        values[index] = *range.start();
+                        index += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether this program and `other` are bisimilar, which implies that they behave the
+    /// same way on all inputs, except for the number of steps they take.
+    ///
+    /// Both programs are executed in lockstep from the start, skipping `Jump` and `Nop`,
+    /// and have to execute the same instruction on the same registers in each step. This
+    /// explores every pair of reachable instructions at most once, so it is only suitable
+    /// for small programs. Programs which are equivalent but compute their results in a
+    /// different way, e.g. using `AddConst` instead of multiple `Increment`s, are not bisimilar.
+    /// The arms of `Choose` have to be in the same order.
+    pub fn is_bisimilar(&self, other: &Program) -> bool {
+        let mut seen = HashSet::new();
+        let mut pending = vec![(resolve(self, 0), resolve(other, 0))];
+        while let Some(pair) = pending.pop() {
+            if !seen.insert(pair) {
+                continue;
+            }
+            let (a, b) = match pair {
+                (Node::At(a), Node::At(b)) => (a, b),
+                (a, b) if a == b => continue,
+                _ => return false,
+            };
+            let (x, y) = (self.instruction(a), other.instruction(b));
+            if opcode(x) != opcode(y)
+                || constant(x) != constant(y)
+                || !x.registers().eq(y.registers())
+            {
+                return false;
+            }
+            pending.extend(
+                x.targets()
+                    .zip(y.targets())
+                    .map(|(a, b)| (resolve(self, a), resolve(other, b))),
+            );
+            if let Instruction::Call(_) = x {
+                pending.push((
+                    resolve(self, a.wrapping_add(1)),
+                    resolve(other, b.wrapping_add(1)),
+                ));
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn equivalence() {
+        // Adds `$0` to `$1` in a loop.
+        let program = Program::new(
+            [
+                Instruction::Decrement(0, 1, 2),
+                Instruction::Increment(1, 0),
+            ]
+            .iter()
+            .copied(),
+        );
+        let optimized = Program::new(
+            [
+                Instruction::Transfer {
+                    src: 0,
+                    dst: 1,
+                    then: 1,
+                },
+                Instruction::Halt,
+            ]
+            .iter()
+            .copied(),
+        );
+        let inputs = [(0, 0..=5), (1, 2..=4)];
+        assert_eq!(
+            program.equivalent_on(&optimized, &inputs, &[0, 1], 100),
+            Ok(18)
+        );
+        assert!(!program.is_bisimilar(&optimized));
+
+        // Does nothing, which is only correct if `$0` is zero.
+        let wrong = Program::new([Instruction::AddConst(1, 0, 1)].iter().copied());
+        let counterexample = program
+            .equivalent_on(&wrong, &inputs, &[0, 1], 100)
+            .unwrap_err();
+        assert_eq!(counterexample.inputs, [(0, 1), (1, 2)]);
+        assert_eq!(counterexample.left_outputs, [0, 3]);
+        assert_eq!(counterexample.right_outputs, [1, 2]);
+        let zero = [(0, 0..=0), (1, 2..=4)];
+        assert_eq!(program.equivalent_on(&wrong, &zero, &[0, 1], 100), Ok(3));
+        // Only the inputs on which both programs stop are compared.
+        let loops = Program::new([Instruction::Jump(0)].iter().copied());
+        assert_eq!(program.equivalent_on(&loops, &inputs, &[], 100), Ok(0));
+        // `Read` reads zero instead of waiting for stdin.
+        let read = Program::new(vec![Instruction::Read(0, 1), Instruction::Write(0, 2)]);
+        let clear = Program::new(vec![Instruction::Clear(0, 1)]);
+        assert_eq!(read.equivalent_on(&clear, &inputs, &[0, 1], 100), Ok(18));
+
+        // The same loop with its instructions in a different order, an unrolled
+        // iteration and a detour through a chain of jumps.
+        let unrolled = Program::new(
+            [
+                Instruction::Jump(3),
+                Instruction::Increment(1, 4),
+                Instruction::HaltWith(0),
+                Instruction::Decrement(0, 1, 2),
+                Instruction::Nop(5),
+                Instruction::Decrement(0, 6, 7),
+                Instruction::Increment(1, 0),
+            ]
+            .iter()
+            .copied(),
+        );
+        assert!(program.is_bisimilar(&unrolled));
+        assert!(unrolled.is_bisimilar(&program));
+        assert_eq!(
+            program.equivalent_on(&unrolled, &inputs, &[0, 1], 100),
+            Ok(18)
+        );
+        let nops = Program::new([Instruction::Jump(1), Instruction::Nop(0)].iter().copied());
+        assert!(nops.is_bisimilar(&loops));
+        assert!(!nops.is_bisimilar(&Program::empty()));
+        assert!(!program.is_bisimilar(&Program::new(
+            [
+                Instruction::Decrement(1, 1, 2),
+                Instruction::Increment(1, 0)
+            ]
+            .iter()
+            .copied()
+        )));
+    }
+}
@@ -0,0 +1,358 @@
+//! Ready-made example programs.
+//!
+//! Each function documents which registers hold the inputs and the result.
+//! All registers not mentioned as inputs must be zero when starting a program,
+//! and unless stated otherwise they are zero again once it halts.
+
+use crate::{routines, Instruction, Program, ProgramBuilder, Target};
+use std::convert::TryFrom;
+
+/// `$0 = $0 + $1`, setting `$1` to zero.
+///
+/// Uses only strict Minsky instructions.
+pub fn addition() -> Program {
+    Program::new(
+        crate::minsky! {
+            start: dec 1 -> inc, done;
+            inc: inc 0 -> start;
+            done: halt;
+        }
+        .iter()
+        .copied(),
+    )
+}
+
+/// `$2 = $0 * $1`, preserving the inputs.
+///
+/// Uses only strict Minsky instructions.
+pub fn multiplication() -> Program {
+    routines::multiply(0, 1, 2, [3, 4])
+}
+
+/// `$2 = $0 / $1` and `$3 = $0 % $1`, preserving the inputs.
+///
+/// Dividing by zero sets `$2` to zero and `$3` to `$0`.
+/// Uses only strict Minsky instructions.
+pub fn division() -> Program {
+    routines::divide(0, 1, 2, 3, [4, 5])
+}
+
+/// `$0 = gcd($0, $1)`, setting `$1` to zero.
+///
+/// Uses the Euclidean algorithm and only strict Minsky instructions.
+pub fn gcd() -> Program {
+    let mut b = ProgramBuilder::new();
+    // $2 = remainder, $3 = quotient
+    b.label("loop").decrement(1, "restore", "done");
+    b.label("restore").increment(1, "divide");
+    b.label("divide")
+        .block(&routines::divide(0, 1, 3, 2, [4, 5]));
+    b.label("quotient").decrement(3, "quotient", "a");
+    b.label("a").decrement(0, "a", "b");
+    b.label("b").decrement(1, "b_inc", "r");
+    b.label("b_inc").increment(0, "b");
+    b.label("r").decrement(2, "r_inc", "loop");
+    b.label("r_inc").increment(1, "r");
+    b.label("done").halt();
+    b.build().unwrap()
+}
+
+/// `$1 = fib($0)`, setting `$0` to zero.
+///
+/// Uses only strict Minsky instructions.
+pub fn fibonacci() -> Program {
+    // `$1` and `$2` are two consecutive Fibonacci numbers,
+    // `$3` is used to compute their sum.
+    Program::new(
+        crate::minsky! {
+            inc 2 -> next;
+            next: dec 0 -> a, done;
+            a: dec 1 -> a_inc, b;
+            a_inc: inc 3 -> a;
+            b: dec 2 -> b_inc, sum;
+            b_inc: inc 1 -> b_inc2;
+            b_inc2: inc 3 -> b;
+            sum: dec 3 -> sum_inc, next;
+            sum_inc: inc 2 -> sum;
+            done: dec 2 -> done, end;
+            end: halt;
+        }
+        .iter()
+        .copied(),
+    )
+}
+
+/// `$1 = A($0, $1)` for the two-argument Ackermann–Péter function, setting `$0` to zero.
+///
+/// As a Minsky machine does not have a stack, the pending values of `m` are stored
+/// as the digits `m + 1` of a single register in base `$0 + 2`. Uses only strict
+/// Minsky instructions. Even for small inputs this takes a huge number of steps.
+pub fn ackermann() -> Program {
+    const N: u8 = 1;
+    const STACK: u8 = 2;
+    const BASE: u8 = 3;
+    const M: u8 = 4;
+    const TMP: u8 = 5;
+    const SCRATCH: [u8; 2] = [6, 7];
+
+    // Pushes `M` onto the stack.
+    let push = {
+        let mut b = ProgramBuilder::new();
+        b.block(&routines::multiply(STACK, BASE, TMP, SCRATCH));
+        b.label("clear").decrement(STACK, "clear", "move");
+        b.label("move").decrement(TMP, "move_inc", "copy");
+        b.label("move_inc").increment(STACK, "move");
+        b.label("copy");
+        let at = b.position();
+        for instruction in &Instruction::copy_sequence(M, STACK, SCRATCH[0], at, at + 5).unwrap() {
+            b.instruction(*instruction);
+        }
+        b.increment(STACK, Target::Addr(at + 6));
+        b.build().unwrap()
+    };
+
+    let mut b = ProgramBuilder::new();
+    // The base is `m + 2` and the stack initially contains `m`.
+    b.block(&routines::copy(0, BASE, SCRATCH[0]));
+    b.increment(BASE, Target::Addr(b.position() + 1));
+    b.increment(BASE, Target::Addr(b.position() + 1));
+    b.label("init").decrement(0, "init_inc", "init_done");
+    b.label("init_inc").increment(STACK, "init");
+    b.label("init_done").increment(STACK, "loop");
+    // Pop `m` or stop if the stack is empty.
+    b.label("loop").decrement(STACK, "restore", "done");
+    b.label("restore").increment(STACK, "pop");
+    b.label("pop")
+        .block(&routines::divide(STACK, BASE, TMP, M, SCRATCH));
+    b.label("clear").decrement(STACK, "clear", "move");
+    b.label("move").decrement(TMP, "move_inc", "digit");
+    b.label("move_inc").increment(STACK, "move");
+    b.label("digit").decrement(M, "m", "m");
+    // A(0, n) = n + 1
+    b.label("m").decrement(M, "m_nonzero", "m_zero");
+    b.label("m_zero").increment(N, "loop");
+    // A(m, 0) = A(m - 1, 1)
+    b.label("m_nonzero").decrement(N, "n_nonzero", "n_zero");
+    b.label("n_zero").increment(N, "push_last");
+    b.label("push_last").block(&push);
+    b.label("clear_last").decrement(M, "clear_last", "loop");
+    // A(m, n) = A(m - 1, A(m, n - 1))
+    b.label("n_nonzero").block(&push);
+    b.increment(M, Target::Addr(b.position() + 1)).block(&push);
+    b.label("clear_m").decrement(M, "clear_m", "loop");
+    b.label("done").decrement(BASE, "done", "halt");
+    b.label("halt").halt();
+    b.build().unwrap()
+}
+
+/// A universal machine for two-register strict Minsky machines.
+///
+/// The program being simulated is stored in `$0` using the base `$1`, its registers
+/// `a` and `b` are stored as `$2 = 2^a * 3^b`. Use [`universal_input`] to compute these
+/// values. When the simulated program halts, so does this machine, with `$2` holding its
+/// final registers and `$3` its instruction pointer. Uses accelerated instructions.
+///
+/// Every instruction of the simulated program is stored as the three digits `kind`, `t`
+/// and `e`, least significant first, in base `$1`. The instruction at `i` starts at digit
+/// `3 * i`. A `kind` of `0` is `Halt`, `1` and `2` are `Increment(0, t)` and `Increment(1, t)`,
+/// `3` and `4` are `Decrement(0, t, e)` and `Decrement(1, t, e)`. Other kinds halt the machine.
+pub fn universal() -> Program {
+    const PROGRAM: u8 = 0;
+    const BASE: u8 = 1;
+    const REGS: u8 = 2;
+    const PTR: u8 = 3;
+    const WORD: u8 = 4;
+    const KIND: u8 = 5;
+    const THEN: u8 = 6;
+    const ELSE: u8 = 7;
+    const TMP: u8 = 8;
+    const COUNT: u8 = 9;
+    const SCRATCH: [u8; 2] = [10, 11];
+    const INSTRUCTION_BASE: u8 = 12;
+
+    let mut b = ProgramBuilder::new();
+    let next = |b: &ProgramBuilder<_>| Target::Addr(b.position() + 1);
+    b.copy(BASE, COUNT, SCRATCH[0], next(&b));
+    b.block(&routines::multiply(BASE, COUNT, TMP, SCRATCH));
+    b.clear(COUNT, next(&b));
+    b.block(&routines::multiply(TMP, BASE, INSTRUCTION_BASE, SCRATCH));
+    b.clear(TMP, "fetch");
+    // Fetch the current instruction, dividing by `$1^3` once for each previous instruction.
+    b.label("fetch");
+    b.clear(WORD, next(&b));
+    b.copy(PROGRAM, WORD, SCRATCH[0], next(&b));
+    b.copy(PTR, COUNT, SCRATCH[0], "skip");
+    b.label("skip").decrement(COUNT, "skip_body", "decode");
+    b.label("skip_body");
+    b.block(&routines::divide(
+        WORD,
+        INSTRUCTION_BASE,
+        TMP,
+        KIND,
+        SCRATCH,
+    ));
+    b.clear(KIND, next(&b));
+    b.clear(WORD, next(&b));
+    b.transfer(TMP, WORD, "skip");
+    b.label("decode");
+    for &digit in &[KIND, THEN, ELSE] {
+        b.block(&routines::divide(WORD, BASE, TMP, digit, SCRATCH));
+        b.clear(WORD, next(&b));
+        b.transfer(TMP, WORD, next(&b));
+    }
+    b.clear(WORD, "dispatch");
+    b.label("dispatch").decrement(KIND, "kind1", "halt");
+    b.label("kind1").decrement(KIND, "kind2", "inc0");
+    b.label("kind2").decrement(KIND, "kind3", "inc1");
+    b.label("kind3").decrement(KIND, "kind4", "dec0");
+    b.label("kind4").decrement(KIND, "invalid", "dec1");
+    b.label("invalid").clear(KIND, "halt");
+    // `$2 *= p` for `Increment`, continuing with `then`.
+    for &(label, p) in &[("inc0", 2), ("inc1", 3)] {
+        b.label(label);
+        b.transfer(REGS, TMP, next(&b));
+        let at = b.position();
+        b.decrement(TMP, Target::Addr(at + 1), "then");
+        b.add_const(REGS, p, Target::Addr(at));
+    }
+    // `$2 /= p` if possible for `Decrement`, continuing with `then`,
+    // otherwise `$2` is unchanged and the machine continues with `else`.
+    for &(label, p) in &[("dec0", 2), ("dec1", 3)] {
+        b.label(label);
+        let at = b.position();
+        b.sub_const(REGS, p, Target::Addr(at + 1), Target::Addr(at + 2));
+        b.increment(TMP, Target::Addr(at));
+        b.branch_zero(REGS, Target::Addr(at + 3), Target::Addr(at + 4));
+        b.transfer(TMP, REGS, "then");
+        b.decrement(TMP, Target::Addr(at + 5), "else");
+        b.add_const(REGS, p, Target::Addr(at + 4));
+    }
+    b.label("then");
+    b.clear(PTR, next(&b));
+    b.transfer(THEN, PTR, next(&b));
+    b.clear(ELSE, "fetch");
+    b.label("else");
+    b.clear(PTR, next(&b));
+    b.transfer(ELSE, PTR, next(&b));
+    b.clear(THEN, "fetch");
+    b.label("halt");
+    b.clear(THEN, next(&b));
+    b.clear(ELSE, next(&b));
+    b.clear(INSTRUCTION_BASE, next(&b));
+    b.halt();
+    b.build().unwrap()
+}
+
+/// The initial values of `$0`, `$1` and `$2` for [`universal`] simulating `program`
+/// with the registers `a` and `b`.
+///
+/// Returns `None` if `program` contains anything but `Halt`, `Increment` and
+/// `Decrement` on the registers `0` and `1`, or if these values do not fit into a `u64`.
+pub fn universal_input(program: &Program, a: u64, b: u64) -> Option<[u64; 3]> {
+    let targets = program.instructions().iter().flat_map(|i| i.targets());
+    let base = targets
+        .map(|target| u64::from(target) + 1)
+        .chain([5, program.len() as u64 + 1].iter().copied())
+        .max()
+        .unwrap();
+
+    let mut encoded = 0u64;
+    let mut digit = 1u64;
+    for (i, instruction) in program.iter() {
+        let digits = match instruction {
+            Instruction::Halt => [0, 0, 0],
+            Instruction::Increment(reg @ 0..=1, t) => [1 + u64::from(reg), u64::from(t), 0],
+            Instruction::Decrement(reg @ 0..=1, t, e) => {
+                [3 + u64::from(reg), u64::from(t), u64::from(e)]
+            }
+            _ => return None,
+        };
+        for (j, &value) in digits.iter().enumerate() {
+            if value != 0 {
+                encoded = encoded.checked_add(value.checked_mul(digit)?)?;
+            }
+            if usize::from(i) + 1 < program.len() || j < 2 {
+                digit = digit.checked_mul(base)?;
+            }
+        }
+    }
+
+    let regs = 2u64
+        .checked_pow(u32::try_from(a).ok()?)?
+        .checked_mul(3u64.checked_pow(u32::try_from(b).ok()?)?)?;
+    Some([encoded, base, regs])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{IsaLevel, Machine, RunOutcome};
+
+    fn run(program: &Program, input: &[u64]) -> Vec<u64> {
+        let mut prog: Machine = Machine::new(program);
+        prog.reset_with(input.to_vec());
+        assert!(matches!(prog.run(1 << 26), RunOutcome::Halted { .. }));
+        prog.registers()[..16].to_vec()
+    }
+
+    fn ackermann_ref(m: u64, n: u64) -> u64 {
+        match (m, n) {
+            (0, n) => n + 1,
+            (m, 0) => ackermann_ref(m - 1, 1),
+            (m, n) => ackermann_ref(m - 1, ackermann_ref(m, n - 1)),
+        }
+    }
+
+    fn with_zeros(values: &[u64]) -> Vec<u64> {
+        let mut registers = values.to_vec();
+        registers.resize(16, 0);
+        registers
+    }
+
+    #[test]
+    fn arithmetic() {
+        for program in &[addition(), multiplication(), division(), gcd(), fibonacci()] {
+            assert_eq!(program.isa_level(), IsaLevel::StrictMinsky);
+        }
+        assert_eq!(ackermann().isa_level(), IsaLevel::StrictMinsky);
+
+        let mut fib = (0, 1);
+        for a in 0..10 {
+            for b in 0..10 {
+                assert_eq!(run(&addition(), &[a, b]), with_zeros(&[a + b]));
+                assert_eq!(run(&multiplication(), &[a, b]), with_zeros(&[a, b, a * b]));
+                let (q, r) = (a.checked_div(b).unwrap_or(0), a.checked_rem(b).unwrap_or(a));
+                assert_eq!(run(&division(), &[a, b]), with_zeros(&[a, b, q, r]));
+                let gcd_ref = (1..=a.max(b))
+                    .filter(|d| a % d == 0 && b % d == 0)
+                    .max()
+                    .unwrap_or(0);
+                assert_eq!(run(&gcd(), &[a, b]), with_zeros(&[gcd_ref]));
+            }
+            assert_eq!(run(&fibonacci(), &[a]), with_zeros(&[0, fib.0]));
+            fib = (fib.1, fib.0 + fib.1);
+        }
+
+        for m in 0..3 {
+            for n in 0..3 {
+                let expected = with_zeros(&[0, ackermann_ref(m, n)]);
+                assert_eq!(run(&ackermann(), &[m, n]), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn universal_machine() {
+        let universal = universal();
+        assert_eq!(universal.isa_level(), IsaLevel::Accelerated);
+        let simulated = addition();
+        let input = universal_input(&simulated, 2, 3).unwrap();
+        assert_eq!(input, [4 + 5 + 2 * 25 + 125, 5, 108]);
+        assert_eq!(run(&universal, &input), with_zeros(&[input[0], 5, 32, 2]));
+
+        let shifted = addition().remap_registers(|r| r + 1);
+        assert_eq!(universal_input(&shifted, 0, 0), None);
+        assert_eq!(universal_input(&multiplication(), 0, 0), None);
+        assert_eq!(universal_input(&Program::empty(), 1, 1), Some([0, 5, 6]));
+    }
+}
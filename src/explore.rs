@@ -0,0 +1,179 @@
+use crate::io::NoIo;
+use crate::{Counter, Instruction, Machine, StepResult};
+use std::collections::HashSet;
+
+/// The result of [`Machine::explore`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Exploration {
+    /// A branch halts with the exit code `code` after `steps` steps.
+    ///
+    /// `choices` are the branches taken at each `Choose` and `Random` instruction,
+    /// with `true` meaning the second target.
+    Halts {
+        steps: u64,
+        code: u16,
+        choices: Vec<bool>,
+    },
+    /// No branch ever halts, as all branches got stuck or only reached
+    /// configurations which were already explored.
+    NeverHalts,
+    /// The step bound was reached while there were still `branches` unexplored branches.
+    Unknown { branches: usize },
+}
+
+impl<'p, C: Counter> Machine<'p, C> {
+    /// Explores all branches of the `Choose` and `Random` instructions breadth-first for
+    /// up to `max_steps` steps, returning whether any branch halts.
+    ///
+    /// Branches reaching an already explored configuration are dropped, so the
+    /// returned [`Exploration::Halts`] always uses the smallest number of steps.
+    /// `Random` instructions take every target they can take, independent of
+    /// the random number generator. `Read` instructions always read zero while exploring.
+    pub fn explore(&self, max_steps: u64) -> Exploration {
+        if let Some(code) = self.exit_code() {
+            return Exploration::Halts {
+                steps: 0,
+                code,
+                choices: Vec::new(),
+            };
+        }
+
+        let mut seen = HashSet::new();
+        seen.insert(self.configuration());
+        let mut branches = vec![(self.clone(), Vec::new())];
+        for steps in 1..=max_steps {
+            let mut next = Vec::new();
+            for (mut machine, choices) in branches {
+                let mut children = Vec::with_capacity(2);
+                if let Instruction::Random(probability, then, els) =
+                    machine.program().instruction(machine.ptr())
+                {
+                    // `els` is taken with a probability of at least `1 / 65536`.
+                    if probability > 0 {
+                        let mut other = machine.clone();
+                        let mut other_choices = choices.clone();
+                        other_choices.push(false);
+                        other.set_ptr(then);
+                        children.push((other, other_choices));
+                    }
+                    let mut choices = choices;
+                    choices.push(true);
+                    machine.set_ptr(els);
+                    children.push((machine, choices));
+                } else {
+                    match machine.step_with_io(&mut NoIo) {
+                        StepResult::Choice => {
+                            let mut other = machine.clone();
+                            let mut other_choices = choices.clone();
+                            other_choices.push(true);
+                            let mut choices = choices;
+                            choices.push(false);
+                            machine.choose(false);
+                            other.choose(true);
+                            children.push((machine, choices));
+                            children.push((other, other_choices));
+                        }
+                        result if result.executed() => children.push((machine, choices)),
+                        _ => {}
+                    }
+                }
+
+                for (machine, choices) in children {
+                    if let Some(code) = machine.exit_code() {
+                        return Exploration::Halts {
+                            steps,
+                            code,
+                            choices,
+                        };
+                    }
+
+                    if seen.insert(machine.configuration()) {
+                        next.push((machine, choices));
+                    }
+                }
+            }
+
+            if next.is_empty() {
+                return Exploration::NeverHalts;
+            }
+            branches = next;
+        }
+
+        Exploration::Unknown {
+            branches: branches.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Instruction, Program, RunOutcome};
+
+    #[test]
+    fn explore() {
+        // Nondeterministically guesses a multiple of 3 and halts if it equals $0.
+        let program = Program::new(
+            [
+                Instruction::Choose(1, 2),        // 0
+                Instruction::AddConst(1, 3, 0),   // 1
+                Instruction::Decrement(0, 3, 4),  // 2
+                Instruction::Decrement(1, 2, 6),  // 3
+                Instruction::BranchZero(1, 5, 6), // 4
+                Instruction::Halt,                // 5
+                Instruction::Jump(6),             // 6
+            ]
+            .iter()
+            .copied(),
+        );
+        let mut prog: Machine = Machine::new(&program);
+        assert_eq!(prog.run(10), RunOutcome::Choice { at: 0 });
+        prog.set_register(0, 6);
+        assert_eq!(
+            prog.explore(100),
+            Exploration::Halts {
+                steps: 19,
+                code: 0,
+                choices: vec![false, false, true],
+            }
+        );
+
+        prog.set_register(0, 7);
+        assert_eq!(prog.explore(3), Exploration::Unknown { branches: 3 });
+        assert!(matches!(prog.explore(1000), Exploration::Unknown { .. }));
+
+        let program = Program::new(
+            [
+                Instruction::Choose(1, 2),
+                Instruction::Jump(0),
+                Instruction::Purged,
+            ]
+            .iter()
+            .copied(),
+        );
+        let prog: Machine = Machine::new(&program);
+        assert_eq!(prog.explore(100), Exploration::NeverHalts);
+    }
+
+    #[test]
+    fn explore_random() {
+        let program = Program::new(vec![Instruction::Random(60000, 0, 1)]);
+        let prog: Machine = Machine::new(&program);
+        assert_eq!(
+            prog.explore(100),
+            Exploration::Halts {
+                steps: 1,
+                code: 0,
+                choices: vec![true],
+            }
+        );
+
+        let program = Program::new(vec![
+            Instruction::Random(0, 2, 1),
+            Instruction::Jump(0),
+            Instruction::Halt,
+        ]);
+        let prog: Machine = Machine::new(&program);
+        assert_eq!(prog.explore(100), Exploration::NeverHalts);
+    }
+}
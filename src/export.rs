@@ -0,0 +1,338 @@
+use crate::{Instruction, Program};
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+/// The operation of `instruction` without its jump targets, e.g. `DEC r1`.
+fn operation(instruction: Instruction) -> String {
+    let asm = instruction.to_string();
+    match asm.find(" -> ") {
+        Some(arrow) => asm[..arrow].to_owned(),
+        None if instruction.targets().next().is_some() => asm.split(' ').next().unwrap().to_owned(),
+        None => asm,
+    }
+}
+
+/// Describes the jump targets of `instruction`, in the order of [`Instruction::targets`].
+///
+/// Returns an empty slice for instructions with a single unconditional target.
+fn target_labels(instruction: Instruction) -> &'static [&'static str] {
+    match instruction {
+        Instruction::Decrement(..) => &["nonzero", "zero"],
+        Instruction::SubConst(..) | Instruction::Random(..) => &["then", "else"],
+        Instruction::BranchZero(..) => &["zero", "nonzero"],
+        Instruction::Compare(..) => &[">=", "<"],
+        Instruction::Choose(..) => &["first", "second"],
+        Instruction::Call(_) => &["call"],
+        _ => &[],
+    }
+}
+
+/// Escapes the special characters of LaTeX in text mode.
+fn escape_latex(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\\' => out.push_str("\\textbackslash{}"),
+            '~' => out.push_str("\\textasciitilde{}"),
+            '^' => out.push_str("\\textasciicircum{}"),
+            '<' => out.push_str("\\textless{}"),
+            '>' => out.push_str("\\textgreater{}"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// The rows of the tables returned by [`Program::to_markdown`] and [`Program::to_latex`].
+struct Row {
+    at: u16,
+    operation: String,
+    targets: String,
+    comment: Option<String>,
+}
+
+impl Program {
+    /// The edges of the control-flow graph starting at the instruction at `at`,
+    /// including the edge to the instruction after a `Call` which is taken
+    /// after returning from it.
+    fn graph_edges(
+        at: u16,
+        instruction: Instruction,
+    ) -> impl Iterator<Item = (u16, Option<&'static str>)> {
+        let labels = target_labels(instruction);
+        let ret = match instruction {
+            Instruction::Call(_) => at.checked_add(1).map(|next| (next, Some("return"))),
+            _ => None,
+        };
+        instruction
+            .targets()
+            .enumerate()
+            .map(move |(i, target)| (target, labels.get(i).copied()))
+            .chain(ret)
+    }
+
+    /// The nodes of the control-flow graph: all meaningful instructions
+    /// and the implicit `Halt`s reached by jumping past them.
+    fn graph_nodes(&self) -> impl Iterator<Item = (u16, Instruction)> + '_ {
+        let len = self.len();
+        let halts: BTreeSet<u16> = self
+            .iter()
+            .take(len)
+            .flat_map(|(at, instruction)| Program::graph_edges(at, instruction))
+            .map(|(target, _)| target)
+            .filter(|&target| usize::from(target) >= len)
+            .collect();
+        self.iter()
+            .take(len)
+            .chain(halts.into_iter().map(|at| (at, Instruction::Halt)))
+    }
+
+    /// The control-flow graph of this program in the DOT language of Graphviz.
+    ///
+    /// Every instruction is a node labeled with its index and operation, with
+    /// an edge to each of its jump targets. `Call`s also have an edge to the following
+    /// instruction, which is executed after returning. Trailing `Halt`s are only included
+    /// if they are the target of a jump.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph program {\n    node [shape=box];\n");
+        for (at, instruction) in self.graph_nodes() {
+            write!(
+                out,
+                "    {} [label=\"{}: {}\"",
+                at,
+                at,
+                operation(instruction)
+            )
+            .unwrap();
+            if let Instruction::Halt | Instruction::HaltWith(_) = instruction {
+                out.push_str(", shape=doublecircle");
+            }
+            out.push_str("];\n");
+            for (target, label) in Program::graph_edges(at, instruction) {
+                write!(out, "    {} -> {}", at, target).unwrap();
+                if let Some(label) = label {
+                    write!(out, " [label=\"{}\"]", label).unwrap();
+                }
+                out.push_str(";\n");
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    fn table_rows(&self, comment: impl Fn(u16) -> Option<String>) -> Vec<Row> {
+        self.iter()
+            .map(|(at, instruction)| Row {
+                at,
+                operation: operation(instruction),
+                targets: instruction
+                    .targets()
+                    .map(|target| target.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                comment: comment(at),
+            })
+            .collect()
+    }
+
+    /// This program as a Markdown table with the index, the operation and
+    /// the jump targets of each instruction, ignoring trailing `Halt`s.
+    ///
+    /// If `comment` returns a comment for any instruction, the table
+    /// has an additional column containing these comments.
+    pub fn to_markdown(&self, comment: impl Fn(u16) -> Option<String>) -> String {
+        let rows = self.table_rows(comment);
+        let comments = rows.iter().any(|row| row.comment.is_some());
+        let mut out = String::from("| Index | Instruction | Targets |");
+        out.push_str(if comments { " Comment |\n" } else { "\n" });
+        out.push_str("|------:|-------------|---------|");
+        out.push_str(if comments { "---------|\n" } else { "\n" });
+        for row in rows {
+            write!(
+                out,
+                "| {} | `{}` | {} |",
+                row.at, row.operation, row.targets
+            )
+            .unwrap();
+            if comments {
+                let comment = row.comment.unwrap_or_default().replace('|', "\\|");
+                write!(out, " {} |", comment).unwrap();
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// This program as a LaTeX `tabular`, with the same columns as [`Program::to_markdown`].
+    pub fn to_latex(&self, comment: impl Fn(u16) -> Option<String>) -> String {
+        let rows = self.table_rows(comment);
+        let comments = rows.iter().any(|row| row.comment.is_some());
+        let mut out = String::new();
+        if comments {
+            out.push_str("\\begin{tabular}{rlll}\n\\hline\n");
+            out.push_str("Index & Instruction & Targets & Comment \\\\\n");
+        } else {
+            out.push_str("\\begin{tabular}{rll}\n\\hline\n");
+            out.push_str("Index & Instruction & Targets \\\\\n");
+        }
+        out.push_str("\\hline\n");
+        for row in rows {
+            write!(
+                out,
+                "{} & \\texttt{{{}}} & {}",
+                row.at,
+                escape_latex(&row.operation),
+                row.targets
+            )
+            .unwrap();
+            if comments {
+                write!(out, " & {}", escape_latex(&row.comment.unwrap_or_default())).unwrap();
+            }
+            out.push_str(" \\\\\n");
+        }
+        out.push_str("\\hline\n\\end{tabular}\n");
+        out
+    }
+
+    /// The control-flow graph of this program as a Mermaid flowchart,
+    /// with the same nodes and edges as [`Program::to_dot`].
+    pub fn to_mermaid(&self) -> String {
+        // Mermaid uses `#lt;` and `#gt;` to escape `<` and `>` in labels.
+        let escape = |label: &str| label.replace('<', "#lt;").replace('>', "#gt;");
+        let mut out = String::from("flowchart TD\n");
+        for (at, instruction) in self.graph_nodes() {
+            let (open, close) = match instruction {
+                Instruction::Halt | Instruction::HaltWith(_) => ("([", "])"),
+                _ => ("[", "]"),
+            };
+            let label = format!("{}: {}", at, operation(instruction));
+            writeln!(out, "    n{}{}\"{}\"{}", at, open, escape(&label), close).unwrap();
+            for (target, label) in Program::graph_edges(at, instruction) {
+                match label {
+                    Some(label) => {
+                        writeln!(out, "    n{} -->|\"{}\"| n{}", at, escape(label), target)
+                    }
+                    None => writeln!(out, "    n{} --> n{}", at, target),
+                }
+                .unwrap();
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dot() {
+        let program = Program::new(
+            [
+                Instruction::Decrement(1, 1, 2),
+                Instruction::AddConst(0, 3, 0),
+                Instruction::Choose(0, 3),
+                Instruction::Call(5),
+                Instruction::Halt,
+            ]
+            .iter()
+            .copied(),
+        );
+        assert_eq!(
+            program.to_dot(),
+            "digraph program {
+    node [shape=box];
+    0 [label=\"0: DEC r1\"];
+    0 -> 1 [label=\"nonzero\"];
+    0 -> 2 [label=\"zero\"];
+    1 [label=\"1: ADD r0, 3\"];
+    1 -> 0;
+    2 [label=\"2: CHOOSE\"];
+    2 -> 0 [label=\"first\"];
+    2 -> 3 [label=\"second\"];
+    3 [label=\"3: CALL\"];
+    3 -> 5 [label=\"call\"];
+    3 -> 4 [label=\"return\"];
+    4 [label=\"4: HALT\", shape=doublecircle];
+    5 [label=\"5: HALT\", shape=doublecircle];
+}
+"
+        );
+    }
+
+    #[test]
+    fn mermaid() {
+        let program = Program::new(
+            [
+                Instruction::Compare(0, 1, 1, 2),
+                Instruction::Jump(0),
+                Instruction::HaltWith(3),
+            ]
+            .iter()
+            .copied(),
+        );
+        assert_eq!(
+            program.to_mermaid(),
+            "flowchart TD
+    n0[\"0: CMP r0, r1\"]
+    n0 -->|\"#gt;=\"| n1
+    n0 -->|\"#lt;\"| n2
+    n1[\"1: JMP\"]
+    n1 --> n0
+    n2([\"2: HALT 3\"])
+"
+        );
+    }
+
+    #[test]
+    fn tables() {
+        let program = Program::new(
+            [
+                Instruction::Decrement(1, 1, 2),
+                Instruction::Increment(0, 0),
+                Instruction::Halt,
+            ]
+            .iter()
+            .copied(),
+        );
+        assert_eq!(
+            program.to_markdown(|_| None),
+            "| Index | Instruction | Targets |
+|------:|-------------|---------|
+| 0 | `DEC r1` | 1, 2 |
+| 1 | `INC r0` | 0 |
+"
+        );
+        let comment = |at| match at {
+            1 => Some("a | b_1".to_owned()),
+            _ => None,
+        };
+        assert_eq!(
+            program.to_markdown(comment),
+            "| Index | Instruction | Targets | Comment |
+|------:|-------------|---------|---------|
+| 0 | `DEC r1` | 1, 2 |  |
+| 1 | `INC r0` | 0 | a \\| b_1 |
+"
+        );
+        assert_eq!(
+            program.to_latex(|_| None),
+            "\\begin{tabular}{rll}
+\\hline
+Index & Instruction & Targets \\\\
+\\hline
+0 & \\texttt{DEC r1} & 1, 2 \\\\
+1 & \\texttt{INC r0} & 0 \\\\
+\\hline
+\\end{tabular}
+"
+        );
+        assert!(program
+            .to_latex(comment)
+            .contains("1 & \\texttt{INC r0} & 0 & a | b\\_1 \\\\\n"));
+    }
+}
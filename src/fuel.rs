@@ -0,0 +1,41 @@
+/// A step budget which can be shared by multiple runs, see
+/// [`Machine::run_with_fuel`](crate::Machine::run_with_fuel).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fuel {
+    remaining: u64,
+    consumed: u64,
+}
+
+impl Fuel {
+    pub fn new(steps: u64) -> Fuel {
+        Fuel {
+            remaining: steps,
+            consumed: 0,
+        }
+    }
+
+    /// The number of steps which can still be executed.
+    pub fn remaining(&self) -> u64 {
+        self.remaining
+    }
+
+    /// The number of steps executed using this fuel so far.
+    pub fn consumed(&self) -> u64 {
+        self.consumed
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.remaining == 0
+    }
+
+    /// Adds `steps` to the remaining fuel.
+    pub fn refill(&mut self, steps: u64) {
+        self.remaining = self.remaining.saturating_add(steps);
+    }
+
+    pub(crate) fn consume(&mut self, steps: u64) {
+        debug_assert!(steps <= self.remaining);
+        self.remaining -= steps;
+        self.consumed += steps;
+    }
+}
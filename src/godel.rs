@@ -0,0 +1,158 @@
+use crate::binary::{constant, opcode, set_constant, template};
+use crate::{Instruction, Program, MAX_INSTRUCTIONS};
+use num_bigint::BigUint;
+use std::convert::TryFrom;
+
+/// The Cantor pairing function `(x + y) * (x + y + 1) / 2 + y`.
+fn pair(x: &BigUint, y: &BigUint) -> BigUint {
+    let sum = x + y;
+    ((&sum * (&sum + 1u32)) >> 1) + y
+}
+
+/// The inverse of [`pair`].
+fn unpair(z: &BigUint) -> (BigUint, BigUint) {
+    let w = ((z * 8u32 + 1u32).sqrt() - 1u32) >> 1;
+    let y = z - ((&w * (&w + 1u32)) >> 1);
+    (&w - &y, y)
+}
+
+/// Encodes a list of numbers as `0` for the empty list and
+/// `pair(first, rest) + 1` for all other lists.
+fn encode_list(values: impl DoubleEndedIterator<Item = BigUint>) -> BigUint {
+    values
+        .rev()
+        .fold(BigUint::default(), |rest, value| pair(&value, &rest) + 1u32)
+}
+
+/// Returns the elements of the list encoded by `n`, see [`encode_list`].
+fn decode_list(n: &BigUint) -> impl Iterator<Item = BigUint> {
+    let mut rest = n.clone();
+    std::iter::from_fn(move || {
+        if rest.bits() == 0 {
+            None
+        } else {
+            let (first, tail) = unpair(&(&rest - 1u32));
+            rest = tail;
+            Some(first)
+        }
+    })
+}
+
+fn encode_instruction(instruction: Instruction) -> BigUint {
+    let registers = instruction.registers().map(u64::from);
+    let values = std::iter::once(u64::from(opcode(instruction)))
+        .chain(registers)
+        .chain(constant(instruction))
+        .chain(instruction.targets().map(u64::from));
+    encode_list(values.map(BigUint::from).collect::<Vec<_>>().into_iter())
+}
+
+fn decode_instruction(n: &BigUint) -> Option<Instruction> {
+    let mut values = decode_list(n).map(|value| u64::try_from(&value).ok());
+    let mut instruction = template(u8::try_from(values.next()??).ok()?)?;
+    for reg in instruction.registers_mut() {
+        *reg = u8::try_from(values.next()??).ok()?;
+    }
+    if constant(instruction).is_some() && !set_constant(&mut instruction, values.next()??) {
+        return None;
+    }
+    for target in instruction.targets_mut() {
+        *target = u16::try_from(values.next()??).ok()?;
+    }
+    match values.next() {
+        Some(_) => None,
+        None => Some(instruction),
+    }
+}
+
+impl Program {
+    /// Encodes this program as a single natural number.
+    ///
+    /// Each instruction is encoded as the list of its opcode, see [`Program::to_bytes`],
+    /// followed by its operands. Lists are encoded using the Cantor pairing function
+    /// `pair`, with `0` being the empty list and `pair(first, rest) + 1` any other list.
+    /// The program itself is the list of its encoded instructions.
+    ///
+    /// Different programs have different numbers. Trailing `Halt`s are not part of
+    /// a program, see [`Program::len`], so they are not encoded.
+    pub fn godel_number(&self) -> BigUint {
+        encode_list(
+            self.instructions()
+                .iter()
+                .map(|&instruction| encode_instruction(instruction))
+                .collect::<Vec<_>>()
+                .into_iter(),
+        )
+    }
+
+    /// Decodes a program encoded using [`Program::godel_number`].
+    ///
+    /// Returns `None` if `n` does not encode a program, e.g. because an operand
+    /// is out of range or an instruction has the wrong number of operands. Numbers
+    /// encoding trailing `Halt`s are accepted, but the `Halt`s are dropped.
+    pub fn from_godel_number(n: &BigUint) -> Option<Program> {
+        let mut instructions = Vec::new();
+        for instruction in decode_list(n) {
+            if instructions.len() == MAX_INSTRUCTIONS {
+                return None;
+            }
+            instructions.push(decode_instruction(&instruction)?);
+        }
+        Some(Program::new(instructions))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pairing() {
+        for x in 0..20u32 {
+            for y in 0..20u32 {
+                let (x, y) = (BigUint::from(x), BigUint::from(y));
+                assert_eq!(unpair(&pair(&x, &y)), (x, y));
+            }
+        }
+        for z in 0..400u32 {
+            let z = BigUint::from(z);
+            let (x, y) = unpair(&z);
+            assert_eq!(pair(&x, &y), z);
+        }
+    }
+
+    #[test]
+    fn godel_number() {
+        assert_eq!(Program::empty().godel_number(), BigUint::from(0u32));
+        // `Halt` is the list `[0]`, encoded as `pair(0, 0) + 1 = 1`,
+        // so a program containing a single `Halt` is encoded as `pair(1, 0) + 1 = 2`.
+        let halt = Program::new([Instruction::Halt].iter().copied());
+        assert_eq!(halt.godel_number(), BigUint::from(0u32));
+        assert_eq!(Program::from_godel_number(&BigUint::from(2u32)), Some(halt));
+
+        let program = Program::new(
+            [
+                Instruction::Decrement(1, 1, 2),
+                Instruction::AddConst(0, u64::MAX, 0),
+                Instruction::HaltWith(3),
+                Instruction::Return,
+            ]
+            .iter()
+            .copied(),
+        );
+        let n = program.godel_number();
+        assert_eq!(Program::from_godel_number(&n), Some(program));
+
+        let mut valid = 0;
+        for n in 0..500u32 {
+            let n = BigUint::from(n);
+            if let Some(program) = Program::from_godel_number(&n) {
+                let number = program.godel_number();
+                assert!(number <= n);
+                assert_eq!(Program::from_godel_number(&number), Some(program));
+                valid += 1;
+            }
+        }
+        assert!(valid > 2);
+    }
+}
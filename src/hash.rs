@@ -0,0 +1,68 @@
+use crate::binary::write_instruction;
+use crate::Program;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+impl Program {
+    /// A hash of this program which is stable across versions of this crate,
+    /// e.g. to deduplicate enumerated programs.
+    ///
+    /// Programs which only differ in ways that cannot affect their behavior
+    /// have the same hash: trailing `Halt`s are ignored and all jump targets past
+    /// the end of the program are treated as equal.
+    ///
+    /// This is the 64 bit FNV-1a hash of the normalized instructions, encoded
+    /// as in version 1 of the binary format without a header, see [`Program::to_bytes`].
+    pub fn canonical_hash(&self) -> u64 {
+        let len = self.len();
+        let mut bytes = Vec::new();
+        for &instruction in self.instructions() {
+            let mut instruction = instruction;
+            for target in instruction.targets_mut() {
+                if *target as usize > len {
+                    *target = len as u16;
+                }
+            }
+            write_instruction(&mut bytes, instruction);
+        }
+        bytes.iter().fold(FNV_OFFSET, |hash, &byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Instruction;
+
+    #[test]
+    fn canonical_hash() {
+        assert_eq!(Program::empty().canonical_hash(), FNV_OFFSET);
+        let program = Program::new(
+            [Instruction::Decrement(0, 1, 2), Instruction::Jump(0)]
+                .iter()
+                .copied(),
+        );
+        assert_eq!(program.canonical_hash(), 0xd6d9_8426_2567_efa3);
+
+        let normalized = Program::new(
+            [
+                Instruction::Decrement(0, 1, 100),
+                Instruction::Jump(0),
+                Instruction::Halt,
+            ]
+            .iter()
+            .copied(),
+        );
+        assert_eq!(normalized.canonical_hash(), program.canonical_hash());
+
+        let different = Program::new(
+            [Instruction::Decrement(0, 1, 1), Instruction::Jump(0)]
+                .iter()
+                .copied(),
+        );
+        assert_ne!(different.canonical_hash(), program.canonical_hash());
+    }
+}
@@ -0,0 +1,315 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Instruction {
+    /// Stops the machine.
+    Halt,
+    /// `Increment(reg, target)` increments `reg` and jumps to `target`.
+    Increment(u8, u16),
+    /// `Decrement(reg, then, els)` decrements `reg` and jumps to `then`
+    /// if it is non-zero, otherwise it jumps to `els`.
+    Decrement(u8, u16, u16),
+    /// A removed instruction which must never be reached.
+    Purged,
+    /// `Jump(target)` jumps to `target` without changing any register.
+    Jump(u16),
+    /// `Clear(reg, target)` sets `reg` to zero and jumps to `target`.
+    Clear(u8, u16),
+    /// `AddConst(reg, n, target)` adds `n` to `reg` and jumps to `target`.
+    AddConst(u8, u64, u16),
+    /// `SubConst(reg, n, then, els)` subtracts `n` from `reg` and jumps to `then`
+    /// if `reg` is at least `n`, otherwise it leaves `reg` unchanged and jumps to `els`.
+    SubConst(u8, u64, u16, u16),
+    /// Adds the value of `src` to `dst`, sets `src` to zero and jumps to `then`.
+    ///
+    /// This is equivalent to the loop `Decrement(src, inc, then)`, `inc: Increment(dst, dec)`,
+    /// except that it takes a single step. Transferring a register to itself does nothing.
+    Transfer { src: u8, dst: u8, then: u16 },
+    /// `BranchZero(reg, zero, nonzero)` jumps to `zero` if `reg` is zero,
+    /// otherwise it jumps to `nonzero`. Unlike `Decrement`, it never changes `reg`.
+    BranchZero(u8, u16, u16),
+    /// Adds the value of `src` to `dst` in a single step, using `scratch` as temporary storage.
+    ///
+    /// This has the same effect as the loop returned by [`Instruction::copy_sequence`]:
+    /// `scratch` is added to `src` and then set to zero, so for a zero `scratch`
+    /// only `dst` changes. If the registers are not distinct this only jumps to `then`.
+    Copy {
+        src: u8,
+        dst: u8,
+        scratch: u8,
+        then: u16,
+    },
+    /// `Nop(target)` does nothing and jumps to `target`.
+    ///
+    /// It behaves like `Jump`, but marks filler inserted by program
+    /// transformations instead of control flow of the original program.
+    Nop(u16),
+    /// `HaltWith(code)` stops the machine with the exit code `code`,
+    /// a plain `Halt` uses the exit code `0`.
+    HaltWith(u16),
+    /// `Read(reg, target)` sets `reg` to the next input value and jumps to `target`.
+    ///
+    /// If the input is exhausted, `reg` is set to zero.
+    Read(u8, u16),
+    /// `Write(reg, target)` outputs the value of `reg` and jumps to `target`.
+    Write(u8, u16),
+    /// `Choose(first, second)` nondeterministically jumps to either `first` or `second`.
+    ///
+    /// Running machines stop at this instruction, see [`Machine::choose`](crate::Machine::choose)
+    /// and [`Machine::explore`](crate::Machine::explore).
+    Choose(u16, u16),
+    /// `Random(probability, then, els)` jumps to `then` with a probability of
+    /// `probability / 2^16`, otherwise it jumps to `els`.
+    ///
+    /// The random numbers are generated by the machine, see [`Machine::with_seed`](crate::Machine::with_seed).
+    Random(u16, u16, u16),
+    /// `Call(target)` pushes the address of the next instruction
+    /// onto the call stack and jumps to `target`.
+    Call(u16),
+    /// Pops an address from the call stack and jumps to it.
+    Return,
+    /// `Swap(a, b, target)` exchanges the values of `a` and `b` and jumps to `target`.
+    Swap(u8, u8, u16),
+    /// `Compare(a, b, ge, lt)` jumps to `ge` if `a` is greater than or equal to `b`,
+    /// otherwise it jumps to `lt`. Neither register is changed.
+    Compare(u8, u8, u16, u16),
+}
+
+/// The instruction sets supported by [`Program::validate`](crate::Program::validate).
+///
+/// Each level contains all instructions of the previous levels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum IsaLevel {
+    /// Only `Halt`, `Increment`, `Decrement` and `Purged`.
+    StrictMinsky,
+    /// Deterministic instructions which only act on the registers, the
+    /// instruction pointer and the call stack, e.g. `Jump`, `Transfer` or `Call`.
+    ///
+    /// These can be simulated by strict Minsky machines.
+    Accelerated,
+    /// All instructions, including `Read`, `Write`, `Choose` and `Random`.
+    Extended,
+}
+
+impl fmt::Display for IsaLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IsaLevel::StrictMinsky => f.write_str("strict Minsky"),
+            IsaLevel::Accelerated => f.write_str("accelerated"),
+            IsaLevel::Extended => f.write_str("extended"),
+        }
+    }
+}
+
+/// Formats the instruction in the assembly language of
+/// [`Program::parse_asm`](crate::Program::parse_asm), e.g. `DEC r1 -> 1, 2`.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Instruction::Halt => write!(f, "HALT"),
+            Instruction::HaltWith(code) => write!(f, "HALT {}", code),
+            Instruction::Purged => write!(f, "PURGED"),
+            Instruction::Increment(reg, t) => write!(f, "INC r{} -> {}", reg, t),
+            Instruction::Decrement(reg, t, e) => write!(f, "DEC r{} -> {}, {}", reg, t, e),
+            Instruction::Jump(t) => write!(f, "JMP {}", t),
+            Instruction::Nop(t) => write!(f, "NOP {}", t),
+            Instruction::Clear(reg, t) => write!(f, "CLR r{} -> {}", reg, t),
+            Instruction::AddConst(reg, n, t) => write!(f, "ADD r{}, {} -> {}", reg, n, t),
+            Instruction::SubConst(reg, n, t, e) => {
+                write!(f, "SUB r{}, {} -> {}, {}", reg, n, t, e)
+            }
+            Instruction::Transfer { src, dst, then } => {
+                write!(f, "MOV r{}, r{} -> {}", src, dst, then)
+            }
+            Instruction::Copy {
+                src,
+                dst,
+                scratch,
+                then,
+            } => write!(f, "COPY r{}, r{}, r{} -> {}", src, dst, scratch, then),
+            Instruction::BranchZero(reg, z, nz) => write!(f, "JZ r{} -> {}, {}", reg, z, nz),
+            Instruction::Compare(a, b, ge, lt) => {
+                write!(f, "CMP r{}, r{} -> {}, {}", a, b, ge, lt)
+            }
+            Instruction::Swap(a, b, t) => write!(f, "SWAP r{}, r{} -> {}", a, b, t),
+            Instruction::Read(reg, t) => write!(f, "READ r{} -> {}", reg, t),
+            Instruction::Write(reg, t) => write!(f, "WRITE r{} -> {}", reg, t),
+            Instruction::Choose(a, b) => write!(f, "CHOOSE {}, {}", a, b),
+            Instruction::Random(p, t, e) => write!(f, "RAND {} -> {}, {}", p, t, e),
+            Instruction::Call(t) => write!(f, "CALL {}", t),
+            Instruction::Return => write!(f, "RET"),
+        }
+    }
+}
+
+impl Instruction {
+    /// The jump targets of this instruction.
+    pub fn targets(self) -> impl Iterator<Item = u16> {
+        let (first, second) = match self {
+            Instruction::Halt
+            | Instruction::HaltWith(_)
+            | Instruction::Purged
+            | Instruction::Return => (None, None),
+            Instruction::Increment(_, target)
+            | Instruction::Jump(target)
+            | Instruction::Nop(target)
+            | Instruction::Call(target)
+            | Instruction::Swap(_, _, target)
+            | Instruction::Clear(_, target)
+            | Instruction::Read(_, target)
+            | Instruction::Write(_, target)
+            | Instruction::AddConst(_, _, target)
+            | Instruction::Transfer { then: target, .. }
+            | Instruction::Copy { then: target, .. } => (Some(target), None),
+            Instruction::Decrement(_, then, els)
+            | Instruction::SubConst(_, _, then, els)
+            | Instruction::BranchZero(_, then, els)
+            | Instruction::Choose(then, els)
+            | Instruction::Random(_, then, els)
+            | Instruction::Compare(_, _, then, els) => (Some(then), Some(els)),
+        };
+
+        first.into_iter().chain(second)
+    }
+
+    /// Mutable references to the jump targets of this instruction, in the same order as [`Instruction::targets`].
+    pub fn targets_mut(&mut self) -> impl Iterator<Item = &mut u16> {
+        let (first, second) = match self {
+            Instruction::Halt
+            | Instruction::HaltWith(_)
+            | Instruction::Purged
+            | Instruction::Return => (None, None),
+            Instruction::Increment(_, target)
+            | Instruction::Jump(target)
+            | Instruction::Nop(target)
+            | Instruction::Call(target)
+            | Instruction::Swap(_, _, target)
+            | Instruction::Clear(_, target)
+            | Instruction::Read(_, target)
+            | Instruction::Write(_, target)
+            | Instruction::AddConst(_, _, target)
+            | Instruction::Transfer { then: target, .. }
+            | Instruction::Copy { then: target, .. } => (Some(target), None),
+            Instruction::Decrement(_, then, els)
+            | Instruction::SubConst(_, _, then, els)
+            | Instruction::BranchZero(_, then, els)
+            | Instruction::Choose(then, els)
+            | Instruction::Random(_, then, els)
+            | Instruction::Compare(_, _, then, els) => (Some(then), Some(els)),
+        };
+
+        first.into_iter().chain(second)
+    }
+
+    /// The registers used by this instruction.
+    pub fn registers(self) -> impl Iterator<Item = u8> {
+        let registers = match self {
+            Instruction::Halt
+            | Instruction::HaltWith(_)
+            | Instruction::Purged
+            | Instruction::Jump(_)
+            | Instruction::Nop(_)
+            | Instruction::Choose(..)
+            | Instruction::Random(..)
+            | Instruction::Call(_)
+            | Instruction::Return => [None, None, None],
+            Instruction::Increment(reg, _)
+            | Instruction::Decrement(reg, ..)
+            | Instruction::Clear(reg, _)
+            | Instruction::AddConst(reg, ..)
+            | Instruction::SubConst(reg, ..)
+            | Instruction::BranchZero(reg, ..)
+            | Instruction::Read(reg, _)
+            | Instruction::Write(reg, _) => [Some(reg), None, None],
+            Instruction::Transfer { src: a, dst: b, .. }
+            | Instruction::Swap(a, b, _)
+            | Instruction::Compare(a, b, ..) => [Some(a), Some(b), None],
+            Instruction::Copy {
+                src, dst, scratch, ..
+            } => [Some(src), Some(dst), Some(scratch)],
+        };
+
+        IntoIterator::into_iter(registers).flatten()
+    }
+
+    /// Mutable references to the registers used by this instruction, in the same order as [`Instruction::registers`].
+    pub fn registers_mut(&mut self) -> impl Iterator<Item = &mut u8> {
+        let registers = match self {
+            Instruction::Halt
+            | Instruction::HaltWith(_)
+            | Instruction::Purged
+            | Instruction::Jump(_)
+            | Instruction::Nop(_)
+            | Instruction::Choose(..)
+            | Instruction::Random(..)
+            | Instruction::Call(_)
+            | Instruction::Return => [None, None, None],
+            Instruction::Increment(reg, _)
+            | Instruction::Decrement(reg, ..)
+            | Instruction::Clear(reg, _)
+            | Instruction::AddConst(reg, ..)
+            | Instruction::SubConst(reg, ..)
+            | Instruction::BranchZero(reg, ..)
+            | Instruction::Read(reg, _)
+            | Instruction::Write(reg, _) => [Some(reg), None, None],
+            Instruction::Transfer { src: a, dst: b, .. }
+            | Instruction::Swap(a, b, _)
+            | Instruction::Compare(a, b, ..) => [Some(a), Some(b), None],
+            Instruction::Copy {
+                src, dst, scratch, ..
+            } => [Some(src), Some(dst), Some(scratch)],
+        };
+
+        IntoIterator::into_iter(registers).flatten()
+    }
+
+    /// The smallest instruction set containing this instruction.
+    pub fn isa_level(self) -> IsaLevel {
+        match self {
+            Instruction::Halt
+            | Instruction::Increment(..)
+            | Instruction::Decrement(..)
+            | Instruction::Purged => IsaLevel::StrictMinsky,
+            Instruction::Jump(_)
+            | Instruction::Nop(_)
+            | Instruction::Clear(..)
+            | Instruction::AddConst(..)
+            | Instruction::SubConst(..)
+            | Instruction::Transfer { .. }
+            | Instruction::BranchZero(..)
+            | Instruction::Copy { .. }
+            | Instruction::HaltWith(_)
+            | Instruction::Call(_)
+            | Instruction::Return
+            | Instruction::Swap(..)
+            | Instruction::Compare(..) => IsaLevel::Accelerated,
+            Instruction::Read(..)
+            | Instruction::Write(..)
+            | Instruction::Choose(..)
+            | Instruction::Random(..) => IsaLevel::Extended,
+        }
+    }
+
+    /// The five strict Minsky instructions adding `src` to `dst` using `scratch`,
+    /// to be placed at `at` and continuing at `then`.
+    ///
+    /// This drains `src` into both `dst` and `scratch` and then drains `scratch`
+    /// back into `src`, see [`Instruction::Copy`] for the accelerated version.
+    /// Returns `None` if the instructions do not fit between `at` and `u16::MAX`.
+    pub fn copy_sequence(
+        src: u8,
+        dst: u8,
+        scratch: u8,
+        at: u16,
+        then: u16,
+    ) -> Option<[Instruction; 5]> {
+        at.checked_add(4)?;
+        Some([
+            Instruction::Decrement(src, at + 1, at + 3),
+            Instruction::Increment(dst, at + 2),
+            Instruction::Increment(scratch, at),
+            Instruction::Decrement(scratch, at + 4, then),
+            Instruction::Increment(src, at + 3),
+        ])
+    }
+}
@@ -0,0 +1,182 @@
+use crate::{Domain, Invariants, Program};
+use std::fmt;
+
+/// A nonempty range of register values `lo..=hi`, where `hi` is `None` if unbounded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Interval {
+    pub lo: u64,
+    pub hi: Option<u64>,
+}
+
+impl Interval {
+    /// All possible register values.
+    pub const ALL: Interval = Interval { lo: 0, hi: None };
+
+    pub fn constant(value: u64) -> Interval {
+        Interval {
+            lo: value,
+            hi: Some(value),
+        }
+    }
+
+    pub fn contains(&self, value: u64) -> bool {
+        self.lo <= value && self.hi.is_none_or(|hi| value <= hi)
+    }
+
+    /// The values of `self` which are at most `hi`.
+    fn at_most(self, hi: Option<u64>) -> Option<Interval> {
+        self.meet(Interval { lo: 0, hi })
+    }
+
+    /// The values of `self` which are at least `lo`.
+    fn at_least(self, lo: u64) -> Option<Interval> {
+        self.meet(Interval { lo, hi: None })
+    }
+}
+
+impl Domain for Interval {
+    fn top() -> Interval {
+        Interval::ALL
+    }
+
+    fn constant(value: u64) -> Interval {
+        Interval::constant(value)
+    }
+
+    fn join(self, other: Interval) -> Interval {
+        Interval {
+            lo: self.lo.min(other.lo),
+            hi: self.hi.and_then(|hi| Some(hi.max(other.hi?))),
+        }
+    }
+
+    fn meet(self, other: Interval) -> Option<Interval> {
+        let lo = self.lo.max(other.lo);
+        let hi = match (self.hi, other.hi) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (hi, None) | (None, hi) => hi,
+        };
+        match hi {
+            Some(hi) if hi < lo => None,
+            _ => Some(Interval { lo, hi }),
+        }
+    }
+
+    /// Extends the bounds of `self` which grew in `new` to `0` or infinity.
+    fn widen(self, new: Interval) -> Interval {
+        Interval {
+            lo: if new.lo < self.lo { 0 } else { self.lo },
+            hi: match (self.hi, new.hi) {
+                (Some(old), Some(new)) if new <= old => Some(old),
+                _ => None,
+            },
+        }
+    }
+
+    /// Bounds which overflow are unbounded.
+    fn add(self, other: Interval) -> Interval {
+        Interval {
+            lo: self.lo.saturating_add(other.lo),
+            hi: self.hi.and_then(|hi| hi.checked_add(other.hi?)),
+        }
+    }
+
+    fn sub_const(self, n: u64) -> Option<Interval> {
+        let at_least = self.at_least(n)?;
+        Some(Interval {
+            lo: at_least.lo - n,
+            hi: at_least.hi.map(|hi| hi - n),
+        })
+    }
+
+    fn below(self, n: u64) -> Option<Interval> {
+        self.at_most(Some(n.checked_sub(1)?))
+    }
+
+    fn refine_ge(a: Interval, b: Interval) -> Option<(Interval, Interval)> {
+        Some((a.at_least(b.lo)?, b.at_most(a.hi)?))
+    }
+
+    fn refine_lt(a: Interval, b: Interval) -> Option<(Interval, Interval)> {
+        let below = match b.hi {
+            Some(hi) => a.at_most(Some(hi.checked_sub(1)?))?,
+            None => a,
+        };
+        Some((below, b.at_least(a.lo.saturating_add(1))?))
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.hi {
+            Some(hi) => write!(f, "[{}, {}]", self.lo, hi),
+            None => write!(f, "[{}, inf)", self.lo),
+        }
+    }
+}
+
+/// The register values at each position of a program, see [`Program::intervals`].
+pub type Intervals = Invariants<Interval>;
+
+impl Program {
+    /// Computes the possible values of each register at each position of this program,
+    /// given the possible initial values of `inputs` and assuming that all other
+    /// registers start at zero.
+    ///
+    /// This uses [`Program::invariants`] with the [`Interval`] domain. Once the state at a
+    /// position has grown a few times, its growing bounds are widened to `0` and infinity.
+    pub fn intervals(&self, inputs: impl IntoIterator<Item = (u8, Interval)>) -> Intervals {
+        self.invariants(inputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Instruction;
+
+    #[test]
+    fn intervals() {
+        let interval = |lo, hi| Interval { lo, hi };
+        let program = Program::new(
+            [
+                Instruction::AddConst(0, 5, 1),
+                Instruction::Decrement(0, 2, 3),
+                Instruction::Increment(1, 1),
+                Instruction::Compare(1, 2, 4, 5),
+                Instruction::HaltWith(1),
+            ]
+            .iter()
+            .copied(),
+        );
+        let intervals = program.intervals(vec![(2, interval(3, Some(7)))]);
+        assert_eq!(intervals.register(1, 0), Some(interval(0, Some(5))));
+        assert_eq!(intervals.register(3, 0), Some(interval(0, Some(0))));
+        assert_eq!(intervals.register(3, 1), Some(interval(0, None)));
+        assert_eq!(intervals.register(4, 2), Some(interval(3, Some(7))));
+        assert_eq!(intervals.register(4, 1), Some(interval(3, None)));
+        assert_eq!(intervals.register(5, 1), Some(interval(0, Some(6))));
+        assert_eq!(intervals.register(9, 1), Some(interval(0, Some(6))));
+        assert_eq!(intervals.bound(0), Some(interval(0, Some(5))));
+        assert_eq!(intervals.bound(3), None);
+        assert!(!intervals.never_halts());
+        assert_eq!(interval(3, None).to_string(), "[3, inf)");
+
+        // $0 is never zero when testing it.
+        let program = Program::new(
+            [
+                Instruction::Increment(0, 1),
+                Instruction::Increment(1, 2),
+                Instruction::BranchZero(0, 3, 0),
+                Instruction::HaltWith(1),
+            ]
+            .iter()
+            .copied(),
+        );
+        let intervals = program.intervals(None);
+        assert!(intervals.never_halts());
+        assert_eq!(intervals.at(3), None);
+        assert_eq!(intervals.register(2, 0), Some(interval(1, None)));
+        assert!(program.intervals(Some((0, Interval::ALL))).never_halts());
+    }
+}
@@ -0,0 +1,90 @@
+use crate::Counter;
+use std::collections::VecDeque;
+use std::io::BufRead;
+
+/// The input and output used by the `Read` and `Write` instructions,
+/// see [`Machine::run_with_io`](crate::Machine::run_with_io).
+pub trait Io<C: Counter = u64> {
+    /// Returns the next input value, or `None` if the input is exhausted.
+    fn read(&mut self) -> Option<C>;
+
+    fn write(&mut self, value: &C);
+}
+
+impl<C: Counter, I: Io<C> + ?Sized> Io<C> for &mut I {
+    fn read(&mut self) -> Option<C> {
+        (**self).read()
+    }
+
+    fn write(&mut self, value: &C) {
+        (**self).write(value)
+    }
+}
+
+/// Reads one decimal value per line from stdin and writes one value per line to stdout.
+///
+/// Lines which can't be parsed are treated like the end of the input.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StdIo;
+
+impl<C: Counter> Io<C> for StdIo {
+    fn read(&mut self) -> Option<C> {
+        let mut line = String::new();
+        match std::io::stdin().lock().read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => line.trim().parse().ok(),
+        }
+    }
+
+    fn write(&mut self, value: &C) {
+        println!("{}", value);
+    }
+}
+
+/// Input and output which read only zeros and ignore all output, used
+/// when running programs which should not block on stdin.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct NoIo;
+
+impl<C: Counter> Io<C> for NoIo {
+    fn read(&mut self) -> Option<C> {
+        None
+    }
+
+    fn write(&mut self, _: &C) {}
+}
+
+/// In-memory input and output, mostly useful for tests.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct MemoryIo<C: Counter = u64> {
+    input: VecDeque<C>,
+    output: Vec<C>,
+}
+
+impl<C: Counter> MemoryIo<C> {
+    pub fn new(input: impl IntoIterator<Item = C>) -> MemoryIo<C> {
+        MemoryIo {
+            input: input.into_iter().collect(),
+            output: Vec::new(),
+        }
+    }
+
+    /// The input which has not been read yet.
+    pub fn input(&self) -> &VecDeque<C> {
+        &self.input
+    }
+
+    pub fn output(&self) -> &[C] {
+        &self.output
+    }
+}
+
+impl<C: Counter> Io<C> for MemoryIo<C> {
+    fn read(&mut self) -> Option<C> {
+        self.input.pop_front()
+    }
+
+    fn write(&mut self, value: &C) {
+        self.output.push(value.clone());
+    }
+}
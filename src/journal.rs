@@ -0,0 +1,72 @@
+use crate::rng::SplitMix64;
+use crate::Counter;
+use std::collections::VecDeque;
+
+/// The information needed to undo a single step.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Undo<C: Counter> {
+    /// The instruction pointer before the step.
+    pub ptr: u16,
+    /// The registers which may have been written by the step and their previous values.
+    pub writes: Vec<(u8, C)>,
+    /// How to undo the change to the call stack made by the step.
+    pub stack: Option<StackUndo>,
+    /// The state of the random number generator before a `Random` step.
+    pub rng: Option<SplitMix64>,
+}
+
+/// The inverse of a change to the call stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum StackUndo {
+    /// Removes the return address pushed by a `Call`.
+    Pop,
+    /// Restores the return address removed by a `Return`.
+    Push(u16),
+}
+
+/// A bounded ring buffer of the most recent steps, used by
+/// [`Machine::step_back`](crate::Machine::step_back).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Journal<C: Counter> {
+    entries: VecDeque<Undo<C>>,
+    capacity: usize,
+}
+
+impl<C: Counter> Journal<C> {
+    /// Creates an empty journal, which only allocates space for
+    /// the recorded steps once they are pushed.
+    pub fn new(capacity: usize) -> Journal<C> {
+        Journal {
+            entries: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Records a step, forgetting the oldest step if the journal is full.
+    pub fn push(&mut self, undo: Undo<C>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(undo);
+    }
+
+    pub fn pop(&mut self) -> Option<Undo<C>> {
+        self.entries.pop_back()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
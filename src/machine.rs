@@ -0,0 +1,2478 @@
+use crate::cycler::is_deterministic;
+use crate::journal::{Journal, StackUndo, Undo};
+use crate::rng::SplitMix64;
+use crate::{
+    BlockSummary, Configuration, Counter, Fuel, Instruction, Io, LoopSummary, Observer, Program,
+    RegisterEffect, Stats, StdIo, Trace,
+};
+use std::collections::BTreeSet;
+use std::convert::TryInto;
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+/// The default maximum depth of the call stack, see [`Machine::with_max_call_depth`].
+pub(crate) const DEFAULT_MAX_CALL_DEPTH: usize = 256;
+
+/// The number of steps between checks of the elapsed time in [`Machine::run_for`]
+/// or of the cancellation flag in [`Machine::run_cancellable`].
+const CHECK_INTERVAL: u64 = 1 << 16;
+
+/// The reason `Machine::run` stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunOutcome {
+    /// The program halted with the exit code `code` after `steps` steps.
+    Halted { steps: u64, code: u16 },
+    /// The step budget was exhausted before the program halted.
+    OutOfFuel,
+    /// The instruction pointer reached the `Purged` instruction at `at`.
+    HitPurged { at: u16 },
+    /// The instruction at `at` would have overflowed `reg` with [`OverflowPolicy::Checked`].
+    Overflow { at: u16, reg: u8 },
+    /// The run was stopped by the caller after `steps` steps.
+    Stopped { steps: u64 },
+    /// The machine never halts, as the configuration after `cycle_start`
+    /// steps repeats every `cycle_len` steps.
+    NonHalting { cycle_start: u64, cycle_len: u64 },
+    /// The time limit was reached after executing `steps` steps.
+    TimedOut { steps: u64 },
+    /// The run was cancelled after executing `steps` steps.
+    Cancelled { steps: u64 },
+    /// The instruction pointer reached the breakpoint at `at`.
+    Breakpoint { at: u16 },
+    /// The `Call` instruction at `at` would exceed the maximum call depth.
+    StackOverflow { at: u16 },
+    /// The `Return` instruction at `at` was reached with an empty call stack.
+    StackUnderflow { at: u16 },
+    /// The instruction pointer reached the `Choose` instruction at `at`,
+    /// see [`Machine::choose`].
+    Choice { at: u16 },
+    /// The instruction before `at` triggered a watchpoint on `reg`.
+    Watchpoint { at: u16, reg: u8 },
+}
+
+/// The result of executing a single instruction with `Machine::step`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepResult {
+    /// An instruction was executed.
+    Continued,
+    /// An instruction was executed and the machine is now halted.
+    Halted,
+    /// The machine was already halted, nothing was executed.
+    AlreadyHalted,
+    /// An instruction was executed and the instruction pointer is now at a breakpoint.
+    Breakpoint,
+    /// An instruction was executed which triggered a watchpoint on `reg`.
+    Watchpoint { reg: u8 },
+    /// The instruction pointer is at a `Purged` instruction, nothing was executed.
+    Purged,
+    /// Executing the current instruction would overflow `reg` with
+    /// [`OverflowPolicy::Checked`], nothing was executed.
+    Overflow { reg: u8 },
+    /// The instruction pointer is at a `Call` instruction and the call stack
+    /// is already at its maximum depth, nothing was executed.
+    StackOverflow,
+    /// The instruction pointer is at a `Return` instruction and the call stack
+    /// is empty, nothing was executed.
+    StackUnderflow,
+    /// The instruction pointer is at a `Choose` instruction, nothing was executed.
+    ///
+    /// Use [`Machine::choose`] to take one of its branches.
+    Choice,
+}
+
+impl StepResult {
+    /// Whether an instruction was executed.
+    pub fn executed(self) -> bool {
+        match self {
+            StepResult::Continued
+            | StepResult::Halted
+            | StepResult::Breakpoint
+            | StepResult::Watchpoint { .. } => true,
+            StepResult::AlreadyHalted
+            | StepResult::Purged
+            | StepResult::Overflow { .. }
+            | StepResult::StackOverflow
+            | StepResult::StackUnderflow
+            | StepResult::Choice => false,
+        }
+    }
+}
+
+/// The result of executing multiple steps with [`Machine::step_n`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepBatchResult {
+    /// The number of executed instructions.
+    pub executed: u64,
+    /// The result of the last step, this is [`StepResult::Continued`] if all
+    /// requested steps were executed without stopping.
+    pub result: StepResult,
+}
+
+/// What happens when incrementing a register which already holds its maximum value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum OverflowPolicy {
+    /// Stop with [`StepResult::Overflow`], leaving the machine unchanged.
+    #[default]
+    Checked,
+    /// Keep the register at its maximum value.
+    Saturating,
+    /// Wrap around to zero.
+    Wrapping,
+}
+
+/// What happens when reaching a `Purged` instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum PurgedPolicy {
+    /// Stop with [`StepResult::Purged`], leaving the machine unchanged.
+    #[default]
+    Error,
+    /// Treat `Purged` like `Halt`.
+    Halt,
+    /// Panic when trying to execute a `Purged` instruction.
+    Panic,
+}
+
+/// The condition of a watchpoint, see [`Machine::add_watchpoint`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Watch<C: Counter = u64> {
+    /// Triggers whenever the register changes.
+    Written,
+    /// Triggers once the register is set to the given value.
+    Reaches(C),
+    /// Triggers once the register is set to a value greater than the given threshold.
+    Exceeds(C),
+}
+
+/// A saved copy of the registers, instruction pointer and call stack of a [`Machine`].
+///
+/// This does not contain the program itself and can be restored
+/// with [`Machine::restore`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Snapshot<C: Counter = u64> {
+    registers: Box<[C; 1 << 8]>,
+    ptr: u16,
+    stack: Vec<u16>,
+}
+
+impl<C: Counter> Snapshot<C> {
+    pub fn registers(&self) -> &[C] {
+        &self.registers[..]
+    }
+
+    pub fn ptr(&self) -> u16 {
+        self.ptr
+    }
+
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    pub fn configuration(&self) -> Configuration<C> {
+        Configuration::new(self.ptr, &self.registers[..], &self.stack)
+    }
+}
+
+impl<C: Counter> From<&Configuration<C>> for Snapshot<C> {
+    fn from(configuration: &Configuration<C>) -> Snapshot<C> {
+        let mut registers: Box<[C; 1 << 8]> = vec![C::zero(); 1 << 8]
+            .into_boxed_slice()
+            .try_into()
+            .unwrap();
+        for (reg, value) in configuration.registers() {
+            registers[*reg as usize] = value.clone();
+        }
+        Snapshot {
+            registers,
+            ptr: configuration.ptr(),
+            stack: configuration.stack().to_vec(),
+        }
+    }
+}
+
+/// The execution state of a [`Program`]: its registers and instruction pointer.
+///
+/// The registers hold values of type `C`, see [`Counter`].
+#[derive(Debug, Clone)]
+pub struct Machine<'p, C: Counter = u64> {
+    program: &'p Program,
+    registers: Box<[C; 1 << 8]>,
+    ptr: u16,
+    overflow_policy: OverflowPolicy,
+    purged_policy: PurgedPolicy,
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<(u8, Watch<C>)>,
+    journal: Option<Journal<C>>,
+    rng: SplitMix64,
+    stack: Vec<u16>,
+    max_call_depth: usize,
+}
+
+impl<'p, C: Counter> Machine<'p, C> {
+    pub fn new(program: &'p Program) -> Machine<'p, C> {
+        Machine {
+            program,
+            registers: vec![C::zero(); 1 << 8]
+                .into_boxed_slice()
+                .try_into()
+                .unwrap(),
+            ptr: 0,
+            overflow_policy: OverflowPolicy::default(),
+            purged_policy: PurgedPolicy::default(),
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+            journal: None,
+            rng: SplitMix64::new(0),
+            stack: Vec::new(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+        }
+    }
+
+    /// Sets the maximum number of nested `Call` instructions, which is 256 by default.
+    pub fn with_max_call_depth(mut self, max_call_depth: usize) -> Machine<'p, C> {
+        self.max_call_depth = max_call_depth;
+        self
+    }
+
+    pub fn max_call_depth(&self) -> usize {
+        self.max_call_depth
+    }
+
+    /// The return addresses of the call stack, starting with the outermost call.
+    pub fn call_stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    /// Seeds the random number generator used by the `Random` instruction.
+    ///
+    /// Machines use the seed `0` by default, so runs are reproducible unless a different seed is used.
+    pub fn with_seed(mut self, seed: u64) -> Machine<'p, C> {
+        self.set_seed(seed);
+        self
+    }
+
+    /// Seeds the random number generator using a value generated by `rng`.
+    #[cfg(feature = "rand")]
+    pub fn with_seed_from(self, rng: &mut impl rand::RngCore) -> Machine<'p, C> {
+        self.with_seed(rng.next_u64())
+    }
+
+    /// Reseeds the random number generator used by the `Random` instruction.
+    ///
+    /// The generator is not affected by [`Machine::reset`]. Undoing a `Random` step
+    /// using [`Machine::step_back`] restores the state of the generator before the step,
+    /// so executing it again takes the same branch.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = SplitMix64::new(seed);
+    }
+
+    pub fn with_overflow_policy(mut self, overflow_policy: OverflowPolicy) -> Machine<'p, C> {
+        self.overflow_policy = overflow_policy;
+        self
+    }
+
+    pub fn overflow_policy(&self) -> OverflowPolicy {
+        self.overflow_policy
+    }
+
+    pub fn with_purged_policy(mut self, purged_policy: PurgedPolicy) -> Machine<'p, C> {
+        self.purged_policy = purged_policy;
+        self
+    }
+
+    pub fn purged_policy(&self) -> PurgedPolicy {
+        self.purged_policy
+    }
+
+    pub fn program(&self) -> &'p Program {
+        self.program
+    }
+
+    /// Resets all registers to zero and moves the instruction pointer back to `0`.
+    ///
+    /// This also clears the journal.
+    pub fn reset(&mut self) {
+        for value in self.registers.iter_mut() {
+            *value = C::zero();
+        }
+        self.ptr = 0;
+        self.stack.clear();
+        self.clear_journal();
+    }
+
+    /// Resets the machine and then stores `inputs` in the registers,
+    /// starting with register `0`.
+    pub fn reset_with(&mut self, inputs: impl IntoIterator<Item = C>) {
+        self.reset();
+        for (tgt, src) in self.registers.iter_mut().zip(inputs) {
+            *tgt = src;
+        }
+    }
+
+    pub fn snapshot(&self) -> Snapshot<C> {
+        Snapshot {
+            registers: self.registers.clone(),
+            ptr: self.ptr,
+            stack: self.stack.clone(),
+        }
+    }
+
+    pub fn configuration(&self) -> Configuration<C> {
+        Configuration::new(self.ptr, &self.registers[..], &self.stack)
+    }
+
+    /// Restores the registers and instruction pointer saved in `snapshot`.
+    ///
+    /// The snapshot may have been taken from a machine running a different
+    /// program. This also clears the journal.
+    pub fn restore(&mut self, snapshot: &Snapshot<C>) {
+        self.registers.clone_from(&snapshot.registers);
+        self.ptr = snapshot.ptr;
+        self.stack.clone_from(&snapshot.stack);
+        self.clear_journal();
+    }
+
+    /// Restores the registers, instruction pointer and call stack of `configuration`.
+    ///
+    /// Like [`Machine::restore`], this also clears the journal.
+    pub fn restore_configuration(&mut self, configuration: &Configuration<C>) {
+        self.restore(&Snapshot::from(configuration));
+    }
+
+    /// Starts recording the last `capacity` steps, so that they
+    /// can be undone using [`Machine::step_back`].
+    ///
+    /// Changes to the machine which are not caused by executing instructions,
+    /// e.g. using [`Machine::set_register`], are not recorded.
+    pub fn enable_journal(&mut self, capacity: usize) {
+        self.journal = Some(Journal::new(capacity));
+    }
+
+    pub fn disable_journal(&mut self) {
+        self.journal = None;
+    }
+
+    /// The number of steps which can currently be undone.
+    pub fn journal_len(&self) -> usize {
+        self.journal.as_ref().map_or(0, |journal| journal.len())
+    }
+
+    /// The maximum number of recorded steps, or `None` if journaling is disabled.
+    pub fn journal_capacity(&self) -> Option<usize> {
+        self.journal.as_ref().map(|journal| journal.capacity())
+    }
+
+    fn clear_journal(&mut self) {
+        if let Some(journal) = &mut self.journal {
+            journal.clear();
+        }
+    }
+
+    /// Undoes the most recent recorded step, returning `false`
+    /// if there is no such step.
+    pub fn step_back(&mut self) -> bool {
+        let undo = match self.journal.as_mut().and_then(|journal| journal.pop()) {
+            Some(undo) => undo,
+            None => return false,
+        };
+
+        self.ptr = undo.ptr;
+        for (reg, value) in undo.writes.into_iter().rev() {
+            self.registers[reg as usize] = value;
+        }
+        match undo.stack {
+            Some(StackUndo::Pop) => {
+                self.stack.pop();
+            }
+            Some(StackUndo::Push(ret)) => self.stack.push(ret),
+            None => {}
+        }
+        if let Some(rng) = undo.rng {
+            self.rng = rng;
+        }
+        true
+    }
+
+    pub fn set_register(&mut self, reg: u8, value: C) {
+        self.registers[reg as usize] = value;
+    }
+
+    pub fn get_register(&self, reg: u8) -> &C {
+        &self.registers[reg as usize]
+    }
+
+    /// All `256` registers, indexed by their register number.
+    pub fn registers(&self) -> &[C] {
+        &self.registers[..]
+    }
+
+    pub fn registers_mut(&mut self) -> &mut [C] {
+        &mut self.registers[..]
+    }
+
+    /// The index of the next instruction to execute.
+    pub fn ptr(&self) -> u16 {
+        self.ptr
+    }
+
+    pub fn set_ptr(&mut self, ptr: u16) {
+        self.ptr = ptr;
+    }
+
+    /// Adds a breakpoint at `ptr`, all runs stop with [`RunOutcome::Breakpoint`]
+    /// once the instruction pointer reaches it.
+    ///
+    /// Breakpoints are only checked after executing an instruction, so
+    /// running a machine which is at a breakpoint does not immediately stop.
+    pub fn add_breakpoint(&mut self, ptr: u16) {
+        self.breakpoints.insert(ptr);
+    }
+
+    /// Removes the breakpoint at `ptr`, returning whether there was one.
+    pub fn remove_breakpoint(&mut self, ptr: u16) -> bool {
+        self.breakpoints.remove(&ptr)
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    /// All breakpoints in increasing order.
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    /// Adds a watchpoint on `reg`. All runs stop with [`RunOutcome::Watchpoint`]
+    /// the first time `reg` is written in a way which satisfies `watch`, which
+    /// also removes the watchpoint.
+    pub fn add_watchpoint(&mut self, reg: u8, watch: Watch<C>) {
+        self.watchpoints.push((reg, watch));
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+    }
+
+    /// All watchpoints which have not been triggered yet.
+    pub fn watchpoints(&self) -> &[(u8, Watch<C>)] {
+        &self.watchpoints
+    }
+
+    /// Removes all watchpoints on `reg` which are satisfied by its current value,
+    /// returning whether there were any.
+    fn trigger_watchpoints(&mut self, reg: u8) -> bool {
+        if self.watchpoints.is_empty() {
+            return false;
+        }
+
+        let value = &self.registers[reg as usize];
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|(r, watch)| {
+            *r != reg
+                || match watch {
+                    Watch::Written => false,
+                    Watch::Reaches(target) => value != target,
+                    Watch::Exceeds(threshold) => value <= threshold,
+                }
+        });
+        self.watchpoints.len() != len
+    }
+
+    /// Triggers the watchpoints of all registers in `written`,
+    /// returning the first register with a triggered watchpoint.
+    fn trigger_all_watchpoints(&mut self, written: Writes) -> Option<u8> {
+        let mut triggered = None;
+        for &reg in written.as_slice() {
+            if self.trigger_watchpoints(reg) && triggered.is_none() {
+                triggered = Some(reg);
+            }
+        }
+        triggered
+    }
+
+    /// Whether the instruction pointer is at a `Halt` or `HaltWith` instruction,
+    /// or at a `Purged` instruction with [`PurgedPolicy::Halt`].
+    pub fn is_halted(&self) -> bool {
+        self.exit_code().is_some()
+    }
+
+    /// The exit code of the machine if it is halted.
+    ///
+    /// `Halt` and `Purged` with [`PurgedPolicy::Halt`] use the exit code `0`.
+    pub fn exit_code(&self) -> Option<u16> {
+        match self.program.instruction(self.ptr) {
+            Instruction::Halt => Some(0),
+            Instruction::HaltWith(code) => Some(code),
+            Instruction::Purged if self.purged_policy == PurgedPolicy::Halt => Some(0),
+            _ => None,
+        }
+    }
+
+    /// Executes the instruction at the instruction pointer.
+    ///
+    /// A halted machine is never modified and only returns [`StepResult::AlreadyHalted`].
+    ///
+    /// The `Read` and `Write` instructions use [`StdIo`], see [`Machine::step_with_io`].
+    pub fn step(&mut self) -> StepResult {
+        self.step_inner(&mut StdIo, None).0
+    }
+
+    /// Executes the instruction at the instruction pointer, using `io`
+    /// for the `Read` and `Write` instructions.
+    pub fn step_with_io(&mut self, io: &mut dyn Io<C>) -> StepResult {
+        self.step_inner(io, None).0
+    }
+
+    /// Executes the `Choose` instruction at the instruction pointer, jumping to
+    /// its second target if `second` is true and to its first target otherwise.
+    ///
+    /// This is the same as [`Machine::step`] if the machine is not at a `Choose` instruction.
+    pub fn choose(&mut self, second: bool) -> StepResult {
+        self.step_inner(&mut StdIo, Some(second)).0
+    }
+
+    /// Executes a single step, also returning the registers which were changed.
+    ///
+    /// `choice` is the branch taken by a `Choose` instruction, see [`Machine::choose`].
+    fn step_inner(&mut self, io: &mut dyn Io<C>, choice: Option<bool>) -> (StepResult, Writes) {
+        let instruction = self.program.instruction(self.ptr);
+        let undo = self.journal.as_ref().map(|_| Undo {
+            ptr: self.ptr,
+            writes: match instruction {
+                Instruction::Increment(reg, _)
+                | Instruction::Decrement(reg, _, _)
+                | Instruction::Clear(reg, _)
+                | Instruction::AddConst(reg, _, _)
+                | Instruction::SubConst(reg, _, _, _)
+                | Instruction::Read(reg, _) => {
+                    vec![(reg, self.registers[reg as usize].clone())]
+                }
+                Instruction::Transfer { src, dst, .. } | Instruction::Swap(src, dst, _) => vec![
+                    (src, self.registers[src as usize].clone()),
+                    (dst, self.registers[dst as usize].clone()),
+                ],
+                Instruction::Copy {
+                    src, dst, scratch, ..
+                } => vec![
+                    (src, self.registers[src as usize].clone()),
+                    (dst, self.registers[dst as usize].clone()),
+                    (scratch, self.registers[scratch as usize].clone()),
+                ],
+                Instruction::Halt
+                | Instruction::HaltWith(_)
+                | Instruction::Purged
+                | Instruction::Jump(_)
+                | Instruction::Nop(_)
+                | Instruction::BranchZero(..)
+                | Instruction::Write(..)
+                | Instruction::Choose(..)
+                | Instruction::Random(..)
+                | Instruction::Call(_)
+                | Instruction::Return
+                | Instruction::Compare(..) => Vec::new(),
+            },
+            stack: match instruction {
+                Instruction::Call(_) => Some(StackUndo::Pop),
+                Instruction::Return => self.stack.last().map(|&ret| StackUndo::Push(ret)),
+                _ => None,
+            },
+            rng: match instruction {
+                Instruction::Random(..) => Some(self.rng.clone()),
+                _ => None,
+            },
+        });
+
+        let written = match instruction {
+            Instruction::Halt | Instruction::HaltWith(_) => {
+                return (StepResult::AlreadyHalted, Writes::default())
+            }
+            Instruction::Increment(reg, target) => {
+                if !self.registers[reg as usize].increment(self.overflow_policy) {
+                    return (StepResult::Overflow { reg }, Writes::default());
+                }
+                self.ptr = target;
+                Writes::one(reg)
+            }
+            Instruction::Decrement(reg, then, els) => {
+                if self.registers[reg as usize].decrement() {
+                    self.ptr = then;
+                    Writes::one(reg)
+                } else {
+                    self.ptr = els;
+                    Writes::default()
+                }
+            }
+            Instruction::Jump(target) | Instruction::Nop(target) => {
+                self.ptr = target;
+                Writes::default()
+            }
+            Instruction::Clear(reg, target) => {
+                self.ptr = target;
+                let value = &mut self.registers[reg as usize];
+                if value.is_zero() {
+                    Writes::default()
+                } else {
+                    *value = C::zero();
+                    Writes::one(reg)
+                }
+            }
+            Instruction::AddConst(reg, n, target) => {
+                if !self.registers[reg as usize].add_const(n, self.overflow_policy) {
+                    return (StepResult::Overflow { reg }, Writes::default());
+                }
+                self.ptr = target;
+                Writes::one(reg)
+            }
+            Instruction::SubConst(reg, n, then, els) => {
+                if self.registers[reg as usize].sub_const(n) {
+                    self.ptr = then;
+                    Writes::one(reg)
+                } else {
+                    self.ptr = els;
+                    Writes::default()
+                }
+            }
+            Instruction::Transfer { src, dst, then } => {
+                if src == dst || self.registers[src as usize].is_zero() {
+                    self.ptr = then;
+                    Writes::default()
+                } else {
+                    let value = std::mem::replace(&mut self.registers[src as usize], C::zero());
+                    let target = &mut self.registers[dst as usize];
+                    if !target.add_counter(&value, self.overflow_policy) {
+                        self.registers[src as usize] = value;
+                        return (StepResult::Overflow { reg: dst }, Writes::default());
+                    }
+                    self.ptr = then;
+                    Writes::two(src, dst)
+                }
+            }
+            Instruction::Copy {
+                src,
+                dst,
+                scratch,
+                then,
+            } => match self.copy(src, dst, scratch) {
+                Ok(writes) => {
+                    self.ptr = then;
+                    writes
+                }
+                Err(reg) => return (StepResult::Overflow { reg }, Writes::default()),
+            },
+            Instruction::Read(reg, target) => {
+                self.registers[reg as usize] = io.read().unwrap_or_else(C::zero);
+                self.ptr = target;
+                Writes::one(reg)
+            }
+            Instruction::Write(reg, target) => {
+                io.write(&self.registers[reg as usize]);
+                self.ptr = target;
+                Writes::default()
+            }
+            Instruction::Choose(first, second) => match choice {
+                Some(take_second) => {
+                    self.ptr = if take_second { second } else { first };
+                    Writes::default()
+                }
+                None => return (StepResult::Choice, Writes::default()),
+            },
+            Instruction::Random(probability, then, els) => {
+                self.ptr = if self.rng.next_u64() >> 48 < u64::from(probability) {
+                    then
+                } else {
+                    els
+                };
+                Writes::default()
+            }
+            Instruction::Swap(a, b, target) => {
+                self.ptr = target;
+                if self.registers[a as usize] == self.registers[b as usize] {
+                    Writes::default()
+                } else {
+                    self.registers.swap(a as usize, b as usize);
+                    Writes::two(a, b)
+                }
+            }
+            Instruction::Compare(a, b, ge, lt) => {
+                self.ptr = if self.registers[a as usize] >= self.registers[b as usize] {
+                    ge
+                } else {
+                    lt
+                };
+                Writes::default()
+            }
+            Instruction::Call(target) => {
+                if self.stack.len() >= self.max_call_depth {
+                    return (StepResult::StackOverflow, Writes::default());
+                }
+                self.stack.push(self.ptr.wrapping_add(1));
+                self.ptr = target;
+                Writes::default()
+            }
+            Instruction::Return => match self.stack.pop() {
+                Some(ret) => {
+                    self.ptr = ret;
+                    Writes::default()
+                }
+                None => return (StepResult::StackUnderflow, Writes::default()),
+            },
+            Instruction::BranchZero(reg, zero, nonzero) => {
+                self.ptr = if self.registers[reg as usize].is_zero() {
+                    zero
+                } else {
+                    nonzero
+                };
+                Writes::default()
+            }
+            Instruction::Purged => match self.purged_policy {
+                PurgedPolicy::Error => return (StepResult::Purged, Writes::default()),
+                PurgedPolicy::Halt => return (StepResult::AlreadyHalted, Writes::default()),
+                PurgedPolicy::Panic => panic!("reached purged instruction at {}", self.ptr),
+            },
+        };
+
+        if let (Some(journal), Some(undo)) = (&mut self.journal, undo) {
+            journal.push(undo);
+        }
+
+        let result = if self.is_halted() {
+            StepResult::Halted
+        } else if let Some(reg) = self.trigger_all_watchpoints(written) {
+            StepResult::Watchpoint { reg }
+        } else if self.breakpoints.contains(&self.ptr) {
+            StepResult::Breakpoint
+        } else {
+            StepResult::Continued
+        };
+        (result, written)
+    }
+
+    /// Executes [`Instruction::Copy`], returning the register which would overflow on failure.
+    fn copy(&mut self, src: u8, dst: u8, scratch: u8) -> Result<Writes, u8> {
+        let mut writes = Writes::default();
+        if src == dst || src == scratch || dst == scratch {
+            return Ok(writes);
+        }
+
+        let value = &self.registers[src as usize];
+        let spare = &self.registers[scratch as usize];
+        let mut new_dst = self.registers[dst as usize].clone();
+        if !new_dst.add_counter(value, self.overflow_policy) {
+            return Err(dst);
+        }
+        let mut new_src = value.clone();
+        if !new_src.add_counter(spare, self.overflow_policy) {
+            return Err(src);
+        }
+
+        if !value.is_zero() {
+            writes.push(dst);
+        }
+        if !spare.is_zero() {
+            writes.push(src);
+            writes.push(scratch);
+        }
+        self.registers[dst as usize] = new_dst;
+        self.registers[src as usize] = new_src;
+        self.registers[scratch as usize] = C::zero();
+        Ok(writes)
+    }
+
+    /// Executes up to `n` instructions, stopping early if a step does not continue.
+    pub fn step_n(&mut self, n: u64) -> StepBatchResult {
+        let mut executed = 0;
+        while executed < n {
+            let result = self.step();
+            if result.executed() {
+                executed += 1;
+            }
+            if result != StepResult::Continued {
+                return StepBatchResult { executed, result };
+            }
+        }
+
+        StepBatchResult {
+            executed,
+            result: StepResult::Continued,
+        }
+    }
+
+    /// Returns an iterator which steps the machine, yielding the configuration
+    /// after each step. It stops once the machine can't make any more progress.
+    ///
+    /// The iterator does not stop on its own if the program does not halt.
+    pub fn states(&mut self) -> States<'_, 'p, C> {
+        States { machine: self }
+    }
+
+    pub fn run(&mut self, max_steps: u64) -> RunOutcome {
+        self.run_with_fuel(&mut Fuel::new(max_steps))
+    }
+
+    /// Runs the machine for at most `max_steps` steps, using `io` for
+    /// the `Read` and `Write` instructions.
+    pub fn run_with_io(&mut self, max_steps: u64, mut io: impl Io<C>) -> RunOutcome {
+        let mut steps = 0;
+        while steps < max_steps {
+            let result = self.step_with_io(&mut io);
+            if result.executed() {
+                steps += 1;
+            }
+            if result != StepResult::Continued {
+                return self.outcome(result, steps);
+            }
+        }
+
+        self.out_of_fuel(steps)
+    }
+
+    /// Runs the machine until it stops or `fuel` runs out, consuming one unit
+    /// of fuel for each executed instruction.
+    ///
+    /// The `steps` of [`RunOutcome::Halted`] only count the steps of this run.
+    pub fn run_with_fuel(&mut self, fuel: &mut Fuel) -> RunOutcome {
+        let batch = self.step_n(fuel.remaining());
+        fuel.consume(batch.executed);
+        match batch.result {
+            StepResult::Continued => self.out_of_fuel(batch.executed),
+            result => self.outcome(result, batch.executed),
+        }
+    }
+
+    /// Runs the machine until it stops or `duration` has elapsed.
+    ///
+    /// The elapsed time is only checked every few thousand steps,
+    /// so this may run slightly longer than `duration`.
+    pub fn run_for(&mut self, duration: Duration) -> RunOutcome {
+        let start = Instant::now();
+        let mut steps = 0;
+        loop {
+            let batch = self.step_n(CHECK_INTERVAL);
+            steps += batch.executed;
+            if batch.result != StepResult::Continued {
+                return self.outcome(batch.result, steps);
+            }
+
+            if start.elapsed() >= duration {
+                return RunOutcome::TimedOut { steps };
+            }
+        }
+    }
+
+    /// Runs the machine for at most `max_steps` steps, stopping with
+    /// [`RunOutcome::Cancelled`] once `cancel` is set.
+    ///
+    /// `cancel` is only checked every few thousand steps, so it can be set from
+    /// another thread to cleanly abort a long run.
+    pub fn run_cancellable(&mut self, max_steps: u64, cancel: &AtomicBool) -> RunOutcome {
+        let mut steps = 0;
+        while steps < max_steps {
+            if cancel.load(Ordering::Relaxed) {
+                return RunOutcome::Cancelled { steps };
+            }
+
+            let batch = self.step_n(CHECK_INTERVAL.min(max_steps - steps));
+            steps += batch.executed;
+            if batch.result != StepResult::Continued {
+                return self.outcome(batch.result, steps);
+            }
+        }
+
+        self.out_of_fuel(steps)
+    }
+
+    /// Runs the machine for at most `max_steps` steps, sending a [`Progress`]
+    /// report through `sender` every `interval` steps.
+    ///
+    /// The run continues even if the receiver has been dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` is zero.
+    pub fn run_with_progress(
+        &mut self,
+        max_steps: u64,
+        interval: u64,
+        sender: &Sender<Progress<C>>,
+    ) -> RunOutcome {
+        assert!(interval > 0, "progress interval must not be zero");
+        let mut steps = 0;
+        while steps < max_steps {
+            let batch = self.step_n(interval.min(max_steps - steps));
+            steps += batch.executed;
+            if batch.result != StepResult::Continued {
+                return self.outcome(batch.result, steps);
+            }
+
+            if steps % interval == 0 {
+                let _ = sender.send(Progress {
+                    steps,
+                    configuration: self.configuration(),
+                });
+            }
+        }
+
+        self.out_of_fuel(steps)
+    }
+
+    /// Runs the machine for at most `max_steps` steps, calling `f` after each step
+    /// with the machine and the number of steps executed so far.
+    ///
+    /// Returns [`RunOutcome::Stopped`] if `f` breaks.
+    pub fn run_with(
+        &mut self,
+        max_steps: u64,
+        mut f: impl FnMut(&Machine<'p, C>, u64) -> ControlFlow<()>,
+    ) -> RunOutcome {
+        let mut steps = 0;
+        while steps < max_steps {
+            let result = self.step();
+            let flow = if result.executed() {
+                steps += 1;
+                f(self, steps)
+            } else {
+                ControlFlow::Continue(())
+            };
+
+            if result != StepResult::Continued {
+                return self.outcome(result, steps);
+            } else if flow.is_break() {
+                return RunOutcome::Stopped { steps };
+            }
+        }
+
+        self.out_of_fuel(steps)
+    }
+
+    /// Runs the machine until `pred` holds, for at most `max_steps` steps.
+    ///
+    /// Returns [`RunOutcome::Stopped`] once `pred` holds, which may be before
+    /// executing any instruction.
+    pub fn run_until(
+        &mut self,
+        pred: impl Fn(&Machine<'p, C>) -> bool,
+        max_steps: u64,
+    ) -> RunOutcome {
+        if pred(self) {
+            return RunOutcome::Stopped { steps: 0 };
+        }
+
+        self.run_with(max_steps, |m, _| {
+            if pred(m) {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        })
+    }
+
+    /// Runs the machine for at most `max_steps` steps, calling the hooks of `observer`.
+    pub fn run_observed<O: Observer<C>>(&mut self, max_steps: u64, mut observer: O) -> RunOutcome {
+        observer.on_start(self);
+        let mut steps = 0;
+        while steps < max_steps {
+            let at = self.ptr;
+            let (result, written) = self.step_inner(&mut StdIo, None);
+            let flow = if result.executed() {
+                steps += 1;
+                for &reg in written.as_slice() {
+                    observer.on_register_write(reg, &self.registers[reg as usize]);
+                }
+                observer.on_step(self, at, written.as_slice())
+            } else {
+                ControlFlow::Continue(())
+            };
+
+            if result != StepResult::Continued {
+                let outcome = self.outcome(result, steps);
+                if let RunOutcome::Halted { steps, .. } = outcome {
+                    observer.on_halt(self, steps);
+                }
+                return outcome;
+            } else if flow.is_break() {
+                return RunOutcome::Stopped { steps };
+            }
+        }
+
+        let outcome = self.out_of_fuel(steps);
+        if let RunOutcome::Halted { steps, .. } = outcome {
+            observer.on_halt(self, steps);
+        }
+        outcome
+    }
+
+    /// Runs the machine for at most `max_steps` steps while collecting statistics in `stats`.
+    pub fn run_with_stats(&mut self, max_steps: u64, stats: &mut Stats<C>) -> RunOutcome {
+        self.run_observed(max_steps, stats)
+    }
+
+    /// Runs the machine for at most `max_steps` steps while recording its steps in `trace`.
+    pub fn run_traced(&mut self, max_steps: u64, trace: &mut Trace<C>) -> RunOutcome {
+        self.run_observed(max_steps, trace)
+    }
+
+    /// Runs the machine for at most `max_steps` steps, stopping with
+    /// [`RunOutcome::NonHalting`] once a configuration repeats.
+    ///
+    /// This uses Brent's algorithm, so it detects the cycle after at most
+    /// `2 * (cycle_start + cycle_len)` steps. Computing `cycle_start` afterwards
+    /// then needs another `cycle_start + cycle_len` steps which are not
+    /// counted towards `max_steps`.
+    ///
+    /// A repeating configuration does not imply a cycle if the program uses `Choose`,
+    /// `Random`, `Read` or `Write`, so such programs are run like [`Machine::run`].
+    pub fn run_detecting_cycles(&mut self, max_steps: u64) -> RunOutcome {
+        if !is_deterministic(self.program) {
+            return self.run(max_steps);
+        }
+        let start = self.snapshot();
+        let mut tortoise = start.clone();
+        let mut power = 1;
+        let mut cycle_len = 0;
+        let mut steps = 0;
+        while steps < max_steps {
+            let result = self.step();
+            if result.executed() {
+                steps += 1;
+            }
+            if result != StepResult::Continued {
+                return self.outcome(result, steps);
+            }
+
+            cycle_len += 1;
+            if self.matches(&tortoise) {
+                let cycle_start = self.cycle_start(&start, cycle_len);
+                return RunOutcome::NonHalting {
+                    cycle_start,
+                    cycle_len,
+                };
+            }
+
+            if power == cycle_len {
+                tortoise = self.snapshot();
+                power *= 2;
+                cycle_len = 0;
+            }
+        }
+
+        self.out_of_fuel(steps)
+    }
+
+    /// Runs the machine for at most `max_steps` steps, executing simple loops in
+    /// constant time, see [`Program::loop_summary`].
+    ///
+    /// Whenever the instruction pointer is at the header of a simple loop, all iterations
+    /// of the loop which fit into the remaining steps are applied at once. The result is
+    /// the same as with [`Machine::run`], including the number of steps. Loops are only
+    /// accelerated if there are no breakpoints, watchpoints and no journal, if the
+    /// counter fits into a `u64`, see [`Counter::to_u64`], and if the loop does not
+    /// overflow any register with [`OverflowPolicy::Checked`].
+    pub fn run_accelerated(&mut self, max_steps: u64) -> RunOutcome {
+        let summaries: Vec<_> = self
+            .program
+            .iter()
+            .map(|(at, _)| self.program.loop_summary(at))
+            .collect();
+        let mut steps = 0;
+        while steps < max_steps {
+            if let Some(Some(summary)) = summaries.get(usize::from(self.ptr)) {
+                let iterations = self.accelerate(summary, max_steps - steps);
+                if iterations > 0 {
+                    steps += iterations * summary.iteration_steps;
+                    continue;
+                }
+            }
+
+            let result = self.step();
+            if result.executed() {
+                steps += 1;
+            }
+            if result != StepResult::Continued {
+                return self.outcome(result, steps);
+            }
+        }
+
+        self.out_of_fuel(steps)
+    }
+
+    /// Whether multiple steps may be executed at once, which is not possible
+    /// when there are breakpoints, watchpoints or a journal.
+    fn can_skip(&self) -> bool {
+        self.journal.is_none() && self.watchpoints.is_empty() && self.breakpoints.is_empty()
+    }
+
+    /// Executes as many iterations of the loop at the instruction pointer as possible
+    /// using at most `max_steps` steps, returning the number of iterations.
+    pub(crate) fn accelerate(&mut self, summary: &LoopSummary, max_steps: u64) -> u64 {
+        if !self.can_skip() {
+            return 0;
+        }
+        let counter = &self.registers[summary.counter as usize];
+        let iterations = match counter.to_u64() {
+            Some(value) => value.min(max_steps / summary.iteration_steps),
+            None => return 0,
+        };
+        if iterations == 0 {
+            return 0;
+        }
+
+        let mut values = Vec::with_capacity(summary.increments.len() + 1);
+        for (&reg, &n) in &summary.increments {
+            let mut value = self.registers[reg as usize].clone();
+            match n.checked_mul(iterations) {
+                Some(total) if value.add_const(total, self.overflow_policy) => {
+                    values.push((reg, value))
+                }
+                _ => return 0,
+            }
+        }
+        let mut counter = counter.clone();
+        if !counter.sub_const(iterations) {
+            return 0;
+        }
+        values.push((summary.counter, counter));
+        for (reg, value) in values {
+            self.registers[reg as usize] = value;
+        }
+        iterations
+    }
+
+    /// Executes the block at the instruction pointer at once, returning
+    /// `false` without changing anything if this is not possible.
+    pub(crate) fn apply_block(&mut self, block: &BlockSummary) -> bool {
+        if !self.can_skip() || self.ptr != block.start {
+            return false;
+        }
+
+        let mut values = Vec::with_capacity(block.effects.len());
+        for (&reg, &effect) in &block.effects {
+            let (mut value, n) = match effect {
+                RegisterEffect::Add(n) => (self.registers[reg as usize].clone(), n),
+                RegisterEffect::Set(n) => (C::zero(), n),
+            };
+            if !value.add_const(n, self.overflow_policy) {
+                return false;
+            }
+            values.push((reg, value));
+        }
+        for (reg, value) in values {
+            self.registers[reg as usize] = value;
+        }
+        self.ptr = block.next;
+        true
+    }
+
+    /// Whether the machine is currently in the state saved in `snapshot`.
+    fn matches(&self, snapshot: &Snapshot<C>) -> bool {
+        self.ptr == snapshot.ptr
+            && self.registers == snapshot.registers
+            && self.stack == snapshot.stack
+    }
+
+    /// Finds the first step of a cycle with length `cycle_len` reached from `start`.
+    fn cycle_start(&self, start: &Snapshot<C>, cycle_len: u64) -> u64 {
+        let mut tortoise = self.clone();
+        tortoise.restore(start);
+        let mut hare = tortoise.clone();
+        for _ in 0..cycle_len {
+            hare.step();
+        }
+
+        let mut cycle_start = 0;
+        while !hare.matches(&tortoise.snapshot()) {
+            tortoise.step();
+            hare.step();
+            cycle_start += 1;
+        }
+        cycle_start
+    }
+
+    /// Converts the result of a step which did not continue into a `RunOutcome`,
+    /// `steps` is the total number of executed steps of the current run.
+    pub(crate) fn outcome(&self, result: StepResult, steps: u64) -> RunOutcome {
+        match result {
+            StepResult::Continued => unreachable!("continued step is not an outcome"),
+            StepResult::Halted | StepResult::AlreadyHalted => match self.exit_code() {
+                Some(code) => RunOutcome::Halted { steps, code },
+                None => unreachable!("halted machine without exit code"),
+            },
+            StepResult::Breakpoint => RunOutcome::Breakpoint { at: self.ptr },
+            StepResult::Watchpoint { reg } => RunOutcome::Watchpoint { at: self.ptr, reg },
+            StepResult::Purged => RunOutcome::HitPurged { at: self.ptr },
+            StepResult::Overflow { reg } => RunOutcome::Overflow { at: self.ptr, reg },
+            StepResult::StackOverflow => RunOutcome::StackOverflow { at: self.ptr },
+            StepResult::StackUnderflow => RunOutcome::StackUnderflow { at: self.ptr },
+            StepResult::Choice => RunOutcome::Choice { at: self.ptr },
+        }
+    }
+
+    /// The outcome after using up all steps of a run which executed `steps` steps.
+    ///
+    /// A machine at a halting instruction is halted even if the run had no steps left.
+    pub(crate) fn out_of_fuel(&self, steps: u64) -> RunOutcome {
+        if let Some(code) = self.exit_code() {
+            return RunOutcome::Halted { steps, code };
+        }
+        match self.program.instruction(self.ptr) {
+            Instruction::Purged if self.purged_policy == PurgedPolicy::Error => {
+                RunOutcome::HitPurged { at: self.ptr }
+            }
+            _ => RunOutcome::OutOfFuel,
+        }
+    }
+}
+
+/// A progress report sent by [`Machine::run_with_progress`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Progress<C: Counter = u64> {
+    /// The number of steps executed so far in this run.
+    pub steps: u64,
+    /// The current configuration of the machine.
+    pub configuration: Configuration<C>,
+}
+
+/// The registers changed by a single step.
+#[derive(Debug, Clone, Copy, Default)]
+struct Writes {
+    regs: [u8; 3],
+    len: usize,
+}
+
+impl Writes {
+    fn one(reg: u8) -> Writes {
+        Writes {
+            regs: [reg, 0, 0],
+            len: 1,
+        }
+    }
+
+    fn two(first: u8, second: u8) -> Writes {
+        Writes {
+            regs: [first, second, 0],
+            len: 2,
+        }
+    }
+
+    fn push(&mut self, reg: u8) {
+        self.regs[self.len] = reg;
+        self.len += 1;
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        &self.regs[..self.len]
+    }
+}
+
+/// An iterator over the configurations of a running machine, see [`Machine::states`].
+pub struct States<'m, 'p, C: Counter> {
+    machine: &'m mut Machine<'p, C>,
+}
+
+impl<'m, 'p, C: Counter> Iterator for States<'m, 'p, C> {
+    type Item = Configuration<C>;
+
+    fn next(&mut self) -> Option<Configuration<C>> {
+        match self.machine.step() {
+            result if result.executed() => Some(self.machine.configuration()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryIo;
+
+    #[test]
+    fn halt() {
+        let program = Program::empty();
+        let mut prog: Machine = Machine::new(&program);
+        assert_eq!(prog.run(u64::MAX), RunOutcome::Halted { steps: 0, code: 0 });
+    }
+
+    #[test]
+    fn substract() {
+        // $0 = $0 - $1
+        let program = Program::new(
+            [
+                // Move $1 to a placeholder location.
+                Instruction::Decrement(1, 1, 2), // 0
+                Instruction::Increment(2, 0),    // 1
+                // Move placeholder back to $1 while decreasing $0
+                Instruction::Decrement(2, 3, 5), // 2
+                Instruction::Increment(1, 4),    // 3
+                Instruction::Decrement(0, 2, 2), // 4
+                Instruction::Halt,               // 5
+            ]
+            .iter()
+            .copied(),
+        );
+        let mut prog: Machine = Machine::new(&program);
+
+        prog.set_register(0, 98);
+        prog.set_register(1, 81);
+
+        assert_eq!(
+            prog.run(u64::MAX),
+            RunOutcome::Halted {
+                steps: 407,
+                code: 0
+            }
+        );
+        assert_eq!(*prog.get_register(0), 17);
+        assert_eq!(*prog.get_register(1), 81);
+    }
+
+    #[test]
+    fn add() {
+        // $0 = $0 + $1
+        let program = Program::new(
+            [
+                // Move $1 to a placeholder location.
+                Instruction::Decrement(1, 1, 2), // 0
+                Instruction::Increment(2, 0),    // 1
+                // Move placeholder back to $1 while also increasing $0
+                Instruction::Decrement(2, 3, 5), // 2
+                Instruction::Increment(1, 4),    // 3
+                Instruction::Increment(0, 2),    // 4
+                Instruction::Halt,               // 5
+            ]
+            .iter()
+            .copied(),
+        );
+        let mut prog: Machine = Machine::new(&program);
+
+        prog.set_register(0, 98);
+        prog.set_register(1, 81);
+
+        assert_eq!(
+            prog.run(u64::MAX),
+            RunOutcome::Halted {
+                steps: 407,
+                code: 0
+            }
+        );
+        assert_eq!(*prog.get_register(0), 179);
+        assert_eq!(*prog.get_register(1), 81);
+    }
+
+    #[test]
+    fn multiply() {
+        // $0 = $0 * $1
+        let program = Program::new(
+            [
+                // Move $1 to a placeholder location ($2).
+                Instruction::Decrement(1, 1, 2), // 0
+                Instruction::Increment(2, 0),    // 1
+                // Move $0 to a placeholder location ($3)
+                Instruction::Decrement(0, 3, 4), // 2
+                Instruction::Increment(3, 2),    // 3
+                // Move $2 back into $1
+                // At each step, take turns either moving $3 to $0 and $4
+                // or moving $4 to $0 and $3.
+                Instruction::Decrement(2, 5, 14), // 4
+                Instruction::Increment(1, 6),     // 5
+                // Move $3 into $0 and $4
+                Instruction::Decrement(3, 7, 9), // 6
+                Instruction::Increment(0, 8),    // 7
+                Instruction::Increment(4, 6),    // 8
+                // Decrement $2
+                Instruction::Decrement(2, 10, 14), // 9
+                Instruction::Increment(1, 11),     // 10
+                // Move $4 into $0 and $3
+                Instruction::Decrement(4, 12, 4), // 11
+                Instruction::Increment(0, 13),    // 12
+                Instruction::Increment(3, 11),    // 13
+                Instruction::Halt,                // 14
+            ]
+            .iter()
+            .copied(),
+        );
+        let mut prog: Machine = Machine::new(&program);
+
+        prog.set_register(0, 98);
+        prog.set_register(1, 81);
+        assert_eq!(
+            prog.run(100000),
+            RunOutcome::Halted {
+                steps: 24418,
+                code: 0
+            }
+        );
+        assert_eq!(*prog.get_register(0), 7938);
+        assert_eq!(*prog.get_register(1), 81);
+    }
+
+    #[test]
+    fn run_outcome() {
+        let instructions = [
+            Instruction::Decrement(0, 0, 1), // 0
+            Instruction::Purged,             // 1
+        ];
+
+        let program = Program::new(instructions.iter().copied());
+        let mut prog: Machine = Machine::new(&program);
+        prog.set_register(0, 3);
+        assert_eq!(prog.run(2), RunOutcome::OutOfFuel);
+        assert_eq!(prog.run(2), RunOutcome::HitPurged { at: 1 });
+
+        // Halting exactly at the limit is not the same as running out of fuel.
+        let program = Program::new([Instruction::Increment(0, 1)].iter().copied());
+        let mut prog: Machine = Machine::new(&program);
+        assert_eq!(prog.run(1), RunOutcome::Halted { steps: 1, code: 0 });
+        let program = Program::new([Instruction::Increment(0, 0)].iter().copied());
+        let mut prog: Machine = Machine::new(&program);
+        assert_eq!(prog.run(1), RunOutcome::OutOfFuel);
+    }
+
+    #[test]
+    fn step_result() {
+        let instructions = [
+            Instruction::Decrement(0, 1, 2), // 0
+            Instruction::Halt,               // 1
+            Instruction::Purged,             // 2
+        ];
+
+        let program = Program::new(instructions.iter().copied());
+        let mut prog: Machine = Machine::new(&program);
+        prog.set_register(0, 1);
+        assert!(!prog.is_halted());
+        assert_eq!(prog.step(), StepResult::Halted);
+        assert!(prog.is_halted());
+        assert_eq!(prog.step(), StepResult::AlreadyHalted);
+        assert_eq!(prog.step(), StepResult::AlreadyHalted);
+        assert_eq!(prog.run(10), RunOutcome::Halted { steps: 0, code: 0 });
+
+        let program = Program::new(instructions.iter().copied());
+        let mut prog: Machine = Machine::new(&program);
+        assert_eq!(prog.step(), StepResult::Continued);
+        assert_eq!(prog.step(), StepResult::Purged);
+    }
+
+    #[test]
+    fn shared_program() {
+        // $0 = $0 + $1, see `add`.
+        let program = Program::new(
+            [
+                Instruction::Decrement(1, 1, 2),
+                Instruction::Increment(2, 0),
+                Instruction::Decrement(2, 3, 5),
+                Instruction::Increment(1, 4),
+                Instruction::Increment(0, 2),
+                Instruction::Halt,
+            ]
+            .iter()
+            .copied(),
+        );
+
+        std::thread::scope(|s| {
+            let handles: Vec<_> = (0..4)
+                .map(|i| {
+                    let program = &program;
+                    s.spawn(move || {
+                        let mut prog: Machine = Machine::new(program);
+                        prog.set_register(0, i);
+                        prog.set_register(1, 10);
+                        prog.run(u64::MAX);
+                        *prog.get_register(0)
+                    })
+                })
+                .collect();
+            let results: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+            assert_eq!(results, [10, 11, 12, 13]);
+        });
+    }
+
+    #[test]
+    fn small_stack() {
+        // Neither the program nor the machine should be built on the stack.
+        std::thread::Builder::new()
+            .stack_size(64 * 1024)
+            .spawn(|| {
+                let program = Program::empty();
+                let mut prog: Machine = Machine::new(&program);
+                assert_eq!(prog.run(u64::MAX), RunOutcome::Halted { steps: 0, code: 0 });
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn overflow_policy() {
+        let program = Program::new([Instruction::Increment(3, 1)].iter().copied());
+        let mut prog: Machine = Machine::new(&program);
+        prog.set_register(3, u64::MAX);
+        assert_eq!(prog.step(), StepResult::Overflow { reg: 3 });
+        assert_eq!(prog.run(10), RunOutcome::Overflow { at: 0, reg: 3 });
+        assert_eq!(*prog.get_register(3), u64::MAX);
+
+        let mut prog: Machine =
+            Machine::new(&program).with_overflow_policy(OverflowPolicy::Saturating);
+        prog.set_register(3, u64::MAX);
+        assert_eq!(prog.run(10), RunOutcome::Halted { steps: 1, code: 0 });
+        assert_eq!(*prog.get_register(3), u64::MAX);
+
+        let mut prog: Machine =
+            Machine::new(&program).with_overflow_policy(OverflowPolicy::Wrapping);
+        prog.set_register(3, u64::MAX);
+        assert_eq!(prog.run(10), RunOutcome::Halted { steps: 1, code: 0 });
+        assert_eq!(*prog.get_register(3), 0);
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn bigint() {
+        use num_bigint::BigUint;
+
+        // $1 = 2 * $0
+        let program = Program::new(
+            [
+                Instruction::Decrement(0, 1, 3),
+                Instruction::Increment(1, 2),
+                Instruction::Increment(1, 0),
+                Instruction::Halt,
+            ]
+            .iter()
+            .copied(),
+        );
+        let mut prog: Machine<BigUint> = Machine::new(&program);
+        prog.set_register(1, BigUint::from(u64::MAX));
+        prog.set_register(0, BigUint::from(3u32));
+        assert_eq!(
+            prog.run(u64::MAX),
+            RunOutcome::Halted { steps: 10, code: 0 }
+        );
+        assert_eq!(*prog.get_register(1), BigUint::from(u64::MAX) + 6u32);
+    }
+
+    #[test]
+    fn reset() {
+        // $0 = $0 + $1, see `add`.
+        let program = Program::new(
+            [
+                Instruction::Decrement(1, 1, 2),
+                Instruction::Increment(2, 0),
+                Instruction::Decrement(2, 3, 5),
+                Instruction::Increment(1, 4),
+                Instruction::Increment(0, 2),
+                Instruction::Halt,
+            ]
+            .iter()
+            .copied(),
+        );
+
+        let mut prog: Machine = Machine::new(&program);
+        for a in 0..5 {
+            for b in 0..5 {
+                prog.reset_with([a, b].iter().copied());
+                assert_eq!(
+                    prog.run(u64::MAX),
+                    RunOutcome::Halted {
+                        steps: 2 + 5 * b,
+                        code: 0
+                    }
+                );
+                assert_eq!(*prog.get_register(0), a + b);
+            }
+        }
+
+        prog.set_register(7, 3);
+        prog.reset();
+        assert_eq!(*prog.get_register(0), 0);
+        assert_eq!(*prog.get_register(7), 0);
+        assert_eq!(prog.run(u64::MAX), RunOutcome::Halted { steps: 2, code: 0 });
+    }
+
+    #[test]
+    fn accessors() {
+        let program = Program::new(
+            [
+                Instruction::Increment(0, 1),
+                Instruction::Increment(1, 2),
+                Instruction::Increment(2, 3),
+            ]
+            .iter()
+            .copied(),
+        );
+        let mut prog: Machine = Machine::new(&program);
+        assert_eq!(prog.registers().len(), 256);
+        prog.set_ptr(1);
+        assert_eq!(prog.ptr(), 1);
+        prog.registers_mut()[1] = 4;
+        assert_eq!(prog.run(u64::MAX), RunOutcome::Halted { steps: 2, code: 0 });
+        assert_eq!(prog.ptr(), 3);
+        assert_eq!(&prog.registers()[..4], &[0, 5, 1, 0]);
+    }
+
+    #[test]
+    fn snapshot() {
+        let program = Program::new(
+            [
+                Instruction::Increment(0, 1),
+                Instruction::Decrement(1, 0, 2),
+            ]
+            .iter()
+            .copied(),
+        );
+        let mut prog: Machine = Machine::new(&program);
+        prog.set_register(1, 3);
+        prog.step();
+        let snapshot = prog.snapshot();
+        assert_eq!(snapshot.ptr(), 1);
+        assert_eq!(&snapshot.registers()[..2], &[1, 3]);
+
+        assert_eq!(prog.run(u64::MAX), RunOutcome::Halted { steps: 7, code: 0 });
+        assert_eq!(*prog.get_register(0), 4);
+        prog.restore(&snapshot);
+        assert_eq!(prog.snapshot(), snapshot);
+        assert_eq!(prog.run(u64::MAX), RunOutcome::Halted { steps: 7, code: 0 });
+        assert_eq!(*prog.get_register(0), 4);
+    }
+
+    #[test]
+    fn configuration() {
+        use std::collections::HashSet;
+
+        let program = Program::new(
+            [
+                Instruction::Increment(4, 1),
+                Instruction::Decrement(4, 0, 2),
+            ]
+            .iter()
+            .copied(),
+        );
+        let mut prog: Machine = Machine::new(&program);
+        prog.set_register(9, 2);
+        let start = prog.configuration();
+        assert_eq!(start.ptr(), 0);
+        assert_eq!(start.registers(), &[(9, 2)]);
+        assert_eq!(start.get_register(9), Some(&2));
+        assert_eq!(start.get_register(4), None);
+
+        let mut seen = HashSet::new();
+        seen.insert(start.clone());
+        prog.step();
+        assert!(seen.insert(prog.configuration()));
+        assert_eq!(prog.configuration().registers(), &[(4, 1), (9, 2)]);
+        prog.step();
+        assert!(!seen.insert(prog.configuration()));
+        assert_eq!(prog.configuration(), start);
+
+        prog.step();
+        let next = prog.configuration();
+        prog.restore_configuration(&start);
+        assert_eq!(prog.configuration(), start);
+        assert_eq!(prog.snapshot().configuration(), start);
+        assert_eq!(Snapshot::from(&next).configuration(), next);
+    }
+
+    #[test]
+    fn states() {
+        let program = Program::new(
+            [
+                Instruction::Decrement(0, 1, 2),
+                Instruction::Increment(1, 0),
+            ]
+            .iter()
+            .copied(),
+        );
+        let mut prog: Machine = Machine::new(&program);
+        prog.set_register(0, 2);
+        let ptrs: Vec<_> = prog.states().map(|c| c.ptr()).collect();
+        assert_eq!(ptrs, [1, 0, 1, 0, 2]);
+        assert_eq!(*prog.get_register(1), 2);
+        assert_eq!(prog.states().next(), None);
+
+        prog.reset();
+        let last = prog.states().last().unwrap();
+        assert_eq!(last, prog.configuration());
+    }
+
+    #[test]
+    fn run_with() {
+        let program = Program::new(
+            [
+                Instruction::Decrement(0, 1, 2),
+                Instruction::Increment(1, 0),
+            ]
+            .iter()
+            .copied(),
+        );
+        let mut prog: Machine = Machine::new(&program);
+        prog.set_register(0, 10);
+        let mut ptrs = Vec::new();
+        let outcome = prog.run_with(u64::MAX, |m, steps| {
+            ptrs.push(m.ptr());
+            if *m.get_register(1) == 3 {
+                assert_eq!(steps, 6);
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        assert_eq!(outcome, RunOutcome::Stopped { steps: 6 });
+        assert_eq!(ptrs, [1, 0, 1, 0, 1, 0]);
+
+        let mut count = 0;
+        let outcome = prog.run_with(u64::MAX, |_, _| {
+            count += 1;
+            ControlFlow::Continue(())
+        });
+        assert_eq!(outcome, RunOutcome::Halted { steps: 15, code: 0 });
+        assert_eq!(count, 15);
+    }
+
+    #[test]
+    fn run_until() {
+        let program = Program::new(
+            [Instruction::Increment(0, 1), Instruction::Increment(1, 0)]
+                .iter()
+                .copied(),
+        );
+        let mut prog: Machine = Machine::new(&program);
+        let outcome = prog.run_until(|m| *m.get_register(1) > 1000, u64::MAX);
+        assert_eq!(outcome, RunOutcome::Stopped { steps: 2002 });
+        assert_eq!(
+            prog.run_until(|m| m.ptr() == 0, 10),
+            RunOutcome::Stopped { steps: 0 }
+        );
+        assert_eq!(
+            prog.run_until(|m| m.ptr() == 1, 10),
+            RunOutcome::Stopped { steps: 1 }
+        );
+        assert_eq!(prog.run_until(|m| m.ptr() == 2, 10), RunOutcome::OutOfFuel);
+    }
+
+    #[test]
+    fn run_detecting_cycles() {
+        let program = Program::new(
+            [
+                // Clear $0, then loop forever moving 3 between $1 and $2.
+                Instruction::Decrement(0, 0, 1), // 0
+                Instruction::Decrement(1, 2, 3), // 1
+                Instruction::Increment(2, 1),    // 2
+                Instruction::Decrement(2, 4, 1), // 3
+                Instruction::Increment(1, 3),    // 4
+            ]
+            .iter()
+            .copied(),
+        );
+        let mut prog: Machine = Machine::new(&program);
+        prog.reset_with([5, 3].iter().copied());
+        // 6 steps to clear $0, the cycle starts while moving $1 the first time.
+        assert_eq!(
+            prog.run_detecting_cycles(1000),
+            RunOutcome::NonHalting {
+                cycle_start: 6,
+                cycle_len: 14,
+            }
+        );
+
+        prog.reset_with([5, 3].iter().copied());
+        assert_eq!(prog.run_detecting_cycles(20), RunOutcome::OutOfFuel);
+
+        prog.reset_with([5, 3].iter().copied());
+        prog.set_ptr(5);
+        assert_eq!(
+            prog.run_detecting_cycles(20),
+            RunOutcome::Halted { steps: 0, code: 0 }
+        );
+
+        let program = Program::new([Instruction::Increment(0, 0)].iter().copied());
+        let mut prog: Machine = Machine::new(&program);
+        assert_eq!(prog.run_detecting_cycles(1000), RunOutcome::OutOfFuel);
+
+        // The configuration repeats after every step, but the state of the generator does not.
+        let program = Program::new(vec![Instruction::Random(60000, 0, 1)]);
+        let mut prog: Machine = Machine::new(&program);
+        let outcome = prog.clone().run(1000);
+        assert!(matches!(outcome, RunOutcome::Halted { .. }));
+        assert_eq!(prog.run_detecting_cycles(1000), outcome);
+    }
+
+    #[test]
+    fn purged_policy() {
+        let program = Program::new(
+            [Instruction::Increment(0, 1), Instruction::Purged]
+                .iter()
+                .copied(),
+        );
+        let mut prog: Machine = Machine::new(&program);
+        assert_eq!(prog.run(10), RunOutcome::HitPurged { at: 1 });
+        let mut prog: Machine = Machine::new(&program).with_purged_policy(PurgedPolicy::Halt);
+        assert_eq!(prog.step(), StepResult::Halted);
+        assert!(prog.is_halted());
+        assert_eq!(prog.step(), StepResult::AlreadyHalted);
+        prog.reset();
+        assert_eq!(prog.run(10), RunOutcome::Halted { steps: 1, code: 0 });
+    }
+
+    #[test]
+    #[should_panic(expected = "reached purged instruction at 1")]
+    fn purged_panic() {
+        let program = Program::new(
+            [Instruction::Increment(0, 1), Instruction::Purged]
+                .iter()
+                .copied(),
+        );
+        let mut prog: Machine = Machine::new(&program).with_purged_policy(PurgedPolicy::Panic);
+        prog.run(10);
+    }
+
+    #[test]
+    fn step_n() {
+        let program = Program::new(
+            [Instruction::Decrement(0, 0, 1), Instruction::Purged]
+                .iter()
+                .copied(),
+        );
+        let mut prog: Machine = Machine::new(&program);
+        prog.set_register(0, 10);
+        assert_eq!(
+            prog.step_n(4),
+            StepBatchResult {
+                executed: 4,
+                result: StepResult::Continued,
+            }
+        );
+        assert_eq!(
+            prog.step_n(100),
+            StepBatchResult {
+                executed: 7,
+                result: StepResult::Purged,
+            }
+        );
+
+        let program = Program::new([Instruction::Increment(0, 1)].iter().copied());
+        let mut prog: Machine = Machine::new(&program);
+        assert_eq!(
+            prog.step_n(100),
+            StepBatchResult {
+                executed: 1,
+                result: StepResult::Halted,
+            }
+        );
+        assert_eq!(
+            prog.step_n(100),
+            StepBatchResult {
+                executed: 0,
+                result: StepResult::AlreadyHalted,
+            }
+        );
+    }
+
+    #[test]
+    fn run_with_fuel() {
+        let program = Program::new([Instruction::Decrement(0, 0, 1)].iter().copied());
+        let mut fuel = Fuel::new(25);
+        let mut prog: Machine = Machine::new(&program);
+        for (input, outcome) in [
+            (10, RunOutcome::Halted { steps: 11, code: 0 }),
+            (5, RunOutcome::Halted { steps: 6, code: 0 }),
+            (10, RunOutcome::OutOfFuel),
+            (0, RunOutcome::OutOfFuel),
+        ] {
+            prog.reset_with(Some(input));
+            assert_eq!(prog.run_with_fuel(&mut fuel), outcome);
+        }
+        assert!(fuel.is_empty());
+        assert_eq!(fuel.consumed(), 25);
+        assert_eq!(*prog.get_register(0), 0);
+
+        fuel.refill(3);
+        assert_eq!(fuel.remaining(), 3);
+        prog.reset();
+        assert_eq!(
+            prog.run_with_fuel(&mut fuel),
+            RunOutcome::Halted { steps: 1, code: 0 }
+        );
+        assert_eq!(fuel.remaining(), 2);
+    }
+
+    #[test]
+    fn out_of_fuel_when_halted() {
+        let program = Program::new(
+            [Instruction::Increment(0, 1), Instruction::HaltWith(3)]
+                .iter()
+                .copied(),
+        );
+        let mut prog: Machine = Machine::new(&program);
+        prog.step();
+        assert_eq!(prog.run(0), RunOutcome::Halted { steps: 0, code: 3 });
+        assert_eq!(
+            prog.run_with_fuel(&mut Fuel::new(0)),
+            RunOutcome::Halted { steps: 0, code: 3 }
+        );
+
+        prog.reset();
+        assert_eq!(prog.run(1), RunOutcome::Halted { steps: 1, code: 3 });
+        let empty = Program::new(Vec::new());
+        let mut prog: Machine = Machine::new(&empty);
+        assert_eq!(prog.run(0), RunOutcome::Halted { steps: 0, code: 0 });
+    }
+
+    #[test]
+    fn run_for() {
+        let program = Program::new([Instruction::Increment(0, 0)].iter().copied());
+        let mut prog: Machine = Machine::new(&program);
+        match prog.run_for(Duration::from_millis(20)) {
+            RunOutcome::TimedOut { steps } => {
+                assert!(steps > 0);
+                assert_eq!(*prog.get_register(0), steps);
+            }
+            outcome => panic!("unexpected outcome: {:?}", outcome),
+        }
+
+        let program = Program::new([Instruction::Decrement(0, 0, 1)].iter().copied());
+        let mut prog: Machine = Machine::new(&program);
+        prog.set_register(0, 100_000);
+        assert_eq!(
+            prog.run_for(Duration::from_secs(60)),
+            RunOutcome::Halted {
+                steps: 100_001,
+                code: 0
+            }
+        );
+    }
+
+    #[test]
+    fn breakpoints() {
+        // $0 = $0 + $1, see `add`.
+        let program = Program::new(
+            [
+                Instruction::Decrement(1, 1, 2),
+                Instruction::Increment(2, 0),
+                Instruction::Decrement(2, 3, 5),
+                Instruction::Increment(1, 4),
+                Instruction::Increment(0, 2),
+                Instruction::Halt,
+            ]
+            .iter()
+            .copied(),
+        );
+        let mut prog: Machine = Machine::new(&program);
+        prog.reset_with([0, 2].iter().copied());
+        prog.add_breakpoint(4);
+        prog.add_breakpoint(5);
+        assert_eq!(prog.breakpoints().collect::<Vec<_>>(), [4, 5]);
+        assert_eq!(prog.run(u64::MAX), RunOutcome::Breakpoint { at: 4 });
+        assert_eq!(&prog.registers()[..3], &[0, 1, 1]);
+        // Continuing from a breakpoint executes its instruction.
+        assert_eq!(prog.run(u64::MAX), RunOutcome::Breakpoint { at: 4 });
+        assert!(prog.remove_breakpoint(4));
+        assert!(!prog.remove_breakpoint(4));
+        // Halting takes precedence over breakpoints.
+        assert_eq!(prog.run(u64::MAX), RunOutcome::Halted { steps: 2, code: 0 });
+
+        prog.reset_with([0, 2].iter().copied());
+        prog.add_breakpoint(2);
+        assert_eq!(prog.step(), StepResult::Continued);
+        assert_eq!(prog.step(), StepResult::Continued);
+        assert_eq!(prog.step_n(10).executed, 3);
+        assert_eq!(prog.ptr(), 2);
+        prog.clear_breakpoints();
+        assert_eq!(prog.run(u64::MAX), RunOutcome::Halted { steps: 7, code: 0 });
+    }
+
+    #[test]
+    fn watchpoints() {
+        let program = Program::new(
+            [
+                Instruction::Increment(0, 1),
+                Instruction::Decrement(1, 0, 2),
+                Instruction::Decrement(2, 2, 3),
+            ]
+            .iter()
+            .copied(),
+        );
+        let mut prog: Machine = Machine::new(&program);
+        prog.reset_with([0, 10].iter().copied());
+        prog.add_watchpoint(0, Watch::Exceeds(3));
+        prog.add_watchpoint(1, Watch::Reaches(5));
+        prog.add_watchpoint(2, Watch::Written);
+        prog.add_watchpoint(3, Watch::Written);
+        assert_eq!(prog.run(u64::MAX), RunOutcome::Watchpoint { at: 1, reg: 0 });
+        assert_eq!(*prog.get_register(0), 4);
+        assert_eq!(prog.run(u64::MAX), RunOutcome::Watchpoint { at: 0, reg: 1 });
+        assert_eq!(*prog.get_register(1), 5);
+        assert_eq!(prog.watchpoints().len(), 2);
+        // Failed decrements don't write the register.
+        assert_eq!(
+            prog.run(u64::MAX),
+            RunOutcome::Halted { steps: 13, code: 0 }
+        );
+
+        prog.reset_with([0, 0, 2].iter().copied());
+        prog.set_ptr(2);
+        assert_eq!(prog.step(), StepResult::Watchpoint { reg: 2 });
+        prog.clear_watchpoints();
+        assert_eq!(prog.run(u64::MAX), RunOutcome::Halted { steps: 2, code: 0 });
+    }
+
+    #[test]
+    fn step_back() {
+        let program = Program::new(
+            [
+                Instruction::Increment(0, 1),
+                Instruction::Decrement(1, 0, 2),
+                Instruction::Decrement(2, 2, 3),
+            ]
+            .iter()
+            .copied(),
+        );
+        let mut prog: Machine =
+            Machine::new(&program).with_overflow_policy(OverflowPolicy::Saturating);
+        prog.reset_with([u64::MAX - 1, 3, 1].iter().copied());
+        assert!(!prog.step_back());
+        prog.enable_journal(4);
+        assert_eq!(prog.journal_capacity(), Some(4));
+        let mut history = vec![prog.snapshot()];
+        for _ in 0..10 {
+            prog.step();
+            history.push(prog.snapshot());
+        }
+        assert!(prog.is_halted());
+        assert_eq!(prog.journal_len(), 4);
+        for expected in history.iter().rev().skip(1).take(4) {
+            assert!(prog.step_back());
+            assert_eq!(prog.snapshot(), *expected);
+        }
+        assert!(!prog.step_back());
+
+        prog.run(u64::MAX);
+        assert_eq!(prog.snapshot(), history[10]);
+        prog.reset();
+        assert_eq!(prog.journal_len(), 0);
+        prog.disable_journal();
+        prog.step();
+        assert!(!prog.step_back());
+
+        // The journal only grows as steps are recorded.
+        prog.enable_journal(usize::MAX);
+        let before = prog.snapshot();
+        prog.step();
+        assert_eq!(prog.journal_len(), 1);
+        assert!(prog.step_back());
+        assert_eq!(prog.snapshot(), before);
+    }
+
+    #[test]
+    fn run_cancellable() {
+        let program = Program::new([Instruction::Increment(0, 0)].iter().copied());
+        let cancel = AtomicBool::new(false);
+        std::thread::scope(|s| {
+            let handle = s.spawn(|| {
+                let mut prog: Machine = Machine::new(&program);
+                let outcome = prog.run_cancellable(u64::MAX, &cancel);
+                (outcome, *prog.get_register(0))
+            });
+            std::thread::sleep(Duration::from_millis(10));
+            cancel.store(true, Ordering::Relaxed);
+            match handle.join().unwrap() {
+                (RunOutcome::Cancelled { steps }, value) => assert_eq!(steps, value),
+                outcome => panic!("unexpected outcome: {:?}", outcome),
+            }
+        });
+
+        let mut prog: Machine = Machine::new(&program);
+        assert_eq!(prog.run_cancellable(0, &cancel), RunOutcome::OutOfFuel);
+        let cancel = AtomicBool::new(false);
+        assert_eq!(prog.run_cancellable(100, &cancel), RunOutcome::OutOfFuel);
+        assert_eq!(*prog.get_register(0), 100);
+    }
+
+    #[test]
+    fn run_with_progress() {
+        let program = Program::new([Instruction::Decrement(0, 0, 1)].iter().copied());
+        let mut prog: Machine = Machine::new(&program);
+        prog.set_register(0, 25);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        assert_eq!(
+            prog.run_with_progress(u64::MAX, 10, &sender),
+            RunOutcome::Halted { steps: 26, code: 0 }
+        );
+        let reports: Vec<_> = receiver
+            .try_iter()
+            .map(|p| (p.steps, p.configuration.get_register(0).copied()))
+            .collect();
+        assert_eq!(reports, [(10, Some(15)), (20, Some(5))]);
+
+        drop(receiver);
+        prog.set_register(0, 25);
+        prog.set_ptr(0);
+        assert_eq!(
+            prog.run_with_progress(15, 10, &sender),
+            RunOutcome::OutOfFuel
+        );
+        assert_eq!(*prog.get_register(0), 10);
+    }
+
+    #[test]
+    fn jump() {
+        let program = Program::new(
+            [
+                Instruction::Jump(2),
+                Instruction::Halt,
+                Instruction::Decrement(0, 3, 1),
+                Instruction::Nop(0),
+            ]
+            .iter()
+            .copied(),
+        );
+        let mut prog: Machine = Machine::new(&program);
+        prog.set_register(0, 3);
+        assert_eq!(
+            prog.run(u64::MAX),
+            RunOutcome::Halted { steps: 11, code: 0 }
+        );
+        assert_eq!(prog.registers().iter().filter(|&&v| v != 0).count(), 0);
+    }
+
+    #[test]
+    fn clear() {
+        let program = Program::new(
+            [Instruction::Clear(0, 1), Instruction::Clear(1, 2)]
+                .iter()
+                .copied(),
+        );
+        let mut prog: Machine = Machine::new(&program);
+        prog.reset_with([u64::MAX, 0, 7].iter().copied());
+        prog.add_watchpoint(1, Watch::Written);
+        prog.enable_journal(2);
+        assert_eq!(prog.run(u64::MAX), RunOutcome::Halted { steps: 2, code: 0 });
+        assert_eq!(&prog.registers()[..3], &[0, 0, 7]);
+        assert_eq!(prog.watchpoints().len(), 1);
+        assert!(prog.step_back());
+        assert!(prog.step_back());
+        assert_eq!(&prog.registers()[..3], &[u64::MAX, 0, 7]);
+    }
+
+    #[test]
+    fn add_sub_const() {
+        // $1 = 1000 * ($0 / 3), $0 = $0 % 3
+        let program = Program::new(
+            [
+                Instruction::SubConst(0, 3, 1, 2),
+                Instruction::AddConst(1, 1000, 0),
+            ]
+            .iter()
+            .copied(),
+        );
+        let mut prog: Machine = Machine::new(&program);
+        prog.set_register(0, 10);
+        assert_eq!(prog.run(u64::MAX), RunOutcome::Halted { steps: 7, code: 0 });
+        assert_eq!(&prog.registers()[..2], &[1, 3000]);
+
+        let mut prog: Machine<u8> = Machine::new(&program);
+        prog.set_register(0, 3);
+        assert_eq!(prog.run(u64::MAX), RunOutcome::Overflow { at: 1, reg: 1 });
+        assert_eq!(&prog.registers()[..2], &[0, 0]);
+    }
+
+    #[test]
+    fn transfer() {
+        let program = Program::new(
+            [
+                Instruction::Transfer {
+                    src: 0,
+                    dst: 1,
+                    then: 1,
+                },
+                Instruction::Transfer {
+                    src: 1,
+                    dst: 1,
+                    then: 2,
+                },
+            ]
+            .iter()
+            .copied(),
+        );
+        let mut prog: Machine = Machine::new(&program);
+        prog.reset_with([5, 7].iter().copied());
+        prog.enable_journal(4);
+        prog.add_watchpoint(1, Watch::Reaches(12));
+        assert_eq!(prog.step(), StepResult::Watchpoint { reg: 1 });
+        assert_eq!(prog.run(u64::MAX), RunOutcome::Halted { steps: 1, code: 0 });
+        assert_eq!(&prog.registers()[..2], &[0, 12]);
+        assert!(prog.step_back());
+        assert!(prog.step_back());
+        assert_eq!(&prog.registers()[..2], &[5, 7]);
+
+        let mut prog: Machine<u8> = Machine::new(&program);
+        prog.reset_with([200, 56].iter().copied());
+        assert_eq!(prog.run(u64::MAX), RunOutcome::Overflow { at: 0, reg: 1 });
+        assert_eq!(&prog.registers()[..2], &[200, 56]);
+    }
+
+    #[test]
+    fn branch_zero() {
+        use crate::{IsaLevel, ProgramError};
+
+        // $1 = 2 * $0 without destroying $0.
+        let program = Program::new(
+            [
+                Instruction::BranchZero(0, 3, 1),
+                Instruction::AddConst(1, 2, 2),
+                Instruction::Halt,
+                Instruction::Increment(2, 2),
+            ]
+            .iter()
+            .copied(),
+        );
+        let mut prog: Machine = Machine::new(&program);
+        prog.set_register(0, 4);
+        assert_eq!(prog.run(u64::MAX), RunOutcome::Halted { steps: 2, code: 0 });
+        assert_eq!(&prog.registers()[..3], &[4, 2, 0]);
+
+        prog.reset();
+        assert_eq!(prog.run(u64::MAX), RunOutcome::Halted { steps: 2, code: 0 });
+        assert_eq!(&prog.registers()[..3], &[0, 0, 1]);
+        assert_eq!(
+            program.validate(IsaLevel::StrictMinsky),
+            Err(ProgramError::UnsupportedInstruction {
+                at: 0,
+                level: IsaLevel::StrictMinsky
+            })
+        );
+    }
+
+    #[test]
+    fn copy() {
+        let accelerated = Program::new(
+            [Instruction::Copy {
+                src: 0,
+                dst: 1,
+                scratch: 2,
+                then: 1,
+            }]
+            .iter()
+            .copied(),
+        );
+        let expanded = Program::new(Instruction::copy_sequence(0, 1, 2, 0, 5).unwrap());
+        assert!(Instruction::copy_sequence(0, 1, 2, u16::MAX - 4, 0).is_some());
+        assert_eq!(Instruction::copy_sequence(0, 1, 2, u16::MAX - 3, 0), None);
+        for &(inputs, steps) in &[([0, 0, 0], 2), ([4, 3, 0], 22), ([4, 3, 2], 26)] {
+            let mut fast: Machine = Machine::new(&accelerated);
+            fast.reset_with(inputs.iter().copied());
+            assert_eq!(fast.run(u64::MAX), RunOutcome::Halted { steps: 1, code: 0 });
+            let mut slow: Machine = Machine::new(&expanded);
+            slow.reset_with(inputs.iter().copied());
+            assert_eq!(slow.run(u64::MAX), RunOutcome::Halted { steps, code: 0 });
+            assert_eq!(fast.snapshot().registers(), slow.snapshot().registers());
+        }
+
+        let mut prog: Machine = Machine::new(&accelerated);
+        prog.reset_with([4, 3, 2].iter().copied());
+        prog.enable_journal(1);
+        assert_eq!(prog.step(), StepResult::Halted);
+        assert_eq!(&prog.registers()[..3], &[6, 7, 0]);
+        assert!(prog.step_back());
+        assert_eq!(&prog.registers()[..3], &[4, 3, 2]);
+    }
+
+    #[test]
+    fn halt_with() {
+        // Exits with 1 if $0 is non-zero and with 2 otherwise.
+        let program = Program::new(
+            [
+                Instruction::Decrement(0, 1, 2),
+                Instruction::HaltWith(1),
+                Instruction::HaltWith(2),
+            ]
+            .iter()
+            .copied(),
+        );
+        let mut prog: Machine = Machine::new(&program);
+        assert_eq!(prog.exit_code(), None);
+        assert_eq!(prog.run(u64::MAX), RunOutcome::Halted { steps: 1, code: 2 });
+        assert_eq!(prog.exit_code(), Some(2));
+        assert_eq!(prog.step(), StepResult::AlreadyHalted);
+
+        prog.reset_with([5].iter().copied());
+        assert_eq!(prog.run(u64::MAX), RunOutcome::Halted { steps: 1, code: 1 });
+        prog.set_ptr(3);
+        assert_eq!(prog.exit_code(), Some(0));
+    }
+
+    #[test]
+    fn io() {
+        // Outputs the sum of all inputs until reading a zero.
+        let program = Program::new(
+            [
+                Instruction::Read(0, 1),
+                Instruction::BranchZero(0, 3, 2),
+                Instruction::Transfer {
+                    src: 0,
+                    dst: 1,
+                    then: 0,
+                },
+                Instruction::Write(1, 4),
+            ]
+            .iter()
+            .copied(),
+        );
+        let mut prog: Machine = Machine::new(&program);
+        let mut io = MemoryIo::new([3, 4, 5, 0, 7].iter().copied());
+        assert_eq!(
+            prog.run_with_io(u64::MAX, &mut io),
+            RunOutcome::Halted { steps: 12, code: 0 }
+        );
+        assert_eq!(io.output(), &[12]);
+        assert_eq!(io.input().len(), 1);
+
+        prog.reset();
+        let mut io = MemoryIo::new([9].iter().copied());
+        assert_eq!(
+            prog.run_with_io(u64::MAX, &mut io),
+            RunOutcome::Halted { steps: 6, code: 0 }
+        );
+        assert_eq!(io.output(), &[9]);
+    }
+
+    #[test]
+    fn random() {
+        // Counts the heads of 1000 fair coin flips in $1.
+        let program = Program::new(
+            [
+                Instruction::Decrement(0, 1, 3),
+                Instruction::Random(1 << 15, 2, 0),
+                Instruction::Increment(1, 0),
+            ]
+            .iter()
+            .copied(),
+        );
+        let run = |seed| {
+            let mut prog: Machine = Machine::new(&program).with_seed(seed);
+            prog.set_register(0, 1000);
+            assert!(matches!(prog.run(u64::MAX), RunOutcome::Halted { .. }));
+            *prog.get_register(1)
+        };
+        let heads = run(0);
+        assert_eq!(run(0), heads);
+        assert!((400..600).contains(&heads), "{}", heads);
+        assert_ne!(run(1), heads);
+
+        let program = Program::new([Instruction::Random(0, 1, 2)].iter().copied());
+        let mut prog: Machine = Machine::new(&program);
+        for seed in 0..100 {
+            prog.set_seed(seed);
+            prog.reset();
+            prog.step();
+            assert_eq!(prog.ptr(), 2);
+        }
+
+        // Stepping back over `Random` steps and executing them again takes the same branches.
+        let program = Program::new(
+            [
+                Instruction::Random(1 << 15, 1, 2),
+                Instruction::Increment(0, 0),
+                Instruction::Increment(1, 0),
+            ]
+            .iter()
+            .copied(),
+        );
+        let mut prog: Machine = Machine::new(&program);
+        prog.enable_journal(100);
+        let mut configurations = vec![prog.configuration()];
+        for _ in 0..40 {
+            prog.step();
+            configurations.push(prog.configuration());
+        }
+        assert!(prog.get_register(0) > &0 && prog.get_register(1) > &0);
+        for expected in configurations.iter().rev().skip(1) {
+            assert!(prog.step_back());
+            assert_eq!(&prog.configuration(), expected);
+        }
+        for expected in &configurations[1..] {
+            prog.step();
+            assert_eq!(&prog.configuration(), expected);
+        }
+    }
+
+    #[test]
+    fn call() {
+        // Calls the subroutine at 3, adding 2 to $0, twice and the one at 4, adding 1, once.
+        let program = Program::new(
+            [
+                Instruction::Call(3),
+                Instruction::Call(3),
+                Instruction::Call(4),
+                Instruction::Increment(0, 4),
+                Instruction::Increment(0, 5),
+                Instruction::Return,
+            ]
+            .iter()
+            .copied(),
+        );
+        let mut prog: Machine = Machine::new(&program);
+        prog.enable_journal(8);
+        assert_eq!(prog.run(3), RunOutcome::OutOfFuel);
+        assert_eq!(prog.call_stack(), &[1]);
+        assert_eq!(prog.configuration().stack(), &[1]);
+        let snapshot = prog.snapshot();
+        assert_eq!(prog.run(u64::MAX), RunOutcome::StackUnderflow { at: 5 });
+        assert_eq!(*prog.get_register(0), 7);
+        assert!(prog.step_back());
+        assert!(prog.step_back());
+        assert_eq!((prog.ptr(), prog.call_stack()), (3, &[][..]));
+        assert!(prog.step_back());
+        assert_eq!((prog.ptr(), prog.call_stack()), (5, &[3][..]));
+        assert!(prog.step_back());
+        assert!(prog.step_back());
+        assert_eq!((prog.ptr(), prog.call_stack()), (2, &[][..]));
+        prog.restore(&snapshot);
+        assert_eq!(prog.call_stack(), &[1]);
+
+        let program = Program::new([Instruction::Call(0)].iter().copied());
+        let mut prog: Machine = Machine::new(&program).with_max_call_depth(3);
+        assert_eq!(prog.run(u64::MAX), RunOutcome::StackOverflow { at: 0 });
+        assert_eq!(prog.call_stack(), &[1, 1, 1]);
+        prog.reset();
+        assert!(prog.call_stack().is_empty());
+    }
+
+    #[test]
+    fn swap() {
+        let program = Program::new(
+            [Instruction::Swap(0, 2, 1), Instruction::Swap(1, 3, 2)]
+                .iter()
+                .copied(),
+        );
+        let mut prog: Machine = Machine::new(&program);
+        prog.reset_with([1, 0, 3].iter().copied());
+        prog.enable_journal(2);
+        let mut stats = Stats::new();
+        assert_eq!(
+            prog.run_with_stats(u64::MAX, &mut stats),
+            RunOutcome::Halted { steps: 2, code: 0 }
+        );
+        assert_eq!(&prog.registers()[..4], &[3, 0, 1, 0]);
+        assert_eq!(stats.max_registers()[0], 3);
+        assert!(prog.step_back());
+        assert!(prog.step_back());
+        assert_eq!(&prog.registers()[..4], &[1, 0, 3, 0]);
+    }
+
+    #[test]
+    fn compare() {
+        // Exits with 1 if $0 >= $1, with 2 if $1 > $0.
+        let program = Program::new(
+            [
+                Instruction::Compare(0, 1, 1, 2),
+                Instruction::HaltWith(1),
+                Instruction::HaltWith(2),
+            ]
+            .iter()
+            .copied(),
+        );
+        let mut prog: Machine = Machine::new(&program);
+        for &(a, b, code) in &[(0, 0, 1), (3, 2, 1), (2, 3, 2), (0, u64::MAX, 2)] {
+            prog.reset_with([a, b].iter().copied());
+            assert_eq!(prog.run(u64::MAX), RunOutcome::Halted { steps: 1, code });
+            assert_eq!(&prog.registers()[..2], &[a, b]);
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use crate::{BlockSummary, Counter, LoopSummary, Machine, RunOutcome, StepBatchResult, StepResult};
+
+/// The summary used to execute the instruction at some position.
+#[derive(Debug, Clone)]
+enum Summary {
+    Loop(LoopSummary),
+    Block(BlockSummary),
+    Step,
+}
+
+/// A simulator which executes a [`Machine`] in macro steps.
+///
+/// Each macro step either executes all remaining iterations of a simple loop, see
+/// [`Program::loop_summary`](crate::Program::loop_summary), a whole block of straight-line
+/// code, see [`Program::block_summary`](crate::Program::block_summary), or a single
+/// instruction. The summaries are computed when first reaching an instruction and reused
+/// afterwards. Step counts always refer to the steps of the underlying machine, so this
+/// behaves exactly like running the machine directly, only faster.
+///
+/// Loops and blocks are executed one instruction at a time if the machine has
+/// breakpoints, watchpoints or a journal, or if they would overflow a register.
+#[derive(Debug, Clone)]
+pub struct MacroSimulator<'p, C: Counter = u64> {
+    machine: Machine<'p, C>,
+    summaries: Vec<Option<Summary>>,
+    steps: u64,
+    macro_steps: u64,
+}
+
+impl<'p, C: Counter> MacroSimulator<'p, C> {
+    pub fn new(machine: Machine<'p, C>) -> MacroSimulator<'p, C> {
+        MacroSimulator {
+            summaries: vec![None; machine.program().len()],
+            machine,
+            steps: 0,
+            macro_steps: 0,
+        }
+    }
+
+    pub fn machine(&self) -> &Machine<'p, C> {
+        &self.machine
+    }
+
+    pub fn machine_mut(&mut self) -> &mut Machine<'p, C> {
+        &mut self.machine
+    }
+
+    pub fn into_machine(self) -> Machine<'p, C> {
+        self.machine
+    }
+
+    /// The total number of steps of the machine executed by this simulator.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// The total number of macro steps executed by this simulator.
+    pub fn macro_steps(&self) -> u64 {
+        self.macro_steps
+    }
+
+    /// The number of instructions whose summary has been computed so far.
+    pub fn summarized(&self) -> usize {
+        self.summaries
+            .iter()
+            .filter(|summary| summary.is_some())
+            .count()
+    }
+
+    /// Executes a single macro step using at most `max_steps` steps of the machine.
+    ///
+    /// `executed` is the number of steps of the machine, which is only zero if
+    /// `max_steps` is zero or if the machine could not execute its next instruction.
+    pub fn macro_step(&mut self, max_steps: u64) -> StepBatchResult {
+        if max_steps == 0 {
+            return StepBatchResult {
+                executed: 0,
+                result: StepResult::Continued,
+            };
+        }
+
+        let program = self.machine.program();
+        let at = self.machine.ptr();
+        let summary = self.summaries.get_mut(usize::from(at)).map(|summary| {
+            &*summary.get_or_insert_with(|| match program.loop_summary(at) {
+                Some(summary) => Summary::Loop(summary),
+                None => program
+                    .block_summary(at)
+                    .map_or(Summary::Step, Summary::Block),
+            })
+        });
+        let executed = match summary {
+            Some(Summary::Loop(summary)) => {
+                self.machine.accelerate(summary, max_steps) * summary.iteration_steps
+            }
+            Some(Summary::Block(block))
+                if block.steps <= max_steps && self.machine.apply_block(block) =>
+            {
+                block.steps
+            }
+            _ => 0,
+        };
+
+        let batch = if executed > 0 {
+            StepBatchResult {
+                executed,
+                result: if self.machine.is_halted() {
+                    StepResult::Halted
+                } else {
+                    StepResult::Continued
+                },
+            }
+        } else {
+            let result = self.machine.step();
+            StepBatchResult {
+                executed: result.executed() as u64,
+                result,
+            }
+        };
+        if batch.executed > 0 {
+            self.steps += batch.executed;
+            self.macro_steps += 1;
+        }
+        batch
+    }
+
+    /// Runs the machine for at most `max_steps` steps.
+    ///
+    /// Like [`Machine::run`], the `steps` of the outcome only count the steps of this run.
+    pub fn run(&mut self, max_steps: u64) -> RunOutcome {
+        let mut steps = 0;
+        while steps < max_steps {
+            let batch = self.macro_step(max_steps - steps);
+            steps += batch.executed;
+            if batch.result != StepResult::Continued {
+                return self.machine.outcome(batch.result, steps);
+            }
+        }
+
+        self.machine.out_of_fuel(steps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{examples, Instruction, Program};
+
+    #[test]
+    fn macro_steps() {
+        let program = examples::multiplication();
+        for &(a, b) in &[(0, 0), (3, 4), (7, 1), (12, 9)] {
+            let mut prog: Machine = Machine::new(&program);
+            prog.set_register(0, a);
+            prog.set_register(1, b);
+            let mut sim = MacroSimulator::new(prog.clone());
+            let outcome = prog.run(u64::MAX);
+            assert_eq!(sim.run(u64::MAX), outcome);
+            assert_eq!(sim.machine().registers(), prog.registers());
+            if let RunOutcome::Halted { steps, .. } = outcome {
+                assert_eq!(sim.steps(), steps);
+            }
+            assert!(sim.macro_steps() <= sim.steps());
+        }
+
+        // $1 += 3 * $0 using a loop and a block after it.
+        let program = Program::new(
+            [
+                Instruction::Decrement(0, 1, 3),
+                Instruction::AddConst(1, 2, 2),
+                Instruction::Increment(1, 0),
+                Instruction::Clear(2, 4),
+                Instruction::Increment(2, 5),
+                Instruction::Jump(6),
+                Instruction::AddConst(2, 4, 7),
+            ]
+            .iter()
+            .copied(),
+        );
+        let mut prog: Machine = Machine::new(&program);
+        prog.set_register(0, 1 << 40);
+        prog.set_register(2, 9);
+        let mut sim = MacroSimulator::new(prog);
+        assert_eq!(
+            sim.run(u64::MAX),
+            RunOutcome::Halted {
+                steps: 3 * (1 << 40) + 5,
+                code: 0
+            }
+        );
+        assert_eq!(sim.machine().registers()[..3], [0, 3 << 40, 5]);
+        // The loop, its header once the counter is zero and the block after it.
+        assert_eq!(sim.macro_steps(), 3);
+        assert_eq!(sim.summarized(), 2);
+
+        // Running out of steps in the middle of a block or a loop.
+        for max_steps in 0..12 {
+            let mut prog: Machine = Machine::new(&program);
+            prog.set_register(0, 2);
+            let mut sim = MacroSimulator::new(prog.clone());
+            assert_eq!(sim.run(max_steps), prog.run(max_steps));
+            assert_eq!(sim.machine().registers(), prog.registers());
+            assert_eq!(sim.machine().ptr(), prog.ptr());
+        }
+
+        // Breakpoints disable macro steps.
+        let mut prog: Machine = Machine::new(&program);
+        prog.add_breakpoint(5);
+        let mut sim = MacroSimulator::new(prog);
+        assert_eq!(sim.run(u64::MAX), RunOutcome::Breakpoint { at: 5 });
+        assert_eq!(sim.steps(), sim.macro_steps());
+    }
+}
@@ -0,0 +1,233 @@
+/// Writes a program as an array of [`Instruction`](crate::Instruction)s using a small assembly language.
+///
+/// Each instruction ends with a `;` and may be preceded by any number of `label:`.
+/// Jump targets are either labels or instruction indices, registers and constants must
+/// be literals. The result is a constant expression, so it can be used to define constants.
+///
+/// | syntax                   | instruction                           |
+/// |--------------------------|---------------------------------------|
+/// | `halt`, `halt code`      | `Halt`, `HaltWith(code)`              |
+/// | `purged`                 | `Purged`                              |
+/// | `inc reg -> t`           | `Increment(reg, t)`                   |
+/// | `dec reg -> t, e`        | `Decrement(reg, t, e)`                |
+/// | `jmp t`, `nop t`         | `Jump(t)`, `Nop(t)`                   |
+/// | `clr reg -> t`           | `Clear(reg, t)`                       |
+/// | `add reg, n -> t`        | `AddConst(reg, n, t)`                 |
+/// | `sub reg, n -> t, e`     | `SubConst(reg, n, t, e)`              |
+/// | `mov src, dst -> t`      | `Transfer { src, dst, then: t }`      |
+/// | `copy src, dst, s -> t`  | `Copy { src, dst, scratch: s, then: t }` |
+/// | `jz reg -> z, nz`        | `BranchZero(reg, z, nz)`              |
+/// | `cmp a, b -> ge, lt`     | `Compare(a, b, ge, lt)`               |
+/// | `swap a, b -> t`         | `Swap(a, b, t)`                       |
+/// | `read reg -> t`          | `Read(reg, t)`                        |
+/// | `write reg -> t`         | `Write(reg, t)`                       |
+/// | `choose a, b`            | `Choose(a, b)`                        |
+/// | `rand p -> t, e`         | `Random(p, t, e)`                     |
+/// | `call t`, `ret`          | `Call(t)`, `Return`                   |
+///
+/// The macro is recursive, so very long programs may need a higher `recursion_limit`.
+#[macro_export]
+macro_rules! minsky {
+    ($($body:tt)*) => {
+        $crate::__minsky!(@parse [0u16] [] [] $($body)*)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __minsky {
+    (@parse [$pos:expr] [$($labels:tt)*] [$($instructions:tt)*]) => {{
+        $($labels)*
+        [$($instructions)*]
+    }};
+    (@parse [$pos:expr] [$($labels:tt)*] [$($instructions:tt)*] $label:ident : $($rest:tt)*) => {
+        $crate::__minsky!(@parse [$pos] [
+            $($labels)*
+            #[allow(dead_code, non_upper_case_globals)]
+            const $label: u16 = $pos;
+        ] [$($instructions)*] $($rest)*)
+    };
+    (@parse [$pos:expr] [$($labels:tt)*] [$($instructions:tt)*] halt; $($rest:tt)*) => {
+        $crate::__minsky!(@emit [$pos] [$($labels)*] [$($instructions)*]
+            ($crate::Instruction::Halt) $($rest)*)
+    };
+    (@parse [$pos:expr] [$($labels:tt)*] [$($instructions:tt)*] halt $code:tt; $($rest:tt)*) => {
+        $crate::__minsky!(@emit [$pos] [$($labels)*] [$($instructions)*]
+            ($crate::Instruction::HaltWith($code)) $($rest)*)
+    };
+    (@parse [$pos:expr] [$($labels:tt)*] [$($instructions:tt)*] purged; $($rest:tt)*) => {
+        $crate::__minsky!(@emit [$pos] [$($labels)*] [$($instructions)*]
+            ($crate::Instruction::Purged) $($rest)*)
+    };
+    (@parse [$pos:expr] [$($labels:tt)*] [$($instructions:tt)*]
+        inc $reg:tt -> $t:expr; $($rest:tt)*) => {
+        $crate::__minsky!(@emit [$pos] [$($labels)*] [$($instructions)*]
+            ($crate::Instruction::Increment($reg, $t)) $($rest)*)
+    };
+    (@parse [$pos:expr] [$($labels:tt)*] [$($instructions:tt)*]
+        dec $reg:tt -> $t:expr, $e:expr; $($rest:tt)*) => {
+        $crate::__minsky!(@emit [$pos] [$($labels)*] [$($instructions)*]
+            ($crate::Instruction::Decrement($reg, $t, $e)) $($rest)*)
+    };
+    (@parse [$pos:expr] [$($labels:tt)*] [$($instructions:tt)*] jmp $t:expr; $($rest:tt)*) => {
+        $crate::__minsky!(@emit [$pos] [$($labels)*] [$($instructions)*]
+            ($crate::Instruction::Jump($t)) $($rest)*)
+    };
+    (@parse [$pos:expr] [$($labels:tt)*] [$($instructions:tt)*] nop $t:expr; $($rest:tt)*) => {
+        $crate::__minsky!(@emit [$pos] [$($labels)*] [$($instructions)*]
+            ($crate::Instruction::Nop($t)) $($rest)*)
+    };
+    (@parse [$pos:expr] [$($labels:tt)*] [$($instructions:tt)*]
+        clr $reg:tt -> $t:expr; $($rest:tt)*) => {
+        $crate::__minsky!(@emit [$pos] [$($labels)*] [$($instructions)*]
+            ($crate::Instruction::Clear($reg, $t)) $($rest)*)
+    };
+    (@parse [$pos:expr] [$($labels:tt)*] [$($instructions:tt)*]
+        add $reg:tt, $n:tt -> $t:expr; $($rest:tt)*) => {
+        $crate::__minsky!(@emit [$pos] [$($labels)*] [$($instructions)*]
+            ($crate::Instruction::AddConst($reg, $n, $t)) $($rest)*)
+    };
+    (@parse [$pos:expr] [$($labels:tt)*] [$($instructions:tt)*]
+        sub $reg:tt, $n:tt -> $t:expr, $e:expr; $($rest:tt)*) => {
+        $crate::__minsky!(@emit [$pos] [$($labels)*] [$($instructions)*]
+            ($crate::Instruction::SubConst($reg, $n, $t, $e)) $($rest)*)
+    };
+    (@parse [$pos:expr] [$($labels:tt)*] [$($instructions:tt)*]
+        mov $src:tt, $dst:tt -> $t:expr; $($rest:tt)*) => {
+        $crate::__minsky!(@emit [$pos] [$($labels)*] [$($instructions)*]
+            ($crate::Instruction::Transfer { src: $src, dst: $dst, then: $t }) $($rest)*)
+    };
+    (@parse [$pos:expr] [$($labels:tt)*] [$($instructions:tt)*]
+        copy $src:tt, $dst:tt, $scratch:tt -> $t:expr; $($rest:tt)*) => {
+        $crate::__minsky!(@emit [$pos] [$($labels)*] [$($instructions)*]
+            ($crate::Instruction::Copy { src: $src, dst: $dst, scratch: $scratch, then: $t })
+            $($rest)*)
+    };
+    (@parse [$pos:expr] [$($labels:tt)*] [$($instructions:tt)*]
+        jz $reg:tt -> $z:expr, $nz:expr; $($rest:tt)*) => {
+        $crate::__minsky!(@emit [$pos] [$($labels)*] [$($instructions)*]
+            ($crate::Instruction::BranchZero($reg, $z, $nz)) $($rest)*)
+    };
+    (@parse [$pos:expr] [$($labels:tt)*] [$($instructions:tt)*]
+        cmp $a:tt, $b:tt -> $ge:expr, $lt:expr; $($rest:tt)*) => {
+        $crate::__minsky!(@emit [$pos] [$($labels)*] [$($instructions)*]
+            ($crate::Instruction::Compare($a, $b, $ge, $lt)) $($rest)*)
+    };
+    (@parse [$pos:expr] [$($labels:tt)*] [$($instructions:tt)*]
+        swap $a:tt, $b:tt -> $t:expr; $($rest:tt)*) => {
+        $crate::__minsky!(@emit [$pos] [$($labels)*] [$($instructions)*]
+            ($crate::Instruction::Swap($a, $b, $t)) $($rest)*)
+    };
+    (@parse [$pos:expr] [$($labels:tt)*] [$($instructions:tt)*]
+        read $reg:tt -> $t:expr; $($rest:tt)*) => {
+        $crate::__minsky!(@emit [$pos] [$($labels)*] [$($instructions)*]
+            ($crate::Instruction::Read($reg, $t)) $($rest)*)
+    };
+    (@parse [$pos:expr] [$($labels:tt)*] [$($instructions:tt)*]
+        write $reg:tt -> $t:expr; $($rest:tt)*) => {
+        $crate::__minsky!(@emit [$pos] [$($labels)*] [$($instructions)*]
+            ($crate::Instruction::Write($reg, $t)) $($rest)*)
+    };
+    (@parse [$pos:expr] [$($labels:tt)*] [$($instructions:tt)*]
+        choose $a:expr, $b:expr; $($rest:tt)*) => {
+        $crate::__minsky!(@emit [$pos] [$($labels)*] [$($instructions)*]
+            ($crate::Instruction::Choose($a, $b)) $($rest)*)
+    };
+    (@parse [$pos:expr] [$($labels:tt)*] [$($instructions:tt)*]
+        rand $p:tt -> $t:expr, $e:expr; $($rest:tt)*) => {
+        $crate::__minsky!(@emit [$pos] [$($labels)*] [$($instructions)*]
+            ($crate::Instruction::Random($p, $t, $e)) $($rest)*)
+    };
+    (@parse [$pos:expr] [$($labels:tt)*] [$($instructions:tt)*] call $t:expr; $($rest:tt)*) => {
+        $crate::__minsky!(@emit [$pos] [$($labels)*] [$($instructions)*]
+            ($crate::Instruction::Call($t)) $($rest)*)
+    };
+    (@parse [$pos:expr] [$($labels:tt)*] [$($instructions:tt)*] ret; $($rest:tt)*) => {
+        $crate::__minsky!(@emit [$pos] [$($labels)*] [$($instructions)*]
+            ($crate::Instruction::Return) $($rest)*)
+    };
+    (@emit [$pos:expr] [$($labels:tt)*] [$($instructions:tt)*] ($instruction:expr) $($rest:tt)*) => {
+        $crate::__minsky!(@parse [$pos + 1] [$($labels)*] [$($instructions)* $instruction,] $($rest)*)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Instruction, Machine, Program, RunOutcome};
+
+    const MULTIPLY: [Instruction; 7] = minsky! {
+        // $0 = $1 * $2
+        outer: dec 1 -> inner, done;
+        inner: dec 2 -> 2, restore;
+        inc 0 -> 3;
+        inc 3 -> inner;
+        restore: dec 3 -> 5, next;
+        inc 2 -> restore;
+        next: jmp outer;
+        done:
+    };
+
+    #[test]
+    fn multiply() {
+        assert_eq!(MULTIPLY[1], Instruction::Decrement(2, 2, 4));
+        assert_eq!(MULTIPLY[6], Instruction::Jump(0));
+        let program = Program::new(MULTIPLY.iter().copied());
+        let mut prog: Machine = Machine::new(&program);
+        prog.reset_with(vec![0, 6, 7]);
+        assert!(matches!(prog.run(u64::MAX), RunOutcome::Halted { .. }));
+        assert_eq!(&prog.registers()[..4], &[42, 0, 7, 0]);
+    }
+
+    #[test]
+    fn extended() {
+        let instructions = minsky! {
+            start: read 0 -> 1;
+            jz 0 -> end, 2;
+            add 1, 10 -> 3;
+            sub 0, 3 -> 2, 4;
+            mov 0, 2 -> 5;
+            copy 1, 3, 4 -> 6;
+            cmp 1, 2 -> 7, 7;
+            swap 1, 2 -> 8;
+            write 1 -> 9;
+            choose 10, 10;
+            rand 100 -> 11, 11;
+            call 13;
+            halt 3;
+            nop 14;
+            clr 5 -> 15;
+            ret;
+            end: halt;
+            purged;
+        };
+        assert_eq!(
+            instructions[..4],
+            [
+                Instruction::Read(0, 1),
+                Instruction::BranchZero(0, 16, 2),
+                Instruction::AddConst(1, 10, 3),
+                Instruction::SubConst(0, 3, 2, 4),
+            ]
+        );
+        assert_eq!(
+            instructions[12..],
+            [
+                Instruction::HaltWith(3),
+                Instruction::Nop(14),
+                Instruction::Clear(5, 15),
+                Instruction::Return,
+                Instruction::Halt,
+                Instruction::Purged,
+            ]
+        );
+        assert_eq!(
+            instructions[5],
+            Instruction::Copy {
+                src: 1,
+                dst: 3,
+                scratch: 4,
+                then: 6
+            }
+        );
+    }
+}
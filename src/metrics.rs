@@ -0,0 +1,164 @@
+use crate::{Instruction, IsaLevel, Program};
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// Static measures of the structure of a program, see [`Program::metrics`].
+///
+/// Except for `instructions`, these only consider the part of the program
+/// which is reachable from its start.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Metrics {
+    /// The number of instructions, see [`Program::len`].
+    pub instructions: usize,
+    /// The number of instructions reachable from the start.
+    pub reachable: usize,
+    /// The number of distinct registers used by reachable instructions.
+    pub registers: usize,
+    /// The number of edges of the control-flow graph starting at reachable instructions.
+    pub edges: usize,
+    /// The number of reachable instructions with more than one successor.
+    pub branches: usize,
+    /// The number of reachable basic blocks.
+    pub blocks: usize,
+    /// The number of reachable `Halt` and `HaltWith` instructions,
+    /// including the implicit `Halt` after the end of the program.
+    pub halts: usize,
+    /// The number of natural loops, with loops sharing a header counted once.
+    pub loops: usize,
+    /// The largest number of natural loops containing each other, zero without loops.
+    pub loop_depth: usize,
+    /// The number of strongly connected components which contain a cycle.
+    pub cyclic_components: usize,
+    /// The smallest instruction set containing all reachable instructions.
+    pub isa_level: IsaLevel,
+}
+
+impl fmt::Display for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "instructions: {} ({} reachable)",
+            self.instructions, self.reachable
+        )?;
+        writeln!(f, "registers: {}", self.registers)?;
+        writeln!(f, "edges: {}", self.edges)?;
+        writeln!(f, "branches: {}", self.branches)?;
+        writeln!(f, "blocks: {}", self.blocks)?;
+        writeln!(f, "halts: {}", self.halts)?;
+        writeln!(f, "loops: {} (depth {})", self.loops, self.loop_depth)?;
+        writeln!(f, "cyclic components: {}", self.cyclic_components)?;
+        write!(f, "instruction set: {}", self.isa_level)
+    }
+}
+
+impl Program {
+    /// Computes static measures of the structure of this program, e.g. to filter or
+    /// group large collections of programs, see [`Metrics`].
+    pub fn metrics(&self) -> Metrics {
+        let cfg = self.control_flow_graph();
+        let reachable = self.reachable_from(0);
+        let exit = cfg.exit();
+        let reaches_exit = reachable
+            .iter()
+            .any(|&at| cfg.successors(at).contains(&exit));
+        let instructions = || reachable.iter().map(|&at| self.instruction(at));
+
+        let registers: BTreeSet<u8> = instructions().flat_map(Instruction::registers).collect();
+        let loops = cfg.natural_loops();
+        let loop_depth = loops
+            .iter()
+            .map(|inner| {
+                loops
+                    .iter()
+                    .filter(|outer| inner.body.is_subset(&outer.body))
+                    .count()
+            })
+            .max()
+            .unwrap_or(0);
+        let cyclic_components = cfg
+            .strongly_connected_components()
+            .into_iter()
+            .filter(|component| reachable.contains(&component[0]))
+            .filter(|component| {
+                component.len() > 1 || cfg.successors(component[0]).contains(&component[0])
+            })
+            .count();
+        Metrics {
+            instructions: self.len(),
+            reachable: reachable.len(),
+            registers: registers.len(),
+            edges: reachable.iter().map(|&at| cfg.successors(at).len()).sum(),
+            branches: reachable
+                .iter()
+                .filter(|&&at| cfg.successors(at).len() > 1)
+                .count(),
+            blocks: cfg
+                .blocks()
+                .iter()
+                .filter(|block| reachable.contains(&block.start))
+                .count(),
+            halts: instructions()
+                .filter(|instruction| {
+                    matches!(instruction, Instruction::Halt | Instruction::HaltWith(_))
+                })
+                .count()
+                + usize::from(reaches_exit),
+            loops: loops.len(),
+            loop_depth,
+            cyclic_components,
+            isa_level: instructions()
+                .map(Instruction::isa_level)
+                .max()
+                .unwrap_or(IsaLevel::StrictMinsky),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metrics() {
+        // Two nested loops, an unreachable instruction and a jump past the end.
+        let program = Program::new(
+            [
+                Instruction::AddConst(0, 3, 1),
+                Instruction::Decrement(0, 2, 5),
+                Instruction::AddConst(1, 2, 3),
+                Instruction::Decrement(1, 4, 1),
+                Instruction::Increment(2, 3),
+                Instruction::Jump(9),
+                Instruction::Choose(0, 1),
+            ]
+            .iter()
+            .copied(),
+        );
+        let metrics = program.metrics();
+        assert_eq!(
+            metrics,
+            Metrics {
+                instructions: 7,
+                reachable: 6,
+                registers: 3,
+                edges: 8,
+                branches: 2,
+                blocks: 6,
+                halts: 1,
+                loops: 2,
+                loop_depth: 2,
+                cyclic_components: 1,
+                isa_level: IsaLevel::Accelerated,
+            }
+        );
+        assert!(metrics
+            .to_string()
+            .starts_with("instructions: 7 (6 reachable)\n"));
+
+        let empty = Program::empty().metrics();
+        assert_eq!(
+            (empty.reachable, empty.edges, empty.halts, empty.loops),
+            (0, 0, 0, 0)
+        );
+    }
+}
@@ -0,0 +1,324 @@
+use crate::{AsmError, AsmErrorKind, Instruction, Program, MAX_INSTRUCTIONS};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+/// The notations for counter machines supported by [`Program::parse_notation`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Dialect {
+    /// The state-based notation used by Minsky and Lambek, e.g. `q1: r2- q3 q5`.
+    ///
+    /// Each line defines a state, which either increments a register and continues
+    /// with the given state, e.g. `q0: r1+ q1`, decrements a register and continues
+    /// with the first state, or with the second state if the register is already
+    /// zero, e.g. `q1: r2- q3 q5`, or halts, written as `HALT` or `H`. The minus sign
+    /// may also be written as `−`. The first state is the initial state and states
+    /// which are never defined halt the machine.
+    Minsky,
+    /// The numbered notation of Shepherdson and Sturgis, with one instruction per line
+    /// and lines numbered starting at `1`.
+    ///
+    /// `P(n)` increments register `n`, `D(n)` decrements it, `J[e]` jumps to line `e`
+    /// and `J(n)[e]` jumps to line `e` if register `n` is zero. All other instructions
+    /// continue with the next line and jumping past the last line halts the machine.
+    /// Decrementing a register which is already zero does nothing.
+    ShepherdsonSturgis,
+}
+
+fn unexpected(expected: &'static str, found: &str) -> AsmErrorKind {
+    AsmErrorKind::Unexpected {
+        expected,
+        found: found.to_owned(),
+    }
+}
+
+fn parse_register(token: &str) -> Result<u8, AsmErrorKind> {
+    token
+        .strip_prefix('r')
+        .unwrap_or(token)
+        .parse()
+        .map_err(|_| AsmErrorKind::InvalidRegister(token.to_owned()))
+}
+
+/// A state of [`Dialect::Minsky`] whose successors are still state names.
+enum State {
+    Increment(u8, String),
+    Decrement(u8, String, String),
+    Halt,
+}
+
+/// Parses the part of a line of [`Dialect::Minsky`] after the name of the state.
+fn parse_state(body: &str) -> Result<State, AsmErrorKind> {
+    let body = body.replace('−', "-");
+    let mut tokens: Vec<String> = body.split_whitespace().map(str::to_owned).collect();
+    // The sign may be separated from the register, e.g. `r2 -`.
+    if tokens.len() >= 2 && (tokens[1] == "+" || tokens[1] == "-") {
+        let sign = tokens.remove(1);
+        tokens[0].push_str(&sign);
+    }
+    let mut tokens = tokens.into_iter();
+    let first = tokens.next().unwrap_or_default();
+    let mut state = |expected| tokens.next().ok_or_else(|| unexpected(expected, ""));
+    let parsed = if first.eq_ignore_ascii_case("HALT") || first.eq_ignore_ascii_case("H") {
+        State::Halt
+    } else if let Some(reg) = first.strip_suffix('+') {
+        State::Increment(parse_register(reg)?, state("a state")?)
+    } else if let Some(reg) = first.strip_suffix('-') {
+        let reg = parse_register(reg)?;
+        State::Decrement(reg, state("a state")?, state("a state")?)
+    } else if first.is_empty() {
+        return Err(unexpected("an instruction", ""));
+    } else {
+        return Err(AsmErrorKind::UnknownMnemonic(first));
+    };
+    match tokens.next() {
+        Some(token) => Err(unexpected("end of line", &token)),
+        None => Ok(parsed),
+    }
+}
+
+fn parse_minsky(src: &str) -> Result<Program, AsmError> {
+    let mut states = Vec::new();
+    let mut names = HashMap::new();
+    for (line, text) in src.lines().enumerate() {
+        let line = line + 1;
+        let error = |kind| AsmError { line, kind };
+        let text = text.split('#').next().unwrap_or("").trim();
+        if text.is_empty() {
+            continue;
+        }
+        let (name, body) = match text.find(':') {
+            Some(colon) => (text[..colon].trim(), &text[colon + 1..]),
+            None => return Err(error(unexpected("`:`", text))),
+        };
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(error(unexpected("a state", name)));
+        } else if names.insert(name, states.len() as u16).is_some() {
+            return Err(error(AsmErrorKind::DuplicateLabel(name.to_owned())));
+        } else if states.len() >= MAX_INSTRUCTIONS {
+            return Err(error(AsmErrorKind::TooLong));
+        }
+        states.push((line, parse_state(body).map_err(error)?));
+    }
+
+    // Undefined states jump to the implicit `Halt` after the last state,
+    // which does not exist if there are already `2^16` states.
+    let halt = u16::try_from(states.len()).ok();
+    let instructions = states
+        .iter()
+        .map(|&(line, ref state)| {
+            let lookup = |name: &str| {
+                names.get(name).copied().or(halt).ok_or(AsmError {
+                    line,
+                    kind: AsmErrorKind::TooLong,
+                })
+            };
+            Ok(match state {
+                State::Increment(reg, next) => Instruction::Increment(*reg, lookup(next)?),
+                State::Decrement(reg, then, zero) => {
+                    Instruction::Decrement(*reg, lookup(then)?, lookup(zero)?)
+                }
+                State::Halt => Instruction::Halt,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Program::new(instructions))
+}
+
+fn parse_shepherdson_sturgis(src: &str) -> Result<Program, AsmError> {
+    let mut instructions = Vec::new();
+    for (line, text) in src.lines().enumerate() {
+        let line = line + 1;
+        let error = |kind| AsmError { line, kind };
+        let text: String = text
+            .split('#')
+            .next()
+            .unwrap_or("")
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        if text.is_empty() {
+            continue;
+        } else if instructions.len() >= MAX_INSTRUCTIONS {
+            return Err(error(AsmErrorKind::TooLong));
+        }
+
+        // The last possible line can't continue with the next one.
+        let next =
+            || u16::try_from(instructions.len() + 1).map_err(|_| error(AsmErrorKind::TooLong));
+        let (op, rest) = text.split_at(text.find(['(', '[']).unwrap_or(text.len()));
+        let (reg, rest) = match rest.strip_prefix('(') {
+            Some(rest) => {
+                let close = rest
+                    .find(')')
+                    .ok_or_else(|| error(unexpected("`)`", rest)))?;
+                let reg = parse_register(&rest[..close]).map_err(error)?;
+                (Some(reg), &rest[close + 1..])
+            }
+            None => (None, rest),
+        };
+        let target = match rest.strip_prefix('[') {
+            Some(rest) => match rest.strip_suffix(']') {
+                Some(line) => match line.parse::<u16>() {
+                    Ok(line) if line > 0 => Some(line - 1),
+                    _ => return Err(error(AsmErrorKind::InvalidNumber(line.to_owned()))),
+                },
+                None => return Err(error(unexpected("`]`", rest))),
+            },
+            None if rest.is_empty() => None,
+            None => return Err(error(unexpected("end of line", rest))),
+        };
+        let instruction = match (op.to_ascii_uppercase().as_str(), reg, target) {
+            ("P", Some(reg), None) => Instruction::Increment(reg, next()?),
+            ("D", Some(reg), None) => Instruction::Decrement(reg, next()?, next()?),
+            ("J", None, Some(target)) => Instruction::Jump(target),
+            ("J", Some(reg), Some(target)) => Instruction::BranchZero(reg, target, next()?),
+            ("HALT", None, None) => Instruction::Halt,
+            ("P", ..) | ("D", ..) | ("J", ..) | ("HALT", ..) => {
+                return Err(error(unexpected("an instruction", &text)))
+            }
+            _ => return Err(error(AsmErrorKind::UnknownMnemonic(op.to_owned()))),
+        };
+        instructions.push(instruction);
+    }
+    Ok(Program::new(instructions))
+}
+
+impl Program {
+    /// Parses a counter machine written in one of the notations used in the
+    /// literature, see [`Dialect`].
+    ///
+    /// Registers are written either as plain numbers or as e.g. `r2`, using the
+    /// register number of the notation. Everything after a `#` is a comment.
+    pub fn parse_notation(src: &str, dialect: Dialect) -> Result<Program, AsmError> {
+        match dialect {
+            Dialect::Minsky => parse_minsky(src),
+            Dialect::ShepherdsonSturgis => parse_shepherdson_sturgis(src),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Machine, RunOutcome};
+
+    #[test]
+    fn minsky() {
+        // Adds r2 to r1, the state `q9` is never defined and halts.
+        let program = Program::parse_notation(
+            "
+            q1: r2− q2 q9
+            q2: r1 + q1
+            ",
+            Dialect::Minsky,
+        )
+        .unwrap();
+        assert_eq!(
+            program.instructions(),
+            [
+                Instruction::Decrement(2, 1, 2),
+                Instruction::Increment(1, 0)
+            ]
+        );
+        let mut prog: Machine = Machine::new(&program);
+        prog.set_register(1, 2);
+        prog.set_register(2, 3);
+        assert!(matches!(prog.run(100), RunOutcome::Halted { .. }));
+        assert_eq!(prog.get_register(1), &5);
+
+        let halt = Program::parse_notation("start: r0- done done\ndone: H", Dialect::Minsky);
+        assert_eq!(
+            halt.unwrap().instructions(),
+            [Instruction::Decrement(0, 1, 1)]
+        );
+
+        let error = |src| Program::parse_notation(src, Dialect::Minsky).unwrap_err();
+        assert_eq!(
+            error("q1: r1+ q1\nq1: H"),
+            AsmError {
+                line: 2,
+                kind: AsmErrorKind::DuplicateLabel("q1".to_owned())
+            }
+        );
+        assert_eq!(error("q1: r1- q2").kind, unexpected("a state", ""));
+        assert_eq!(
+            error("q1: rx+ q2").kind,
+            AsmErrorKind::InvalidRegister("rx".to_owned())
+        );
+        assert_eq!(error("r1+ q2").kind, unexpected("`:`", "r1+ q2"));
+
+        // With `2^16` states, there is no implicit `Halt` left for undefined states.
+        let mut src: String = (0..u16::MAX).map(|i| format!("q{}: H\n", i)).collect();
+        let program =
+            Program::parse_notation(&format!("{}q65535: r1+ q0", src), Dialect::Minsky).unwrap();
+        assert_eq!(program.instruction(u16::MAX), Instruction::Increment(1, 0));
+        src.push_str("q65535: r1+ q65536");
+        assert_eq!(
+            error(&src),
+            AsmError {
+                line: 1 << 16,
+                kind: AsmErrorKind::TooLong
+            }
+        );
+    }
+
+    #[test]
+    fn shepherdson_sturgis() {
+        // Moves r1 to r2.
+        let program = Program::parse_notation(
+            "
+            J(1)[5]
+            D(1)
+            P (2)
+            J[1]
+            ",
+            Dialect::ShepherdsonSturgis,
+        )
+        .unwrap();
+        assert_eq!(
+            program.instructions(),
+            [
+                Instruction::BranchZero(1, 4, 1),
+                Instruction::Decrement(1, 2, 2),
+                Instruction::Increment(2, 3),
+                Instruction::Jump(0),
+            ]
+        );
+        let mut prog: Machine = Machine::new(&program);
+        prog.set_register(1, 3);
+        assert!(matches!(prog.run(100), RunOutcome::Halted { .. }));
+        assert_eq!(prog.registers()[1..3], [0, 3]);
+
+        let error = |src| Program::parse_notation(src, Dialect::ShepherdsonSturgis).unwrap_err();
+        assert_eq!(error("P(1)\nJ[0]").line, 2);
+        assert_eq!(
+            error("J[0]").kind,
+            AsmErrorKind::InvalidNumber("0".to_owned())
+        );
+        assert_eq!(
+            error("X(1)").kind,
+            AsmErrorKind::UnknownMnemonic("X".to_owned())
+        );
+        assert_eq!(error("P[1]").kind, unexpected("an instruction", "P[1]"));
+
+        // The last possible line can't continue with the next one.
+        let src = "J[1]\n".repeat(usize::from(u16::MAX) - 1);
+        let program =
+            Program::parse_notation(&format!("{}P(1)", src), Dialect::ShepherdsonSturgis).unwrap();
+        assert_eq!(
+            program.instruction(u16::MAX - 1),
+            Instruction::Increment(1, u16::MAX)
+        );
+        let src = format!("{}J[1]\n", src);
+        assert!(
+            Program::parse_notation(&format!("{}J[1]", src), Dialect::ShepherdsonSturgis).is_ok()
+        );
+        assert_eq!(
+            error(&format!("{}P(1)", src)),
+            AsmError {
+                line: 1 << 16,
+                kind: AsmErrorKind::TooLong
+            }
+        );
+    }
+}
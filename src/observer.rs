@@ -0,0 +1,191 @@
+use crate::{Counter, Machine, Stats, Trace};
+use std::ops::ControlFlow;
+
+/// Hooks called while running a machine with [`Machine::run_observed`].
+///
+/// All methods do nothing by default. Observers can be combined using tuples,
+/// e.g. `machine.run_observed(steps, &mut (stats, trace))`.
+pub trait Observer<C: Counter = u64> {
+    /// Called once at the start of each run.
+    fn on_start(&mut self, machine: &Machine<'_, C>) {
+        let _ = machine;
+    }
+
+    /// Called after executing the instruction at `at`, which changed the registers `written`.
+    ///
+    /// Breaking stops the run with [`RunOutcome::Stopped`](crate::RunOutcome::Stopped).
+    fn on_step(&mut self, machine: &Machine<'_, C>, at: u16, written: &[u8]) -> ControlFlow<()> {
+        let _ = (machine, at, written);
+        ControlFlow::Continue(())
+    }
+
+    /// Called after a step changed `reg` to `value`, before calling [`Observer::on_step`].
+    fn on_register_write(&mut self, reg: u8, value: &C) {
+        let _ = (reg, value);
+    }
+
+    /// Called when the run stops because the machine halted after executing `steps` steps.
+    fn on_halt(&mut self, machine: &Machine<'_, C>, steps: u64) {
+        let _ = (machine, steps);
+    }
+}
+
+impl<C: Counter, O: Observer<C> + ?Sized> Observer<C> for &mut O {
+    fn on_start(&mut self, machine: &Machine<'_, C>) {
+        (**self).on_start(machine)
+    }
+
+    fn on_step(&mut self, machine: &Machine<'_, C>, at: u16, written: &[u8]) -> ControlFlow<()> {
+        (**self).on_step(machine, at, written)
+    }
+
+    fn on_register_write(&mut self, reg: u8, value: &C) {
+        (**self).on_register_write(reg, value)
+    }
+
+    fn on_halt(&mut self, machine: &Machine<'_, C>, steps: u64) {
+        (**self).on_halt(machine, steps)
+    }
+}
+
+impl<C: Counter> Observer<C> for () {}
+
+impl<C: Counter, A: Observer<C>, B: Observer<C>> Observer<C> for (A, B) {
+    fn on_start(&mut self, machine: &Machine<'_, C>) {
+        self.0.on_start(machine);
+        self.1.on_start(machine);
+    }
+
+    fn on_step(&mut self, machine: &Machine<'_, C>, at: u16, written: &[u8]) -> ControlFlow<()> {
+        let first = self.0.on_step(machine, at, written);
+        let second = self.1.on_step(machine, at, written);
+        if first.is_break() || second.is_break() {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    }
+
+    fn on_register_write(&mut self, reg: u8, value: &C) {
+        self.0.on_register_write(reg, value);
+        self.1.on_register_write(reg, value);
+    }
+
+    fn on_halt(&mut self, machine: &Machine<'_, C>, steps: u64) {
+        self.0.on_halt(machine, steps);
+        self.1.on_halt(machine, steps);
+    }
+}
+
+impl<C: Counter> Observer<C> for Stats<C> {
+    fn on_start(&mut self, machine: &Machine<'_, C>) {
+        self.observe_registers(machine.registers());
+    }
+
+    fn on_step(&mut self, machine: &Machine<'_, C>, at: u16, written: &[u8]) -> ControlFlow<()> {
+        self.record(at, machine.program().instruction(at), !written.is_empty());
+        ControlFlow::Continue(())
+    }
+
+    fn on_register_write(&mut self, reg: u8, value: &C) {
+        self.observe_register(reg, value);
+    }
+}
+
+impl<C: Counter> Observer<C> for Trace<C> {
+    fn on_step(&mut self, machine: &Machine<'_, C>, at: u16, written: &[u8]) -> ControlFlow<()> {
+        self.record(
+            at,
+            written.iter().map(|&reg| (reg, machine.get_register(reg))),
+            machine.registers(),
+        );
+        ControlFlow::Continue(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Instruction, Program, RunOutcome};
+
+    #[derive(Default)]
+    struct Log {
+        events: Vec<String>,
+    }
+
+    impl Observer for Log {
+        fn on_start(&mut self, machine: &Machine<'_>) {
+            self.events.push(format!("start {}", machine.ptr()));
+        }
+
+        fn on_step(&mut self, machine: &Machine<'_>, at: u16, _: &[u8]) -> ControlFlow<()> {
+            self.events
+                .push(format!("step {} -> {}", at, machine.ptr()));
+            if machine.ptr() == 3 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        }
+
+        fn on_register_write(&mut self, reg: u8, value: &u64) {
+            self.events.push(format!("write ${} = {}", reg, value));
+        }
+
+        fn on_halt(&mut self, _: &Machine<'_>, steps: u64) {
+            self.events.push(format!("halt {}", steps));
+        }
+    }
+
+    #[test]
+    fn observer() {
+        let program = Program::new(
+            [
+                Instruction::Decrement(0, 1, 2),
+                Instruction::Increment(1, 2),
+                Instruction::Decrement(2, 2, 4),
+                Instruction::Halt,
+            ]
+            .iter()
+            .copied(),
+        );
+        let mut prog: Machine = Machine::new(&program);
+        prog.set_register(0, 1);
+        let mut log = Log::default();
+        let mut stats = Stats::new();
+        let outcome = prog.run_observed(u64::MAX, &mut (&mut log, &mut stats));
+        assert_eq!(outcome, RunOutcome::Halted { steps: 3, code: 0 });
+        assert_eq!(
+            log.events,
+            [
+                "start 0",
+                "write $0 = 0",
+                "step 0 -> 1",
+                "write $1 = 1",
+                "step 1 -> 2",
+                "step 2 -> 4",
+                "halt 3",
+            ]
+        );
+        assert_eq!(stats.steps(), 3);
+        assert_eq!(stats.zero_tests(), 1);
+
+        let program = Program::new(
+            [
+                Instruction::Increment(0, 3),
+                Instruction::Halt,
+                Instruction::Halt,
+                Instruction::Increment(0, 3),
+            ]
+            .iter()
+            .copied(),
+        );
+        let mut prog: Machine = Machine::new(&program);
+        let mut log = Log::default();
+        assert_eq!(
+            prog.run_observed(u64::MAX, &mut log),
+            RunOutcome::Stopped { steps: 1 }
+        );
+        assert_eq!(log.events, ["start 0", "write $0 = 1", "step 0 -> 3"]);
+    }
+}
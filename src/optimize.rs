@@ -0,0 +1,289 @@
+use crate::{Instruction, Program};
+
+impl Program {
+    /// Follows chains of `Jump`s and `Nop`s starting at `target`, returning
+    /// the first position which is not an unconditional jump.
+    ///
+    /// Stops after `len` jumps, so that loops of jumps don't hang.
+    fn skip_jumps(&self, mut target: u16) -> u16 {
+        for _ in 0..self.len() {
+            match self.instruction(target) {
+                Instruction::Jump(next) | Instruction::Nop(next) => target = next,
+                _ => break,
+            }
+        }
+        target
+    }
+
+    /// Shortens the paths through this program by skipping unconditional jumps
+    /// and jumping to halting instructions directly.
+    ///
+    /// Jump targets which refer to a chain of `Jump`s or `Nop`s are changed to the end
+    /// of the chain, and all targets which end up at a `Halt` are changed to the implicit
+    /// `Halt` at the end of the program. Each `Jump` or `Nop` which reaches a `Halt` or
+    /// `HaltWith` this way is replaced by it. All instructions keep their positions,
+    /// use [`Program::eliminate_dead_code`] to remove unused ones.
+    pub fn thread_jumps(&self) -> Program {
+        let end = self.len() as u16;
+        let target = |target: u16| {
+            let target = self.skip_jumps(target);
+            match self.instruction(target) {
+                Instruction::Halt => end,
+                _ => target,
+            }
+        };
+        Program::new(self.iter().map(|(_, mut instruction)| {
+            if let Instruction::Jump(next) | Instruction::Nop(next) = instruction {
+                if let halt @ (Instruction::Halt | Instruction::HaltWith(_)) =
+                    self.instruction(self.skip_jumps(next))
+                {
+                    return halt;
+                }
+            }
+            for t in instruction.targets_mut() {
+                *t = target(*t);
+            }
+            instruction
+        }))
+    }
+
+    /// Removes dead code from this program.
+    ///
+    /// Jump targets which refer to a chain of `Jump`s or `Nop`s are changed to the end
+    /// of the chain, then all instructions which are not reachable from the first one are
+    /// removed and all targets are renumbered accordingly. Targets past the end of the
+    /// program refer to the end of the new program.
+    ///
+    /// Also returns the new position of each instruction of this program, or `None`
+    /// if it was removed, e.g. to correlate traces of both programs.
+    pub fn eliminate_dead_code(&self) -> (Program, Vec<Option<u16>>) {
+        let threaded = self.remap_targets(|target| self.skip_jumps(target));
+
+        let reachable = threaded.reachable_from(0);
+        let mut positions = vec![None; self.len()];
+        for (new, &at) in reachable.iter().enumerate() {
+            positions[usize::from(at)] = Some(new as u16);
+        }
+        let end = reachable.len() as u16;
+        let position = |at: u16| {
+            positions
+                .get(usize::from(at))
+                .copied()
+                .flatten()
+                .unwrap_or(end)
+        };
+
+        let instructions = reachable.iter().map(|&at| {
+            let mut instruction = threaded.instruction(at);
+            for target in instruction.targets_mut() {
+                *target = position(*target);
+            }
+            instruction
+        });
+        (Program::new(instructions), positions)
+    }
+
+    /// Specializes this program for the given initial register values, see
+    /// [`Program::constant_registers`] for the meaning of `inputs`.
+    ///
+    /// Branches whose condition is known are replaced by a `Jump` to the branch
+    /// which is taken, or by the same instruction with both targets set to it if
+    /// it still modifies a register. Instructions which don't change any register,
+    /// e.g. clearing a register which is already zero, become a `Jump` as well and
+    /// unreachable instructions are replaced with `Purged`. All instructions keep
+    /// their positions, use [`Program::eliminate_dead_code`] to remove unused ones.
+    ///
+    /// The specialized program behaves the same as this program for all register
+    /// values matching `inputs`.
+    pub fn specialize(&self, inputs: impl IntoIterator<Item = (u8, Option<u64>)>) -> Program {
+        let known = self.constant_registers(inputs);
+        Program::new(self.iter().map(|(at, instruction)| {
+            let known = match &known[usize::from(at)] {
+                Some(known) => known,
+                None => return Instruction::Purged,
+            };
+            let value = |reg| known.get(&reg).copied();
+            match instruction {
+                Instruction::Decrement(reg, then, els) => match value(reg) {
+                    Some(0) => Instruction::Jump(els),
+                    Some(_) => Instruction::Decrement(reg, then, then),
+                    None => instruction,
+                },
+                Instruction::SubConst(reg, n, then, els) => match value(reg) {
+                    Some(v) if v < n => Instruction::Jump(els),
+                    Some(_) if n == 0 => Instruction::Jump(then),
+                    Some(_) => Instruction::SubConst(reg, n, then, then),
+                    None => instruction,
+                },
+                Instruction::BranchZero(reg, zero, nonzero) => match value(reg) {
+                    Some(0) => Instruction::Jump(zero),
+                    Some(_) => Instruction::Jump(nonzero),
+                    None => instruction,
+                },
+                Instruction::Compare(a, b, ge, lt) => match (value(a), value(b)) {
+                    (Some(a), Some(b)) => Instruction::Jump(if a >= b { ge } else { lt }),
+                    _ => instruction,
+                },
+                Instruction::Clear(reg, then) if value(reg) == Some(0) => Instruction::Jump(then),
+                Instruction::Transfer { src, then, .. } if value(src) == Some(0) => {
+                    Instruction::Jump(then)
+                }
+                Instruction::Copy {
+                    src, scratch, then, ..
+                } if value(src) == Some(0) && value(scratch) == Some(0) => Instruction::Jump(then),
+                _ => instruction,
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Machine, RunOutcome};
+
+    #[test]
+    fn dead_code() {
+        let program = Program::new(
+            [
+                Instruction::Decrement(0, 2, 5),
+                Instruction::Increment(3, 0),
+                Instruction::Nop(3),
+                Instruction::Jump(4),
+                Instruction::Increment(1, 0),
+                Instruction::Jump(9),
+                Instruction::Jump(6),
+            ]
+            .iter()
+            .copied(),
+        );
+        let (optimized, positions) = program.eliminate_dead_code();
+        assert_eq!(
+            optimized.instructions(),
+            [
+                Instruction::Decrement(0, 1, 2),
+                Instruction::Increment(1, 0),
+            ]
+        );
+        assert_eq!(positions, [Some(0), None, None, None, Some(1), None, None]);
+
+        let mut prog: Machine = Machine::new(&optimized);
+        prog.set_register(0, 3);
+        prog.run(100);
+        assert_eq!(prog.registers()[..2], [0, 3]);
+
+        // The entry and loops of jumps are kept.
+        let program = Program::new([Instruction::Jump(1), Instruction::Jump(1)].iter().copied());
+        let (optimized, positions) = program.eliminate_dead_code();
+        assert_eq!(
+            optimized.instructions(),
+            [Instruction::Jump(1), Instruction::Jump(1)]
+        );
+        assert_eq!(positions, [Some(0), Some(1)]);
+    }
+
+    #[test]
+    fn jump_threading() {
+        let program = Program::new(
+            [
+                Instruction::Decrement(0, 1, 3),
+                Instruction::Jump(2),
+                Instruction::Nop(0),
+                Instruction::Jump(4),
+                Instruction::Halt,
+                Instruction::Jump(6),
+                Instruction::HaltWith(2),
+                Instruction::Nop(1),
+            ]
+            .iter()
+            .copied(),
+        );
+        let threaded = program.thread_jumps();
+        assert_eq!(
+            threaded.instructions(),
+            [
+                Instruction::Decrement(0, 0, 8),
+                Instruction::Jump(0),
+                Instruction::Nop(0),
+                Instruction::Halt,
+                Instruction::Halt,
+                Instruction::HaltWith(2),
+                Instruction::HaltWith(2),
+                Instruction::Nop(0),
+            ]
+        );
+
+        let mut prog: Machine = Machine::new(&program);
+        prog.set_register(0, 5);
+        let before = prog.run(100);
+        let mut prog: Machine = Machine::new(&threaded);
+        prog.set_register(0, 5);
+        assert_eq!(before, RunOutcome::Halted { steps: 17, code: 0 });
+        assert_eq!(prog.run(100), RunOutcome::Halted { steps: 6, code: 0 });
+    }
+
+    #[test]
+    fn specialize() {
+        // $0 += $1 if $2 is zero, otherwise $0 = 0.
+        let program = Program::new(
+            [
+                Instruction::Clear(3, 1),
+                Instruction::BranchZero(2, 2, 5),
+                Instruction::Transfer {
+                    src: 1,
+                    dst: 0,
+                    then: 3,
+                },
+                Instruction::Decrement(3, 4, 6),
+                Instruction::Increment(0, 6),
+                Instruction::Clear(0, 6),
+            ]
+            .iter()
+            .copied(),
+        );
+        let known = program.constant_registers(vec![(0, None), (1, Some(4))]);
+        assert_eq!(known[2].as_ref().unwrap()[&1], 4);
+        assert_eq!(known[3].as_ref().unwrap().get(&0), None);
+        assert_eq!(known[4], None);
+        assert_eq!(known[5], None);
+
+        let specialized = program.specialize(vec![(0, None), (1, Some(4))]);
+        assert_eq!(
+            specialized.diff(&program),
+            [
+                (0, Instruction::Jump(1), Instruction::Clear(3, 1)),
+                (1, Instruction::Jump(2), Instruction::BranchZero(2, 2, 5)),
+                (3, Instruction::Jump(6), Instruction::Decrement(3, 4, 6)),
+                (4, Instruction::Purged, Instruction::Increment(0, 6)),
+                (5, Instruction::Purged, Instruction::Clear(0, 6)),
+            ]
+        );
+        let (compact, _) = specialized.eliminate_dead_code();
+        assert_eq!(compact.len(), 2);
+        for input in 0..5 {
+            let mut prog: Machine = Machine::new(&compact);
+            prog.set_register(0, input);
+            prog.set_register(1, 4);
+            assert_eq!(prog.run(100), RunOutcome::Halted { steps: 2, code: 0 });
+            assert_eq!(prog.registers()[..4], [input + 4, 0, 0, 0]);
+        }
+
+        // The loop counter is unknown after the first iteration,
+        // and nothing is known after returning from a `Call`.
+        let program = Program::new(
+            [
+                Instruction::Decrement(0, 1, 2),
+                Instruction::Increment(1, 0),
+                Instruction::Call(3),
+                Instruction::Return,
+            ]
+            .iter()
+            .copied(),
+        );
+        let known = program.constant_registers(vec![(0, Some(3))]);
+        assert!(known[0].as_ref().unwrap().is_empty());
+        assert_eq!(known[2].as_ref().unwrap().get(&0), Some(&0));
+        assert!(known[3].as_ref().unwrap().is_empty());
+        assert_eq!(program.specialize(vec![(0, Some(3))]), program);
+    }
+}
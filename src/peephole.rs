@@ -0,0 +1,164 @@
+//! Rewriting short instruction patterns, see [`Program::peephole`].
+//!
+//! Rules replace a single instruction, usually the head of a loop, with an equivalent
+//! accelerated instruction. The other instructions of the pattern are left unchanged
+//! and often become unreachable, so they can be removed afterwards using
+//! [`Program::eliminate_dead_code`].
+
+use crate::{Instruction, Program};
+
+/// A rewrite rule for [`Program::peephole`].
+pub trait PeepholeRule {
+    /// Returns the replacement for the instruction at `at`, or `None` if the rule
+    /// does not apply there.
+    ///
+    /// The replacement must have the same effect as the instructions executed
+    /// starting at `at`, except for taking fewer steps.
+    fn rewrite(&self, program: &Program, at: u16) -> Option<Instruction>;
+}
+
+impl<F: Fn(&Program, u16) -> Option<Instruction>> PeepholeRule for F {
+    fn rewrite(&self, program: &Program, at: u16) -> Option<Instruction> {
+        self(program, at)
+    }
+}
+
+/// Rewrites the loop `at: DEC r -> at, e` to `CLR r -> e`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SelfLoopClear;
+
+impl PeepholeRule for SelfLoopClear {
+    fn rewrite(&self, program: &Program, at: u16) -> Option<Instruction> {
+        match program.instruction(at) {
+            Instruction::Decrement(reg, then, els) if then == at => {
+                Some(Instruction::Clear(reg, els))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Rewrites the loop `at: DEC src -> inc, e`, `inc: INC dst -> at` with
+/// distinct registers to `MOV src, dst -> e`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DrainTransfer;
+
+impl PeepholeRule for DrainTransfer {
+    fn rewrite(&self, program: &Program, at: u16) -> Option<Instruction> {
+        match program.instruction(at) {
+            Instruction::Decrement(src, inc, els) => match program.instruction(inc) {
+                Instruction::Increment(dst, back) if back == at && dst != src => {
+                    Some(Instruction::Transfer {
+                        src,
+                        dst,
+                        then: els,
+                    })
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Rewrites `at: INC r -> dec`, `dec: DEC r -> t, e`, which always
+/// continues at `t` without changing `r`, to `JMP t`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IncrementDecrement;
+
+impl PeepholeRule for IncrementDecrement {
+    fn rewrite(&self, program: &Program, at: u16) -> Option<Instruction> {
+        match program.instruction(at) {
+            Instruction::Increment(reg, dec) => match program.instruction(dec) {
+                Instruction::Decrement(r, then, _) if r == reg => Some(Instruction::Jump(then)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// The rules defined in this module, in the order in which they should be tried.
+pub fn default_rules() -> Vec<Box<dyn PeepholeRule>> {
+    vec![
+        Box::new(SelfLoopClear),
+        Box::new(DrainTransfer),
+        Box::new(IncrementDecrement),
+    ]
+}
+
+impl Program {
+    /// Applies the first matching rule of `rules` to each instruction of this program,
+    /// see [`default_rules`].
+    ///
+    /// Rules are applied in a single pass from the first to the last instruction, and
+    /// see the rewrites of all previous instructions. The rewritten program has the same
+    /// effect as this program, but usually takes fewer steps.
+    pub fn peephole(&self, rules: &[Box<dyn PeepholeRule>]) -> Program {
+        let mut program = self.clone();
+        for at in 0..self.len() as u16 {
+            if let Some(instruction) = rules.iter().find_map(|rule| rule.rewrite(&program, at)) {
+                program.instructions_mut()[usize::from(at)] = instruction;
+            }
+        }
+        program
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Machine, RunOutcome};
+
+    #[test]
+    fn peephole() {
+        // Clears $2, moves $0 to $1 and then increments and decrements $3.
+        let program = Program::new(
+            [
+                Instruction::Decrement(2, 0, 1),
+                Instruction::Decrement(0, 2, 3),
+                Instruction::Increment(1, 1),
+                Instruction::Increment(3, 4),
+                Instruction::Decrement(3, 5, 5),
+            ]
+            .iter()
+            .copied(),
+        );
+        let optimized = program.peephole(&default_rules());
+        assert_eq!(
+            optimized.instructions(),
+            [
+                Instruction::Clear(2, 1),
+                Instruction::Transfer {
+                    src: 0,
+                    dst: 1,
+                    then: 3
+                },
+                Instruction::Increment(1, 1),
+                Instruction::Jump(5),
+                Instruction::Decrement(3, 5, 5),
+            ]
+        );
+        let (compact, _) = optimized.eliminate_dead_code();
+        assert_eq!(compact.len(), 2);
+
+        for program in &[program, compact] {
+            let mut prog: Machine = Machine::new(program);
+            prog.set_register(0, 4);
+            prog.set_register(2, 7);
+            assert!(matches!(prog.run(100), RunOutcome::Halted { .. }));
+            assert_eq!(prog.registers()[..4], [0, 4, 0, 0]);
+        }
+
+        let custom = |program: &Program, at| match program.instruction(at) {
+            Instruction::Jump(target) => Some(Instruction::Nop(target)),
+            _ => None,
+        };
+        let rules: Vec<Box<dyn PeepholeRule>> = vec![Box::new(custom)];
+        let program = Program::new([Instruction::Jump(1)].iter().copied());
+        assert_eq!(
+            program.peephole(&rules).instructions(),
+            [Instruction::Nop(1)]
+        );
+    }
+}
@@ -0,0 +1,844 @@
+use crate::{Instruction, IsaLevel};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::FromIterator;
+use std::ops::Index;
+
+/// The maximum number of instructions in a program.
+pub const MAX_INSTRUCTIONS: usize = 1 << 16;
+
+/// An error returned when constructing an invalid [`Program`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgramError {
+    /// The program has more than [`MAX_INSTRUCTIONS`] instructions.
+    TooLong,
+    /// The instruction at `at` jumps to `target`, which is a `Purged` instruction.
+    TargetPurged { at: u16, target: u16 },
+    /// The instruction at `at` is not part of the instruction set `level`.
+    UnsupportedInstruction { at: u16, level: IsaLevel },
+    /// `at` is past the implicit `Halt` after the last instruction, so it can't be replaced.
+    InstructionOutOfRange { at: u16 },
+    /// The instruction at `at` jumps to `target`, which does not fit into a `u16`
+    /// once offset by `offset`, e.g. when appending it to another program.
+    TargetOverflow { at: u16, target: u16, offset: u16 },
+}
+
+impl fmt::Display for ProgramError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ProgramError::TooLong => {
+                write!(f, "program has more than {} instructions", MAX_INSTRUCTIONS)
+            }
+            ProgramError::TargetPurged { at, target } => {
+                write!(f, "instruction {} jumps to {}, which is purged", at, target)
+            }
+            ProgramError::UnsupportedInstruction { at, level } => write!(
+                f,
+                "instruction {} is not part of the {} instruction set",
+                at, level
+            ),
+            ProgramError::InstructionOutOfRange { at } => {
+                write!(f, "instruction {} is out of range", at)
+            }
+            ProgramError::TargetOverflow { at, target, offset } => write!(
+                f,
+                "instruction {} jumps to {}, which overflows when offset by {}",
+                at, target, offset
+            ),
+        }
+    }
+}
+
+impl Error for ProgramError {}
+
+/// The immutable instruction table of a Minsky machine.
+///
+/// A `Program` does not contain any execution state, use a [`Machine`](crate::Machine)
+/// to run it. As programs are never modified while running, a single program
+/// can be shared by any number of machines.
+///
+/// Only the instructions which were actually provided are stored, every
+/// instruction pointer past the end of the program refers to an implicit `Halt`.
+/// Trailing `Halt`s are therefore not part of the program, so programs which only
+/// differ in trailing `Halt`s are equal, see [`Program::len`].
+#[derive(Debug, Clone)]
+pub struct Program {
+    instructions: Vec<Instruction>,
+}
+
+impl PartialEq for Program {
+    fn eq(&self, other: &Program) -> bool {
+        self.instructions() == other.instructions()
+    }
+}
+
+impl Eq for Program {}
+
+impl Hash for Program {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.instructions().hash(state)
+    }
+}
+
+impl Program {
+    pub fn empty() -> Program {
+        Program {
+            instructions: Vec::new(),
+        }
+    }
+
+    /// Creates a new program, ignoring all instructions after the first `2^16`.
+    pub fn new(instructions: impl IntoIterator<Item = Instruction>) -> Program {
+        Program {
+            instructions: instructions.into_iter().take(MAX_INSTRUCTIONS).collect(),
+        }
+    }
+
+    /// Creates a new program, checking that it is not too long and that no jump
+    /// target refers to a purged instruction.
+    ///
+    /// Targets past the end of the program jump to its implicit `Halt`.
+    pub fn try_new(
+        instructions: impl IntoIterator<Item = Instruction>,
+    ) -> Result<Program, ProgramError> {
+        let program = instructions.into_iter().collect::<Result<Program, _>>()?;
+
+        for (at, instruction) in program.iter() {
+            program.check_targets(at, instruction)?;
+        }
+
+        Ok(program)
+    }
+
+    /// Checks that no target of `instruction`, stored at `at`, refers to a purged instruction.
+    fn check_targets(&self, at: u16, instruction: Instruction) -> Result<(), ProgramError> {
+        match instruction
+            .targets()
+            .find(|&target| self.instruction(target) == Instruction::Purged)
+        {
+            Some(target) => Err(ProgramError::TargetPurged { at, target }),
+            None => Ok(()),
+        }
+    }
+
+    /// The smallest instruction set containing all instructions of this program.
+    pub fn isa_level(&self) -> IsaLevel {
+        self.instructions
+            .iter()
+            .map(|instruction| instruction.isa_level())
+            .max()
+            .unwrap_or(IsaLevel::StrictMinsky)
+    }
+
+    /// Checks that all instructions are part of the instruction set `level`.
+    ///
+    /// Use [`IsaLevel::StrictMinsky`] to make sure that results about a
+    /// program don't rely on any extensions of the original Minsky machine.
+    pub fn validate(&self, level: IsaLevel) -> Result<(), ProgramError> {
+        match self
+            .instructions
+            .iter()
+            .position(|instruction| instruction.isa_level() > level)
+        {
+            Some(at) => Err(ProgramError::UnsupportedInstruction {
+                at: at as u16,
+                level,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// The index of the last instruction which is not `Halt`, plus one.
+    pub fn len(&self) -> usize {
+        self.instructions
+            .iter()
+            .rposition(|&instruction| instruction != Instruction::Halt)
+            .map_or(0, |at| at + 1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The instructions up to [`Program::len`], without any trailing `Halt`s.
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions[..self.len()]
+    }
+
+    /// Mutable access to the instructions up to [`Program::len`], without any checks.
+    pub(crate) fn instructions_mut(&mut self) -> &mut [Instruction] {
+        let len = self.len();
+        &mut self.instructions[..len]
+    }
+
+    /// Iterates over the instructions up to [`Program::len`] together with their index.
+    pub fn iter(&self) -> impl Iterator<Item = (u16, Instruction)> + '_ {
+        self.instructions()
+            .iter()
+            .enumerate()
+            .map(|(at, &instruction)| (at as u16, instruction))
+    }
+
+    pub fn instruction(&self, ptr: u16) -> Instruction {
+        self.instructions
+            .get(ptr as usize)
+            .copied()
+            .unwrap_or(Instruction::Halt)
+    }
+
+    /// Replaces the instruction at `at` with `instruction`, returning the previous one.
+    ///
+    /// The targets of `instruction` must not refer to purged instructions
+    /// and `instruction` may only be `Purged` if no instruction jumps to `at`.
+    /// `at` may also be [`Program::len`], replacing the implicit `Halt` after the last
+    /// instruction. The program is unchanged if this returns an error.
+    pub fn set_instruction(
+        &mut self,
+        at: u16,
+        instruction: Instruction,
+    ) -> Result<Instruction, ProgramError> {
+        if at as usize > self.len() {
+            return Err(ProgramError::InstructionOutOfRange { at });
+        }
+        self.check_targets(at, instruction)?;
+        if instruction == Instruction::Purged {
+            for (from, other) in self.iter() {
+                if from != at && other.targets().any(|target| target == at) {
+                    return Err(ProgramError::TargetPurged {
+                        at: from,
+                        target: at,
+                    });
+                }
+            }
+        }
+        if at as usize == self.instructions.len() {
+            self.instructions.push(Instruction::Halt);
+        }
+        Ok(std::mem::replace(
+            &mut self.instructions[at as usize],
+            instruction,
+        ))
+    }
+
+    /// The instructions which differ between `self` and `other`, as
+    /// `(ptr, self.instruction(ptr), other.instruction(ptr))`.
+    ///
+    /// Missing instructions are treated as the implicit `Halt`, so programs which
+    /// only differ in trailing `Halt`s don't have any differences.
+    pub fn diff(&self, other: &Program) -> Vec<(u16, Instruction, Instruction)> {
+        (0..self.len().max(other.len()))
+            .map(|ptr| ptr as u16)
+            .map(|ptr| (ptr, self.instruction(ptr), other.instruction(ptr)))
+            .filter(|(_, a, b)| a != b)
+            .collect()
+    }
+
+    /// Creates a copy of this program with every jump target `t` replaced by `f(t)`.
+    ///
+    /// The instructions stay at their current positions, so `f` is usually
+    /// combined with moving the instructions themselves, e.g. when inlining.
+    pub fn remap_targets(&self, f: impl Fn(u16) -> u16) -> Program {
+        let mut program = self.clone();
+        for instruction in &mut program.instructions {
+            for target in instruction.targets_mut() {
+                *target = f(*target);
+            }
+        }
+        program
+    }
+
+    /// Creates a copy of this program with every register `r` replaced by `f(r)`.
+    ///
+    /// If `f` is not injective, registers which used to be distinct are merged,
+    /// which usually changes the behavior of the program.
+    pub fn remap_registers(&self, f: impl Fn(u8) -> u8) -> Program {
+        let mut program = self.clone();
+        for instruction in &mut program.instructions {
+            for reg in instruction.registers_mut() {
+                *reg = f(*reg);
+            }
+        }
+        program
+    }
+
+    /// The instructions of this program with all jump targets offset by `offset`,
+    /// or `None` if a target does not fit into a `u16`.
+    ///
+    /// If `exit` is set, every `Halt` is replaced with a `Nop(exit)` and every jump
+    /// to a `Halt`, including the implicit one after the end, instead jumps to `exit`.
+    pub(crate) fn relocate(&self, offset: u16, exit: Option<u16>) -> Option<Vec<Instruction>> {
+        let mut instructions = Vec::with_capacity(self.len());
+        for &instruction in self.instructions() {
+            let mut instruction = match (instruction, exit) {
+                (Instruction::Halt, Some(exit)) => Instruction::Nop(exit),
+                (instruction, _) => instruction,
+            };
+            for target in instruction.targets_mut() {
+                *target = match exit {
+                    Some(exit) if self.instruction(*target) == Instruction::Halt => exit,
+                    _ => target.checked_add(offset)?,
+                };
+            }
+            instructions.push(instruction);
+        }
+        Some(instructions)
+    }
+
+    /// Creates a program which first runs `self` and then `other`.
+    ///
+    /// The jump targets of `other` are offset by the length of `self`. Every jump of
+    /// `self` to a `Halt`, including the implicit one after its end, continues with the
+    /// first instruction of `other` instead, and every `Halt` of `self` is replaced with
+    /// a `Nop` to the same instruction. `HaltWith` instructions are not changed.
+    ///
+    /// This fails with [`ProgramError::TargetOverflow`] if a jump target of `other`
+    /// does not fit into a `u16` once offset, with `at` being its position in `other`.
+    pub fn concat(&self, other: &Program) -> Result<Program, ProgramError> {
+        if other.is_empty() {
+            return Ok(self.clone());
+        } else if self.len() + other.len() > MAX_INSTRUCTIONS {
+            return Err(ProgramError::TooLong);
+        }
+
+        let offset = self.len() as u16;
+        for (at, instruction) in other.iter() {
+            for target in instruction.targets() {
+                if target.checked_add(offset).is_none() {
+                    return Err(ProgramError::TargetOverflow { at, target, offset });
+                }
+            }
+        }
+        let mut instructions = self
+            .relocate(0, Some(offset))
+            .expect("offset 0 never overflows");
+        instructions.extend(
+            other
+                .relocate(offset, None)
+                .expect("targets have been checked"),
+        );
+        Ok(Program { instructions })
+    }
+
+    /// Creates a standalone program from all instructions reachable from `entry`.
+    ///
+    /// `entry` is moved to the start of the new program, the order of all other
+    /// reachable instructions is kept. As returning from a `Call` continues with the
+    /// instruction after it, a `Nop` is inserted after each `Call` whose following
+    /// instruction is not placed directly after it.
+    ///
+    /// This fails with [`ProgramError::TooLong`] if these `Nop`s would cause the
+    /// new program to have more than [`MAX_INSTRUCTIONS`] instructions.
+    pub fn extract(&self, entry: u16) -> Result<Program, ProgramError> {
+        let mut reachable = self.reachable_from(entry);
+        let mut order = Vec::with_capacity(reachable.len());
+        if reachable.remove(&entry) {
+            order.push(entry);
+        }
+        order.extend(reachable);
+
+        // Whether the instruction at `order[i]` needs a `Nop` after it.
+        let needs_nop = |i: usize| {
+            let at = order[i];
+            matches!(self.instructions[at as usize], Instruction::Call(_))
+                && order.get(i + 1) != Some(&at.wrapping_add(1))
+        };
+        let mut positions = HashMap::with_capacity(order.len());
+        let mut len = 0;
+        for (i, &at) in order.iter().enumerate() {
+            positions.insert(at, len as u16);
+            len += 1 + needs_nop(i) as usize;
+        }
+        if len > MAX_INSTRUCTIONS {
+            return Err(ProgramError::TooLong);
+        }
+        let position = |at: u16| positions.get(&at).map_or(len as u16, |&pos| pos);
+
+        let mut instructions = Vec::with_capacity(len);
+        for (i, &at) in order.iter().enumerate() {
+            let mut instruction = self.instructions[at as usize];
+            for target in instruction.targets_mut() {
+                *target = position(*target);
+            }
+            instructions.push(instruction);
+            if needs_nop(i) {
+                instructions.push(Instruction::Nop(position(at.wrapping_add(1))));
+            }
+        }
+        Ok(Program { instructions })
+    }
+}
+
+impl TryFrom<Vec<Instruction>> for Program {
+    type Error = ProgramError;
+
+    /// Like [`Program::new`], but fails with [`ProgramError::TooLong`] instead of
+    /// ignoring instructions. The jump targets are not checked.
+    fn try_from(instructions: Vec<Instruction>) -> Result<Program, ProgramError> {
+        if instructions.len() > MAX_INSTRUCTIONS {
+            Err(ProgramError::TooLong)
+        } else {
+            Ok(Program { instructions })
+        }
+    }
+}
+
+impl TryFrom<&[Instruction]> for Program {
+    type Error = ProgramError;
+
+    /// See the conversion from `Vec<Instruction>`.
+    fn try_from(instructions: &[Instruction]) -> Result<Program, ProgramError> {
+        Program::try_from(instructions.to_vec())
+    }
+}
+
+impl FromIterator<Instruction> for Result<Program, ProgramError> {
+    /// See the conversion from `Vec<Instruction>`.
+    fn from_iter<I: IntoIterator<Item = Instruction>>(iter: I) -> Result<Program, ProgramError> {
+        let mut iter = iter.into_iter();
+        let program = Program::new(iter.by_ref());
+        match iter.next() {
+            Some(_) => Err(ProgramError::TooLong),
+            None => Ok(program),
+        }
+    }
+}
+
+impl Index<u16> for Program {
+    type Output = Instruction;
+
+    /// The instruction at `ptr`, see [`Program::instruction`].
+    fn index(&self, ptr: u16) -> &Instruction {
+        self.instructions
+            .get(ptr as usize)
+            .unwrap_or(&Instruction::Halt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Machine, RunOutcome};
+
+    #[test]
+    fn implicit_halt() {
+        let program = Program::new([Instruction::Increment(0, 7)].iter().copied());
+        assert_eq!(program.len(), 1);
+        assert_eq!(program.instruction(0), Instruction::Increment(0, 7));
+        assert_eq!(program.instruction(7), Instruction::Halt);
+        assert_eq!(program.instruction(u16::MAX), Instruction::Halt);
+
+        assert_eq!(program.instructions(), &[Instruction::Increment(0, 7)]);
+        assert_eq!(
+            program.iter().collect::<Vec<_>>(),
+            [(0, Instruction::Increment(0, 7))]
+        );
+
+        let trailing = Program::new(vec![Instruction::Increment(0, 7), Instruction::Halt]);
+        assert_eq!(trailing.len(), 1);
+        assert_eq!(trailing.instructions(), program.instructions());
+        assert_eq!(trailing, program);
+        let hash = |program: &Program| {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            program.hash(&mut hasher);
+            hasher.finish()
+        };
+        assert_eq!(hash(&trailing), hash(&program));
+        assert!(Program::new(vec![Instruction::Halt; 3]).is_empty());
+
+        let long = Program::new(vec![Instruction::Purged; 1 << 17]);
+        assert_eq!(long.len(), 1 << 16);
+        assert_eq!(long.iter().last(), Some((u16::MAX, Instruction::Purged)));
+    }
+
+    #[test]
+    fn try_new() {
+        let valid = [
+            Instruction::Decrement(0, 1, 2),
+            Instruction::Increment(1, 0),
+            Instruction::Halt,
+        ];
+        let program = Program::try_new(valid.iter().copied()).unwrap();
+        assert_eq!(program, Program::new(valid.iter().copied()));
+
+        assert_eq!(
+            Program::try_new(vec![Instruction::Halt; (1 << 16) + 1]),
+            Err(ProgramError::TooLong)
+        );
+        assert!(Program::try_new(vec![Instruction::Halt; 1 << 16]).is_ok());
+
+        assert!(Program::try_new([Instruction::Decrement(0, 0, 1)].iter().copied()).is_ok());
+        assert!(Program::try_new(vec![Instruction::Jump(u16::MAX)]).is_ok());
+        let add = crate::routines::add(0, 1, 2, 3);
+        assert_eq!(
+            Program::try_new(add.instructions().iter().copied()),
+            Ok(add)
+        );
+        assert_eq!(
+            Program::try_new(
+                [
+                    Instruction::Halt,
+                    Instruction::Increment(3, 2),
+                    Instruction::Purged
+                ]
+                .iter()
+                .copied()
+            ),
+            Err(ProgramError::TargetPurged { at: 1, target: 2 })
+        );
+    }
+
+    #[test]
+    fn conversions() {
+        let instructions = [Instruction::Increment(0, 3), Instruction::Halt];
+        let program = Program::new(instructions.iter().copied());
+        assert_eq!(Program::try_from(&instructions[..]), Ok(program.clone()));
+        assert_eq!(
+            Program::try_from(instructions.to_vec()),
+            Ok(program.clone())
+        );
+        assert_eq!(
+            instructions.iter().copied().collect::<Result<Program, _>>(),
+            Ok(program)
+        );
+
+        let long = vec![Instruction::Halt; (1 << 16) + 1];
+        assert_eq!(Program::try_from(&long[..]), Err(ProgramError::TooLong));
+        assert_eq!(
+            long.iter().copied().collect::<Result<Program, _>>(),
+            Err(ProgramError::TooLong)
+        );
+        assert_eq!(Program::try_from(long), Err(ProgramError::TooLong));
+    }
+
+    #[test]
+    fn set_instruction() {
+        let mut program = Program::new(
+            [
+                Instruction::Decrement(0, 1, 2),
+                Instruction::Increment(1, 0),
+                Instruction::Halt,
+            ]
+            .iter()
+            .copied(),
+        );
+        assert_eq!(
+            program.set_instruction(2, Instruction::Jump(0)),
+            Ok(Instruction::Halt)
+        );
+        assert_eq!(program[2], Instruction::Jump(0));
+        assert_eq!(program[3], Instruction::Halt);
+
+        let before = program.clone();
+        assert_eq!(
+            program.set_instruction(4, Instruction::Halt),
+            Err(ProgramError::InstructionOutOfRange { at: 4 })
+        );
+        assert_eq!(
+            program.set_instruction(1, Instruction::Purged),
+            Err(ProgramError::TargetPurged { at: 0, target: 1 })
+        );
+        assert_eq!(program, before);
+
+        program.set_instruction(0, Instruction::Jump(2)).unwrap();
+        assert_eq!(
+            program.set_instruction(1, Instruction::Purged),
+            Ok(Instruction::Increment(1, 0))
+        );
+        assert_eq!(
+            program.set_instruction(0, Instruction::Jump(1)),
+            Err(ProgramError::TargetPurged { at: 0, target: 1 })
+        );
+
+        // Programs which only differ in trailing `Halt`s behave the same.
+        let mut trailing = Program::new(vec![Instruction::Jump(1), Instruction::Halt]);
+        let mut trimmed = Program::new(vec![Instruction::Jump(1)]);
+        for program in [&mut trailing, &mut trimmed] {
+            assert_eq!(
+                program.set_instruction(0, Instruction::Jump(1)),
+                Ok(Instruction::Jump(1))
+            );
+            assert_eq!(
+                program.set_instruction(2, Instruction::Halt),
+                Err(ProgramError::InstructionOutOfRange { at: 2 })
+            );
+            assert_eq!(
+                program.set_instruction(1, Instruction::Increment(0, 0)),
+                Ok(Instruction::Halt)
+            );
+            assert_eq!(program.len(), 2);
+        }
+        assert_eq!(trailing, trimmed);
+    }
+
+    #[test]
+    fn extract() {
+        // `$1 = 2 * $0` starting at `3`, calling a subroutine at `1`.
+        let program = Program::new(
+            [
+                Instruction::Purged,
+                Instruction::Increment(1, 2),
+                Instruction::Increment(1, 9),
+                Instruction::Decrement(0, 4, 6),
+                Instruction::Call(1),
+                Instruction::Jump(3),
+                Instruction::Halt,
+                Instruction::Increment(5, 8),
+                Instruction::Purged,
+                Instruction::Return,
+            ]
+            .iter()
+            .copied(),
+        );
+        let extracted = program.extract(3).unwrap();
+        assert_eq!(
+            extracted.instructions(),
+            &[
+                Instruction::Decrement(0, 3, 5),
+                Instruction::Increment(1, 2),
+                Instruction::Increment(1, 6),
+                Instruction::Call(1),
+                Instruction::Jump(0),
+                Instruction::Halt,
+                Instruction::Return,
+            ]
+        );
+        let mut prog: Machine = Machine::new(&extracted);
+        prog.set_register(0, 3);
+        assert!(matches!(prog.run(1000), RunOutcome::Halted { .. }));
+        assert_eq!(&prog.registers()[..2], &[0, 6]);
+
+        assert_eq!(
+            program.extract(7).unwrap().instructions(),
+            &[Instruction::Increment(5, 1), Instruction::Purged]
+        );
+        assert_eq!(program.extract(10), Ok(Program::empty()));
+
+        // The call at `1` returns to the entry.
+        let program = Program::new(
+            [
+                Instruction::Return,
+                Instruction::Call(0),
+                Instruction::Jump(1),
+            ]
+            .iter()
+            .copied(),
+        );
+        assert_eq!(
+            program.extract(2).unwrap().instructions(),
+            &[
+                Instruction::Jump(2),
+                Instruction::Return,
+                Instruction::Call(1),
+                Instruction::Nop(0),
+            ]
+        );
+    }
+
+    #[test]
+    fn diff() {
+        let a = Program::new(
+            [
+                Instruction::Decrement(0, 1, 2),
+                Instruction::Increment(1, 0),
+            ]
+            .iter()
+            .copied(),
+        );
+        let b = Program::new(
+            [
+                Instruction::Decrement(0, 1, 2),
+                Instruction::Increment(2, 0),
+                Instruction::Halt,
+                Instruction::Jump(0),
+            ]
+            .iter()
+            .copied(),
+        );
+        assert_eq!(
+            a.diff(&b),
+            [
+                (
+                    1,
+                    Instruction::Increment(1, 0),
+                    Instruction::Increment(2, 0)
+                ),
+                (3, Instruction::Halt, Instruction::Jump(0)),
+            ]
+        );
+        assert_eq!(b.diff(&b), []);
+        assert_eq!(
+            a.diff(&Program::new(
+                a.instructions()
+                    .iter()
+                    .copied()
+                    .chain(Some(Instruction::Halt))
+            )),
+            []
+        );
+    }
+
+    #[test]
+    fn remap() {
+        let program = Program::new(
+            [
+                Instruction::Decrement(0, 1, 2),
+                Instruction::Copy {
+                    src: 1,
+                    dst: 2,
+                    scratch: 3,
+                    then: 0,
+                },
+                Instruction::Halt,
+            ]
+            .iter()
+            .copied(),
+        );
+        assert_eq!(
+            program.remap_targets(|t| 2 - t).instructions(),
+            &[
+                Instruction::Decrement(0, 1, 0),
+                Instruction::Copy {
+                    src: 1,
+                    dst: 2,
+                    scratch: 3,
+                    then: 2,
+                },
+            ]
+        );
+        assert_eq!(
+            program.remap_registers(|r| r + 10).instructions(),
+            &[
+                Instruction::Decrement(10, 1, 2),
+                Instruction::Copy {
+                    src: 11,
+                    dst: 12,
+                    scratch: 13,
+                    then: 0,
+                },
+            ]
+        );
+        assert_eq!(
+            program.instruction(1).registers().collect::<Vec<_>>(),
+            [1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn validate() {
+        let program = Program::new(
+            [
+                Instruction::Decrement(0, 1, 2),
+                Instruction::AddConst(1, 1000, 0),
+                Instruction::Halt,
+            ]
+            .iter()
+            .copied(),
+        );
+        assert_eq!(program.isa_level(), IsaLevel::Accelerated);
+        assert_eq!(program.validate(IsaLevel::Extended), Ok(()));
+        assert_eq!(program.validate(IsaLevel::Accelerated), Ok(()));
+        assert_eq!(
+            program.validate(IsaLevel::StrictMinsky),
+            Err(ProgramError::UnsupportedInstruction {
+                at: 1,
+                level: IsaLevel::StrictMinsky
+            })
+        );
+        assert_eq!(Program::empty().isa_level(), IsaLevel::StrictMinsky);
+        assert_eq!(Program::empty().validate(IsaLevel::StrictMinsky), Ok(()));
+
+        let program = Program::new(
+            [
+                Instruction::Decrement(0, 1, 2),
+                Instruction::Choose(0, 2),
+                Instruction::Read(0, 3),
+            ]
+            .iter()
+            .copied(),
+        );
+        assert_eq!(program.isa_level(), IsaLevel::Extended);
+        let error = program.validate(IsaLevel::Accelerated).unwrap_err();
+        assert_eq!(
+            error,
+            ProgramError::UnsupportedInstruction {
+                at: 1,
+                level: IsaLevel::Accelerated
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "instruction 1 is not part of the accelerated instruction set"
+        );
+    }
+
+    #[test]
+    fn concat() {
+        use crate::routines;
+        use crate::{Machine, RunOutcome};
+
+        // $2 = $0 + $1, then $3 = $2 * $1
+        let program = routines::add(0, 1, 2, 4)
+            .concat(&routines::multiply(2, 1, 3, [4, 5]))
+            .unwrap();
+        assert_eq!(program.validate(IsaLevel::StrictMinsky), Ok(()));
+        let mut prog: Machine = Machine::new(&program);
+        prog.reset_with(vec![2, 3]);
+        assert!(matches!(prog.run(u64::MAX), RunOutcome::Halted { .. }));
+        assert_eq!(&prog.registers()[..4], &[2, 3, 5, 15]);
+
+        let first = Program::new(
+            [
+                Instruction::Halt,
+                Instruction::Decrement(0, 0, 2),
+                Instruction::HaltWith(3),
+            ]
+            .iter()
+            .copied(),
+        );
+        let second = Program::new([Instruction::Increment(1, 7)].iter().copied());
+        assert_eq!(
+            first.concat(&second).unwrap(),
+            Program::new(
+                [
+                    Instruction::Nop(3),
+                    Instruction::Decrement(0, 3, 2),
+                    Instruction::HaltWith(3),
+                    Instruction::Increment(1, 10),
+                ]
+                .iter()
+                .copied()
+            )
+        );
+        assert_eq!(first.concat(&Program::empty()), Ok(first.clone()));
+        assert_eq!(
+            Program::new(vec![Instruction::Nop(0); (1 << 15) + 1])
+                .concat(&Program::new(vec![Instruction::Jump(0); 1 << 15])),
+            Err(ProgramError::TooLong)
+        );
+        let error = Program::new(vec![Instruction::Nop(0); 10])
+            .concat(&Program::new(
+                [
+                    Instruction::Increment(0, 1),
+                    Instruction::Jump(u16::MAX - 5),
+                ]
+                .iter()
+                .copied(),
+            ))
+            .unwrap_err();
+        assert_eq!(
+            error,
+            ProgramError::TargetOverflow {
+                at: 1,
+                target: u16::MAX - 5,
+                offset: 10
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "instruction 1 jumps to 65530, which overflows when offset by 10"
+        );
+    }
+}
@@ -0,0 +1,321 @@
+use crate::{Instruction, Program};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt;
+
+/// A ranking function for a strongly connected part of a program,
+/// see [`Program::prove_termination`].
+///
+/// The sum of the `registers` never increases while executing the `component`,
+/// and it decreases by at least one whenever one of the `decreasing` instructions
+/// takes its first branch, e.g. a `Decrement` of one of the `registers`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RankingFunction {
+    /// The positions of the instructions of the component, sorted.
+    pub component: Vec<u16>,
+    pub registers: BTreeSet<u8>,
+    pub decreasing: Vec<u16>,
+    /// The number of ranking functions of enclosing components.
+    pub depth: usize,
+}
+
+impl fmt::Display for RankingFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, reg) in self.registers.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" + ")?;
+            }
+            write!(f, "${}", reg)?;
+        }
+        f.write_str(" decreases at")?;
+        for (i, at) in self.decreasing.iter().enumerate() {
+            write!(f, "{} {}", if i > 0 { "," } else { "" }, at)?;
+        }
+        write!(f, " in {:?}", self.component)
+    }
+}
+
+/// A proof that a program stops on all inputs, see [`Program::prove_termination`].
+///
+/// Each cycle of the program is part of a component with a ranking function. Once all
+/// instructions decreasing the ranking function of a component are removed, its remaining
+/// cycles are part of nested components with ranking functions of their own, which are
+/// listed after it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TerminationProof {
+    pub ranking_functions: Vec<RankingFunction>,
+}
+
+/// The edges of a subgraph of the control-flow graph,
+/// as pairs of an instruction and the index of its target.
+type Edges = BTreeMap<u16, Vec<(usize, u16)>>;
+
+/// The strongly connected components of `edges` which contain a cycle.
+fn cyclic_components(edges: &Edges) -> Vec<BTreeSet<u16>> {
+    // Kosaraju's algorithm, first ordering the nodes by the
+    // time their depth-first search finishes.
+    let mut order = Vec::new();
+    let mut visited = HashSet::new();
+    for &root in edges.keys() {
+        if !visited.insert(root) {
+            continue;
+        }
+        let mut stack = vec![(root, 0)];
+        while let Some((node, index)) = stack.pop() {
+            match edges[&node].get(index) {
+                Some(&(_, next)) => {
+                    stack.push((node, index + 1));
+                    if visited.insert(next) {
+                        stack.push((next, 0));
+                    }
+                }
+                None => order.push(node),
+            }
+        }
+    }
+
+    let mut reverse: BTreeMap<u16, Vec<u16>> = BTreeMap::new();
+    for (&node, targets) in edges {
+        for &(_, target) in targets {
+            reverse.entry(target).or_default().push(node);
+        }
+    }
+    let mut assigned = HashSet::new();
+    let mut components = Vec::new();
+    for &root in order.iter().rev() {
+        if !assigned.insert(root) {
+            continue;
+        }
+        let mut component = BTreeSet::new();
+        let mut stack = vec![root];
+        while let Some(node) = stack.pop() {
+            component.insert(node);
+            for &pred in reverse.get(&node).into_iter().flatten() {
+                if assigned.insert(pred) {
+                    stack.push(pred);
+                }
+            }
+        }
+        let node = *component.iter().next().unwrap();
+        if component.len() > 1 || edges[&node].iter().any(|&(_, next)| next == node) {
+            components.push(component);
+        }
+    }
+    components
+}
+
+/// The registers of the largest ranking function for `edges` and the instructions which
+/// decrease it, see [`RankingFunction`].
+///
+/// Starts with all registers and removes registers which may increase the sum.
+fn ranking_function(program: &Program, edges: &Edges) -> (BTreeSet<u8>, Vec<u16>) {
+    let instructions = || edges.keys().map(|&at| program.instruction(at));
+    let mut registers: BTreeSet<u8> = instructions().flat_map(Instruction::registers).collect();
+    loop {
+        let before = registers.len();
+        for instruction in instructions() {
+            let ranked = |reg| registers.contains(&reg);
+            let unranked: Vec<u8> = match instruction {
+                Instruction::Increment(reg, _)
+                | Instruction::AddConst(reg, 1..=u64::MAX, _)
+                | Instruction::Read(reg, _) => vec![reg],
+                Instruction::Transfer { src, dst, .. } if !ranked(src) => vec![dst],
+                Instruction::Copy {
+                    src, dst, scratch, ..
+                } if src != dst && src != scratch && dst != scratch => {
+                    if ranked(scratch) {
+                        vec![dst]
+                    } else {
+                        vec![dst, src]
+                    }
+                }
+                Instruction::Swap(a, b, _) if !ranked(a) || !ranked(b) => vec![a, b],
+                _ => vec![],
+            };
+            for reg in unranked {
+                registers.remove(&reg);
+            }
+        }
+        if registers.len() == before {
+            break;
+        }
+    }
+
+    let decreasing = edges
+        .iter()
+        .filter(|(_, targets)| targets.iter().any(|&(branch, _)| branch == 0))
+        .map(|(&at, _)| at)
+        .filter(|&at| match program.instruction(at) {
+            Instruction::Decrement(reg, ..) => registers.contains(&reg),
+            Instruction::SubConst(reg, n, ..) => n > 0 && registers.contains(&reg),
+            _ => false,
+        })
+        .collect();
+    (registers, decreasing)
+}
+
+/// Finds ranking functions for all cycles of `edges`, returning `false` if there is
+/// a cycle without one.
+fn rank(program: &Program, edges: &Edges, depth: usize, proof: &mut Vec<RankingFunction>) -> bool {
+    for component in cyclic_components(edges) {
+        let restricted: Edges = component
+            .iter()
+            .map(|at| {
+                let targets = edges[at]
+                    .iter()
+                    .copied()
+                    .filter(|(_, target)| component.contains(target))
+                    .collect();
+                (*at, targets)
+            })
+            .collect();
+        let (registers, decreasing) = ranking_function(program, &restricted);
+        if decreasing.is_empty() {
+            return false;
+        }
+        let remaining: Edges = restricted
+            .iter()
+            .map(|(at, targets)| {
+                let targets = targets
+                    .iter()
+                    .copied()
+                    .filter(|&(branch, _)| branch != 0 || !decreasing.contains(at))
+                    .collect();
+                (*at, targets)
+            })
+            .collect();
+        proof.push(RankingFunction {
+            component: component.into_iter().collect(),
+            registers,
+            decreasing,
+            depth,
+        });
+        if !rank(program, &remaining, depth + 1, proof) {
+            return false;
+        }
+    }
+    true
+}
+
+impl Program {
+    /// Tries to prove that this program stops for all initial register values by
+    /// finding ranking functions for all of its cycles, see [`TerminationProof`].
+    ///
+    /// Ranking functions are sums of registers which never increase inside a strongly
+    /// connected component of the control-flow graph and which decrease on some of its
+    /// instructions. This covers e.g. nested loops in which the inner loops use registers
+    /// that the outer loops refill. The program may still stop without halting, e.g. at
+    /// a `Purged` instruction, and this holds for every outcome of `Choose`, `Random`
+    /// and `Read`. Returns `None` if the program uses `Call` or `Return` or some cycle
+    /// has no ranking function.
+    pub fn prove_termination(&self) -> Option<TerminationProof> {
+        let end = self.len() as u16;
+        let mut edges = Edges::new();
+        for at in self.reachable_from(0) {
+            let instruction = self.instruction(at);
+            if let Instruction::Call(_) | Instruction::Return = instruction {
+                return None;
+            }
+            let targets = instruction
+                .targets()
+                .enumerate()
+                .filter(|&(_, target)| target < end)
+                .collect();
+            edges.insert(at, targets);
+        }
+        let mut ranking_functions = Vec::new();
+        if rank(self, &edges, 0, &mut ranking_functions) {
+            Some(TerminationProof { ranking_functions })
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Machine, RunOutcome};
+
+    #[test]
+    fn prove_termination() {
+        // For each unit of `$0`, adds 3 to `$1` and then moves `$1` into `$2`.
+        let nested = Program::new(
+            [
+                Instruction::Decrement(0, 1, 4),
+                Instruction::AddConst(1, 3, 2),
+                Instruction::Decrement(1, 3, 0),
+                Instruction::Increment(2, 2),
+            ]
+            .iter()
+            .copied(),
+        );
+        let proof = nested.prove_termination().unwrap();
+        let functions: Vec<_> = proof
+            .ranking_functions
+            .iter()
+            .map(|f| f.to_string())
+            .collect();
+        assert_eq!(
+            functions,
+            [
+                "$0 decreases at 0 in [0, 1, 2, 3]",
+                "$1 decreases at 2 in [2, 3]"
+            ]
+        );
+        assert_eq!(proof.ranking_functions[1].depth, 1);
+        let mut machine: Machine = Machine::new(&nested);
+        machine.set_register(0, 5);
+        assert_eq!(
+            machine.run(1000),
+            RunOutcome::Halted {
+                steps: 1 + 5 * 9,
+                code: 0
+            }
+        );
+
+        // Moves `$0` into `$1` and back forever.
+        let swapping = Program::new(
+            [
+                Instruction::Transfer {
+                    src: 0,
+                    dst: 1,
+                    then: 1,
+                },
+                Instruction::Transfer {
+                    src: 1,
+                    dst: 0,
+                    then: 2,
+                },
+                Instruction::Decrement(0, 0, 3),
+            ]
+            .iter()
+            .copied(),
+        );
+        let proof = swapping.prove_termination().unwrap();
+        assert_eq!(proof.ranking_functions.len(), 1);
+        assert_eq!(
+            proof.ranking_functions[0].to_string(),
+            "$0 + $1 decreases at 2 in [0, 1, 2]"
+        );
+
+        let grows = Program::new(
+            [
+                Instruction::Decrement(0, 1, 2),
+                Instruction::AddConst(0, 2, 0),
+            ]
+            .iter()
+            .copied(),
+        );
+        assert_eq!(grows.prove_termination(), None);
+        assert_eq!(
+            Program::new([Instruction::Jump(0)].iter().copied()).prove_termination(),
+            None
+        );
+        assert_eq!(
+            Program::new([Instruction::Increment(0, 1)].iter().copied()).prove_termination(),
+            Some(TerminationProof {
+                ranking_functions: vec![]
+            })
+        );
+    }
+}
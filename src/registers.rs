@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::fmt;
+
+/// A mapping between symbolic register names and physical register indices.
+///
+/// Registers are either defined explicitly, e.g. to follow a calling convention,
+/// or allocated in increasing order starting at `$0`, skipping defined registers.
+/// Allocated registers may be unnamed, e.g. for scratch registers.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RegisterFile {
+    by_name: HashMap<String, u8>,
+    names: HashMap<u8, String>,
+    next: u16,
+}
+
+impl RegisterFile {
+    pub fn new() -> RegisterFile {
+        RegisterFile::default()
+    }
+
+    /// Allocates an unnamed register, returning `None` if all registers are in use.
+    pub fn allocate(&mut self) -> Option<u8> {
+        while self.next <= u16::from(u8::MAX) {
+            let reg = self.next as u8;
+            self.next += 1;
+            if !self.names.contains_key(&reg) {
+                return Some(reg);
+            }
+        }
+        None
+    }
+
+    /// The register named `name`, allocating it on first use.
+    ///
+    /// Returns `None` if `name` is not yet defined and all registers are in use.
+    pub fn resolve(&mut self, name: &str) -> Option<u8> {
+        if let Some(&reg) = self.by_name.get(name) {
+            return Some(reg);
+        }
+        let reg = self.allocate()?;
+        self.by_name.insert(name.to_owned(), reg);
+        self.names.insert(reg, name.to_owned());
+        Some(reg)
+    }
+
+    /// Names the register `reg`, returning `false` without changing anything
+    /// if either `name` or `reg` is already in use.
+    pub fn define(&mut self, name: &str, reg: u8) -> bool {
+        if self.by_name.contains_key(name)
+            || self.names.contains_key(&reg)
+            || u16::from(reg) < self.next
+        {
+            return false;
+        }
+        self.by_name.insert(name.to_owned(), reg);
+        self.names.insert(reg, name.to_owned());
+        true
+    }
+
+    /// The register named `name`, if it exists.
+    pub fn get(&self, name: &str) -> Option<u8> {
+        self.by_name.get(name).copied()
+    }
+
+    /// The name of `reg`, if it has one.
+    pub fn name(&self, reg: u8) -> Option<&str> {
+        self.names.get(&reg).map(String::as_str)
+    }
+
+    /// The number of registers which are either named or allocated.
+    pub fn len(&self) -> usize {
+        let defined_above = self
+            .names
+            .keys()
+            .filter(|&&reg| u16::from(reg) >= self.next)
+            .count();
+        self.next as usize + defined_above
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterates over all named registers in order of their index.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u8)> {
+        let mut named: Vec<_> = self
+            .names
+            .iter()
+            .map(|(&reg, name)| (name.as_str(), reg))
+            .collect();
+        named.sort_by_key(|&(_, reg)| reg);
+        named.into_iter()
+    }
+
+    /// Displays `reg` using its name, or as `$reg` if it does not have one.
+    pub fn display(&self, reg: u8) -> RegName<'_> {
+        RegName {
+            reg,
+            name: self.name(reg),
+        }
+    }
+}
+
+/// A register displayed using its name in a [`RegisterFile`], see [`RegisterFile::display`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RegName<'a> {
+    reg: u8,
+    name: Option<&'a str>,
+}
+
+impl RegName<'_> {
+    pub fn reg(&self) -> u8 {
+        self.reg
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name
+    }
+}
+
+impl fmt::Display for RegName<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name {
+            Some(name) => f.write_str(name),
+            None => write!(f, "${}", self.reg),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registers() {
+        let mut regs = RegisterFile::new();
+        assert!(regs.define("input0", 0));
+        assert!(regs.define("acc", 2));
+        assert!(!regs.define("acc", 3));
+        assert!(!regs.define("other", 2));
+        assert_eq!(regs.resolve("scratch"), Some(1));
+        assert_eq!(regs.allocate(), Some(3));
+        assert!(!regs.define("late", 3));
+        assert_eq!(regs.resolve("acc"), Some(2));
+        assert_eq!(regs.get("scratch"), Some(1));
+        assert_eq!(regs.get("missing"), None);
+        assert_eq!(regs.len(), 4);
+        assert_eq!(
+            regs.iter().collect::<Vec<_>>(),
+            [("input0", 0), ("scratch", 1), ("acc", 2)]
+        );
+        assert_eq!(regs.display(2).to_string(), "acc");
+        assert_eq!(regs.display(3).to_string(), "$3");
+
+        let mut regs = RegisterFile::new();
+        assert!(regs.define("last", 255));
+        assert_eq!(regs.len(), 1);
+        for reg in 0..255 {
+            assert_eq!(regs.allocate(), Some(reg));
+        }
+        assert_eq!(regs.allocate(), None);
+        assert_eq!(regs.resolve("new"), None);
+        assert_eq!(regs.len(), 256);
+    }
+}
@@ -0,0 +1,183 @@
+//! Arithmetic routines using only strict Minsky instructions.
+//!
+//! Each routine returns a relocatable block which starts at instruction `0` and
+//! finishes by jumping to the first instruction after the block, so it can be
+//! appended to other programs. All registers passed to a routine must be distinct.
+//! The inputs are preserved and the scratch registers must be zero when starting
+//! the routine, they are zero again once it finishes.
+
+use crate::{Instruction, Program};
+
+/// Appends code setting `reg` to zero.
+fn clear(code: &mut Vec<Instruction>, reg: u8) {
+    let at = code.len() as u16;
+    code.push(Instruction::Decrement(reg, at, at + 1));
+}
+
+/// Appends code adding `src` to `dst`.
+fn copy_add(code: &mut Vec<Instruction>, src: u8, dst: u8, scratch: u8) {
+    let at = code.len() as u16;
+    code.extend_from_slice(&Instruction::copy_sequence(src, dst, scratch, at, at + 5).unwrap());
+}
+
+/// Appends code moving `src` into `dst`, setting `src` to zero.
+fn transfer(code: &mut Vec<Instruction>, src: u8, dst: u8) {
+    let at = code.len() as u16;
+    code.push(Instruction::Decrement(src, at + 1, at + 2));
+    code.push(Instruction::Increment(dst, at));
+}
+
+/// Appends code setting `dst` to `a - b`, or zero if `b` is greater than `a`.
+fn subtract_into(code: &mut Vec<Instruction>, a: u8, b: u8, dst: u8, scratch: u8) {
+    clear(code, dst);
+    copy_add(code, a, dst, scratch);
+    let at = code.len() as u16;
+    code.push(Instruction::Decrement(b, at + 1, at + 3));
+    code.push(Instruction::Increment(scratch, at + 2));
+    code.push(Instruction::Decrement(dst, at, at));
+    transfer(code, scratch, b);
+}
+
+/// `dst = src`
+pub fn copy(src: u8, dst: u8, scratch: u8) -> Program {
+    let mut code = Vec::new();
+    clear(&mut code, dst);
+    copy_add(&mut code, src, dst, scratch);
+    Program::new(code)
+}
+
+/// `dst = a + b`
+pub fn add(a: u8, b: u8, dst: u8, scratch: u8) -> Program {
+    let mut code = Vec::new();
+    clear(&mut code, dst);
+    copy_add(&mut code, a, dst, scratch);
+    copy_add(&mut code, b, dst, scratch);
+    Program::new(code)
+}
+
+/// `dst = a - b`, or zero if `b` is greater than `a`.
+pub fn subtract(a: u8, b: u8, dst: u8, scratch: u8) -> Program {
+    let mut code = Vec::new();
+    subtract_into(&mut code, a, b, dst, scratch);
+    Program::new(code)
+}
+
+/// `dst = a * b`
+pub fn multiply(a: u8, b: u8, dst: u8, scratch: [u8; 2]) -> Program {
+    let [counter, inner] = scratch;
+    let mut code = Vec::new();
+    clear(&mut code, dst);
+    let at = code.len() as u16;
+    code.push(Instruction::Decrement(a, at + 1, at + 7));
+    code.push(Instruction::Increment(counter, at + 2));
+    code.extend_from_slice(&Instruction::copy_sequence(b, dst, inner, at + 2, at).unwrap());
+    transfer(&mut code, counter, a);
+    Program::new(code)
+}
+
+/// `quotient = a / b` and `remainder = a % b`.
+///
+/// Dividing by zero sets `quotient` to zero and `remainder` to `a`.
+pub fn divide(a: u8, b: u8, quotient: u8, remainder: u8, scratch: [u8; 2]) -> Program {
+    let [subtracted, moved] = scratch;
+    let mut code = Vec::new();
+    clear(&mut code, quotient);
+    clear(&mut code, remainder);
+    copy_add(&mut code, a, remainder, subtracted);
+    let at = code.len() as u16;
+    let (check, start, full, partial) = (at, at + 2, at + 6, at + 10);
+    // Skip the division if `b` is zero.
+    code.push(Instruction::Decrement(b, check + 1, partial + 4));
+    code.push(Instruction::Increment(b, start));
+    // Try to subtract `b` from `remainder`, counting the subtracted units.
+    code.push(Instruction::Decrement(b, start + 1, full));
+    code.push(Instruction::Increment(moved, start + 2));
+    code.push(Instruction::Decrement(remainder, start + 3, partial));
+    code.push(Instruction::Increment(subtracted, start));
+    // `b` was subtracted completely, restore it and try again.
+    code.push(Instruction::Increment(quotient, full + 1));
+    code.push(Instruction::Decrement(moved, full + 2, full + 3));
+    code.push(Instruction::Increment(b, full + 1));
+    code.push(Instruction::Decrement(subtracted, full + 3, start));
+    // `remainder` was less than `b`, undo the partial subtraction.
+    transfer(&mut code, subtracted, remainder);
+    transfer(&mut code, moved, b);
+    Program::new(code)
+}
+
+/// `dst = 1` if `a >= b`, otherwise `dst = 0`.
+pub fn compare(a: u8, b: u8, dst: u8, scratch: [u8; 2]) -> Program {
+    let [difference, inner] = scratch;
+    let mut code = Vec::new();
+    clear(&mut code, dst);
+    subtract_into(&mut code, b, a, difference, inner);
+    let at = code.len() as u16;
+    code.push(Instruction::Decrement(difference, at + 1, at + 2));
+    code.push(Instruction::Decrement(difference, at + 1, at + 3));
+    code.push(Instruction::Increment(dst, at + 3));
+    Program::new(code)
+}
+
+/// `dst = min(a, b)`
+pub fn min(a: u8, b: u8, dst: u8, scratch: [u8; 2]) -> Program {
+    let [difference, inner] = scratch;
+    let mut code = Vec::new();
+    subtract_into(&mut code, a, b, difference, inner);
+    subtract_into(&mut code, a, difference, dst, inner);
+    clear(&mut code, difference);
+    Program::new(code)
+}
+
+/// `dst = max(a, b)`
+pub fn max(a: u8, b: u8, dst: u8, scratch: [u8; 2]) -> Program {
+    let [difference, inner] = scratch;
+    let mut code = Vec::new();
+    subtract_into(&mut code, a, b, difference, inner);
+    clear(&mut code, dst);
+    copy_add(&mut code, b, dst, inner);
+    transfer(&mut code, difference, dst);
+    Program::new(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{IsaLevel, Machine, RunOutcome};
+
+    /// Runs `program` with `a` in `$0`, `b` in `$1` and garbage in `$2` and `$3`,
+    /// returning the values of the registers `$2` to `$5`.
+    fn run(program: &Program, a: u64, b: u64) -> [u64; 4] {
+        assert_eq!(program.validate(IsaLevel::StrictMinsky), Ok(()));
+        let mut prog: Machine = Machine::new(program);
+        prog.reset_with(vec![a, b, 17, 23]);
+        assert!(matches!(prog.run(1 << 20), RunOutcome::Halted { .. }));
+        assert_eq!(&prog.registers()[..2], &[a, b]);
+        assert_eq!(prog.ptr() as usize, program.len());
+        let mut result = [0; 4];
+        result.copy_from_slice(&prog.registers()[2..6]);
+        result
+    }
+
+    #[test]
+    fn routines() {
+        for a in 0..8 {
+            for b in 0..8 {
+                assert_eq!(run(&copy(0, 2, 4), a, b)[..3], [a, 23, 0]);
+                assert_eq!(run(&add(0, 1, 2, 4), a, b)[..3], [a + b, 23, 0]);
+                assert_eq!(
+                    run(&subtract(0, 1, 2, 4), a, b)[..3],
+                    [a.saturating_sub(b), 23, 0]
+                );
+                assert_eq!(run(&multiply(0, 1, 2, [4, 5]), a, b), [a * b, 23, 0, 0]);
+                let (q, r) = (a.checked_div(b).unwrap_or(0), a.checked_rem(b).unwrap_or(a));
+                assert_eq!(run(&divide(0, 1, 2, 3, [4, 5]), a, b), [q, r, 0, 0]);
+                assert_eq!(
+                    run(&compare(0, 1, 2, [4, 5]), a, b),
+                    [(a >= b) as u64, 23, 0, 0]
+                );
+                assert_eq!(run(&min(0, 1, 2, [4, 5]), a, b), [a.min(b), 23, 0, 0]);
+                assert_eq!(run(&max(0, 1, 2, [4, 5]), a, b), [a.max(b), 23, 0, 0]);
+            }
+        }
+    }
+}
@@ -0,0 +1,432 @@
+use crate::binary::{constant, opcode, set_constant, template};
+use crate::{DecodeError, Instruction, Program};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+/// The first bytes of every seed database, see [`SeedDbWriter`].
+pub const SEED_DB_MAGIC: [u8; 4] = *b"MNDB";
+
+/// The version of the seed database format written by [`SeedDbWriter`].
+pub const SEED_DB_VERSION: u8 = 1;
+
+/// The size of the header: the magic, the version, a reserved byte
+/// and the number of instructions per record.
+const HEADER_LEN: u64 = 8;
+
+/// The size of a single instruction in a record.
+const SLOT_LEN: usize = 16;
+
+/// An error while reading or writing a seed database.
+#[derive(Debug)]
+pub enum SeedDbError {
+    Io(io::Error),
+    /// The data does not start with [`SEED_DB_MAGIC`].
+    BadMagic,
+    /// The data uses a different version of the format.
+    UnsupportedVersion(u8),
+    /// The records have a width of zero, so they could not be told apart
+    /// from the end of the data.
+    ZeroWidth,
+    /// A program with `len` meaningful instructions does not fit into records
+    /// with `width` instructions.
+    TooLong {
+        len: usize,
+        width: u16,
+    },
+    /// The record at `index` is invalid.
+    InvalidRecord {
+        index: u64,
+        error: DecodeError,
+    },
+}
+
+impl fmt::Display for SeedDbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SeedDbError::Io(e) => write!(f, "{}", e),
+            SeedDbError::BadMagic => f.write_str("data is not a seed database"),
+            SeedDbError::UnsupportedVersion(version) => {
+                write!(f, "unsupported seed database version {}", version)
+            }
+            SeedDbError::ZeroWidth => f.write_str("records must contain at least one instruction"),
+            SeedDbError::TooLong { len, width } => write!(
+                f,
+                "program with {} instructions does not fit into records of {} instructions",
+                len, width
+            ),
+            SeedDbError::InvalidRecord { index, error } => {
+                write!(f, "record {}: {}", index, error)
+            }
+        }
+    }
+}
+
+impl Error for SeedDbError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SeedDbError::Io(e) => Some(e),
+            SeedDbError::InvalidRecord { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SeedDbError {
+    fn from(e: io::Error) -> SeedDbError {
+        SeedDbError::Io(e)
+    }
+}
+
+/// Stores `instruction` as its opcode, three register bytes, its constant
+/// as a big endian `u64` and two big endian `u16` targets. Unused operands are zero.
+fn write_slot(slot: &mut [u8], instruction: Instruction) {
+    slot[0] = opcode(instruction);
+    for (byte, reg) in slot[1..4].iter_mut().zip(instruction.registers()) {
+        *byte = reg;
+    }
+    slot[4..12].copy_from_slice(&constant(instruction).unwrap_or(0).to_be_bytes());
+    for (bytes, target) in slot[12..].chunks_mut(2).zip(instruction.targets()) {
+        bytes.copy_from_slice(&target.to_be_bytes());
+    }
+}
+
+fn read_slot(slot: &[u8], at: u16) -> Result<Instruction, DecodeError> {
+    let mut instruction = template(slot[0]).ok_or(DecodeError::InvalidOpcode {
+        at,
+        opcode: slot[0],
+    })?;
+    for (reg, &byte) in instruction.registers_mut().zip(&slot[1..4]) {
+        *reg = byte;
+    }
+    let mut value = [0; 8];
+    value.copy_from_slice(&slot[4..12]);
+    let value = u64::from_be_bytes(value);
+    if constant(instruction).is_some() && !set_constant(&mut instruction, value) {
+        return Err(DecodeError::InvalidNumber { at });
+    }
+    for (target, bytes) in instruction.targets_mut().zip(slot[12..].chunks(2)) {
+        *target = u16::from_be_bytes([bytes[0], bytes[1]]);
+    }
+
+    // Unused operands must be zero, so that every program has exactly one record.
+    let mut canonical = [0; SLOT_LEN];
+    write_slot(&mut canonical, instruction);
+    if canonical[..] != *slot {
+        return Err(DecodeError::InvalidNumber { at });
+    }
+    Ok(instruction)
+}
+
+/// Writes programs as fixed-width records, similar to the seed databases used
+/// to exchange undecided machines when searching for busy beavers.
+///
+/// The database starts with [`SEED_DB_MAGIC`], [`SEED_DB_VERSION`], a reserved zero
+/// byte and the nonzero number of instructions per record as a big endian `u16`. Every record
+/// then stores exactly that many instructions using 16 bytes per instruction,
+/// padding programs with `Halt`s. This allows accessing records by their index,
+/// see [`SeedDbReader::get`].
+#[derive(Debug)]
+pub struct SeedDbWriter<W: Write> {
+    writer: W,
+    width: u16,
+    records: u64,
+}
+
+impl<W: Write> SeedDbWriter<W> {
+    /// Writes the header of a database whose records contain `width` instructions,
+    /// failing with [`SeedDbError::ZeroWidth`] if `width` is zero.
+    pub fn new(mut writer: W, width: u16) -> Result<SeedDbWriter<W>, SeedDbError> {
+        if width == 0 {
+            return Err(SeedDbError::ZeroWidth);
+        }
+        writer.write_all(&SEED_DB_MAGIC)?;
+        writer.write_all(&[SEED_DB_VERSION, 0])?;
+        writer.write_all(&width.to_be_bytes())?;
+        Ok(SeedDbWriter {
+            writer,
+            width,
+            records: 0,
+        })
+    }
+
+    /// The number of instructions per record.
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    /// The number of records written so far.
+    pub fn records(&self) -> u64 {
+        self.records
+    }
+
+    /// Appends a record for `program`, failing if it does not fit into
+    /// a record when ignoring trailing `Halt`s.
+    pub fn push(&mut self, program: &Program) -> Result<(), SeedDbError> {
+        let len = program.len();
+        if len > usize::from(self.width) {
+            return Err(SeedDbError::TooLong {
+                len,
+                width: self.width,
+            });
+        }
+
+        let mut record = vec![0; usize::from(self.width) * SLOT_LEN];
+        for (slot, &instruction) in record.chunks_mut(SLOT_LEN).zip(program.instructions()) {
+            write_slot(slot, instruction);
+        }
+        self.writer.write_all(&record)?;
+        self.records += 1;
+        Ok(())
+    }
+
+    /// Flushes and returns the underlying writer.
+    pub fn finish(mut self) -> Result<W, SeedDbError> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Reads programs written by a [`SeedDbWriter`].
+///
+/// Iterating over the reader returns the remaining records in order.
+#[derive(Debug)]
+pub struct SeedDbReader<R: Read> {
+    reader: R,
+    width: u16,
+    next: u64,
+}
+
+impl<R: Read> SeedDbReader<R> {
+    /// Reads the header of a database.
+    pub fn new(mut reader: R) -> Result<SeedDbReader<R>, SeedDbError> {
+        let mut header = [0; HEADER_LEN as usize];
+        match reader.read_exact(&mut header) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(SeedDbError::BadMagic)
+            }
+            result => result?,
+        }
+        if header[..4] != SEED_DB_MAGIC {
+            return Err(SeedDbError::BadMagic);
+        }
+        if header[4] != SEED_DB_VERSION {
+            return Err(SeedDbError::UnsupportedVersion(header[4]));
+        }
+        let width = u16::from_be_bytes([header[6], header[7]]);
+        if width == 0 {
+            return Err(SeedDbError::ZeroWidth);
+        }
+        Ok(SeedDbReader {
+            reader,
+            width,
+            next: 0,
+        })
+    }
+
+    /// The number of instructions per record.
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    fn record_len(&self) -> u64 {
+        u64::from(self.width) * SLOT_LEN as u64
+    }
+
+    /// Reads the next record, returning `None` at the end of the database.
+    pub fn read_program(&mut self) -> Result<Option<Program>, SeedDbError> {
+        let index = self.next;
+        let mut record = vec![0; usize::from(self.width) * SLOT_LEN];
+        let mut filled = 0;
+        while filled < record.len() {
+            match self.reader.read(&mut record[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        if filled == 0 && !record.is_empty() {
+            return Ok(None);
+        }
+        if filled < record.len() {
+            return Err(SeedDbError::InvalidRecord {
+                index,
+                error: DecodeError::UnexpectedEnd,
+            });
+        }
+
+        self.next += 1;
+        let instructions = record
+            .chunks(SLOT_LEN)
+            .enumerate()
+            .map(|(at, slot)| read_slot(slot, at as u16))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|error| SeedDbError::InvalidRecord { index, error })?;
+        Ok(Some(Program::new(instructions)))
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read + Seek> SeedDbReader<R> {
+    /// The number of records in the database.
+    pub fn len(&mut self) -> Result<u64, SeedDbError> {
+        let current = self.reader.stream_position()?;
+        let end = self.reader.seek(SeekFrom::End(0))?;
+        self.reader.seek(SeekFrom::Start(current))?;
+        let data = end.saturating_sub(HEADER_LEN);
+        Ok(match self.record_len() {
+            0 => 0,
+            len => data / len,
+        })
+    }
+
+    pub fn is_empty(&mut self) -> Result<bool, SeedDbError> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Reads the record at `index`, returning `None` if there is no such record.
+    ///
+    /// Iteration continues after the returned record.
+    pub fn get(&mut self, index: u64) -> Result<Option<Program>, SeedDbError> {
+        if index >= self.len()? {
+            return Ok(None);
+        }
+        self.reader
+            .seek(SeekFrom::Start(HEADER_LEN + index * self.record_len()))?;
+        self.next = index;
+        self.read_program()
+    }
+}
+
+impl<R: Read> Iterator for SeedDbReader<R> {
+    type Item = Result<Program, SeedDbError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_program().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn round_trip() {
+        let programs = [
+            Program::new(
+                [
+                    Instruction::Decrement(0, 1, 2),
+                    Instruction::Increment(1, 0),
+                ]
+                .iter()
+                .copied(),
+            ),
+            Program::empty(),
+            Program::new(
+                [
+                    Instruction::SubConst(3, u64::MAX, 1, u16::MAX),
+                    Instruction::Copy {
+                        src: 1,
+                        dst: 2,
+                        scratch: 3,
+                        then: 2,
+                    },
+                    Instruction::Halt,
+                    Instruction::Halt,
+                ]
+                .iter()
+                .copied(),
+            ),
+        ];
+        let mut writer = SeedDbWriter::new(Vec::new(), 2).unwrap();
+        for program in &programs {
+            writer.push(program).unwrap();
+        }
+        assert!(matches!(
+            writer.push(&Program::new(vec![Instruction::Return; 3])),
+            Err(SeedDbError::TooLong { len: 3, width: 2 })
+        ));
+        assert_eq!(writer.records(), 3);
+        let bytes = writer.finish().unwrap();
+        assert_eq!(bytes.len(), 8 + 3 * 2 * 16);
+        assert_eq!(&bytes[..8], b"MNDB\x01\x00\x00\x02");
+
+        let mut reader = SeedDbReader::new(Cursor::new(&bytes)).unwrap();
+        assert_eq!(reader.width(), 2);
+        assert_eq!(reader.len().unwrap(), 3);
+        let read = reader.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(read.len(), 3);
+        for (read, program) in read.iter().zip(&programs) {
+            assert_eq!(read, program);
+        }
+
+        assert_eq!(reader.get(1).unwrap().unwrap(), read[1]);
+        assert_eq!(reader.next().unwrap().unwrap(), read[2]);
+        assert!(reader.next().is_none());
+        assert!(reader.get(3).unwrap().is_none());
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(
+            SeedDbReader::new(&b"MNDB"[..]),
+            Err(SeedDbError::BadMagic)
+        ));
+        assert!(matches!(
+            SeedDbReader::new(&b"MNDB\x02\x00\x00\x01"[..]),
+            Err(SeedDbError::UnsupportedVersion(2))
+        ));
+        assert!(matches!(
+            SeedDbWriter::new(Vec::new(), 0),
+            Err(SeedDbError::ZeroWidth)
+        ));
+        assert!(matches!(
+            SeedDbReader::new(&b"MNDB\x01\x00\x00\x00"[..]),
+            Err(SeedDbError::ZeroWidth)
+        ));
+
+        let mut bytes = b"MNDB\x01\x00\x00\x01".to_vec();
+        bytes.extend(&[0; 16]);
+        bytes.push(0x15);
+        bytes.extend(&[0; 15]);
+        bytes.extend(&[0; 15]);
+        let mut reader = SeedDbReader::new(&bytes[..]).unwrap();
+        assert_eq!(
+            reader.next().unwrap().unwrap(),
+            Program::new(Some(Instruction::Halt))
+        );
+        assert!(matches!(
+            reader.next(),
+            Some(Err(SeedDbError::InvalidRecord {
+                index: 1,
+                error: DecodeError::InvalidOpcode {
+                    at: 0,
+                    opcode: 0x15
+                }
+            }))
+        ));
+        assert!(matches!(
+            reader.next(),
+            Some(Err(SeedDbError::InvalidRecord {
+                index: 2,
+                error: DecodeError::UnexpectedEnd
+            }))
+        ));
+
+        // `Jump` does not use any registers, so they must be zero.
+        let mut bytes = b"MNDB\x01\x00\x00\x01\x05\x01".to_vec();
+        bytes.extend(&[0; 14]);
+        assert!(matches!(
+            SeedDbReader::new(&bytes[..]).unwrap().next(),
+            Some(Err(SeedDbError::InvalidRecord {
+                index: 0,
+                error: DecodeError::InvalidNumber { at: 0 }
+            }))
+        ));
+    }
+}
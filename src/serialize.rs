@@ -0,0 +1,127 @@
+//! `serde` support for programs and machine states.
+//!
+//! Instructions are stored as strings in the assembly language of
+//! [`Program::parse_asm`], e.g. `"DEC r1 -> 1, 2"`, and programs as sequences of
+//! instructions without trailing `Halt`s. Configurations and snapshots are stored
+//! as their instruction pointer, their non-zero registers and their call stack.
+
+use crate::{AsmError, Configuration, Counter, Instruction, Program, Snapshot};
+use serde::de::{self, Deserializer};
+use serde::ser::{SerializeSeq, Serializer};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::convert::TryFrom;
+
+impl Serialize for Instruction {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Instruction {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Instruction, D::Error> {
+        let s = Cow::<str>::deserialize(deserializer)?;
+        s.parse().map_err(|e: AsmError| de::Error::custom(e.kind))
+    }
+}
+
+impl Serialize for Program {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.len()))?;
+        for instruction in self.instructions() {
+            seq.serialize_element(instruction)?;
+        }
+        seq.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Program {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Program, D::Error> {
+        let instructions = Vec::<Instruction>::deserialize(deserializer)?;
+        Program::try_from(instructions).map_err(de::Error::custom)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "Configuration")]
+struct Repr<C> {
+    ptr: u16,
+    registers: Vec<(u8, C)>,
+    stack: Vec<u16>,
+}
+
+impl<C: Counter + Serialize> Serialize for Configuration<C> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Repr {
+            ptr: self.ptr(),
+            registers: self.registers().to_vec(),
+            stack: self.stack().to_vec(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, C: Counter + Deserialize<'de>> Deserialize<'de> for Configuration<C> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Configuration<C>, D::Error> {
+        let repr = Repr::<C>::deserialize(deserializer)?;
+        Configuration::from_parts(repr.ptr, repr.registers, repr.stack).ok_or_else(|| {
+            de::Error::custom("registers must be non-zero and sorted by their register number")
+        })
+    }
+}
+
+impl<C: Counter + Serialize> Serialize for Snapshot<C> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.configuration().serialize(serializer)
+    }
+}
+
+impl<'de, C: Counter + Deserialize<'de>> Deserialize<'de> for Snapshot<C> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Snapshot<C>, D::Error> {
+        Ok(Snapshot::from(&Configuration::deserialize(deserializer)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Machine;
+
+    #[test]
+    fn serde() {
+        let program = Program::new(
+            [
+                Instruction::Decrement(0, 1, 2),
+                Instruction::Call(0),
+                Instruction::Halt,
+            ]
+            .iter()
+            .copied(),
+        );
+        let json = serde_json::to_string(&program).unwrap();
+        assert_eq!(json, r#"["DEC r0 -> 1, 2","CALL 0"]"#);
+        let parsed: Program = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.diff(&program), []);
+        assert!(serde_json::from_str::<Program>(r#"["JMP x"]"#).is_err());
+
+        let mut prog: Machine = Machine::new(&program);
+        prog.set_register(0, 2);
+        prog.set_register(7, 5);
+        prog.step();
+        prog.step();
+        let json = serde_json::to_string(&prog.configuration()).unwrap();
+        assert_eq!(json, r#"{"ptr":0,"registers":[[0,1],[7,5]],"stack":[2]}"#);
+        let configuration: Configuration = serde_json::from_str(&json).unwrap();
+        assert_eq!(configuration, prog.configuration());
+        let snapshot: Snapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(snapshot, prog.snapshot());
+        assert_eq!(serde_json::to_string(&snapshot).unwrap(), json);
+
+        for invalid in &[
+            r#"{"ptr":0,"registers":[[7,5],[0,1]],"stack":[]}"#,
+            r#"{"ptr":0,"registers":[[0,0]],"stack":[]}"#,
+        ] {
+            assert!(serde_json::from_str::<Configuration>(invalid).is_err());
+        }
+    }
+}
@@ -0,0 +1,305 @@
+use crate::{Instruction, Program};
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fmt::{self, Write as _};
+use std::io::{self, Write as _};
+use std::process::{Command, Stdio};
+
+/// An error returned by [`Program::check_bounded_halting`].
+#[derive(Debug)]
+pub enum SmtError {
+    /// Running the solver failed.
+    Io(io::Error),
+    /// The program uses `Call` or `Return`, which are not supported.
+    Unsupported,
+    /// The solver could not decide the query, e.g. because it timed out.
+    Unknown,
+    /// The output of the solver could not be parsed.
+    InvalidOutput(String),
+}
+
+impl fmt::Display for SmtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SmtError::Io(err) => write!(f, "failed to run the solver: {}", err),
+            SmtError::Unsupported => {
+                f.write_str("programs using `Call` or `Return` are not supported")
+            }
+            SmtError::Unknown => f.write_str("the solver returned `unknown`"),
+            SmtError::InvalidOutput(output) => write!(f, "unexpected solver output: {}", output),
+        }
+    }
+}
+
+impl Error for SmtError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SmtError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SmtError {
+    fn from(err: io::Error) -> SmtError {
+        SmtError::Io(err)
+    }
+}
+
+/// Parses the output of a solver for a query generated by [`Program::bounded_halting_smtlib`],
+/// returning the initial value of each input if the query is satisfiable.
+pub fn parse_smt_model(output: &str) -> Result<Option<BTreeMap<u8, u64>>, SmtError> {
+    let invalid = || SmtError::InvalidOutput(output.to_owned());
+    let mut tokens = output
+        .split(|c: char| c == '(' || c == ')' || c.is_whitespace())
+        .filter(|token| !token.is_empty());
+    match tokens.next() {
+        Some("sat") => {}
+        Some("unsat") => return Ok(None),
+        Some("unknown") => return Err(SmtError::Unknown),
+        _ => return Err(invalid()),
+    }
+    let mut model = BTreeMap::new();
+    while let Some(name) = tokens.next() {
+        let reg = name
+            .strip_prefix('r')
+            .and_then(|name| name.strip_suffix("_0"))
+            .and_then(|reg| reg.parse().ok())
+            .ok_or_else(invalid)?;
+        let value = tokens
+            .next()
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(invalid)?;
+        model.insert(reg, value);
+    }
+    Ok(Some(model))
+}
+
+impl Program {
+    /// Encodes the question whether this program halts within `steps` steps for some
+    /// initial register values as an SMT-LIB script using linear integer arithmetic.
+    ///
+    /// Each register in `inputs` starts at a value between zero and the given bound,
+    /// all other registers start at zero. The script uses the constants `pc_k` and `rN_k`
+    /// for the instruction pointer and the register `N` after `k` steps. If the script is
+    /// satisfiable, the solver prints the initial values of the inputs, see
+    /// [`parse_smt_model`]. Registers are unbounded and `Choose`, `Random` and `Read` may
+    /// take any branch or return any value. Reaching `Purged` never halts.
+    ///
+    /// Returns `None` if the program uses `Call` or `Return`. The size of the script is
+    /// proportional to `steps` times the number of instructions.
+    pub fn bounded_halting_smtlib(&self, inputs: &[(u8, u64)], steps: u32) -> Option<String> {
+        let end = self.len() as u16;
+        let mut registers: BTreeSet<u8> = self
+            .iter()
+            .flat_map(|(_, instruction)| instruction.registers())
+            .collect();
+        registers.extend(inputs.iter().map(|&(reg, _)| reg));
+        let bounds: BTreeMap<u8, u64> = inputs.iter().copied().collect();
+        let mut halting = vec![end];
+        for (at, instruction) in self.iter() {
+            match instruction {
+                Instruction::Call(_) | Instruction::Return => return None,
+                Instruction::Halt | Instruction::HaltWith(_) => halting.push(at),
+                _ => {}
+            }
+        }
+
+        let mut out = String::from("(set-logic QF_LIA)\n");
+        for k in 0..=steps {
+            writeln!(out, "(declare-fun pc_{} () Int)", k).unwrap();
+            for reg in &registers {
+                writeln!(out, "(declare-fun r{}_{} () Int)", reg, k).unwrap();
+            }
+        }
+        out.push_str("(assert (= pc_0 0))\n");
+        for &reg in &registers {
+            match bounds.get(&reg) {
+                Some(bound) => writeln!(
+                    out,
+                    "(assert (and (<= 0 r{0}_0) (<= r{0}_0 {1})))",
+                    reg, bound
+                ),
+                None => writeln!(out, "(assert (= r{}_0 0))", reg),
+            }
+            .unwrap();
+        }
+
+        for k in 0..steps {
+            let r = |reg: u8| format!("r{}_{}", reg, k);
+            // The next state, with `changes` to some registers and jumping to `target`.
+            let next = |target: u16, changes: &[(u8, String)]| {
+                let mut state = format!("(and (= pc_{} {})", k + 1, target.min(end));
+                for &reg in &registers {
+                    let value = changes
+                        .iter()
+                        .rev()
+                        .find(|&&(r, _)| r == reg)
+                        .map_or_else(|| r(reg), |(_, value)| value.clone());
+                    write!(state, " (= r{}_{} {})", reg, k + 1, value).unwrap();
+                }
+                state.push(')');
+                state
+            };
+            for at in 0..=end {
+                let instruction = self.instruction(at);
+                let body = match instruction {
+                    Instruction::Increment(reg, target) => {
+                        next(target, &[(reg, format!("(+ {} 1)", r(reg)))])
+                    }
+                    Instruction::AddConst(reg, n, target) => {
+                        next(target, &[(reg, format!("(+ {} {})", r(reg), n))])
+                    }
+                    Instruction::Decrement(reg, then, els) => format!(
+                        "(ite (> {0} 0) {1} {2})",
+                        r(reg),
+                        next(then, &[(reg, format!("(- {} 1)", r(reg)))]),
+                        next(els, &[])
+                    ),
+                    Instruction::SubConst(reg, n, then, els) => format!(
+                        "(ite (>= {0} {1}) {2} {3})",
+                        r(reg),
+                        n,
+                        next(then, &[(reg, format!("(- {} {})", r(reg), n))]),
+                        next(els, &[])
+                    ),
+                    Instruction::Clear(reg, target) => next(target, &[(reg, "0".to_owned())]),
+                    Instruction::Transfer { src, dst, then } if src != dst => next(
+                        then,
+                        &[
+                            (dst, format!("(+ {} {})", r(dst), r(src))),
+                            (src, "0".to_owned()),
+                        ],
+                    ),
+                    Instruction::Copy {
+                        src,
+                        dst,
+                        scratch,
+                        then,
+                    } if src != dst && src != scratch && dst != scratch => next(
+                        then,
+                        &[
+                            (dst, format!("(+ {} {})", r(dst), r(src))),
+                            (src, format!("(+ {} {})", r(src), r(scratch))),
+                            (scratch, "0".to_owned()),
+                        ],
+                    ),
+                    Instruction::Swap(a, b, target) => next(target, &[(a, r(b)), (b, r(a))]),
+                    Instruction::BranchZero(reg, zero, nonzero) => format!(
+                        "(ite (= {} 0) {} {})",
+                        r(reg),
+                        next(zero, &[]),
+                        next(nonzero, &[])
+                    ),
+                    Instruction::Compare(a, b, ge, lt) => format!(
+                        "(ite (>= {} {}) {} {})",
+                        r(a),
+                        r(b),
+                        next(ge, &[]),
+                        next(lt, &[])
+                    ),
+                    Instruction::Choose(first, second) | Instruction::Random(_, first, second) => {
+                        format!("(or {} {})", next(first, &[]), next(second, &[]))
+                    }
+                    Instruction::Read(reg, target) => format!(
+                        "(and {} (>= r{}_{} 0))",
+                        next(target, &[(reg, format!("r{}_{}", reg, k + 1))]),
+                        reg,
+                        k + 1
+                    ),
+                    Instruction::Halt | Instruction::HaltWith(_) | Instruction::Purged => {
+                        next(at, &[])
+                    }
+                    _ => match instruction.targets().next() {
+                        Some(target) => next(target, &[]),
+                        None => next(at, &[]),
+                    },
+                };
+                writeln!(out, "(assert (=> (= pc_{} {}) {}))", k, at, body).unwrap();
+            }
+        }
+
+        out.push_str("(assert (or");
+        for at in halting {
+            write!(out, " (= pc_{} {})", steps, at).unwrap();
+        }
+        out.push_str("))\n(check-sat)\n");
+        if !bounds.is_empty() {
+            out.push_str("(get-value (");
+            let names: Vec<_> = bounds.keys().map(|reg| format!("r{}_0", reg)).collect();
+            out.push_str(&names.join(" "));
+            out.push_str("))\n");
+        }
+        Some(out)
+    }
+
+    /// Checks whether this program halts within `steps` steps for some initial values
+    /// of the `inputs`, see [`Program::bounded_halting_smtlib`], returning these values
+    /// if it does.
+    ///
+    /// The query is written to the standard input of `solver`, which has to print its
+    /// result to its standard output, e.g. `Command::new("z3").arg("-in")`.
+    pub fn check_bounded_halting(
+        &self,
+        inputs: &[(u8, u64)],
+        steps: u32,
+        solver: &mut Command,
+    ) -> Result<Option<BTreeMap<u8, u64>>, SmtError> {
+        let script = self
+            .bounded_halting_smtlib(inputs, steps)
+            .ok_or(SmtError::Unsupported)?;
+        let mut child = solver
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        child.stdin.take().unwrap().write_all(script.as_bytes())?;
+        let output = child.wait_with_output()?;
+        parse_smt_model(&String::from_utf8_lossy(&output.stdout))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smtlib() {
+        // Halts once $0 reaches 3.
+        let program = Program::new(
+            [
+                Instruction::SubConst(0, 3, 2, 1),
+                Instruction::Jump(1),
+                Instruction::HaltWith(1),
+            ]
+            .iter()
+            .copied(),
+        );
+        let script = program.bounded_halting_smtlib(&[(0, 10)], 2).unwrap();
+        let lines: Vec<_> = script.lines().collect();
+        assert_eq!(lines[0], "(set-logic QF_LIA)");
+        assert!(lines.contains(&"(assert (and (<= 0 r0_0) (<= r0_0 10)))"));
+        assert!(lines.contains(
+            &"(assert (=> (= pc_1 0) (ite (>= r0_1 3) (and (= pc_2 2) (= r0_2 (- r0_1 3))) \
+               (and (= pc_2 1) (= r0_2 r0_1)))))"
+        ));
+        assert!(lines.contains(&"(assert (=> (= pc_0 3) (and (= pc_1 3) (= r0_1 r0_0))))"));
+        assert_eq!(
+            lines[lines.len() - 3],
+            "(assert (or (= pc_2 3) (= pc_2 2)))"
+        );
+        assert_eq!(lines[lines.len() - 1], "(get-value (r0_0))");
+        let call = Program::new([Instruction::Call(0)].iter().copied());
+        assert_eq!(call.bounded_halting_smtlib(&[], 2), None);
+
+        let model = parse_smt_model("sat\n((r0_0 7)\n (r12_0 0))\n")
+            .unwrap()
+            .unwrap();
+        assert_eq!(model.into_iter().collect::<Vec<_>>(), [(0, 7), (12, 0)]);
+        assert_eq!(parse_smt_model("unsat\n").unwrap(), None);
+        assert!(matches!(parse_smt_model("unknown"), Err(SmtError::Unknown)));
+        assert!(matches!(
+            parse_smt_model("sat ((x 1))"),
+            Err(SmtError::InvalidOutput(_))
+        ));
+    }
+}
@@ -0,0 +1,159 @@
+use crate::{Counter, Instruction};
+
+/// Statistics collected while running a machine with
+/// [`Machine::run_with_stats`](crate::Machine::run_with_stats).
+///
+/// Running multiple times with the same `Stats` accumulates the results.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stats<C: Counter = u64> {
+    hits: Vec<u64>,
+    max_registers: Vec<C>,
+    increments: u64,
+    decrements: u64,
+    zero_tests: u64,
+}
+
+impl<C: Counter> Default for Stats<C> {
+    fn default() -> Stats<C> {
+        Stats::new()
+    }
+}
+
+impl<C: Counter> Stats<C> {
+    pub fn new() -> Stats<C> {
+        Stats {
+            hits: Vec::new(),
+            max_registers: vec![C::zero(); 1 << 8],
+            increments: 0,
+            decrements: 0,
+            zero_tests: 0,
+        }
+    }
+
+    /// The total number of executed instructions.
+    pub fn steps(&self) -> u64 {
+        self.hits.iter().sum()
+    }
+
+    /// How often each instruction has been executed, indexed by instruction.
+    ///
+    /// Instructions past the end of this slice have never been executed.
+    pub fn hits(&self) -> &[u64] {
+        &self.hits
+    }
+
+    /// The largest value held by each register.
+    pub fn max_registers(&self) -> &[C] {
+        &self.max_registers
+    }
+
+    /// The number of executed `Increment` instructions.
+    pub fn increments(&self) -> u64 {
+        self.increments
+    }
+
+    /// The number of `Decrement` instructions which decremented their register.
+    pub fn decrements(&self) -> u64 {
+        self.decrements
+    }
+
+    /// The number of `Decrement` instructions which found their register to be zero.
+    pub fn zero_tests(&self) -> u64 {
+        self.zero_tests
+    }
+
+    pub(crate) fn observe_registers(&mut self, registers: &[C]) {
+        for (reg, value) in registers.iter().enumerate() {
+            self.observe_register(reg as u8, value);
+        }
+    }
+
+    pub(crate) fn observe_register(&mut self, reg: u8, value: &C) {
+        let max = &mut self.max_registers[reg as usize];
+        if *value > *max {
+            max.clone_from(value);
+        }
+    }
+
+    /// Records the execution of `instruction` at `at`, `written` is whether
+    /// the instruction changed a register.
+    pub(crate) fn record(&mut self, at: u16, instruction: Instruction, written: bool) {
+        let at = at as usize;
+        if self.hits.len() <= at {
+            self.hits.resize(at + 1, 0);
+        }
+        self.hits[at] += 1;
+
+        match instruction {
+            Instruction::Increment(..) | Instruction::AddConst(..) => self.increments += 1,
+            Instruction::Decrement(..) | Instruction::SubConst(..) if written => {
+                self.decrements += 1
+            }
+            Instruction::Decrement(..) | Instruction::SubConst(..) => self.zero_tests += 1,
+            Instruction::Halt
+            | Instruction::HaltWith(_)
+            | Instruction::Purged
+            | Instruction::Jump(_)
+            | Instruction::Nop(_)
+            | Instruction::Clear(..)
+            | Instruction::Transfer { .. }
+            | Instruction::BranchZero(..)
+            | Instruction::Copy { .. }
+            | Instruction::Read(..)
+            | Instruction::Write(..)
+            | Instruction::Choose(..)
+            | Instruction::Random(..)
+            | Instruction::Call(_)
+            | Instruction::Return
+            | Instruction::Swap(..)
+            | Instruction::Compare(..) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Machine, Program, RunOutcome};
+
+    #[test]
+    fn stats() {
+        // $0 = $0 + $1, see `add` in the machine tests.
+        let program = Program::new(
+            [
+                Instruction::Decrement(1, 1, 2),
+                Instruction::Increment(2, 0),
+                Instruction::Decrement(2, 3, 5),
+                Instruction::Increment(1, 4),
+                Instruction::Increment(0, 2),
+                Instruction::Halt,
+            ]
+            .iter()
+            .copied(),
+        );
+        let mut prog: Machine = Machine::new(&program);
+        prog.reset_with([7, 3].iter().copied());
+        let mut stats = Stats::new();
+        assert_eq!(
+            prog.run_with_stats(u64::MAX, &mut stats),
+            RunOutcome::Halted { steps: 17, code: 0 }
+        );
+        assert_eq!(stats.steps(), 17);
+        assert_eq!(stats.hits(), &[4, 3, 4, 3, 3]);
+        assert_eq!(stats.increments(), 9);
+        assert_eq!(stats.decrements(), 6);
+        assert_eq!(stats.zero_tests(), 2);
+        assert_eq!(&stats.max_registers()[..4], &[10, 3, 3, 0]);
+
+        prog.reset_with([0, 1].iter().copied());
+        prog.run_with_stats(u64::MAX, &mut stats);
+        assert_eq!(stats.steps(), 24);
+        assert_eq!(stats.hits(), &[6, 4, 6, 4, 4]);
+
+        let program = Program::new([Instruction::Jump(1)].iter().copied());
+        let mut prog: Machine = Machine::new(&program);
+        let mut stats = Stats::new();
+        prog.run_with_stats(u64::MAX, &mut stats);
+        assert_eq!(stats.steps(), 1);
+    }
+}
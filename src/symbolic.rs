@@ -0,0 +1,580 @@
+use crate::machine::DEFAULT_MAX_CALL_DEPTH;
+use crate::{Instruction, Program};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// A linear expression `c + a0 * $0 + a1 * $1 + ...` over the initial register values.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct LinearExpr {
+    constant: i128,
+    coefficients: BTreeMap<u8, i128>,
+}
+
+impl LinearExpr {
+    pub fn constant(value: i128) -> LinearExpr {
+        LinearExpr {
+            constant: value,
+            coefficients: BTreeMap::new(),
+        }
+    }
+
+    /// The initial value of `reg`.
+    pub fn input(reg: u8) -> LinearExpr {
+        LinearExpr {
+            constant: 0,
+            coefficients: std::iter::once((reg, 1)).collect(),
+        }
+    }
+
+    /// The constant term of this expression.
+    pub fn constant_term(&self) -> i128 {
+        self.constant
+    }
+
+    /// The coefficient of the initial value of `reg`.
+    pub fn coefficient(&self, reg: u8) -> i128 {
+        self.coefficients.get(&reg).copied().unwrap_or(0)
+    }
+
+    /// The value of this expression if it does not depend on the inputs.
+    pub fn value(&self) -> Option<i128> {
+        if self.coefficients.is_empty() {
+            Some(self.constant)
+        } else {
+            None
+        }
+    }
+
+    /// Evaluates this expression, using zero for all registers which are not in `inputs`.
+    pub fn evaluate(&self, inputs: &BTreeMap<u8, u64>) -> i128 {
+        self.coefficients
+            .iter()
+            .map(|(reg, &a)| a * i128::from(inputs.get(reg).copied().unwrap_or(0)))
+            .sum::<i128>()
+            + self.constant
+    }
+
+    /// Adds `factor * other` to `self`, returning `None` on overflow.
+    fn add_scaled(&self, other: &LinearExpr, factor: i128) -> Option<LinearExpr> {
+        let mut sum = self.clone();
+        sum.constant = sum
+            .constant
+            .checked_add(other.constant.checked_mul(factor)?)?;
+        for (&reg, &a) in &other.coefficients {
+            let coefficient = sum.coefficient(reg).checked_add(a.checked_mul(factor)?)?;
+            if coefficient == 0 {
+                sum.coefficients.remove(&reg);
+            } else {
+                sum.coefficients.insert(reg, coefficient);
+            }
+        }
+        Some(sum)
+    }
+
+    fn offset(&self, n: i128) -> Option<LinearExpr> {
+        self.add_scaled(&LinearExpr::constant(n), 1)
+    }
+
+    /// Replaces the initial value of `reg` with `value`.
+    fn substitute(&self, reg: u8, value: i128) -> Option<LinearExpr> {
+        let mut result = self.clone();
+        if let Some(a) = result.coefficients.remove(&reg) {
+            result.constant = result.constant.checked_add(a.checked_mul(value)?)?;
+        }
+        Some(result)
+    }
+}
+
+impl fmt::Display for LinearExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut first = true;
+        for (&reg, &a) in &self.coefficients {
+            let sign = if a < 0 { "-" } else { "+" };
+            match (first, a.abs()) {
+                (true, 1) if a < 0 => write!(f, "-${}", reg)?,
+                (true, 1) => write!(f, "${}", reg)?,
+                (true, _) => write!(f, "{}*${}", a, reg)?,
+                (false, 1) => write!(f, " {} ${}", sign, reg)?,
+                (false, abs) => write!(f, " {} {}*${}", sign, abs, reg)?,
+            }
+            first = false;
+        }
+        match (first, self.constant) {
+            (true, c) => write!(f, "{}", c),
+            (false, 0) => Ok(()),
+            (false, c) if c < 0 => write!(f, " - {}", c.unsigned_abs()),
+            (false, c) => write!(f, " + {}", c),
+        }
+    }
+}
+
+/// A condition on the initial register values, see [`SymbolicPath`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Condition {
+    /// The expression is zero.
+    Zero(LinearExpr),
+    /// The expression is not negative.
+    NonNegative(LinearExpr),
+}
+
+impl Condition {
+    /// Whether this condition holds for `inputs`, using zero for all other registers.
+    pub fn holds(&self, inputs: &BTreeMap<u8, u64>) -> bool {
+        match self {
+            Condition::Zero(expr) => expr.evaluate(inputs) == 0,
+            Condition::NonNegative(expr) => expr.evaluate(inputs) >= 0,
+        }
+    }
+
+    fn expr(&self) -> &LinearExpr {
+        match self {
+            Condition::Zero(expr) | Condition::NonNegative(expr) => expr,
+        }
+    }
+
+    fn map(&self, f: impl FnOnce(&LinearExpr) -> Option<LinearExpr>) -> Option<Condition> {
+        Some(match self {
+            Condition::Zero(expr) => Condition::Zero(f(expr)?),
+            Condition::NonNegative(expr) => Condition::NonNegative(f(expr)?),
+        })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Condition::Zero(expr) => write!(f, "{} = 0", expr),
+            Condition::NonNegative(expr) => write!(f, "{} >= 0", expr),
+        }
+    }
+}
+
+/// How a [`SymbolicPath`] ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PathEnd {
+    /// The program halts with the exit code `code`.
+    Halted { code: u16 },
+    /// The path did not end within the step limit.
+    OutOfSteps,
+    /// The path reaches the `Purged` instruction at `at`.
+    HitPurged { at: u16 },
+    /// The `Call` instruction at `at` exceeds the maximum call depth.
+    StackOverflow { at: u16 },
+    /// The `Return` instruction at `at` is reached with an empty call stack.
+    StackUnderflow { at: u16 },
+    /// The instruction at `at` can't be executed symbolically, e.g. because it is a
+    /// `Read` or the coefficients of an expression overflow.
+    Unsupported { at: u16 },
+}
+
+/// A path through a program taken by all initial register values
+/// satisfying its `conditions`, see [`Program::symbolic_paths`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolicPath {
+    /// The conditions on the initial register values, the path is taken if all of them hold.
+    pub conditions: Vec<Condition>,
+    /// The value of all registers which are not known to be zero at the end of the path.
+    pub registers: BTreeMap<u8, LinearExpr>,
+    /// The position of the last instruction of the path.
+    pub ptr: u16,
+    /// The number of executed steps.
+    pub steps: u64,
+    pub end: PathEnd,
+}
+
+impl SymbolicPath {
+    /// Whether this path is taken for `inputs`, using zero for all other registers.
+    pub fn is_taken(&self, inputs: &BTreeMap<u8, u64>) -> bool {
+        self.conditions
+            .iter()
+            .all(|condition| condition.holds(inputs))
+    }
+}
+
+/// A partially executed path, see [`Program::symbolic_paths`].
+#[derive(Debug, Clone)]
+struct State {
+    ptr: u16,
+    registers: BTreeMap<u8, LinearExpr>,
+    conditions: Vec<Condition>,
+    /// The known lower and upper bounds of the inputs.
+    bounds: BTreeMap<u8, (i128, Option<i128>)>,
+    stack: Vec<u16>,
+    steps: u64,
+}
+
+impl State {
+    fn get(&self, reg: u8) -> LinearExpr {
+        self.registers.get(&reg).cloned().unwrap_or_default()
+    }
+
+    fn set(&mut self, reg: u8, expr: LinearExpr) {
+        if expr.value() == Some(0) {
+            self.registers.remove(&reg);
+        } else {
+            self.registers.insert(reg, expr);
+        }
+    }
+
+    /// Adds `condition` to the path condition, returning `false` if it is known
+    /// to be unsatisfiable. Conditions which only refer to a single input are stored
+    /// as bounds of that input, and inputs with a single possible value are replaced by it.
+    fn assume(&mut self, condition: Condition) -> bool {
+        let expr = condition.expr();
+        if let Some(value) = expr.value() {
+            return match condition {
+                Condition::Zero(_) => value == 0,
+                Condition::NonNegative(_) => value >= 0,
+            };
+        }
+        if expr.coefficients.len() != 1 {
+            self.conditions.push(condition);
+            return true;
+        }
+
+        let (&reg, &a) = expr.coefficients.iter().next().unwrap();
+        let c = expr.constant;
+        let (lo, hi) = self.bounds.get(&reg).copied().unwrap_or((0, None));
+        let (lo, hi) = match condition {
+            // `a * x + c = 0`
+            Condition::Zero(_) if c % a != 0 => return false,
+            Condition::Zero(_) => (lo.max(-c / a), Some(hi.map_or(-c / a, |hi| hi.min(-c / a)))),
+            // `a * x >= -c`
+            Condition::NonNegative(_) if a > 0 => (lo.max(div_ceil(-c, a)), hi),
+            Condition::NonNegative(_) => {
+                let bound = div_floor(c, -a);
+                (lo, Some(hi.map_or(bound, |hi| hi.min(bound))))
+            }
+        };
+        if hi.is_some_and(|hi| hi < lo) {
+            return false;
+        }
+        self.bounds.insert(reg, (lo, hi));
+        hi != Some(lo) || self.substitute(reg, lo)
+    }
+
+    /// Replaces the input `reg` with `value`, returning `false` if this makes
+    /// the path condition unsatisfiable or an expression overflows.
+    fn substitute(&mut self, reg: u8, value: i128) -> bool {
+        let conditions = std::mem::take(&mut self.conditions);
+        for condition in conditions {
+            match condition.map(|expr| expr.substitute(reg, value)) {
+                Some(condition) if condition.expr().value().is_none() => {
+                    self.conditions.push(condition)
+                }
+                Some(condition) if condition.holds(&BTreeMap::new()) => {}
+                _ => return false,
+            }
+        }
+        let registers = std::mem::take(&mut self.registers);
+        for (r, expr) in registers {
+            match expr.substitute(reg, value) {
+                Some(expr) => self.set(r, expr),
+                None => return false,
+            }
+        }
+        true
+    }
+
+    /// The path taken by this state, which ends with `end`.
+    fn into_path(self, end: PathEnd) -> SymbolicPath {
+        let mut conditions = Vec::new();
+        for (&reg, &(lo, hi)) in &self.bounds {
+            let input = LinearExpr::input(reg);
+            if hi == Some(lo) {
+                conditions.extend(input.offset(-lo).map(Condition::Zero));
+                continue;
+            }
+            if lo > 0 {
+                conditions.extend(input.offset(-lo).map(Condition::NonNegative));
+            }
+            if let Some(hi) = hi {
+                let below = LinearExpr::constant(hi).add_scaled(&input, -1);
+                conditions.extend(below.map(Condition::NonNegative));
+            }
+        }
+        conditions.extend(self.conditions);
+        SymbolicPath {
+            conditions,
+            registers: self.registers,
+            ptr: self.ptr,
+            steps: self.steps,
+            end,
+        }
+    }
+}
+
+fn div_floor(a: i128, b: i128) -> i128 {
+    let q = a / b;
+    if a % b != 0 && (a < 0) != (b < 0) {
+        q - 1
+    } else {
+        q
+    }
+}
+
+fn div_ceil(a: i128, b: i128) -> i128 {
+    -div_floor(-a, b)
+}
+
+impl Program {
+    /// Executes this program symbolically, with the registers in `inputs` starting at
+    /// unknown values and all other registers at zero.
+    ///
+    /// Registers hold linear expressions over the initial values of the `inputs`. Each
+    /// branch whose condition depends on them splits the current path in two, one for
+    /// each outcome, with the branch condition added to the conditions of the path.
+    /// Conditions which only depend on a single input are used to prune paths which
+    /// can't be taken, all other conditions are kept as is, so some of the returned
+    /// paths may be impossible. Nondeterministic branches, i.e. `Choose` and `Random`,
+    /// also split the path without adding a condition. Registers are assumed to be
+    /// unbounded, and `Write` instructions are ignored.
+    ///
+    /// Each path ends after at most `max_steps` steps. Stops once `max_paths` paths
+    /// have been found, ignoring the remaining ones.
+    pub fn symbolic_paths(
+        &self,
+        inputs: impl IntoIterator<Item = u8>,
+        max_steps: u64,
+        max_paths: usize,
+    ) -> Vec<SymbolicPath> {
+        let initial = State {
+            ptr: 0,
+            registers: inputs
+                .into_iter()
+                .map(|reg| (reg, LinearExpr::input(reg)))
+                .collect(),
+            conditions: Vec::new(),
+            bounds: BTreeMap::new(),
+            stack: Vec::new(),
+            steps: 0,
+        };
+        let mut paths = Vec::new();
+        let mut worklist = vec![initial];
+        while let Some(mut state) = worklist.pop() {
+            if paths.len() == max_paths {
+                break;
+            }
+            let end = loop {
+                match self.symbolic_step(&mut state, max_steps) {
+                    Ok(None) => {}
+                    Ok(Some(other)) => worklist.push(other),
+                    Err(end) => break end,
+                }
+            };
+            paths.push(state.into_path(end));
+        }
+        paths
+    }
+
+    /// Executes a single instruction of `state`, returning the other branch if the path
+    /// splits, or how the path ends.
+    fn symbolic_step(&self, state: &mut State, max_steps: u64) -> Result<Option<State>, PathEnd> {
+        let at = state.ptr;
+        let instruction = self.instruction(at);
+        match instruction {
+            Instruction::Halt => return Err(PathEnd::Halted { code: 0 }),
+            Instruction::HaltWith(code) => return Err(PathEnd::Halted { code }),
+            Instruction::Purged => return Err(PathEnd::HitPurged { at }),
+            _ if state.steps == max_steps => return Err(PathEnd::OutOfSteps),
+            _ => {}
+        }
+        let unsupported = PathEnd::Unsupported { at };
+        state.steps += 1;
+
+        // The conditions for taking the first and second target of a branch.
+        let branch = match instruction {
+            Instruction::Decrement(reg, ..) | Instruction::SubConst(reg, 1, ..) => {
+                let value = state.get(reg);
+                Some((value.offset(-1), Some(Condition::Zero(value))))
+            }
+            Instruction::SubConst(reg, n, ..) => {
+                let value = state.get(reg);
+                let n = i128::from(n);
+                let below = LinearExpr::constant(n - 1).add_scaled(&value, -1);
+                Some((value.offset(-n), below.map(Condition::NonNegative)))
+            }
+            Instruction::BranchZero(reg, ..) => {
+                let value = state.get(reg);
+                Some((
+                    Some(value.clone()),
+                    value.offset(-1).map(Condition::NonNegative),
+                ))
+            }
+            Instruction::Compare(a, b, ..) => {
+                let (a, b) = (state.get(a), state.get(b));
+                let ge = a.add_scaled(&b, -1);
+                let lt = b.add_scaled(&a, -1).and_then(|lt| lt.offset(-1));
+                Some((ge, lt.map(Condition::NonNegative)))
+            }
+            _ => None,
+        };
+        if let Some((first, second)) = branch {
+            let first = match instruction {
+                Instruction::BranchZero(..) => first.map(Condition::Zero),
+                _ => first.map(Condition::NonNegative),
+            };
+            let (first, second) = match (first, second) {
+                (Some(first), Some(second)) => (first, second),
+                _ => return Err(unsupported),
+            };
+            let targets: Vec<u16> = instruction.targets().collect();
+            let mut other = state.clone();
+            let taken = state.assume(first);
+            let other_taken = other.assume(second);
+            if taken {
+                self.symbolic_effect(state, instruction)
+                    .ok_or(unsupported)?;
+                state.ptr = targets[0];
+            }
+            other.ptr = targets[1];
+            return match (taken, other_taken) {
+                (true, true) => Ok(Some(other)),
+                (true, false) => Ok(None),
+                (false, true) => {
+                    *state = other;
+                    Ok(None)
+                }
+                // The current path was impossible from the start.
+                (false, false) => Err(unsupported),
+            };
+        }
+
+        match instruction {
+            Instruction::Choose(first, second) | Instruction::Random(_, first, second) => {
+                let mut other = state.clone();
+                state.ptr = first;
+                other.ptr = second;
+                return Ok(Some(other));
+            }
+            Instruction::Read(..) => return Err(unsupported),
+            Instruction::Call(target) => {
+                if state.stack.len() >= DEFAULT_MAX_CALL_DEPTH {
+                    state.steps -= 1;
+                    return Err(PathEnd::StackOverflow { at });
+                }
+                state.stack.push(at.wrapping_add(1));
+                state.ptr = target;
+            }
+            Instruction::Return => match state.stack.pop() {
+                Some(ret) => state.ptr = ret,
+                None => {
+                    state.steps -= 1;
+                    return Err(PathEnd::StackUnderflow { at });
+                }
+            },
+            _ => {
+                self.symbolic_effect(state, instruction)
+                    .ok_or(unsupported)?;
+                state.ptr = instruction.targets().next().unwrap_or(at);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Applies the effect of `instruction` on the registers of `state`, assuming that
+    /// it takes its first target. Returns `None` if an expression overflows.
+    fn symbolic_effect(&self, state: &mut State, instruction: Instruction) -> Option<()> {
+        match instruction {
+            Instruction::Increment(reg, _) => state.set(reg, state.get(reg).offset(1)?),
+            Instruction::AddConst(reg, n, _) => {
+                state.set(reg, state.get(reg).offset(i128::from(n))?)
+            }
+            Instruction::Decrement(reg, ..) => state.set(reg, state.get(reg).offset(-1)?),
+            Instruction::SubConst(reg, n, ..) => {
+                state.set(reg, state.get(reg).offset(-i128::from(n))?)
+            }
+            Instruction::Clear(reg, _) => state.set(reg, LinearExpr::default()),
+            Instruction::Transfer { src, dst, .. } if src != dst => {
+                state.set(dst, state.get(dst).add_scaled(&state.get(src), 1)?);
+                state.set(src, LinearExpr::default());
+            }
+            Instruction::Copy {
+                src, dst, scratch, ..
+            } if src != dst && src != scratch && dst != scratch => {
+                let (value, spare) = (state.get(src), state.get(scratch));
+                state.set(dst, state.get(dst).add_scaled(&value, 1)?);
+                state.set(src, value.add_scaled(&spare, 1)?);
+                state.set(scratch, LinearExpr::default());
+            }
+            Instruction::Swap(a, b, _) => {
+                let (x, y) = (state.get(a), state.get(b));
+                state.set(a, y);
+                state.set(b, x);
+            }
+            _ => {}
+        }
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Machine, RunOutcome};
+
+    #[test]
+    fn symbolic_paths() {
+        let program = Program::new(
+            [
+                Instruction::Transfer {
+                    src: 0,
+                    dst: 1,
+                    then: 1,
+                },
+                Instruction::AddConst(1, 2, 2),
+                Instruction::Compare(1, 2, 3, 4),
+                Instruction::HaltWith(1),
+                Instruction::Decrement(1, 4, 5),
+            ]
+            .iter()
+            .copied(),
+        );
+        let paths = program.symbolic_paths(vec![0, 2], 100, 100);
+        let halted: Vec<_> = paths
+            .iter()
+            .filter(|path| path.end == (PathEnd::Halted { code: 1 }))
+            .collect();
+        assert_eq!(halted.len(), 1);
+        assert_eq!(halted[0].conditions.len(), 1);
+        assert_eq!(halted[0].conditions[0].to_string(), "$0 - $2 + 2 >= 0");
+        assert_eq!(halted[0].registers[&1].to_string(), "$0 + 2");
+        assert_eq!(halted[0].steps, 3);
+
+        for x in 0..4 {
+            for y in 0..8 {
+                let inputs: BTreeMap<u8, u64> = [(0, x), (2, y)].iter().copied().collect();
+                let taken: Vec<_> = paths.iter().filter(|path| path.is_taken(&inputs)).collect();
+                assert_eq!(taken.len(), 1);
+                let mut prog: Machine = Machine::new(&program);
+                prog.set_register(0, x);
+                prog.set_register(2, y);
+                match (prog.run(100), taken[0].end) {
+                    (RunOutcome::Halted { steps, code }, PathEnd::Halted { code: expected }) => {
+                        assert_eq!((steps, code), (taken[0].steps, expected));
+                        for (reg, expr) in &taken[0].registers {
+                            assert_eq!(
+                                expr.evaluate(&inputs),
+                                i128::from(prog.registers()[*reg as usize])
+                            );
+                        }
+                    }
+                    (outcome, end) => panic!("{:?} {:?}", outcome, end),
+                }
+            }
+        }
+
+        // Each path of a countdown pins the input to a single value.
+        let program = Program::new([Instruction::Decrement(0, 0, 1)].iter().copied());
+        let paths = program.symbolic_paths(Some(0), 10, 100);
+        assert_eq!(paths.len(), 11);
+        assert_eq!(paths[0].conditions[0].to_string(), "$0 - 10 >= 0");
+        assert_eq!(paths[0].end, PathEnd::OutOfSteps);
+        let zero = &paths[10];
+        assert_eq!(zero.conditions.len(), 1);
+        assert_eq!(zero.conditions[0].to_string(), "$0 = 0");
+        assert_eq!((zero.steps, zero.end), (1, PathEnd::Halted { code: 0 }));
+        assert_eq!(paths[1].conditions[0].to_string(), "$0 - 9 = 0");
+        assert_eq!(program.symbolic_paths(Some(0), 10, 3).len(), 3);
+    }
+}
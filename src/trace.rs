@@ -0,0 +1,357 @@
+use crate::{Counter, RegisterFile};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io::{self, Write};
+use std::ops::Range;
+
+/// A single recorded step of a [`Trace`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TraceEntry<C: Counter = u64> {
+    /// The index of the step, counting from the first step traced with this trace.
+    pub step: u64,
+    /// The instruction pointer of the executed instruction.
+    pub ptr: u16,
+    /// The registers changed by this step and their new values.
+    pub writes: Vec<(u8, C)>,
+    /// All nonzero registers after this step, in increasing order.
+    pub registers: Vec<(u8, C)>,
+}
+
+impl<C: Counter> TraceEntry<C> {
+    /// Displays this entry as e.g. `3 @ 1: acc = 2, $5 = 0`, using the names of `registers`.
+    pub fn display<'a>(&'a self, registers: &'a RegisterFile) -> impl fmt::Display + 'a {
+        DisplayEntry {
+            entry: self,
+            registers,
+        }
+    }
+}
+
+struct DisplayEntry<'a, C: Counter> {
+    entry: &'a TraceEntry<C>,
+    registers: &'a RegisterFile,
+}
+
+impl<C: Counter> fmt::Display for DisplayEntry<'_, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} @ {}:", self.entry.step, self.entry.ptr)?;
+        for (i, (reg, value)) in self.entry.writes.iter().enumerate() {
+            let sep = if i == 0 { " " } else { ", " };
+            write!(f, "{}{} = {}", sep, self.registers.display(*reg), value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes `s` as a JSON string.
+fn write_json_str(out: &mut impl Write, s: &str) -> io::Result<()> {
+    write!(out, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(out, "\\\"")?,
+            '\\' => write!(out, "\\\\")?,
+            c if c.is_control() => write!(out, "\\u{:04x}", c as u32)?,
+            c => write!(out, "{}", c)?,
+        }
+    }
+    write!(out, "\"")
+}
+
+/// Records the steps of a run, see [`Machine::run_traced`](crate::Machine::run_traced).
+///
+/// Only every `interval`-th step is recorded and recording stops
+/// once `max_len` entries have been recorded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trace<C: Counter = u64> {
+    entries: Vec<TraceEntry<C>>,
+    interval: u64,
+    max_len: usize,
+    steps: u64,
+}
+
+impl<C: Counter> Trace<C> {
+    /// Creates a trace recording every `interval`-th step, up to `max_len` steps.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` is zero.
+    pub fn new(interval: u64, max_len: usize) -> Trace<C> {
+        assert!(interval > 0, "trace interval must not be zero");
+        Trace {
+            entries: Vec::new(),
+            interval,
+            max_len,
+            steps: 0,
+        }
+    }
+
+    pub fn entries(&self) -> &[TraceEntry<C>] {
+        &self.entries
+    }
+
+    pub fn interval(&self) -> u64 {
+        self.interval
+    }
+
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    /// The number of steps seen by this trace, including the ones which were not recorded.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Whether the trace has stopped recording as it reached `max_len`.
+    pub fn is_full(&self) -> bool {
+        self.entries.len() >= self.max_len
+    }
+
+    /// Writes the recorded entries as JSON Lines, one configuration per line, e.g.
+    /// `{"step":3,"ptr":1,"registers":{"0":1,"1":2},"writes":{"1":2}}`.
+    ///
+    /// `registers` contains all nonzero registers after the step
+    /// and `writes` the registers changed by it.
+    pub fn write_jsonl(&self, mut out: impl Write) -> io::Result<()> {
+        let write_map = |out: &mut dyn Write, key: &str, values: &[(u8, C)]| {
+            write!(out, ",\"{}\":{{", key)?;
+            for (i, (reg, value)) in values.iter().enumerate() {
+                let sep = if i == 0 { "" } else { "," };
+                write!(out, "{}\"{}\":{}", sep, reg, value)?;
+            }
+            write!(out, "}}")
+        };
+        for entry in &self.entries {
+            write!(out, "{{\"step\":{},\"ptr\":{}", entry.step, entry.ptr)?;
+            write_map(&mut out, "registers", &entry.registers)?;
+            write_map(&mut out, "writes", &entry.writes)?;
+            writeln!(out, "}}")?;
+        }
+        Ok(())
+    }
+
+    /// Writes the recorded entries as CSV, with a header line and one configuration
+    /// per line.
+    ///
+    /// The columns are `step`, `ptr` and one column for each register which is
+    /// nonzero in any entry, e.g. `r3`, containing its value after that step.
+    pub fn write_csv(&self, mut out: impl Write) -> io::Result<()> {
+        let registers: BTreeSet<u8> = self
+            .entries
+            .iter()
+            .flat_map(|entry| entry.registers.iter().map(|&(reg, _)| reg))
+            .collect();
+        write!(out, "step,ptr")?;
+        for reg in &registers {
+            write!(out, ",r{}", reg)?;
+        }
+        writeln!(out)?;
+        for entry in &self.entries {
+            write!(out, "{},{}", entry.step, entry.ptr)?;
+            let mut values = entry.registers.iter().peekable();
+            for &reg in &registers {
+                match values.next_if(|&&(nonzero, _)| nonzero == reg) {
+                    Some((_, value)) => write!(out, ",{}", value)?,
+                    None => write!(out, ",0")?,
+                }
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the recorded entries in the trace event format of Chrome, which can be
+    /// viewed in Perfetto or `chrome://tracing`, using one microsecond per step.
+    ///
+    /// Each of the named `regions` of the program is shown as its own track, every
+    /// instruction which is not part of a region has a separate track. Consecutive
+    /// entries in the same track are merged into a single slice. If the regions
+    /// overlap, instructions belong to the first region containing them.
+    pub fn write_chrome_trace(
+        &self,
+        mut out: impl Write,
+        regions: &[(&str, Range<u16>)],
+    ) -> io::Result<()> {
+        let track = |ptr: u16| {
+            regions
+                .iter()
+                .position(|(_, range)| range.contains(&ptr))
+                .unwrap_or(regions.len() + 1 + usize::from(ptr))
+        };
+        let mut slices: Vec<(usize, u64, u64)> = Vec::new();
+        for entry in &self.entries {
+            let tid = track(entry.ptr);
+            match slices.last_mut() {
+                Some((last, _, end)) if *last == tid => *end = entry.step + self.interval,
+                _ => slices.push((tid, entry.step, entry.step + self.interval)),
+            }
+        }
+
+        write!(out, "{{\"traceEvents\":[")?;
+        let mut tracks: Vec<_> = slices.iter().map(|&(tid, ..)| tid).collect();
+        tracks.sort_unstable();
+        tracks.dedup();
+        let name = |tid: usize| match regions.get(tid) {
+            Some((name, _)) => name.to_string(),
+            None => format!("ptr {}", tid - regions.len() - 1),
+        };
+        for (i, &tid) in tracks.iter().enumerate() {
+            let sep = if i == 0 { "\n" } else { ",\n" };
+            write!(
+                out,
+                "{}{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":0,\"tid\":{},\"args\":{{\"name\":",
+                sep, tid
+            )?;
+            write_json_str(&mut out, &name(tid))?;
+            write!(out, "}}}}")?;
+        }
+        for (tid, start, end) in slices {
+            write!(out, ",\n{{\"name\":")?;
+            write_json_str(&mut out, &name(tid))?;
+            write!(
+                out,
+                ",\"ph\":\"X\",\"pid\":0,\"tid\":{},\"ts\":{},\"dur\":{}}}",
+                tid,
+                start,
+                end - start
+            )?;
+        }
+        writeln!(out, "\n]}}")
+    }
+
+    /// Records the step executing the instruction at `ptr`, which changed the registers
+    /// `writes`, with `registers` being the values of all registers after the step.
+    pub(crate) fn record<'a>(
+        &mut self,
+        ptr: u16,
+        writes: impl Iterator<Item = (u8, &'a C)>,
+        registers: &[C],
+    ) where
+        C: 'a,
+    {
+        let step = self.steps;
+        self.steps += 1;
+        if step % self.interval == 0 && !self.is_full() {
+            self.entries.push(TraceEntry {
+                step,
+                ptr,
+                writes: writes.map(|(reg, value)| (reg, value.clone())).collect(),
+                registers: registers
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, value)| !value.is_zero())
+                    .map(|(reg, value)| (reg as u8, value.clone()))
+                    .collect(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Instruction, Machine, Program, RunOutcome};
+
+    #[test]
+    fn trace() {
+        let program = Program::new(
+            [
+                Instruction::Decrement(0, 1, 2),
+                Instruction::Increment(1, 0),
+            ]
+            .iter()
+            .copied(),
+        );
+        let mut prog: Machine = Machine::new(&program);
+        prog.set_register(0, 2);
+        let mut trace = Trace::new(1, 100);
+        assert_eq!(
+            prog.run_traced(u64::MAX, &mut trace),
+            RunOutcome::Halted { steps: 5, code: 0 }
+        );
+        let entries: Vec<_> = trace
+            .entries()
+            .iter()
+            .map(|e| (e.step, e.ptr, e.writes.clone(), e.registers.clone()))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                (0, 0, vec![(0, 1)], vec![(0, 1)]),
+                (1, 1, vec![(1, 1)], vec![(0, 1), (1, 1)]),
+                (2, 0, vec![(0, 0)], vec![(1, 1)]),
+                (3, 1, vec![(1, 2)], vec![(1, 2)]),
+                (4, 0, vec![], vec![(1, 2)]),
+            ]
+        );
+
+        prog.reset_with([10].iter().copied());
+        let mut trace = Trace::new(3, 4);
+        assert_eq!(
+            prog.run_traced(u64::MAX, &mut trace),
+            RunOutcome::Halted { steps: 21, code: 0 }
+        );
+        assert_eq!(trace.steps(), 21);
+        assert!(trace.is_full());
+        let steps: Vec<_> = trace.entries().iter().map(|e| e.step).collect();
+        assert_eq!(steps, [0, 3, 6, 9]);
+        assert_eq!(trace.entries()[3].registers, [(0, 5), (1, 5)]);
+
+        let mut registers = RegisterFile::new();
+        registers.define("counter", 0);
+        let entry = &trace.entries()[1];
+        assert_eq!(entry.display(&registers).to_string(), "3 @ 1: $1 = 2");
+        assert_eq!(
+            trace.entries()[2].display(&registers).to_string(),
+            "6 @ 0: counter = 6"
+        );
+    }
+
+    #[test]
+    fn export() {
+        let mut trace = Trace::<u64>::new(1, 100);
+        trace.record(0, [(0, &1)].iter().copied(), &[1]);
+        trace.record(1, [(3, &2), (0, &0)].iter().copied(), &[0, 0, 0, 2]);
+        trace.record(2, None.into_iter(), &[0, 0, 0, 2]);
+
+        let mut jsonl = Vec::new();
+        trace.write_jsonl(&mut jsonl).unwrap();
+        assert_eq!(
+            String::from_utf8(jsonl).unwrap(),
+            r#"{"step":0,"ptr":0,"registers":{"0":1},"writes":{"0":1}}
+{"step":1,"ptr":1,"registers":{"3":2},"writes":{"3":2,"0":0}}
+{"step":2,"ptr":2,"registers":{"3":2},"writes":{}}
+"#
+        );
+
+        let mut csv = Vec::new();
+        trace.write_csv(&mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "step,ptr,r0,r3\n0,0,1,0\n1,1,0,2\n2,2,0,2\n"
+        );
+    }
+
+    #[test]
+    fn chrome_trace() {
+        let mut trace = Trace::<u64>::new(1, 100);
+        for &ptr in &[0, 1, 0, 1, 2, 0] {
+            trace.record(ptr, None.into_iter(), &[]);
+        }
+        let mut out = Vec::new();
+        trace
+            .write_chrome_trace(&mut out, &[("\"loop\"", 0..2)])
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            r#"{"traceEvents":[
+{"name":"thread_name","ph":"M","pid":0,"tid":0,"args":{"name":"\"loop\""}},
+{"name":"thread_name","ph":"M","pid":0,"tid":4,"args":{"name":"ptr 2"}},
+{"name":"\"loop\"","ph":"X","pid":0,"tid":0,"ts":0,"dur":4},
+{"name":"ptr 2","ph":"X","pid":0,"tid":4,"ts":4,"dur":1},
+{"name":"\"loop\"","ph":"X","pid":0,"tid":0,"ts":5,"dur":1}
+]}
+"#
+        );
+    }
+}
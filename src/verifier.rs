@@ -0,0 +1,765 @@
+//! An independent checker for [`Certificate`]s.
+//!
+//! The checks in this module only depend on the program itself and the definition of its
+//! instructions. They use their own interpreter and abstract semantics instead of the
+//! [`Machine`](crate::Machine) or the analyses creating the certificates, so that trusting
+//! a certificate only requires trusting this module. Registers are unbounded and the call
+//! stack may grow without limit.
+
+use crate::{
+    BackwardCertificate, Certificate, Congruence, CyclerCertificate, Instruction, Interval,
+    InvariantCertificate, Program, Sign, TranslatedCyclerCertificate,
+};
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+
+/// Checks that `certificate` proves that `program` never halts when starting
+/// with all registers at zero.
+///
+/// This may reject valid certificates for programs using `Read`, `Write`, `Choose`
+/// or `Random`, where cyclers are never accepted, or `Call` and `Return`, which are
+/// only supported by cyclers.
+pub fn verify(program: &Program, certificate: &Certificate) -> bool {
+    match certificate {
+        Certificate::Cycler(certificate) => verify_cycler(program, certificate),
+        Certificate::TranslatedCycler(certificate) => {
+            verify_translated_cycler(program, certificate)
+        }
+        Certificate::Backward(certificate) => verify_backward(program, certificate),
+        Certificate::Intervals(certificate) => verify_invariant(program, certificate),
+        Certificate::Congruences(certificate) => verify_invariant(program, certificate),
+    }
+}
+
+/// A configuration of a program, storing only the nonzero registers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct State {
+    ptr: u16,
+    registers: BTreeMap<u8, u64>,
+    stack: Vec<u16>,
+}
+
+impl State {
+    fn get(&self, reg: u8) -> u64 {
+        self.registers.get(&reg).copied().unwrap_or(0)
+    }
+
+    fn set(&mut self, reg: u8, value: u64) {
+        if value == 0 {
+            self.registers.remove(&reg);
+        } else {
+            self.registers.insert(reg, value);
+        }
+    }
+
+    /// Executes a single instruction, returning `false` if the program stops or the
+    /// instruction is not deterministic. Values which don't fit into a `u64` stop as well.
+    fn step(&mut self, program: &Program) -> bool {
+        let instruction = program.instruction(self.ptr);
+        let get = |reg| self.get(reg);
+        let (changes, next): (Vec<(u8, Option<u64>)>, u16) = match instruction {
+            Instruction::Increment(reg, target) => (vec![(reg, get(reg).checked_add(1))], target),
+            Instruction::AddConst(reg, n, target) => (vec![(reg, get(reg).checked_add(n))], target),
+            Instruction::Decrement(reg, then, els) => match get(reg) {
+                0 => (vec![], els),
+                value => (vec![(reg, Some(value - 1))], then),
+            },
+            Instruction::SubConst(reg, n, then, els) => match get(reg).checked_sub(n) {
+                Some(value) => (vec![(reg, Some(value))], then),
+                None => (vec![], els),
+            },
+            Instruction::Clear(reg, target) => (vec![(reg, Some(0))], target),
+            Instruction::Transfer { src, dst, then } if src != dst => (
+                vec![(dst, get(dst).checked_add(get(src))), (src, Some(0))],
+                then,
+            ),
+            Instruction::Copy {
+                src,
+                dst,
+                scratch,
+                then,
+            } if src != dst && src != scratch && dst != scratch => (
+                vec![
+                    (dst, get(dst).checked_add(get(src))),
+                    (src, get(src).checked_add(get(scratch))),
+                    (scratch, Some(0)),
+                ],
+                then,
+            ),
+            Instruction::Swap(a, b, target) => (vec![(a, Some(get(b))), (b, Some(get(a)))], target),
+            Instruction::BranchZero(reg, zero, nonzero) => {
+                (vec![], if get(reg) == 0 { zero } else { nonzero })
+            }
+            Instruction::Compare(a, b, ge, lt) => (vec![], if get(a) >= get(b) { ge } else { lt }),
+            Instruction::Call(target) => {
+                self.stack.push(self.ptr.wrapping_add(1));
+                (vec![], target)
+            }
+            Instruction::Return => match self.stack.pop() {
+                Some(ret) => (vec![], ret),
+                None => return false,
+            },
+            Instruction::Halt
+            | Instruction::HaltWith(_)
+            | Instruction::Purged
+            | Instruction::Read(..)
+            | Instruction::Write(..)
+            | Instruction::Choose(..)
+            | Instruction::Random(..) => return false,
+            Instruction::Transfer { then, .. } | Instruction::Copy { then, .. } => (vec![], then),
+            Instruction::Jump(target) | Instruction::Nop(target) => (vec![], target),
+        };
+        let mut values = Vec::with_capacity(changes.len());
+        for (reg, value) in changes {
+            match value {
+                Some(value) => values.push((reg, value)),
+                None => return false,
+            }
+        }
+        for (reg, value) in values {
+            self.set(reg, value);
+        }
+        self.ptr = next;
+        true
+    }
+}
+
+/// Runs `program` from the start for `steps` steps, returning `None` if it stops earlier.
+fn run(program: &Program, steps: u64) -> Option<State> {
+    let mut state = State {
+        ptr: 0,
+        registers: BTreeMap::new(),
+        stack: Vec::new(),
+    };
+    for _ in 0..steps {
+        if !state.step(program) {
+            return None;
+        }
+    }
+    Some(state)
+}
+
+fn verify_cycler(program: &Program, certificate: &CyclerCertificate) -> bool {
+    if certificate.period == 0 {
+        return false;
+    }
+    let mut state = match run(program, certificate.start) {
+        Some(state) => state,
+        None => return false,
+    };
+    let start = state.clone();
+    (0..certificate.period).all(|_| state.step(program)) && state == start
+}
+
+fn verify_translated_cycler(program: &Program, certificate: &TranslatedCyclerCertificate) -> bool {
+    let reg = certificate.register;
+    if certificate.period == 0 || certificate.shift == 0 {
+        return false;
+    }
+    let mut state = match run(program, certificate.start) {
+        Some(state) => state,
+        None => return false,
+    };
+    let start = state.clone();
+    for _ in 0..certificate.period {
+        // Each step must behave the same way if `reg` is larger.
+        let value = state.get(reg);
+        let independent = match program.instruction(state.ptr) {
+            Instruction::Decrement(r, ..) | Instruction::BranchZero(r, ..) => r != reg || value > 0,
+            Instruction::SubConst(r, n, ..) => r != reg || value >= n,
+            Instruction::Clear(r, _) => r != reg,
+            Instruction::Transfer { src, .. } => src != reg,
+            Instruction::Copy { src, scratch, .. } => src != reg && scratch != reg,
+            Instruction::Swap(a, b, _) | Instruction::Compare(a, b, ..) => a != reg && b != reg,
+            _ => true,
+        };
+        if !independent || !state.step(program) {
+            return false;
+        }
+    }
+    let mut shifted = start.clone();
+    match start.get(reg).checked_add(certificate.shift) {
+        Some(value) => shifted.set(reg, value),
+        None => return false,
+    }
+    state == shifted
+}
+
+/// Whether `program` uses `Call` or `Return`.
+fn uses_calls(program: &Program) -> bool {
+    program
+        .iter()
+        .any(|(_, instruction)| matches!(instruction, Instruction::Call(_) | Instruction::Return))
+}
+
+/// The possible signs of the registers written by taking the `branch`th target of
+/// `instruction` from configurations whose registers have the signs `pre`, or `None`
+/// if the branch can't be taken. Each possible sign is given as `[zero, positive]`.
+fn sign_step(
+    instruction: Instruction,
+    branch: usize,
+    pre: impl Fn(u8) -> Sign,
+) -> Option<Vec<(u8, [bool; 2])>> {
+    const ZERO: [bool; 2] = [true, false];
+    const POSITIVE: [bool; 2] = [false, true];
+    const ANY: [bool; 2] = [true, true];
+    let exact = |sign| if sign == Sign::Zero { ZERO } else { POSITIVE };
+    let sum = |a, b| {
+        exact(if pre(a) == Sign::Zero {
+            pre(b)
+        } else {
+            Sign::Positive
+        })
+    };
+    let zero = |reg| pre(reg) == Sign::Zero;
+    Some(match (instruction, branch) {
+        (Instruction::Increment(reg, _), _) => vec![(reg, POSITIVE)],
+        (Instruction::AddConst(reg, n, _), _) => {
+            vec![(reg, if n == 0 { exact(pre(reg)) } else { POSITIVE })]
+        }
+        (Instruction::Clear(reg, _), _) => vec![(reg, ZERO)],
+        (Instruction::Read(reg, _), _) => vec![(reg, ANY)],
+        (Instruction::Decrement(reg, ..), 0) if !zero(reg) => vec![(reg, ANY)],
+        (Instruction::Decrement(reg, ..), 1) if zero(reg) => vec![],
+        (Instruction::SubConst(reg, 0, ..), 0) => vec![(reg, exact(pre(reg)))],
+        (Instruction::SubConst(reg, ..), 0) if !zero(reg) => vec![(reg, ANY)],
+        (Instruction::SubConst(reg, n, ..), 1) if n > 0 && (zero(reg) || n > 1) => vec![],
+        (Instruction::BranchZero(reg, ..), 0) if zero(reg) => vec![],
+        (Instruction::BranchZero(reg, ..), 1) if !zero(reg) => vec![],
+        (Instruction::Compare(a, b, ..), 0) if a == b || !zero(a) || zero(b) => vec![],
+        (Instruction::Compare(a, b, ..), 1) if a != b && !zero(b) => vec![],
+        (Instruction::Transfer { src, dst, .. }, _) if src != dst => {
+            vec![(dst, sum(dst, src)), (src, ZERO)]
+        }
+        (
+            Instruction::Copy {
+                src, dst, scratch, ..
+            },
+            _,
+        ) if src != dst && src != scratch && dst != scratch => vec![
+            (dst, sum(dst, src)),
+            (src, sum(src, scratch)),
+            (scratch, ZERO),
+        ],
+        (Instruction::Swap(a, b, _), _) => vec![(a, exact(pre(b))), (b, exact(pre(a)))],
+        (
+            Instruction::Decrement(..)
+            | Instruction::SubConst(..)
+            | Instruction::BranchZero(..)
+            | Instruction::Compare(..),
+            _,
+        ) => return None,
+        _ => vec![],
+    })
+}
+
+fn verify_backward(program: &Program, certificate: &BackwardCertificate) -> bool {
+    if uses_calls(program) {
+        return false;
+    }
+    let end = program.len() as u16;
+    let covered = |at: u16, signs: &BTreeMap<u8, Sign>| {
+        certificate.states.iter().any(|(state_at, constraints)| {
+            *state_at == at
+                && constraints
+                    .iter()
+                    .all(|(reg, sign)| signs.get(reg) == Some(sign))
+        })
+    };
+
+    // All halting configurations are included.
+    let mut halting = program
+        .iter()
+        .filter(|(_, instruction)| {
+            matches!(instruction, Instruction::Halt | Instruction::HaltWith(_))
+        })
+        .map(|(at, _)| at)
+        .chain(std::iter::once(end));
+    if !halting.all(|at| covered(at, &BTreeMap::new())) {
+        return false;
+    }
+    // The start configuration is excluded.
+    let starts = certificate
+        .states
+        .iter()
+        .any(|(at, constraints)| *at == 0 && constraints.values().all(|&sign| sign == Sign::Zero));
+    if starts {
+        return false;
+    }
+
+    // Every configuration stepping into a state is included as well. For each instruction,
+    // all signs of its registers are tried separately, other registers keep their sign.
+    for (target, post) in &certificate.states {
+        for (at, instruction) in program.iter() {
+            let registers: Vec<u8> = instruction
+                .registers()
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect();
+            for (branch, next) in instruction.targets().enumerate() {
+                if next.min(end) != *target {
+                    continue;
+                }
+                for pattern in 0..1u32 << registers.len() {
+                    let mut pre: BTreeMap<u8, Sign> = post
+                        .iter()
+                        .filter(|(reg, _)| !registers.contains(reg))
+                        .map(|(&reg, &sign)| (reg, sign))
+                        .collect();
+                    for (i, &reg) in registers.iter().enumerate() {
+                        let sign = if pattern & 1 << i == 0 {
+                            Sign::Zero
+                        } else {
+                            Sign::Positive
+                        };
+                        pre.insert(reg, sign);
+                    }
+                    let written = match sign_step(instruction, branch, |reg| pre[&reg]) {
+                        Some(written) => written,
+                        None => continue,
+                    };
+                    let reaches_post = post.iter().all(|(reg, &sign)| {
+                        match written.iter().find(|(written, _)| written == reg) {
+                            Some((_, possible)) => possible[sign as usize],
+                            None => pre.get(reg).is_none_or(|&pre| pre == sign),
+                        }
+                    });
+                    if reaches_post && !covered(at, &pre) {
+                        return false;
+                    }
+                }
+            }
+        }
+    }
+    true
+}
+
+/// The operations needed to check an [`InvariantCertificate`].
+///
+/// Sets of values are never empty, operations return `None` for the empty set.
+trait Value: Copy + Sized {
+    fn constant(value: u64) -> Self;
+    fn top() -> Self;
+    fn contains_zero(self) -> bool;
+    fn subset(self, other: Self) -> bool;
+    fn add(self, other: Self) -> Self;
+    /// The values which are at least `n`, with `n` subtracted from them.
+    fn sub_const(self, n: u64) -> Option<Self>;
+    /// The values which are less than `n`.
+    fn below(self, n: u64) -> Option<Self>;
+    /// The values `a` and `b` of both sets for which `a >= b`.
+    fn ge(a: Self, b: Self) -> Option<(Self, Self)>;
+    /// The values `a` and `b` of both sets for which `a < b`.
+    fn lt(a: Self, b: Self) -> Option<(Self, Self)>;
+
+    fn positive(self) -> Option<Self> {
+        Some(self.sub_const(1)?.add(Self::constant(1)))
+    }
+}
+
+fn interval(lo: u64, hi: Option<u64>) -> Option<Interval> {
+    Some(Interval { lo, hi }).filter(|_| hi.is_none_or(|hi| lo <= hi))
+}
+
+impl Value for Interval {
+    fn constant(value: u64) -> Interval {
+        Interval {
+            lo: value,
+            hi: Some(value),
+        }
+    }
+
+    fn top() -> Interval {
+        Interval { lo: 0, hi: None }
+    }
+
+    fn contains_zero(self) -> bool {
+        self.lo == 0
+    }
+
+    fn subset(self, other: Interval) -> bool {
+        self.lo >= other.lo
+            && match (self.hi, other.hi) {
+                (_, None) => true,
+                (Some(hi), Some(other)) => hi <= other,
+                (None, Some(_)) => false,
+            }
+    }
+
+    fn add(self, other: Interval) -> Interval {
+        Interval {
+            lo: self.lo.saturating_add(other.lo),
+            hi: self.hi.and_then(|hi| hi.checked_add(other.hi?)),
+        }
+    }
+
+    fn sub_const(self, n: u64) -> Option<Interval> {
+        let hi = match self.hi {
+            Some(hi) if hi < n => return None,
+            hi => hi.map(|hi| hi - n),
+        };
+        interval(self.lo.max(n) - n, hi)
+    }
+
+    fn below(self, n: u64) -> Option<Interval> {
+        let max = n.checked_sub(1)?;
+        interval(self.lo, Some(self.hi.map_or(max, |hi| hi.min(max))))
+    }
+
+    fn ge(a: Interval, b: Interval) -> Option<(Interval, Interval)> {
+        let a = interval(a.lo.max(b.lo), a.hi)?;
+        let b = interval(
+            b.lo,
+            match (a.hi, b.hi) {
+                (Some(x), Some(y)) => Some(x.min(y)),
+                (x, None) | (None, x) => x,
+            },
+        )?;
+        Some((a, b))
+    }
+
+    fn lt(a: Interval, b: Interval) -> Option<(Interval, Interval)> {
+        let a = match b.hi {
+            Some(hi) => a.below(hi)?,
+            None => a,
+        };
+        let lo = b.lo.max(a.lo.saturating_add(1));
+        Some((a, interval(lo, b.hi)?))
+    }
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        let rest = a % b;
+        a = b;
+        b = rest;
+    }
+    a
+}
+
+/// The value of a congruence which only contains a single value.
+fn single(congruence: Congruence) -> Option<u64> {
+    Some(congruence.residue()).filter(|_| congruence.modulus() == 0)
+}
+
+/// The congruence `residue mod modulus`, which may be zero for a single value.
+fn congruence(modulus: u64, residue: u128) -> Congruence {
+    let residue = match modulus {
+        0 => residue,
+        modulus => residue % u128::from(modulus),
+    };
+    match u64::try_from(residue)
+        .ok()
+        .and_then(|residue| Congruence::new(modulus, residue))
+    {
+        Some(congruence) => congruence,
+        None => Congruence::top(),
+    }
+}
+
+impl Value for Congruence {
+    fn constant(value: u64) -> Congruence {
+        congruence(0, u128::from(value))
+    }
+
+    fn top() -> Congruence {
+        congruence(1, 0)
+    }
+
+    fn contains_zero(self) -> bool {
+        self.residue() == 0
+    }
+
+    fn subset(self, other: Congruence) -> bool {
+        match (self.modulus(), other.modulus()) {
+            (_, 1) => true,
+            (0, 0) => self.residue() == other.residue(),
+            (0, m) => self.residue() % m == other.residue(),
+            (_, 0) => false,
+            (n, m) => n % m == 0 && self.residue() % m == other.residue(),
+        }
+    }
+
+    fn add(self, other: Congruence) -> Congruence {
+        let sum = u128::from(self.residue()) + u128::from(other.residue());
+        if let (0, 0) = (self.modulus(), other.modulus()) {
+            if sum > u128::from(u64::MAX) {
+                return Congruence::top();
+            }
+        }
+        congruence(gcd(self.modulus(), other.modulus()), sum)
+    }
+
+    fn sub_const(self, n: u64) -> Option<Congruence> {
+        match (single(self), self.modulus()) {
+            (Some(value), _) => Some(Congruence::constant(value.checked_sub(n)?)),
+            (None, m) => {
+                let residue = u128::from(self.residue()) + u128::from(m) - u128::from(n % m);
+                Some(congruence(m, residue))
+            }
+        }
+    }
+
+    fn below(self, n: u64) -> Option<Congruence> {
+        match single(self) {
+            _ if n == 0 => None,
+            Some(value) => Some(self).filter(|_| value < n),
+            None if n == 1 => Some(Congruence::constant(0)).filter(|_| self.contains_zero()),
+            None => Some(self),
+        }
+    }
+
+    fn ge(a: Congruence, b: Congruence) -> Option<(Congruence, Congruence)> {
+        match (single(a), single(b)) {
+            (Some(x), Some(y)) if x < y => None,
+            _ => Some((a, b)),
+        }
+    }
+
+    fn lt(a: Congruence, b: Congruence) -> Option<(Congruence, Congruence)> {
+        match (single(a), single(b)) {
+            (Some(x), Some(y)) if x >= y => None,
+            (_, Some(0)) => None,
+            _ => Some((a, b)),
+        }
+    }
+}
+
+/// The states after taking each target of `instruction` from `before`.
+fn value_step<V: Value>(
+    instruction: Instruction,
+    before: &BTreeMap<u8, V>,
+) -> Vec<Option<BTreeMap<u8, V>>> {
+    let value = |reg| before[&reg];
+    let with = |changes: &[(u8, Option<V>)]| {
+        let mut after = before.clone();
+        for &(reg, value) in changes {
+            after.insert(reg, value?);
+        }
+        Some(after)
+    };
+    let zero = V::constant(0);
+    let is_zero = |reg| Some(zero).filter(|_| value(reg).contains_zero());
+    match instruction {
+        Instruction::Increment(reg, _) => {
+            vec![with(&[(reg, Some(value(reg).add(V::constant(1))))])]
+        }
+        Instruction::AddConst(reg, n, _) => {
+            vec![with(&[(reg, Some(value(reg).add(V::constant(n))))])]
+        }
+        Instruction::Clear(reg, _) => vec![with(&[(reg, Some(zero))])],
+        Instruction::Read(reg, _) => vec![with(&[(reg, Some(V::top()))])],
+        Instruction::Decrement(reg, ..) => vec![
+            with(&[(reg, value(reg).sub_const(1))]),
+            with(&[(reg, is_zero(reg))]),
+        ],
+        Instruction::SubConst(reg, n, ..) => vec![
+            with(&[(reg, value(reg).sub_const(n))]),
+            with(&[(reg, value(reg).below(n))]),
+        ],
+        Instruction::BranchZero(reg, ..) => vec![
+            with(&[(reg, is_zero(reg))]),
+            with(&[(reg, value(reg).positive())]),
+        ],
+        Instruction::Compare(a, b, ..) if a == b => vec![Some(before.clone()), None],
+        Instruction::Compare(a, b, ..) => {
+            let refined = |refine: fn(V, V) -> Option<(V, V)>| {
+                let (x, y) = refine(value(a), value(b))?;
+                with(&[(a, Some(x)), (b, Some(y))])
+            };
+            vec![refined(V::ge), refined(V::lt)]
+        }
+        Instruction::Transfer { src, dst, .. } if src != dst => vec![with(&[
+            (dst, Some(value(dst).add(value(src)))),
+            (src, Some(zero)),
+        ])],
+        Instruction::Copy {
+            src, dst, scratch, ..
+        } if src != dst && src != scratch && dst != scratch => vec![with(&[
+            (dst, Some(value(dst).add(value(src)))),
+            (src, Some(value(src).add(value(scratch)))),
+            (scratch, Some(zero)),
+        ])],
+        Instruction::Swap(a, b, _) => vec![with(&[(a, Some(value(b))), (b, Some(value(a)))])],
+        _ => instruction
+            .targets()
+            .map(|_| Some(before.clone()))
+            .collect(),
+    }
+}
+
+fn verify_invariant<V: Value>(program: &Program, certificate: &InvariantCertificate<V>) -> bool {
+    if uses_calls(program) {
+        return false;
+    }
+    let end = program.len() as u16;
+    let used: BTreeSet<u8> = program
+        .iter()
+        .flat_map(|(_, instruction)| instruction.registers())
+        .collect();
+    let states = &certificate.states;
+    let complete = |state: &BTreeMap<u8, V>| used.iter().all(|reg| state.contains_key(reg));
+
+    let start = match states.get(&0) {
+        Some(start) => start,
+        None => return false,
+    };
+    if !used
+        .iter()
+        .all(|reg| start.get(reg).is_some_and(|v| v.contains_zero()))
+    {
+        return false;
+    }
+    states.iter().all(|(&at, state)| {
+        let instruction = program.instruction(at);
+        at < end
+            && complete(state)
+            && !matches!(instruction, Instruction::Halt | Instruction::HaltWith(_))
+            && instruction
+                .targets()
+                .zip(value_step(instruction, state))
+                .all(|(target, after)| match (after, states.get(&target)) {
+                    (None, _) => true,
+                    (Some(after), Some(next)) => {
+                        complete(next) && used.iter().all(|reg| after[reg].subset(next[reg]))
+                    }
+                    (Some(_), None) => false,
+                })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::SplitMix64;
+    use crate::{
+        Backward, CongruenceAnalysis, Cycler, Decider, Decision, IntervalAnalysis, Machine,
+        RunOutcome, TranslatedCycler,
+    };
+
+    fn random_program(rng: &mut SplitMix64) -> Program {
+        let mut next = |n: u64| rng.next_u64() % n;
+        let instructions: Vec<_> = (0..4)
+            .map(|_| {
+                let (a, b, c) = (next(3) as u8, next(3) as u8, next(3) as u8);
+                let (t, e) = (next(5) as u16, next(5) as u16);
+                match next(12) {
+                    0 => Instruction::Increment(a, t),
+                    1 => Instruction::Decrement(a, t, e),
+                    2 => Instruction::BranchZero(a, t, e),
+                    3 => Instruction::Jump(t),
+                    4 => Instruction::Clear(a, t),
+                    5 => Instruction::Transfer {
+                        src: a,
+                        dst: b,
+                        then: t,
+                    },
+                    6 => Instruction::Copy {
+                        src: a,
+                        dst: b,
+                        scratch: c,
+                        then: t,
+                    },
+                    7 => Instruction::SubConst(a, next(3), t, e),
+                    8 => Instruction::Compare(a, b, t, e),
+                    9 => Instruction::Swap(a, b, t),
+                    10 => Instruction::AddConst(a, next(3), t),
+                    _ => Instruction::Halt,
+                }
+            })
+            .collect();
+        Program::new(instructions)
+    }
+
+    #[test]
+    fn decider_certificates() {
+        let deciders: Vec<Box<dyn Decider>> = vec![
+            Box::new(Cycler {
+                max_steps: 200,
+                history: 50,
+            }),
+            Box::new(TranslatedCycler {
+                max_steps: 200,
+                history: 50,
+            }),
+            Box::new(Backward { max_states: 200 }),
+            Box::new(IntervalAnalysis),
+            Box::new(CongruenceAnalysis),
+        ];
+        let mut verified = vec![0; deciders.len()];
+        let mut rng = SplitMix64::new(7);
+        for _ in 0..500 {
+            let program = random_program(&mut rng);
+            for (decider, verified) in deciders.iter().zip(&mut verified) {
+                if let Decision::NeverHalts(certificate) = decider.decide(&program) {
+                    assert!(verify(&program, &certificate), "{:?}", program);
+                    let mut machine: Machine = Machine::new(&program);
+                    assert!(!matches!(machine.run(1000), RunOutcome::Halted { .. }));
+                    *verified += 1;
+                }
+            }
+        }
+        assert!(
+            verified.iter().all(|&verified| verified > 0),
+            "{:?}",
+            verified
+        );
+    }
+
+    #[test]
+    fn rejects_invalid() {
+        let cycles = Program::new([Instruction::Jump(0)].iter().copied());
+        let halts = Program::new([Instruction::Halt].iter().copied());
+        let cycler = |start, period| Certificate::Cycler(CyclerCertificate { start, period });
+        assert!(verify(&cycles, &cycler(3, 1)));
+        assert!(!verify(&cycles, &cycler(3, 0)));
+        assert!(!verify(&halts, &cycler(0, 1)));
+
+        let grows = Program::new([Instruction::Increment(0, 0)].iter().copied());
+        let translated = |register, shift| {
+            Certificate::TranslatedCycler(TranslatedCyclerCertificate {
+                start: 0,
+                period: 1,
+                register,
+                shift,
+            })
+        };
+        assert!(verify(&grows, &translated(0, 1)));
+        assert!(!verify(&grows, &translated(0, 2)));
+        assert!(!verify(&grows, &translated(1, 1)));
+
+        // Only halts once `$1` becomes nonzero, which never happens.
+        let unreachable = Program::new(
+            [
+                Instruction::Increment(0, 1),
+                Instruction::BranchZero(1, 0, 2),
+                Instruction::Halt,
+            ]
+            .iter()
+            .copied(),
+        );
+        let mut backward = unreachable.find_backward_proof(100).unwrap();
+        assert!(verify(
+            &unreachable,
+            &Certificate::Backward(backward.clone())
+        ));
+        backward.states.retain(|&(at, _)| at != 2);
+        assert!(!verify(&unreachable, &Certificate::Backward(backward)));
+
+        let mut intervals = InvariantCertificate::from(&unreachable.intervals(None));
+        assert!(verify(
+            &unreachable,
+            &Certificate::Intervals(intervals.clone())
+        ));
+        intervals
+            .states
+            .get_mut(&1)
+            .unwrap()
+            .insert(0, Interval::constant(1));
+        assert!(!verify(&unreachable, &Certificate::Intervals(intervals)));
+        assert!(!verify(
+            &halts,
+            &Certificate::Intervals(InvariantCertificate::from(&halts.intervals(None)))
+        ));
+    }
+}